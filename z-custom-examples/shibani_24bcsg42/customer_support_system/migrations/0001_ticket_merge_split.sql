@@ -0,0 +1,7 @@
+-- Links between tickets produced by merge and split operations.
+ALTER TABLE tickets
+    ADD COLUMN merged_into_id UUID NULL REFERENCES tickets (id),
+    ADD COLUMN parent_ticket_id UUID NULL REFERENCES tickets (id);
+
+CREATE INDEX idx_tickets_merged_into_id ON tickets (merged_into_id);
+CREATE INDEX idx_tickets_parent_ticket_id ON tickets (parent_ticket_id);
@@ -0,0 +1,34 @@
+-- Replace per-ticket free-text tags with a shared catalog and a join table.
+ALTER TABLE tags RENAME TO tags_legacy;
+
+CREATE TABLE tags (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    colour TEXT NULL,
+    description TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX idx_tags_name_lower ON tags (lower(name));
+
+CREATE TABLE ticket_tags (
+    ticket_id UUID NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (ticket_id, tag_id)
+);
+
+CREATE INDEX idx_ticket_tags_tag_id ON ticket_tags (tag_id);
+
+-- The first spelling seen for each case-insensitive name wins.
+INSERT INTO tags (id, name)
+SELECT DISTINCT ON (lower(tag_name)) gen_random_uuid(), tag_name
+FROM tags_legacy
+ORDER BY lower(tag_name), tag_name;
+
+INSERT INTO ticket_tags (ticket_id, tag_id)
+SELECT DISTINCT l.ticket_id, t.id
+FROM tags_legacy l
+JOIN tags t ON lower(t.name) = lower(l.tag_name);
+
+DROP TABLE tags_legacy;
@@ -0,0 +1,22 @@
+-- Customer satisfaction surveys issued when a ticket is resolved.
+CREATE TABLE csat_surveys (
+    id UUID PRIMARY KEY,
+    ticket_id UUID NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    customer_id UUID NOT NULL,
+    agent_id UUID NULL REFERENCES users (id),
+    rating SMALLINT NULL CHECK (rating BETWEEN 1 AND 5),
+    comment TEXT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    responded_at TIMESTAMPTZ NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_csat_surveys_ticket_id ON csat_surveys (ticket_id);
+CREATE INDEX idx_csat_surveys_agent_id ON csat_surveys (agent_id);
+
+-- Daily per-agent CSAT rollup; average = csat_score_total / csat_responses.
+ALTER TABLE analytics
+    ADD COLUMN csat_responses INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN csat_score_total INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_analytics_date_agent ON analytics (date, agent_id);
@@ -0,0 +1,35 @@
+-- Publishing workflow and immutable revision history for knowledge-base articles.
+ALTER TABLE knowledge_base
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'draft'
+        CHECK (status IN ('draft', 'in_review', 'published', 'archived')),
+    ADD COLUMN published_revision_id UUID NULL,
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE TABLE article_revisions (
+    id UUID PRIMARY KEY,
+    article_id UUID NOT NULL REFERENCES knowledge_base (id) ON DELETE CASCADE,
+    revision_number INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    category TEXT NOT NULL,
+    author_id UUID NOT NULL REFERENCES users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (article_id, revision_number)
+);
+
+ALTER TABLE knowledge_base
+    ADD CONSTRAINT fk_knowledge_base_published_revision
+        FOREIGN KEY (published_revision_id) REFERENCES article_revisions (id);
+
+-- Existing articles were already visible to everyone, so they start out published
+-- with their current text as revision 1.
+INSERT INTO article_revisions (id, article_id, revision_number, title, content, category, author_id, created_at)
+SELECT gen_random_uuid(), id, 1, title, content, category, created_by, created_at
+FROM knowledge_base;
+
+UPDATE knowledge_base kb
+SET status = 'published',
+    published_revision_id = r.id,
+    updated_at = kb.created_at
+FROM article_revisions r
+WHERE r.article_id = kb.id AND r.revision_number = 1;
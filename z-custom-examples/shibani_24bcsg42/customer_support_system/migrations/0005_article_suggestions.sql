@@ -0,0 +1,14 @@
+-- Suggested articles shown to agents, and whether the agent went on to use them.
+CREATE TABLE article_suggestions (
+    id UUID PRIMARY KEY,
+    ticket_id UUID NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    article_id UUID NOT NULL REFERENCES knowledge_base (id) ON DELETE CASCADE,
+    agent_id UUID NOT NULL REFERENCES users (id),
+    rank INTEGER NOT NULL,
+    score DOUBLE PRECISION NOT NULL,
+    action TEXT NULL CHECK (action IN ('linked', 'sent')),
+    shown_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    acted_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX idx_article_suggestions_ticket_article ON article_suggestions (ticket_id, article_id);
@@ -0,0 +1,5 @@
+-- Counters for the public help center.
+ALTER TABLE knowledge_base
+    ADD COLUMN view_count BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN helpful_votes INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN unhelpful_votes INTEGER NOT NULL DEFAULT 0;
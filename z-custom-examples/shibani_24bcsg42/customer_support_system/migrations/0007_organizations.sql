@@ -0,0 +1,33 @@
+-- Multi-tenant workspaces. Every tenant-owned table gets a tenant_id; rows that
+-- existed before this migration belong to the "default" organization.
+CREATE TABLE organizations (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO organizations (id, name, slug)
+VALUES ('00000000-0000-0000-0000-000000000001', 'Default', 'default');
+
+DO $$
+DECLARE
+    t TEXT;
+BEGIN
+    FOREACH t IN ARRAY ARRAY[
+        'users', 'customers', 'tickets', 'communications', 'knowledge_base',
+        'tags', 'analytics', 'audit_logs', 'csat_surveys', 'article_suggestions'
+    ]
+    LOOP
+        EXECUTE format(
+            'ALTER TABLE %I ADD COLUMN tenant_id UUID NOT NULL
+                DEFAULT ''00000000-0000-0000-0000-000000000001''
+                REFERENCES organizations (id)', t);
+        EXECUTE format('ALTER TABLE %I ALTER COLUMN tenant_id DROP DEFAULT', t);
+        EXECUTE format('CREATE INDEX idx_%s_tenant_id ON %I (tenant_id)', t, t);
+    END LOOP;
+END $$;
+
+-- Tag names are unique per tenant rather than globally.
+DROP INDEX idx_tags_name_lower;
+CREATE UNIQUE INDEX idx_tags_tenant_name_lower ON tags (tenant_id, lower(name));
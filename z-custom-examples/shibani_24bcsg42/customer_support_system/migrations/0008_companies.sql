@@ -0,0 +1,26 @@
+-- B2B accounts. Customers become contacts of a company, matched on the domain
+-- of their email address when they are created.
+CREATE TABLE companies (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    name TEXT NOT NULL,
+    shared_visibility BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_companies_tenant_id ON companies (tenant_id);
+
+-- Domains are stored lowercased; one domain maps to at most one company per tenant.
+CREATE TABLE company_domains (
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    domain TEXT NOT NULL,
+    company_id UUID NOT NULL REFERENCES companies (id) ON DELETE CASCADE,
+    PRIMARY KEY (tenant_id, domain)
+);
+
+CREATE INDEX idx_company_domains_company_id ON company_domains (company_id);
+
+ALTER TABLE customers
+    ADD COLUMN company_id UUID NULL REFERENCES companies (id) ON DELETE SET NULL;
+
+CREATE INDEX idx_customers_company_id ON customers (company_id);
@@ -0,0 +1,35 @@
+-- Duplicate customers. Emails are stored normalised (trimmed, lowercase) and
+-- are unique per tenant among customers that have not been merged away.
+ALTER TABLE customers
+    ADD COLUMN merged_into_id UUID NULL REFERENCES customers (id);
+
+CREATE INDEX idx_customers_merged_into_id ON customers (merged_into_id);
+
+UPDATE customers SET email = lower(trim(email));
+
+-- Existing exact duplicates are merged into the oldest record so the unique
+-- index below can be created. Nothing is deleted.
+CREATE TEMPORARY TABLE customer_merge_map AS
+SELECT id AS duplicate_id,
+       first_value(id) OVER (PARTITION BY tenant_id, email ORDER BY created_at, id) AS survivor_id
+FROM customers;
+
+DELETE FROM customer_merge_map WHERE duplicate_id = survivor_id;
+
+UPDATE tickets t SET customer_id = m.survivor_id
+FROM customer_merge_map m WHERE t.customer_id = m.duplicate_id;
+
+UPDATE communications c SET sender_id = m.survivor_id
+FROM customer_merge_map m WHERE c.sender_type = 'customer' AND c.sender_id = m.duplicate_id;
+
+UPDATE csat_surveys s SET customer_id = m.survivor_id
+FROM customer_merge_map m WHERE s.customer_id = m.duplicate_id;
+
+UPDATE customers c SET merged_into_id = m.survivor_id
+FROM customer_merge_map m WHERE c.id = m.duplicate_id;
+
+DROP TABLE customer_merge_map;
+
+CREATE UNIQUE INDEX idx_customers_tenant_email
+    ON customers (tenant_id, email)
+    WHERE merged_into_id IS NULL;
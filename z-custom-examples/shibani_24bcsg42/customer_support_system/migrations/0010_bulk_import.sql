@@ -0,0 +1,29 @@
+-- Bulk import. Records loaded from another helpdesk keep that system's id in
+-- external_id, so re-running an import updates rows instead of duplicating them.
+ALTER TABLE customers ADD COLUMN external_id TEXT NULL;
+ALTER TABLE tickets ADD COLUMN external_id TEXT NULL;
+ALTER TABLE communications ADD COLUMN external_id TEXT NULL;
+ALTER TABLE knowledge_base ADD COLUMN external_id TEXT NULL;
+
+CREATE UNIQUE INDEX idx_customers_external_id ON customers (tenant_id, external_id) WHERE external_id IS NOT NULL;
+CREATE UNIQUE INDEX idx_tickets_external_id ON tickets (tenant_id, external_id) WHERE external_id IS NOT NULL;
+CREATE UNIQUE INDEX idx_communications_external_id ON communications (tenant_id, external_id) WHERE external_id IS NOT NULL;
+CREATE UNIQUE INDEX idx_knowledge_base_external_id ON knowledge_base (tenant_id, external_id) WHERE external_id IS NOT NULL;
+
+-- One row per import run; the error report is a CSV of the rows that failed.
+CREATE TABLE import_jobs (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    kind TEXT NOT NULL,
+    format TEXT NOT NULL,
+    created_by UUID NOT NULL REFERENCES users (id),
+    processed INTEGER NOT NULL DEFAULT 0,
+    created INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    error_report TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX idx_import_jobs_tenant_id ON import_jobs (tenant_id);
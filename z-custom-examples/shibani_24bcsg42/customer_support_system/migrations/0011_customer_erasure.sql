@@ -0,0 +1,4 @@
+-- Right to erasure. An erased customer keeps its row, tickets and survey
+-- ratings for reporting, but its personal data is overwritten.
+ALTER TABLE customers
+    ADD COLUMN erased_at TIMESTAMPTZ NULL;
@@ -0,0 +1,17 @@
+-- Soft delete. Deleting a user, customer, ticket or article sets deleted_at;
+-- the row stays in the trash until it is restored or the retention job
+-- purges it.
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMPTZ NULL;
+ALTER TABLE customers ADD COLUMN deleted_at TIMESTAMPTZ NULL;
+ALTER TABLE tickets ADD COLUMN deleted_at TIMESTAMPTZ NULL;
+ALTER TABLE knowledge_base ADD COLUMN deleted_at TIMESTAMPTZ NULL;
+
+CREATE INDEX idx_users_deleted_at ON users (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_customers_deleted_at ON customers (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_tickets_deleted_at ON tickets (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_knowledge_base_deleted_at ON knowledge_base (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- A trashed customer no longer holds on to its email address.
+DROP INDEX idx_customers_tenant_email;
+CREATE UNIQUE INDEX idx_customers_tenant_email
+    ON customers (tenant_id, email) WHERE merged_into_id IS NULL AND deleted_at IS NULL;
@@ -0,0 +1,5 @@
+-- Optimistic concurrency. Every update bumps version; clients send the
+-- version they read back in If-Match and get 412 when someone else saved first.
+ALTER TABLE tickets ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE customers ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE knowledge_base ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,26 @@
+-- Changes made to a ticket, for its timeline. Messages and notes live in
+-- communications; this table holds everything else.
+CREATE TABLE ticket_events (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    ticket_id UUID NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    actor_type TEXT NOT NULL,     -- "user", "customer" or "system"
+    actor_id UUID NULL,
+    kind TEXT NOT NULL,
+    from_value TEXT NULL,
+    to_value TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_ticket_events_ticket_id ON ticket_events (ticket_id, created_at);
+CREATE INDEX idx_ticket_events_tenant_id ON ticket_events (tenant_id);
+
+-- Merges and splits were already audited; carry them over so older tickets
+-- show them too. The audit action is "<kind>:<other ticket id>".
+INSERT INTO ticket_events (id, tenant_id, ticket_id, actor_type, actor_id, kind, to_value, created_at)
+SELECT gen_random_uuid(), a.tenant_id, a.entity_id, 'user', a.user_id,
+       split_part(a.action, ':', 1), split_part(a.action, ':', 2), a.timestamp
+FROM audit_logs a
+JOIN tickets t ON t.id = a.entity_id
+WHERE a.entity = 'ticket'
+  AND split_part(a.action, ':', 1) IN ('merged_into', 'merged_from', 'split_to', 'split_from');
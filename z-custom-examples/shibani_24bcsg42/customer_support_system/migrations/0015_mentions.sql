@@ -0,0 +1,22 @@
+-- @mentions of agents in internal notes. Each row is one agent's inbox entry.
+CREATE TABLE mentions (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    communication_id UUID NOT NULL REFERENCES communications (id) ON DELETE CASCADE,
+    ticket_id UUID NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    mentioned_by UUID NULL,
+    read_at TIMESTAMPTZ NULL,
+    delivered_at TIMESTAMPTZ NULL,   -- set once the user's notification channel accepted it
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (communication_id, user_id)
+);
+
+CREATE INDEX idx_mentions_user_id ON mentions (user_id, created_at DESC);
+CREATE INDEX idx_mentions_unread ON mentions (user_id) WHERE read_at IS NULL;
+
+-- How each agent wants to hear about mentions. "inbox" only lists them;
+-- "webhook" also POSTs to notification_target (a Slack/Teams style URL).
+ALTER TABLE users
+    ADD COLUMN notification_channel TEXT NOT NULL DEFAULT 'inbox',
+    ADD COLUMN notification_target TEXT NULL;
@@ -0,0 +1,5 @@
+-- Consecutive failed logins per account. Past the threshold the account is
+-- locked until locked_until, for twice as long on every further failure.
+ALTER TABLE users
+    ADD COLUMN failed_logins INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN locked_until TIMESTAMPTZ NULL;
@@ -0,0 +1,23 @@
+-- Named keys for service-to-service calls. Only a SHA-256 hash of the key is
+-- stored; the key itself is shown once, when it is created.
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,             -- first characters of the key, to recognise it in lists
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL,             -- space separated, e.g. "tickets:write customers:read"
+    allowed_ips TEXT NULL,            -- space separated addresses or CIDR ranges; NULL allows any
+    created_by UUID NOT NULL REFERENCES users (id),
+    expires_at TIMESTAMPTZ NULL,
+    last_used_at TIMESTAMPTZ NULL,
+    last_used_ip TEXT NULL,
+    revoked_at TIMESTAMPTZ NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_api_keys_tenant_id ON api_keys (tenant_id);
+
+-- Actions taken with a key are logged under the admin who created it, with
+-- the key named here.
+ALTER TABLE audit_logs ADD COLUMN api_key_id UUID NULL REFERENCES api_keys (id);
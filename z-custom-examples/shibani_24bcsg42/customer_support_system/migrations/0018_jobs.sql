@@ -0,0 +1,40 @@
+-- Background work outside the request path. Workers claim queued rows with
+-- FOR UPDATE SKIP LOCKED, so any number of them can share the table.
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY,
+    tenant_id UUID NULL REFERENCES organizations (id),   -- NULL for jobs that span tenants
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',   -- queued, running, succeeded or dead
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    locked_at TIMESTAMPTZ NULL,
+    locked_by TEXT NULL,
+    last_error TEXT NULL,
+    unique_key TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX idx_jobs_ready ON jobs (run_at) WHERE status = 'queued';
+CREATE INDEX idx_jobs_status_kind ON jobs (status, kind);
+CREATE INDEX idx_jobs_tenant_id ON jobs (tenant_id);
+
+-- At most one pending or running job per (kind, unique_key); a finished one
+-- does not block the next.
+CREATE UNIQUE INDEX uq_jobs_unique_key ON jobs (kind, unique_key)
+    WHERE unique_key IS NOT NULL AND status IN ('queued', 'running');
+
+-- Recurring jobs. The schedules themselves are defined in code; this table
+-- only records when each is next due, so that with several workers running
+-- each occurrence is enqueued once.
+CREATE TABLE job_schedules (
+    name TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    cron TEXT NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    last_run_at TIMESTAMPTZ NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
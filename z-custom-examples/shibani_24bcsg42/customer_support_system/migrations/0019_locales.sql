@@ -0,0 +1,15 @@
+-- Every article is written in one locale. A translation is an article of its
+-- own (own revisions, own publishing workflow) pointing at the original it
+-- translates, so the help center can pick the variant the reader asked for
+-- and fall back to the original when there is none.
+ALTER TABLE knowledge_base
+    ADD COLUMN locale TEXT NOT NULL DEFAULT 'en',
+    ADD COLUMN translation_of UUID NULL REFERENCES knowledge_base (id) ON DELETE SET NULL;
+
+CREATE INDEX idx_knowledge_base_translation_of ON knowledge_base (translation_of);
+CREATE UNIQUE INDEX uq_knowledge_base_translation_locale
+    ON knowledge_base (translation_of, locale)
+    WHERE translation_of IS NOT NULL;
+
+-- Language customer-facing messages are written in. NULL means English.
+ALTER TABLE customers ADD COLUMN preferred_locale TEXT NULL;
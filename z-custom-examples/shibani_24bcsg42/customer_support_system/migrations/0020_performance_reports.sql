@@ -0,0 +1,8 @@
+-- Agents belong to at most one team, for grouping reports. Free text, like
+-- ticket channels and priorities.
+ALTER TABLE users ADD COLUMN team TEXT NULL;
+
+-- Reports scan a tenant's tickets by creation date and look up each ticket's
+-- first agent reply.
+CREATE INDEX idx_tickets_tenant_created_at ON tickets (tenant_id, created_at);
+CREATE INDEX idx_communications_ticket_id ON communications (ticket_id, timestamp);
@@ -0,0 +1,24 @@
+-- One analytics row per agent and day, so CSAT answers can be counted with an
+-- upsert instead of reading the row and writing it back.
+UPDATE analytics a
+SET total_tickets = s.total_tickets,
+    resolved_tickets = s.resolved_tickets,
+    csat_responses = s.csat_responses,
+    csat_score_total = s.csat_score_total
+FROM (
+    SELECT tenant_id, agent_id, date,
+           SUM(total_tickets) AS total_tickets,
+           SUM(resolved_tickets) AS resolved_tickets,
+           SUM(csat_responses) AS csat_responses,
+           SUM(csat_score_total) AS csat_score_total
+    FROM analytics
+    GROUP BY tenant_id, agent_id, date
+    HAVING COUNT(*) > 1
+) s
+WHERE a.tenant_id = s.tenant_id AND a.agent_id = s.agent_id AND a.date = s.date;
+
+DELETE FROM analytics a
+USING analytics b
+WHERE a.tenant_id = b.tenant_id AND a.agent_id = b.agent_id AND a.date = b.date AND a.id > b.id;
+
+CREATE UNIQUE INDEX uq_analytics_tenant_agent_date ON analytics (tenant_id, agent_id, date);
@@ -0,0 +1,22 @@
+-- Phone numbers saved before 0009 were never normalised, so they do not match
+-- the E.164 form new ones are stored in. Same rules as dedupe::normalise_phone:
+-- a leading + or 00 is international, anything else is a national number with
+-- country code 91. Numbers that still do not come out as 8 to 15 digits, or
+-- contain letters, are left as they are.
+WITH parsed AS (
+    SELECT id,
+           CASE
+               WHEN btrim(phone) LIKE '+%' THEN regexp_replace(phone, '\D', '', 'g')
+               WHEN regexp_replace(phone, '\D', '', 'g') LIKE '00%' THEN substr(regexp_replace(phone, '\D', '', 'g'), 3)
+               ELSE '91' || ltrim(regexp_replace(phone, '\D', '', 'g'), '0')
+           END AS international
+    FROM customers
+    WHERE btrim(phone) <> ''
+      AND phone !~ '^\+[1-9][0-9]{7,14}$'
+      AND phone !~ '[[:alpha:]]'
+)
+UPDATE customers c
+SET phone = '+' || p.international
+FROM parsed p
+WHERE c.id = p.id
+  AND p.international ~ '^[1-9][0-9]{7,14}$';
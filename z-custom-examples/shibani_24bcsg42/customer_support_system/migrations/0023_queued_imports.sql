@@ -0,0 +1,10 @@
+-- Imports run as background jobs. The uploaded file waits in import_uploads
+-- until the job has worked through it; status tells a queued or running import
+-- from a finished one. Imports from before this ran inline and are finished.
+ALTER TABLE import_jobs ADD COLUMN status TEXT NOT NULL DEFAULT 'finished';
+ALTER TABLE import_jobs ALTER COLUMN status SET DEFAULT 'queued';
+
+CREATE TABLE import_uploads (
+    import_id UUID PRIMARY KEY REFERENCES import_jobs (id) ON DELETE CASCADE,
+    data BYTEA NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Failed logins are counted per account and client address, so knowing an
+-- email is not enough to lock its owner out: only the address the guesses come
+-- from is locked. Locks held on accounts as a whole are dropped.
+CREATE TABLE login_failures (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    ip TEXT NOT NULL,
+    tenant_id UUID NOT NULL REFERENCES organizations (id),
+    failed_logins INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMPTZ NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, ip)
+);
+
+ALTER TABLE users
+    DROP COLUMN failed_logins,
+    DROP COLUMN locked_until;
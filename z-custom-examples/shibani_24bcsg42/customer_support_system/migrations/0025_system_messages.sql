@@ -0,0 +1,5 @@
+-- Messages the system sends on an agent's behalf (survey invitations, merge
+-- notices). They show in the conversation like any agent reply but are not a
+-- human answer, so first-response times leave them out.
+ALTER TABLE communications
+    ADD COLUMN is_system BOOLEAN NOT NULL DEFAULT false;
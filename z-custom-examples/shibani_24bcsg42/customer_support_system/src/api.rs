@@ -1,30 +1,63 @@
 use axum::{
-    response::IntoResponse,
+    body::Body,
+    response::{IntoResponse, Response},
     extract::{State, Path, Query},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     Json,
 };
 use axum::debug_handler;
 use chrono::{NaiveDate}; 
-use sea_orm::{EntityTrait, Set, ActiveModelTrait, DatabaseConnection, QueryFilter, ColumnTrait, ModelTrait, Condition, QuerySelect};
+use sea_orm::{EntityTrait, Set, ActiveModelTrait, QueryFilter, ColumnTrait, ModelTrait, Condition, QuerySelect, QueryOrder, PaginatorTrait, TransactionTrait, ConnectionTrait, FromQueryResult, JoinType, RelationTrait, DbErr, SqlErr, DbBackend, Statement};
+use sea_orm::sea_query::{Alias, Expr, Func, OnConflict, Query as SubQuery};
 use serde::{Deserialize, Serialize};
 use sqlx::types::chrono::Utc;
 use sea_orm::prelude::Uuid;
 use crate::{app_state::AppState};
-use crate::entity::tickets::Model; 
 use crate::entity::prelude::*;
 use crate::entity::users;
+use crate::entity::login_failures;
 use crate::entity::customers;
+use crate::entity::companies;
+use crate::entity::company_domains;
 use crate::entity::tickets;
 use crate::entity::communications;
 use crate::entity::knowledge_base;
+use crate::entity::article_revisions;
+use crate::entity::article_suggestions;
 use crate::entity::tags;
+use crate::entity::ticket_tags;
 use crate::entity::analytics;   
 use crate::entity::audit_logs;
+use crate::entity::csat_surveys;
+use crate::entity::organizations;
+use crate::entity::import_jobs;
+use crate::entity::import_uploads;
+use crate::entity::ticket_events;
+use crate::entity::mentions;
+use crate::entity::api_keys;
+use crate::entity::jobs;
+use crate::entity::job_schedules;
 use crate::auth::{AuthUser, require_role};
 use utoipa::{ToSchema, IntoParams}; 
 use crate::auth;
-use crate::error_handle::AppError;
+use crate::error_handle::{conflict_on_unique, AppError};
+use crate::diff::{line_diff, DiffLine};
+use crate::dedupe::{duplicate_score, normalise_email, normalise_phone};
+use crate::rate_limit::{account_delay_for, lockout_for, ClientIp, ANY_ADDRESS};
+use crate::http_cache::cached_json;
+use crate::gdpr::{erased_email, Archive, ERASED_NAME, KEPT_EVENT_VALUES, REDACTED};
+use crate::bulk::{export_body, read_rows, ErrorReport, Format, Keyed, Row, EXPORT_PAGE_SIZE};
+use crate::tenant::TenantScoped;
+use std::collections::HashMap;
+use crate::concurrency::{save_versioned, with_etag, IfMatch};
+use crate::mentions as mention_parser;
+use crate::notify;
+use crate::jobs::{EnqueueOptions, Job};
+use crate::metrics;
+use crate::locale::{self, Text};
+use crate::kb_index;
+use crate::forecast::{self, arrivals_statement, ArrivalRow};
+use crate::reports::{self, performance_statement, GroupBy, PerformanceRow, ReportFormat};
 
 
 //-----------login--------------
@@ -32,6 +65,7 @@ use crate::error_handle::AppError;
 pub struct LoginInput {
     pub email: String,
     pub password: String,
+    pub organization: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -45,39 +79,240 @@ pub struct LoginResponse {
     request_body = LoginInput,
     responses(
         (status = 200, description = "Login successful", body = LoginResponse),
-        (status = 401, description = "Unauthorized")
+        (status = 401, description = "Unknown email, wrong password, or the account is locked for this address or held back after failures from many"),
+        (status = 400, description = "The password matches accounts in several organizations; specify organization"),
+        (status = 429, description = "Too many attempts from this address; see Retry-After")
     ),
-    tag = "User"
+    tag = "User",
+    security(())
 )]
 pub async fn login_user(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Json(input): Json<LoginInput>,
 ) -> Result<Json<LoginResponse>, AppError> {
     use crate::entity::users;
 
-    let user = users::Entity::find()
+    let db = state.db.as_ref();
+
+    // cross-tenant: login is the one place that looks across tenants; the
+    // account found decides which organization the token is issued for.
+    let mut query = users::Entity::find()
         .filter(users::Column::Email.eq(input.email.clone()))
-        .one(state.db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?
-        .ok_or(AppError::Unauthorized)?;
+        .filter(users::Column::DeletedAt.is_null());
+    if let Some(slug) = &input.organization {
+        let org = OrganizationEntity::find()
+            .filter(organizations::Column::Slug.eq(slug.as_str()))
+            .one(db)
+            .await?
+            .ok_or(AppError::Unauthorized)?;
+        query = query.filter(users::Column::TenantId.eq(org.id));
+    }
 
+    let matches = query.all(db).await.map_err(|_| AppError::Db(()))?;
+
+    // Every account with this email is tried, so being told the email is used
+    // in several organizations takes the right password for more than one.
+    // An account is locked for this address, so someone guessing elsewhere
+    // cannot shut its owner out, and held back briefly from every address, so
+    // guesses spread over many addresses slow down too. Both are checked
+    // before the password, so guesses made meanwhile tell the caller nothing,
+    // and answer like a wrong password, so they do not show the email exists.
+    let addresses = [ip.as_str(), ANY_ADDRESS];
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+    for user in matches {
+        let failures = LoginFailureEntity::find_in(user.tenant_id)
+            .filter(login_failures::Column::UserId.eq(user.id))
+            .filter(login_failures::Column::Ip.is_in(addresses))
+            .all(db)
+            .await?;
+        if failures.iter().filter_map(|f| f.locked_until).any(|until| until > Utc::now()) {
+            continue;
+        }
+        if input.password == user.password_hash {
+            accepted.push((user, !failures.is_empty()));
+        } else {
+            rejected.push(user);
+        }
+    }
 
-    if input.password != user.password_hash {
+    if accepted.len() > 1 {
+        return Err(AppError::BadRequest("Email is used in several organizations; specify organization".into()));
+    }
+    let Some((user, had_failures)) = accepted.pop() else {
+        for user in &rejected {
+            record_failed_login(db, user, &ip).await?;
+        }
         return Err(AppError::Unauthorized);
+    };
+
+    if had_failures {
+        LoginFailureEntity::delete_many_in(user.tenant_id)
+            .filter(login_failures::Column::UserId.eq(user.id))
+            .filter(login_failures::Column::Ip.is_in(addresses))
+            .exec(db)
+            .await?;
     }
 
-    let token = crate::auth::generate_jwt(&user.id.to_string(), &user.role);
+    let token = crate::auth::generate_jwt(&state.jwt, &user.id.to_string(), &user.role, &user.tenant_id.to_string());
 
     Ok(Json(LoginResponse { token }))
 }
 
+// Counts a failed login from `ip` against the account, both for that address
+// and for the account as a whole, and locks whichever is past its threshold.
+async fn record_failed_login(db: &sea_orm::DatabaseConnection, user: &users::Model, ip: &str) -> Result<(), AppError> {
+    count_failed_login(db, user, ip, ip, lockout_for).await?;
+    count_failed_login(db, user, ip, ANY_ADDRESS, account_delay_for).await
+}
+
+// The counter is bumped in SQL so parallel guesses are all counted.
+async fn count_failed_login(
+    db: &sea_orm::DatabaseConnection,
+    user: &users::Model,
+    ip: &str,
+    address: &str,
+    lockout_for: fn(i32) -> Option<chrono::Duration>,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let failure = login_failures::ActiveModel {
+        user_id: Set(user.id),
+        ip: Set(address.to_string()),
+        tenant_id: Set(user.tenant_id),
+        failed_logins: Set(1),
+        locked_until: Set(None),
+        updated_at: Set(now),
+    };
+    let counted = LoginFailureEntity::insert(failure)
+        .on_conflict(
+            OnConflict::columns([login_failures::Column::UserId, login_failures::Column::Ip])
+                .value(
+                    login_failures::Column::FailedLogins,
+                    Expr::col((LoginFailureEntity, login_failures::Column::FailedLogins)).add(1),
+                )
+                .value(login_failures::Column::UpdatedAt, Expr::value(now))
+                .to_owned(),
+        )
+        .exec_with_returning(db)
+        .await?;
+
+    if let Some(lockout) = lockout_for(counted.failed_logins) {
+        LoginFailureEntity::update_many_in(user.tenant_id)
+            .col_expr(login_failures::Column::LockedUntil, Expr::value(now + lockout))
+            .filter(login_failures::Column::UserId.eq(user.id))
+            .filter(login_failures::Column::Ip.eq(address))
+            .exec(db)
+            .await?;
+        let actor = AuditActor { tenant_id: user.tenant_id, user_id: user.id, api_key_id: None };
+        let action = if address == ANY_ADDRESS { "login_delayed" } else { "login_locked" };
+        record_audit(db, &actor, action, "user", user.id, ip).await?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Deserialize, IntoParams, ToSchema)]
 pub struct Pagination {
     pub limit: Option<u64>,
     pub offset: Option<u64>,
 }
 
+//----------audit helpers----------------
+fn auth_uuid(auth: &AuthUser) -> Result<Uuid, AppError> {
+    Uuid::parse_str(&auth.u_id).map_err(|_| AppError::Unauthorized)
+}
+
+// Who an audit entry is attributed to. Calls made with an API key are logged
+// under the admin who created the key, with the key itself named too.
+struct AuditActor {
+    tenant_id: Uuid,
+    user_id: Uuid,
+    api_key_id: Option<Uuid>,
+}
+
+impl AuditActor {
+    fn of(auth: &AuthUser) -> Result<Self, AppError> {
+        Ok(AuditActor {
+            tenant_id: auth.tenant_id,
+            user_id: auth_uuid(auth)?,
+            api_key_id: auth.api_key_id,
+        })
+    }
+}
+
+async fn record_audit<C: ConnectionTrait>(
+    db: &C,
+    actor: &AuditActor,
+    action: &str,
+    entity: &str,
+    entity_id: Uuid,
+    ip_address: &str,
+) -> Result<(), AppError> {
+    let log = audit_logs::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(actor.tenant_id),
+        user_id: Set(actor.user_id),
+        api_key_id: Set(actor.api_key_id),
+        action: Set(action.to_string()),
+        entity: Set(entity.to_string()),
+        entity_id: Set(entity_id),
+        timestamp: Set(Utc::now()),
+        ip_address: Set(ip_address.to_string()),
+    };
+
+    log.insert(db).await.map_err(|e| {
+        tracing::error!(error = %e, "Audit log error");
+        AppError::Internal("Could not record audit log".into())
+    })?;
+
+    Ok(())
+}
+
+// One change to a ticket, shown on its timeline. `kind` names what changed;
+// `from` and `to` are the old and new values as text.
+async fn record_ticket_event<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    ticket_id: Uuid,
+    kind: &str,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<(), AppError> {
+    let (actor_type, actor_id) = match auth.api_key_id {
+        Some(key_id) => ("api_key", Some(key_id)),
+        None if auth.role == "customer" => ("customer", Uuid::parse_str(&auth.u_id).ok()),
+        None => ("user", Uuid::parse_str(&auth.u_id).ok()),
+    };
+    ticket_events::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        ticket_id: Set(ticket_id),
+        actor_type: Set(actor_type.into()),
+        actor_id: Set(actor_id),
+        kind: Set(kind.into()),
+        from_value: Set(from),
+        to_value: Set(to),
+        created_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+// Audit entry for moving a record to or out of the trash.
+async fn audit_trash<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    action: &str,
+    entity: &str,
+    entity_id: Uuid,
+    ip: &str,
+) -> Result<(), AppError> {
+    let actor = AuditActor::of(auth)?;
+    record_audit(db, &actor, action, entity, entity_id, ip).await
+}
+
 //----------user----------------
 #[derive(Deserialize, ToSchema)]
 pub struct CreateUserInput {
@@ -85,9 +320,17 @@ pub struct CreateUserInput {
     name: String,
     password_hash: String,
     role: String,
+    // Team the agent reports under; only admins can set it. On update, left
+    // out keeps the current team and "" clears it.
+    #[serde(default)]
+    team: Option<String>,
 }
 use sea_orm::IntoActiveModel;
 
+fn team_name(team: Option<String>) -> Option<String> {
+    team.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+}
+
 
 #[derive(Serialize, ToSchema)]
 pub struct UserResponse {
@@ -95,6 +338,7 @@ pub struct UserResponse {
     email: String,
     name: String,
     role: String,
+    team: Option<String>,
 }
 
 
@@ -110,22 +354,33 @@ pub struct UserResponse {
 )]
  pub async fn create_user(
     State(state): State<AppState>,
+    auth: AuthUser,
     Json(input): Json<CreateUserInput>,
 ) -> Result<Json<UserResponse>, AppError> {
+    require_role(&auth, "admin")?;
+    if input.role == auth::SUPER_ADMIN && auth.role != auth::SUPER_ADMIN {
+        return Err(AppError::Forbidden);
+    }
+
     let user = users::ActiveModel {
         id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
         email: Set(input.email),
         name: Set(input.name),
         password_hash: Set(input.password_hash.to_string()),
         role: Set(input.role.to_string()),
-        created_at: Set(Utc::now().into())
+        team: Set(team_name(input.team)),
+        notification_channel: Set(crate::notify::INBOX.into()),
+        notification_target: Set(None),
+        deleted_at: Set(None),
+        created_at: Set(Utc::now())
     };
 
     let db = &state.db;
     let res = users::ActiveModel::insert(user, db.as_ref())
         .await
         .map_err(|e| {
-            eprintln!("Failed to create user: {}", e);
+            tracing::error!(error = %e, "Failed to create user");
             AppError::Internal("Failed to create user".into())
     })?;
 
@@ -134,6 +389,7 @@ pub struct UserResponse {
         email: res.email,
         name: res.name,
         role: res.role,
+        team: res.team,
     }))
 }
 
@@ -152,11 +408,11 @@ pub async fn get_users(
     require_role(&_auth_user, "admin")?;
 
     let db = &state.db;
-    let users = UserEntity::find()
+    let users = UserEntity::find_in(_auth_user.tenant_id)
         .all(db.as_ref())
         .await
         .map_err(|e| {
-            eprintln!("Failed to retrieve users: {}", e);
+            tracing::error!(error = %e, "Failed to retrieve users");
             AppError::Internal("Failed to retrieve users".into())
         })?;
 
@@ -167,6 +423,7 @@ pub async fn get_users(
             email: user.email,
             name: user.name,
             role: user.role,
+            team: user.team,
         })
         .collect();
 
@@ -185,13 +442,18 @@ pub async fn get_users(
 )]
 pub async fn update_user(
     State(state): State<AppState>,
+    auth: AuthUser,
     Path(id): Path<Uuid>,
     Json(input): Json<CreateUserInput>,
 ) -> Result<Json<UserResponse>, (StatusCode, String)> {
     let db = &state.db;
     use axum::http::StatusCode;
 
-let user = UserEntity::find_by_id(id)
+    if !auth.is_admin() && auth.u_id != id.to_string() {
+        return Err((StatusCode::FORBIDDEN, "Access denied".into()));
+    }
+
+let user = UserEntity::find_by_id_in(id, auth.tenant_id)
     .one(db.as_ref())
     .await
     .map_err(|err| {
@@ -202,13 +464,20 @@ let user = UserEntity::find_by_id(id)
     })?;
 
 
-    let mut active_user: users::ActiveModel = user.unwrap().into();
+    let mut active_user: users::ActiveModel = user
+        .ok_or((StatusCode::NOT_FOUND, "User not found".into()))?
+        .into();
     active_user.email = Set(input.email);
     active_user.name = Set(input.name);
+    if auth.is_admin()
+        && let Some(team) = input.team
+    {
+        active_user.team = Set(team_name(Some(team)));
+    }
     active_user.created_at = Set(Utc::now()); 
 
     let res = active_user.update(db.as_ref()).await.map_err(|e| {
-        eprintln!("Failed to update user: {}", e);
+        tracing::error!(error = %e, "Failed to update user");
         (StatusCode::INTERNAL_SERVER_ERROR, "Update failed".into())
     })?;
 
@@ -217,6 +486,7 @@ let user = UserEntity::find_by_id(id)
         email: res.email,
         name: res.name,
         role: res.role,
+        team: res.team,
     }))
 }
 
@@ -224,32 +494,96 @@ let user = UserEntity::find_by_id(id)
     delete,
     path = "/users/{id}",
     responses(
-        (status = 204, description = "User deleted successfully"),
+        (status = 204, description = "User moved to the trash"),
+        (status = 400, description = "Cannot delete your own account"),
         (status = 404, description = "User not found")
     ),
     tag = "User"
 )]
 pub async fn delete_user(
     State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, (StatusCode, String)> {
+    if !auth.is_admin() {
+        return Err((StatusCode::FORBIDDEN, "Access denied".into()));
+    }
+    if auth.u_id == id.to_string() {
+        return Err((StatusCode::BAD_REQUEST, "Cannot delete your own account".into()));
+    }
+
     let db = &state.db;
-    UserEntity::delete_by_id(id)
+    let trashed = UserEntity::update_many_in(auth.tenant_id)
+        .col_expr(users::Column::DeletedAt, Expr::value(Utc::now()))
+        .filter(users::Column::Id.eq(id))
+        .filter(users::Column::DeletedAt.is_null())
         .exec(db.as_ref())
         .await
         .map_err(|e| {
-            eprintln!("Failed to delete user: {}", e);
+            tracing::error!(error = %e, "Failed to delete user");
             (StatusCode::INTERNAL_SERVER_ERROR, "Deletion failed".into())
         })?;
+    if trashed.rows_affected == 0 {
+        return Err((StatusCode::NOT_FOUND, "User not found".into()));
+    }
+
+    audit_trash(db.as_ref(), &auth, "trashed", "user", id, &ip)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Could not record audit log".into()))?;
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    post,
+    path = "/users/{id}/unlock",
+    params(("id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Lockout cleared and failed login count reset", body = UserResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "User not found")
+    ),
+    tag = "User"
+)]
+pub async fn unlock_user(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+) -> Result<Json<UserResponse>, AppError> {
+    require_role(&auth, "admin")?;
+    let db = state.db.as_ref();
+
+    let user = UserEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    LoginFailureEntity::delete_many_in(auth.tenant_id)
+        .filter(login_failures::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+
+    let actor = AuditActor::of(&auth)?;
+    record_audit(db, &actor, "unlocked", "user", id, &ip).await?;
+
+    Ok(Json(UserResponse {
+        id: user.id,
+        email: user.email,
+        name: user.name,
+        role: user.role,
+        team: user.team,
+    }))
+}
+
 //----------customer----------------
 #[derive(Deserialize, ToSchema)]
 pub struct CreateCustomerInput {
     pub name: String,
     pub email: String,
     pub phone: String,
+    // Language of the messages the customer is sent, e.g. "fr" or "pt-BR".
+    #[serde(default)]
+    pub preferred_locale: Option<String>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -258,6 +592,108 @@ pub struct CustomerResponse {
     pub name: String,
     pub email: String,
     pub phone: String,
+    pub company_id: Option<Uuid>,
+    pub merged_into_id: Option<Uuid>,
+    pub preferred_locale: Option<String>,
+    pub version: i32,
+}
+
+impl From<customers::Model> for CustomerResponse {
+    fn from(c: customers::Model) -> Self {
+        CustomerResponse {
+            id: c.id,
+            name: c.name,
+            email: c.email,
+            phone: c.phone,
+            company_id: c.company_id,
+            merged_into_id: c.merged_into_id,
+            preferred_locale: c.preferred_locale,
+            version: c.version,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct DuplicateQuery {
+    pub min_score: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DuplicateCandidate {
+    pub customer: CustomerResponse,
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MergeCustomersInput {
+    pub source_customer_ids: Vec<Uuid>,
+}
+
+// Normalised (email, phone) for storage. An empty phone is allowed; anything
+// else must be convertible to E.164.
+fn normalise_contact(input: &CreateCustomerInput) -> Result<(String, String), (StatusCode, String)> {
+    let email = normalise_email(&input.email);
+    if !email.contains('@') {
+        return Err((StatusCode::BAD_REQUEST, "Invalid email".into()));
+    }
+    let phone = if input.phone.trim().is_empty() {
+        String::new()
+    } else {
+        normalise_phone(&input.phone)
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid phone number".into()))?
+    };
+    Ok((email, phone))
+}
+
+fn preferred_locale(input: &CreateCustomerInput) -> Result<Option<String>, (StatusCode, String)> {
+    match input.preferred_locale.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(tag) => locale::normalise(tag)
+            .map(Some)
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid locale".into())),
+    }
+}
+
+async fn email_in_use<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    email: &str,
+    except: Option<Uuid>,
+) -> Result<bool, (StatusCode, String)> {
+    let mut query = CustomerEntity::find_in(tenant_id)
+        .filter(customers::Column::Email.eq(email))
+        .filter(customers::Column::MergedIntoId.is_null());
+    if let Some(id) = except {
+        query = query.filter(customers::Column::Id.ne(id));
+    }
+    let existing = query
+        .one(db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    Ok(existing.is_some())
+}
+
+fn email_domain(email: &str) -> Option<String> {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim().to_lowercase())
+        .filter(|domain| !domain.is_empty())
+}
+
+// New contacts join the company that owns their email domain, if any.
+async fn company_for_email<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    email: &str,
+) -> Result<Option<Uuid>, DbErr> {
+    let Some(domain) = email_domain(email) else {
+        return Ok(None);
+    };
+    let found = CompanyDomainEntity::find_by_id_in((tenant_id, domain), tenant_id)
+        .one(db)
+        .await?;
+    Ok(found.map(|d| d.company_id))
 }
 
 // CREATE
@@ -267,33 +703,53 @@ pub struct CustomerResponse {
     request_body = CreateCustomerInput,
     responses(
         (status = 201, description = "Customer created", body = CustomerResponse),
+        (status = 400, description = "Invalid email, phone number or locale"),
+        (status = 409, description = "A customer with this email already exists")
     ),
     tag = "Customer"
 )]
 pub async fn create_customer(
     State(state): State<AppState>,
+    auth: AuthUser,
     Json(input): Json<CreateCustomerInput>,
 ) -> Result<Json<CustomerResponse>, (StatusCode, String)> {
+    let db = &state.db;
+    let (email, phone) = normalise_contact(&input)?;
+    let preferred_locale = preferred_locale(&input)?;
+    if email_in_use(db.as_ref(), auth.tenant_id, &email, None).await? {
+        return Err((StatusCode::CONFLICT, "A customer with this email already exists".into()));
+    }
+    let company_id = company_for_email(db.as_ref(), auth.tenant_id, &email)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+
     let customer = customers::ActiveModel {
         id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
         name: Set(input.name),
-        email: Set(input.email),
-        phone: Set(input.phone),
-        created_at: Set(Utc::now().into()),
+        email: Set(email),
+        phone: Set(phone),
+        company_id: Set(company_id),
+        merged_into_id: Set(None),
+        external_id: Set(None),
+        preferred_locale: Set(preferred_locale),
+        erased_at: Set(None),
+        deleted_at: Set(None),
+        version: Set(1),
+        created_at: Set(Utc::now()),
     };
 
-    let db = &state.db;
-    let res = customer.insert(db.as_ref()).await.map_err(|e| {
-        eprintln!("Error creating customer: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not create customer".into())
+    let res = customer.insert(db.as_ref()).await.map_err(|e| match e.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(_)) => {
+            (StatusCode::CONFLICT, "A customer with this email already exists".into())
+        }
+        _ => {
+            tracing::error!(error = %e, "Error creating customer");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not create customer".into())
+        }
     })?;
 
-    Ok(Json(CustomerResponse {
-        id: res.id,
-        name: res.name,
-        email: res.email,
-        phone: res.phone,
-    }))
+    Ok(Json(CustomerResponse::from(res)))
 }
 
 // READ ALL
@@ -312,65 +768,113 @@ pub async fn get_customers(
     Query(pagination): Query<Pagination>,
 ) -> Result<Json<Vec<CustomerResponse>>, AppError> {
     let db = &state.db;
-    let list = CustomerEntity::find()
+    let list = CustomerEntity::find_in(_auth.tenant_id)
+        .filter(customers::Column::MergedIntoId.is_null())
         .limit(pagination.limit.unwrap_or(10))
         .offset(pagination.offset.unwrap_or(0))
         .all(db.as_ref())
         .await
         .map_err(|e| {
-            eprintln!("Error fetching customers: {}", e);
+            tracing::error!(error = %e, "Error fetching customers");
             AppError::Internal("Could not fetch customers".into())
     })?;
 
-    let response = list.into_iter().map(|c| CustomerResponse {
-        id: c.id,
-        name: c.name,
-        email: c.email,
-        phone: c.phone,
-    }).collect();
+    let response = list.into_iter().map(CustomerResponse::from).collect();
 
     Ok(Json(response))
 }
 
+// READ by ID
+#[utoipa::path(
+    get,
+    path = "/customers/{id}",
+    responses(
+        (status = 200, description = "Customer, with its version as ETag", body = CustomerResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer not found")
+    ),
+    tag = "Customer"
+)]
+pub async fn get_customer(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+
+    let customer = CustomerEntity::find_by_id_in(id, auth.tenant_id)
+        .one(state.db.as_ref())
+        .await?
+        .ok_or(AppError::NotFound("Customer not found".into()))?;
+
+    Ok(with_etag(customer.version, Json(CustomerResponse::from(customer))))
+}
+
 // UPDATE
 #[utoipa::path(
     put,
     path = "/customers/{id}",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
     request_body = CreateCustomerInput,
     responses(
         (status = 200, description = "Customer updated", body = CustomerResponse),
-        (status = 404, description = "Customer not found")
+        (status = 400, description = "Invalid email, phone number or locale"),
+        (status = 404, description = "Customer not found"),
+        (status = 409, description = "Another customer already has this email"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
     ),
     tag = "Customer"
 )]
 pub async fn update_customer(
     State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
     Path(id): Path<Uuid>,
     Json(input): Json<CreateCustomerInput>,
-) -> Result<Json<CustomerResponse>, (StatusCode, String)> {
-    let db = &state.db;
-    let record = CustomerEntity::find_by_id(id).one(db.as_ref()).await.map_err(|e| {
-        eprintln!("Find error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not find customer".into())
-    })?;
+) -> Result<Response, AppError> {
+    let if_match = IfMatch::from_headers(&headers)?;
+    let db = state.db.as_ref();
+    let (email, phone) = normalise_contact(&input).map_err(|(_, msg)| AppError::BadRequest(msg))?;
+    let preferred_locale = preferred_locale(&input).map_err(|(_, msg)| AppError::BadRequest(msg))?;
+
+    let record = CustomerEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Customer not found".into()))?;
+    if_match.check(record.version, || CustomerResponse::from(record.clone()))?;
+
+    let in_use = email_in_use(db, auth.tenant_id, &email, Some(id))
+        .await
+        .map_err(|(_, msg)| AppError::Internal(msg))?;
+    if in_use {
+        return Err(AppError::Conflict("Another customer already has this email".into()));
+    }
 
-    let mut model = record.ok_or((StatusCode::NOT_FOUND, "Customer not found".into()))?.into_active_model();
+    // A new email domain moves the contact to the company owning it. A company
+    // set by hand stays unless the new domain belongs to another one.
+    let mut company_id = record.company_id;
+    if email_domain(&email) != email_domain(&record.email) {
+        let matched = company_for_email(db, auth.tenant_id, &email).await?;
+        if matched.is_some() || record.company_id == company_for_email(db, auth.tenant_id, &record.email).await? {
+            company_id = matched;
+        }
+    }
 
+    let version = record.version;
+    let mut model = record.into_active_model();
     model.name = Set(input.name);
-    model.email = Set(input.email);
-    model.phone = Set(input.phone);
+    model.email = Set(email);
+    model.phone = Set(phone);
+    model.company_id = Set(company_id);
+    model.preferred_locale = Set(preferred_locale);
 
-    let updated = model.update(db.as_ref()).await.map_err(|e| {
-        eprintln!("Update error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not update customer".into())
-    })?;
+    let updated = save_versioned(db, model, version)
+        .await
+        .map_err(conflict_on_unique("Another customer already has this email"))?
+        .or_stale(|c| (c.version, CustomerResponse::from(c)))?;
 
-    Ok(Json(CustomerResponse {
-        id: updated.id,
-        name: updated.name,
-        email: updated.email,
-        phone: updated.phone,
-    }))
+    Ok(with_etag(updated.version, Json(CustomerResponse::from(updated))))
 }
 
 // DELETE
@@ -378,1079 +882,7007 @@ pub async fn update_customer(
     delete,
     path = "/customers/{id}",
     responses(
-        (status = 204, description = "Customer deleted"),
-        (status = 404, description = "Customer not found")
+        (status = 204, description = "Customer moved to the trash"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer not found"),
+        (status = 409, description = "Customer has tickets; erase it instead")
     ),
     tag = "Customer"
 )]
 pub async fn delete_customer(
     State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, (StatusCode, String)> {
+    if !auth.is_admin() && auth.role != "agent" {
+        return Err((StatusCode::FORBIDDEN, "Access denied".into()));
+    }
     let db = &state.db;
-    CustomerEntity::delete_by_id(id).exec(db.as_ref()).await.map_err(|e| {
-        eprintln!("Deletion error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not delete customer".into())
-    })?;
-
-    Ok(StatusCode::NO_CONTENT)
-}
 
-//----------ticket----------------
-#[derive(Deserialize, ToSchema)]
-pub struct CreateTicketInput {
-    pub title: String,
-    pub description: String,
-    pub status: String,
-    pub priority: String,
-    pub channel: String,
-    pub customer_id: Uuid,
-    pub assigned_agent_id: Option<Uuid>,
-}
+    // Deleting would orphan the tickets and messages that still name the
+    // customer; those records go through erasure instead.
+    let ticket_count = TicketEntity::find_in(auth.tenant_id)
+        .filter(tickets::Column::CustomerId.eq(id))
+        .count(db.as_ref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Deletion error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not delete customer".into())
+        })?;
+    if ticket_count > 0 {
+        return Err((StatusCode::CONFLICT, "Customer has tickets; use POST /customers/{id}/erase".into()));
+    }
 
-#[derive(Serialize, ToSchema)]
-pub struct TicketResponse {
-    pub id: Uuid,
-    pub title: String,
-    pub description: String,
-    pub status: String,
-    pub priority: String,
-    pub channel: String,
-    pub customer_id: Uuid,
-    pub assigned_agent_id: Option<Uuid>,
-}
+    let trashed = CustomerEntity::update_many_in(auth.tenant_id)
+        .col_expr(customers::Column::DeletedAt, Expr::value(Utc::now()))
+        .filter(customers::Column::Id.eq(id))
+        .filter(customers::Column::DeletedAt.is_null())
+        .exec(db.as_ref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Deletion error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not delete customer".into())
+        })?;
+    if trashed.rows_affected == 0 {
+        return Err((StatusCode::NOT_FOUND, "Customer not found".into()));
+    }
 
-#[derive(Debug, Deserialize)]
-pub struct TicketFilter {
-    pub status: Option<String>,
-    pub priority: Option<String>,
-    pub assigned_to: Option<i32>,
-    pub customer_id: Option<i32>,
-    pub from: Option<NaiveDate>,
-    pub to: Option<NaiveDate>,
-    // pub limit: Option<u64>,
-    // pub offset: Option<u64>,
+    audit_trash(db.as_ref(), &auth, "trashed", "customer", id, &ip)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Could not record audit log".into()))?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-// CREATE
+// DUPLICATES
 #[utoipa::path(
-    post,
-    path = "/tickets",
-    request_body = CreateTicketInput,
+    get,
+    path = "/customers/{id}/duplicates",
+    params(DuplicateQuery),
     responses(
-        (status = 201, description = "Ticket created", body = TicketResponse)
+        (status = 200, description = "Likely duplicates of the customer, best match first", body = [DuplicateCandidate]),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer not found")
     ),
-    tag = "Ticket"
+    tag = "Customer"
 )]
-pub async fn create_ticket(
+pub async fn get_duplicate_customers(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
-    Json(input): Json<CreateTicketInput>,
-) -> Result<Json<TicketResponse>, (StatusCode, String)> {
-    let db = &state.db;
-    let ticket = tickets::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        title: Set(input.title),
-        description: Set(input.description),
-        status: Set(input.status),
-        priority: Set(input.priority),
-        channel: Set(input.channel),
-        customer_id: Set(input.customer_id),
-        assigned_agent_id: Set(input.assigned_agent_id),
-        created_at: Set(Utc::now().into()),
-        updated_at: Set(Utc::now().into()),
-    };
-
-    let saved = ticket.insert(db.as_ref()).await.map_err(|e| {
-        eprintln!("Failed to insert ticket: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Ticket creation failed".into())
-    })?;
-
-    Ok(Json(TicketResponse {
-        id: saved.id,
-        title: saved.title,
-        description: saved.description,
-        status: saved.status,
-        priority: saved.priority,
-        channel: saved.channel,
-        customer_id: saved.customer_id,
-        assigned_agent_id: saved.assigned_agent_id,
-    }))
-}
-
+    auth: AuthUser,
+    Query(query): Query<DuplicateQuery>,
+) -> Result<Json<Vec<DuplicateCandidate>>, AppError> {
+    require_staff(&auth)?;
+    let min_score = query.min_score.unwrap_or(0.3);
+
+    let db = state.db.as_ref();
+    let customer = CustomerEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Customer not found".into()))?;
+
+    let others = CustomerEntity::find_in(auth.tenant_id)
+        .filter(customers::Column::Id.ne(customer.id))
+        .filter(customers::Column::MergedIntoId.is_null())
+        .all(db)
+        .await?;
+
+    let mut candidates: Vec<DuplicateCandidate> = others
+        .into_iter()
+        .filter_map(|other| {
+            let (score, reasons) = duplicate_score(&customer, &other);
+            (score >= min_score).then(|| DuplicateCandidate {
+                customer: CustomerResponse::from(other),
+                score,
+                reasons,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
 
-//ASSIGNED_AGENT_ID
-#[derive(Deserialize, ToSchema)]
-pub struct AssignInput {
-    pub agent_id: String,
+    Ok(Json(candidates))
 }
 
+// MERGE
 #[utoipa::path(
-    patch,
-    path = "/tickets/{id}/assign",
-    request_body = AssignInput,
+    post,
+    path = "/customers/{id}/merge",
+    request_body = MergeCustomersInput,
     responses(
-        (status = 200, description = "Ticket assigned")
+        (status = 200, description = "Source customers merged into this one", body = CustomerResponse),
+        (status = 400, description = "Invalid merge request"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer not found"),
+        (status = 412, description = "A customer changed while it was being merged")
     ),
-    tag = "Ticket"
+    tag = "Customer"
 )]
-pub async fn assign_ticket(
-    Path(ticket_id): Path<String>,
+pub async fn merge_customers(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
     auth: AuthUser,
-    Json(input): Json<AssignInput>,
-) -> Result<Json<String>, AppError> {
-    require_role(&auth, "admin")?;
+    ClientIp(ip): ClientIp,
+    Json(input): Json<MergeCustomersInput>,
+) -> Result<Json<CustomerResponse>, AppError> {
+    require_staff(&auth)?;
+    let actor = AuditActor::of(&auth)?;
+    
+    let source_ids = unique_ids(&input.source_customer_ids);
+    if source_ids.is_empty() {
+        return Err(AppError::BadRequest("No source customers given".into()));
+    }
+    if source_ids.contains(&id) {
+        return Err(AppError::BadRequest("A customer cannot be merged into itself".into()));
+    }
 
-    let uuid = uuid::Uuid::parse_str(&ticket_id)
-        .map_err(|_| AppError::BadRequest("Invalid UUID".to_string()))?;
+    let txn = state.db.begin().await?;
 
-    let db = &state.db;
-    let mut ticket = tickets::Entity::find_by_id(uuid)
-        .one(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?
-        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    let target = CustomerEntity::find_by_id_in(id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Customer not found".into()))?;
+    if target.merged_into_id.is_some() {
+        return Err(AppError::BadRequest("Target customer has already been merged".into()));
+    }
 
-    ticket.assigned_agent_id = Some(uuid::Uuid::parse_str(&input.agent_id)
-        .map_err(|_| AppError::BadRequest("Invalid agent UUID".into()))?);
+    let sources = CustomerEntity::find_in(auth.tenant_id)
+        .filter(customers::Column::Id.is_in(source_ids.clone()))
+        .all(&txn)
+        .await?;
+    if sources.len() != source_ids.len() {
+        return Err(AppError::NotFound("Source customer not found".into()));
+    }
 
-    let mut active: tickets::ActiveModel = ticket.clone().into();
-    active.assigned_agent_id = Set(ticket.assigned_agent_id);
+    let mut phone = target.phone.clone();
+    let mut company_id = target.company_id;
+    let mut preferred_locale = target.preferred_locale.clone();
+    for source in sources {
+        if source.merged_into_id.is_some() {
+            return Err(AppError::BadRequest("Source customer has already been merged".into()));
+        }
+
+        TicketEntity::update_many_in(auth.tenant_id)
+            .col_expr(tickets::Column::CustomerId, Expr::value(target.id))
+            .col_expr(tickets::Column::Version, Expr::col(tickets::Column::Version).add(1))
+            .filter(tickets::Column::CustomerId.eq(source.id))
+            .exec(&txn)
+            .await?;
+        CommunicationEntity::update_many_in(auth.tenant_id)
+            .col_expr(communications::Column::SenderId, Expr::value(target.id))
+            .filter(communications::Column::SenderType.eq("customer"))
+            .filter(communications::Column::SenderId.eq(source.id))
+            .exec(&txn)
+            .await?;
+        CsatSurveyEntity::update_many_in(auth.tenant_id)
+            .col_expr(csat_surveys::Column::CustomerId, Expr::value(target.id))
+            .filter(csat_surveys::Column::CustomerId.eq(source.id))
+            .exec(&txn)
+            .await?;
+
+        // The survivor keeps its own details and only fills in what it lacks.
+        if phone.is_empty() {
+            phone = source.phone.clone();
+        }
+        company_id = company_id.or(source.company_id);
+        preferred_locale = preferred_locale.or(source.preferred_locale.clone());
+
+        let (source_id, version) = (source.id, source.version);
+        let mut active = source.into_active_model();
+        active.merged_into_id = Set(Some(target.id));
+        save_versioned(&txn, active, version)
+            .await?
+            .or_stale(|c| (c.version, CustomerResponse::from(c)))?;
+
+        record_audit(&txn, &actor, &format!("merged_into:{}", target.id), "customer", source_id, &ip).await?;
+        record_audit(&txn, &actor, &format!("merged_from:{}", source_id), "customer", target.id, &ip).await?;
+    }
 
-    
-    active
-        .update(db.as_ref())
-        .await
-        .map_err(|_| AppError::Internal("Update failed".into()))?;
+    let version = target.version;
+    let mut active = target.into_active_model();
+    active.phone = Set(phone);
+    active.company_id = Set(company_id);
+    active.preferred_locale = Set(preferred_locale);
+    let updated = save_versioned(&txn, active, version)
+        .await?
+        .or_stale(|c| (c.version, CustomerResponse::from(c)))?;
+
+    txn.commit().await?;
 
-    Ok(Json("Agent assigned successfully".into()))
+    Ok(Json(CustomerResponse::from(updated)))
 }
 
-// READ ALL
+// DATA SUBJECT REQUESTS
+#[derive(Serialize)]
+struct CommunicationExportRow {
+    id: Uuid,
+    ticket_id: Uuid,
+    sender_type: String,
+    sender_id: Uuid,
+    message: String,
+    channel: String,
+    is_internal: bool,
+    timestamp: chrono::DateTime<Utc>,
+}
 
-fn can_read_or_edit_ticket(
-    auth: &AuthUser,
-    ticket: &tickets::Model,
-) -> Result<(), AppError> {
-    if auth.role == "admin" {
-        return Ok(());
+impl From<communications::Model> for CommunicationExportRow {
+    fn from(c: communications::Model) -> Self {
+        CommunicationExportRow {
+            id: c.id,
+            ticket_id: c.ticket_id,
+            sender_type: c.sender_type,
+            sender_id: c.sender_id,
+            message: c.message,
+            channel: c.channel,
+            is_internal: c.is_internal,
+            timestamp: c.timestamp,
+        }
     }
+}
 
-    if auth.role == "agent"
-        && ticket.assigned_agent_id
-            .as_ref()
-            .map(|id| id.to_string()) == Some(auth.u_id.to_string())
-    {
-        return Ok(());
+#[derive(Serialize)]
+struct AuditExportRow {
+    id: Uuid,
+    user_id: Uuid,
+    action: String,
+    entity: String,
+    entity_id: Uuid,
+    timestamp: chrono::DateTime<Utc>,
+    ip_address: String,
+}
+
+impl From<audit_logs::Model> for AuditExportRow {
+    fn from(a: audit_logs::Model) -> Self {
+        AuditExportRow {
+            id: a.id,
+            user_id: a.user_id,
+            action: a.action,
+            entity: a.entity,
+            entity_id: a.entity_id,
+            timestamp: a.timestamp,
+            ip_address: a.ip_address,
+        }
     }
+}
 
-   if auth.role == "customer"
-    && Some(auth.u_id.to_string()) == Some(ticket.customer_id.to_string())
-    {
-         return Ok(());
+#[derive(Serialize, ToSchema)]
+pub struct ErasureResponse {
+    pub customer_id: Uuid,
+    pub customers: u64,
+    pub tickets: u64,
+    pub communications: u64,
+    pub surveys: u64,
+    pub events: u64,
+    pub mentions: u64,
+    pub notices: u64,
+    pub erased_at: chrono::DateTime<Utc>,
+}
+
+// A customer together with the records that were merged into it; they are the
+// same person, so requests about one cover all of them. Trashed records are
+// still personal data and are included.
+async fn data_subject<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    id: Uuid,
+) -> Result<(customers::Model, Vec<customers::Model>), AppError> {
+    let customer = CustomerEntity::find_by_id_with_trashed_in(id, tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Customer not found".into()))?;
+    if customer.merged_into_id.is_some() {
+        return Err(AppError::BadRequest("Customer has been merged; use the surviving record".into()));
     }
 
+    let merged = CustomerEntity::find_with_trashed_in(tenant_id)
+        .filter(customers::Column::MergedIntoId.eq(customer.id))
+        .all(db)
+        .await?;
 
-    Err(AppError::Forbidden)
+    Ok((customer, merged))
 }
-// READ by ID
+
+// Messages on the customer's tickets plus anything they sent elsewhere.
+fn subject_communications(customer_ids: &[Uuid], ticket_ids: &[Uuid]) -> Condition {
+    Condition::any()
+        .add(communications::Column::TicketId.is_in(ticket_ids.to_vec()))
+        .add(
+            Condition::all()
+                .add(communications::Column::SenderType.eq("customer"))
+                .add(communications::Column::SenderId.is_in(customer_ids.to_vec())),
+        )
+}
+
 #[utoipa::path(
     get,
-    path = "/tickets/{id}",
+    path = "/customers/{id}/data-export",
     responses(
-        (status = 200, description = "Ticket details", body = TicketResponse),
-        (status = 404, description = "Ticket not found")
+        (status = 200, description = "Zip archive of everything held about the customer", body = Vec<u8>, content_type = "application/zip"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer not found")
     ),
-    tag = "Ticket"
+    tag = "Customer"
 )]
-pub async fn get_ticket_by_id(
-    Path(ticket_id): Path<String>,
+pub async fn export_customer_data(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
     auth: AuthUser,
-) -> Result<impl IntoResponse, AppError> {
-    let id = Uuid::parse_str(&ticket_id)
-        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
-
-    let db = &state.db;
-    let ticket = tickets::Entity::find_by_id(id)
-        .one(db.as_ref())
-        .await
-        .map_err(|_| AppError::Internal("Database error".into()))?
-        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    ClientIp(ip): ClientIp,
+) -> Result<Response, AppError> {
+    require_role(&auth, "admin")?;
+    let actor = AuditActor::of(&auth)?;
+    let db = state.db.as_ref();
+
+    let (customer, merged) = data_subject(db, auth.tenant_id, id).await?;
+    let customer_ids: Vec<Uuid> = std::iter::once(customer.id).chain(merged.iter().map(|c| c.id)).collect();
+
+    let tickets = TicketEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(tickets::Column::CustomerId.is_in(customer_ids.clone()))
+        .order_by_asc(tickets::Column::CreatedAt)
+        .all(db)
+        .await?;
+    let ticket_ids: Vec<Uuid> = tickets.iter().map(|t| t.id).collect();
+
+    let messages = CommunicationEntity::find_in(auth.tenant_id)
+        .filter(subject_communications(&customer_ids, &ticket_ids))
+        .order_by_asc(communications::Column::Timestamp)
+        .all(db)
+        .await?;
+
+    let surveys = CsatSurveyEntity::find_in(auth.tenant_id)
+        .filter(csat_surveys::Column::CustomerId.is_in(customer_ids.clone()))
+        .all(db)
+        .await?;
+
+    let audit = AuditLogEntity::find_in(auth.tenant_id)
+        .filter(
+            Condition::any()
+                .add(
+                    Condition::all()
+                        .add(audit_logs::Column::Entity.eq("customer"))
+                        .add(audit_logs::Column::EntityId.is_in(customer_ids.clone())),
+                )
+                .add(
+                    Condition::all()
+                        .add(audit_logs::Column::Entity.eq("ticket"))
+                        .add(audit_logs::Column::EntityId.is_in(ticket_ids.clone())),
+                ),
+        )
+        .order_by_asc(audit_logs::Column::Timestamp)
+        .all(db)
+        .await?;
 
+    let profiles: Vec<CustomerExportRow> = std::iter::once(customer)
+        .chain(merged)
+        .map(CustomerExportRow::from)
+        .collect();
+    let tickets: Vec<TicketExportRow> = tickets.into_iter().map(TicketExportRow::from).collect();
+    let messages: Vec<CommunicationExportRow> = messages.into_iter().map(CommunicationExportRow::from).collect();
+    let surveys: Vec<SurveyResponse> = surveys.into_iter().map(SurveyResponse::from).collect();
+    let audit: Vec<AuditExportRow> = audit.into_iter().map(AuditExportRow::from).collect();
+
+    let mut archive = Archive::new();
+    let build = archive
+        .add_json("manifest.json", &serde_json::json!({
+            "customer_id": id,
+            "generated_at": Utc::now(),
+            "generated_by": actor.user_id,
+            "tickets": tickets.len(),
+            "messages": messages.len(),
+            "surveys": surveys.len(),
+            "audit_entries": audit.len(),
+        }))
+        .and_then(|_| archive.add_json("profile.json", &profiles))
+        .and_then(|_| archive.add_json("tickets.json", &tickets))
+        .and_then(|_| archive.add_json("messages.json", &messages))
+        .and_then(|_| archive.add_json("surveys.json", &surveys))
+        .and_then(|_| archive.add_json("audit_log.json", &audit));
+    let bytes = build.and_then(|_| archive.finish()).map_err(|e| {
+        tracing::error!(error = %e, "Data export error");
+        AppError::Internal("Could not build export archive".into())
+    })?;
 
-    can_read_or_edit_ticket(&auth, &ticket)?;
+    record_audit(db, &actor, "gdpr_export", "customer", id, &ip).await?;
 
-    Ok(Json(ticket))
+    Ok(download(&format!("customer-{}.zip", id), "application/zip", Body::from(bytes)))
 }
-// GET /tickets — Admin only
 
 #[utoipa::path(
-    get,
-    path = "/tickets",
-    params(Pagination),
+    post,
+    path = "/customers/{id}/erase",
     responses(
-        (status = 200, description = "List of all tickets", body = [TicketResponse])
+        (status = 200, description = "Personal data anonymised", body = ErasureResponse),
+        (status = 400, description = "Customer already erased or merged"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer not found"),
+        (status = 412, description = "A customer changed while it was being erased")
     ),
-    tag = "Ticket"
+    tag = "Customer"
 )]
-#[debug_handler]
-pub async fn get_all_tickets (
+pub async fn erase_customer(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
     auth: AuthUser,
-    Query(pagination): Query<Pagination>,
-    Query(filter): Query<TicketFilter>,
-) -> Result<impl IntoResponse, AppError> {
+    ClientIp(ip): ClientIp,
+) -> Result<Json<ErasureResponse>, AppError> {
     require_role(&auth, "admin")?;
-    
-    let limit = pagination.limit.unwrap_or(10);
-    let offset = pagination.offset.unwrap_or(0);
+    let actor = AuditActor::of(&auth)?;
 
-    let db = &state.db;
-    let mut query = tickets::Entity::find();
-    if let Some(ref status) = filter.status {
-        query = query.filter(tickets::Column::Status.eq(status));
-    }
+    let txn = state.db.begin().await?;
 
-    if let Some(ref priority) = filter.priority {
-        query = query.filter(tickets::Column::Priority.eq(priority));
+    let (customer, merged) = data_subject(&txn, auth.tenant_id, id).await?;
+    if customer.erased_at.is_some() {
+        return Err(AppError::BadRequest("Customer has already been erased".into()));
     }
+    let customer_ids: Vec<Uuid> = std::iter::once(customer.id).chain(merged.iter().map(|c| c.id)).collect();
 
-    if let Some(assigned_to) = filter.assigned_to {
-        query = query.filter(tickets::Column::AssignedAgentId.eq(assigned_to));
+    let ticket_ids: Vec<Uuid> = TicketEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(tickets::Column::CustomerId.is_in(customer_ids.clone()))
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|t| t.id)
+        .collect();
+
+    // Messages go first: the condition reads the ticket ids collected above.
+    let communications = CommunicationEntity::update_many_in(auth.tenant_id)
+        .col_expr(communications::Column::Message, Expr::value(REDACTED))
+        .col_expr(communications::Column::ExternalId, Expr::value(Option::<String>::None))
+        .filter(subject_communications(&customer_ids, &ticket_ids))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    let tickets = TicketEntity::update_many_in(auth.tenant_id)
+        .col_expr(tickets::Column::Title, Expr::value(REDACTED))
+        .col_expr(tickets::Column::Description, Expr::value(REDACTED))
+        .col_expr(tickets::Column::ExternalId, Expr::value(Option::<String>::None))
+        .col_expr(tickets::Column::Version, Expr::col(tickets::Column::Version).add(1))
+        .filter(tickets::Column::Id.is_in(ticket_ids.clone()))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    // Ratings stay for CSAT reporting; only the free-text comment is personal.
+    let surveys = CsatSurveyEntity::update_many_in(auth.tenant_id)
+        .col_expr(csat_surveys::Column::Comment, Expr::value(Option::<String>::None))
+        .filter(csat_surveys::Column::CustomerId.is_in(customer_ids.clone()))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    let redact = |column: ticket_events::Column| {
+        Expr::case(Expr::col(column).is_null(), Expr::value(Option::<String>::None)).finally(REDACTED)
+    };
+    let events = TicketEventEntity::update_many_in(auth.tenant_id)
+        .col_expr(ticket_events::Column::FromValue, redact(ticket_events::Column::FromValue).into())
+        .col_expr(ticket_events::Column::ToValue, redact(ticket_events::Column::ToValue).into())
+        .filter(ticket_events::Column::TicketId.is_in(ticket_ids.clone()))
+        .filter(ticket_events::Column::Kind.is_not_in(KEPT_EVENT_VALUES))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+
+    // The notes are erased above; the inbox entries pointing at them go.
+    let mentions = MentionEntity::delete_many_in(auth.tenant_id)
+        .filter(mentions::Column::TicketId.is_in(ticket_ids.clone()))
+        .exec(&txn)
+        .await?
+        .rows_affected;
+    let notices = notify::erase_notices(&txn, auth.tenant_id, &ticket_ids, REDACTED).await?;
+
+    let erased_at = Utc::now();
+    for record in std::iter::once(customer).chain(merged) {
+        let (record_id, version) = (record.id, record.version);
+        let mut active = record.into_active_model();
+        active.name = Set(ERASED_NAME.to_string());
+        active.email = Set(erased_email(record_id));
+        active.phone = Set(String::new());
+        active.external_id = Set(None);
+        active.erased_at = Set(Some(erased_at));
+        save_versioned(&txn, active, version)
+            .await?
+            .or_stale(|c| (c.version, CustomerResponse::from(c)))?;
     }
 
-    if let Some(customer_id) = filter.customer_id {
-        query = query.filter(tickets::Column::CustomerId.eq(customer_id));
+    for ticket_id in &ticket_ids {
+        record_audit(&txn, &actor, "gdpr_erasure", "ticket", *ticket_id, &ip).await?;
     }
+    record_audit(&txn, &actor, "gdpr_erasure", "customer", id, &ip).await?;
+
+    txn.commit().await?;
+
+    Ok(Json(ErasureResponse {
+        customer_id: id,
+        customers: customer_ids.len() as u64,
+        tickets,
+        communications,
+        surveys,
+        events,
+        mentions,
+        notices,
+        erased_at,
+    }))
+}
 
-    if let Some(from) = filter.from {
-        query = query.filter(tickets::Column::CreatedAt.gte(from.and_hms_opt(0, 0, 0)));
+//----------company----------------
+#[derive(Deserialize, ToSchema)]
+pub struct CreateCompanyInput {
+    pub name: String,
+    pub domains: Vec<String>,
+    pub shared_visibility: Option<bool>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CompanyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub domains: Vec<String>,
+    pub shared_visibility: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CustomerCompanyInput {
+    pub company_id: Option<Uuid>,
+}
+
+fn normalise_domains(domains: &[String]) -> Result<Vec<String>, AppError> {
+    let mut out: Vec<String> = Vec::new();
+    for domain in domains {
+        let domain = domain.trim().trim_start_matches('@').to_lowercase();
+        if domain.is_empty() || !domain.contains('.') || domain.contains(char::is_whitespace) {
+            return Err(AppError::BadRequest(format!("Invalid domain: {}", domain)));
+        }
+        if !out.contains(&domain) {
+            out.push(domain);
+        }
     }
+    Ok(out)
+}
 
-    if let Some(to) = filter.to {
-        query = query.filter(tickets::Column::CreatedAt.lte(to.and_hms_opt(23, 59, 59)));
+// Replaces the company's domains, refusing any domain another company already owns.
+async fn set_company_domains<C: ConnectionTrait>(
+    db: &C,
+    company: &companies::Model,
+    domains: &[String],
+) -> Result<(), AppError> {
+    let taken = CompanyDomainEntity::find_in(company.tenant_id)
+        .filter(company_domains::Column::Domain.is_in(domains.to_vec()))
+        .filter(company_domains::Column::CompanyId.ne(company.id))
+        .one(db)
+        .await?;
+    if let Some(taken) = taken {
+        return Err(AppError::BadRequest(format!("Domain {} belongs to another company", taken.domain)));
     }
 
-    let all_tickets = query
-        .limit(limit)
-        .offset(offset)
-        .all(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?;
+    CompanyDomainEntity::delete_many_in(company.tenant_id)
+        .filter(company_domains::Column::CompanyId.eq(company.id))
+        .exec(db)
+        .await?;
+    for domain in domains {
+        company_domains::ActiveModel {
+            tenant_id: Set(company.tenant_id),
+            domain: Set(domain.clone()),
+            company_id: Set(company.id),
+        }
+        .insert(db)
+        .await?;
+    }
 
-    Ok(Json(all_tickets))
+    Ok(())
 }
 
+async fn company_response<C: ConnectionTrait>(
+    db: &C,
+    company: companies::Model,
+) -> Result<CompanyResponse, AppError> {
+    let domains = company
+        .find_related(CompanyDomainEntity)
+        .order_by_asc(company_domains::Column::Domain)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|d| d.domain)
+        .collect();
 
-// UPDATE
-#[derive(Deserialize, ToSchema)]
-pub struct StatusInput {
-    pub status: String,
+    Ok(CompanyResponse {
+        id: company.id,
+        name: company.name,
+        domains,
+        shared_visibility: company.shared_visibility,
+    })
 }
 
-#[derive(Deserialize, ToSchema)]
-pub struct PriorityInput {
-    pub priority: String,
-}
+// The company of the signed-in customer, but only when it shares tickets
+// between its contacts.
+async fn shared_company_of<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+) -> Result<Option<companies::Model>, AppError> {
+    if auth.role != "customer" {
+        return Ok(None);
+    }
+    let customer = CustomerEntity::find_by_id_in(auth_uuid(auth)?, auth.tenant_id)
+        .one(db)
+        .await?;
+    let Some(company_id) = customer.and_then(|c| c.company_id) else {
+        return Ok(None);
+    };
 
+    let company = CompanyEntity::find_by_id_in(company_id, auth.tenant_id)
+        .filter(companies::Column::SharedVisibility.eq(true))
+        .one(db)
+        .await?;
+    Ok(company)
+}
 
-fn can_edit_ticket(auth: &AuthUser, ticket: &tickets::Model) -> Result<(), AppError> {
-    if auth.role == "admin" {
+// Staff, or a contact of this company when the company shares visibility.
+async fn can_view_company<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    company_id: Uuid,
+) -> Result<(), AppError> {
+    if require_staff(auth).is_ok() {
         return Ok(());
     }
-    if auth.role == "agent" && Some(auth.u_id.clone()) == ticket.assigned_agent_id.map(|id| id.to_string()) {
-        return Ok(());
+    match shared_company_of(db, auth).await? {
+        Some(company) if company.id == company_id => Ok(()),
+        _ => Err(AppError::Forbidden),
     }
-    Err(AppError::Forbidden)
 }
 
-// UPDATE status
+// CREATE
 #[utoipa::path(
-    put,
-    path = "/tickets/{id}/status",
-    request_body = StatusInput,
+    post,
+    path = "/companies",
+    request_body = CreateCompanyInput,
     responses(
-        (status = 200, description = "Ticket status updated")
+        (status = 201, description = "Company created", body = CompanyResponse),
+        (status = 400, description = "Invalid domain or domain already in use"),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Ticket"
+    tag = "Company"
 )]
-pub async fn update_ticket_status(
-    Path(ticket_id): Path<String>,
+pub async fn create_company(
     State(state): State<AppState>,
     auth: AuthUser,
-    Json(input): Json<StatusInput>,
-) -> Result<Json<String>, AppError> {
-    let id = uuid::Uuid::parse_str(&ticket_id)
-        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
-
-    let db = &state.db;
-    let ticket = tickets::Entity::find_by_id(id)
-        .one(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?
-        .ok_or(AppError::NotFound("Ticket not found".into()))?;
-
-    can_edit_ticket(&auth, &ticket)?;
+    Json(input): Json<CreateCompanyInput>,
+) -> Result<(StatusCode, Json<CompanyResponse>), AppError> {
+    require_staff(&auth)?;
+    let domains = normalise_domains(&input.domains)?;
 
-    let mut active = ticket.into_active_model();
-    active.status = Set(input.status);
-
-    active
-        .update(db.as_ref())
-        .await
-        .map_err(|_| AppError::Internal("Failed to update status".into()))?;
+    let txn = state.db.begin().await?;
+    let company = companies::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        name: Set(input.name),
+        shared_visibility: Set(input.shared_visibility.unwrap_or(false)),
+        created_at: Set(Utc::now()),
+    }
+    .insert(&txn)
+    .await?;
+    set_company_domains(&txn, &company, &domains).await?;
+    let response = company_response(&txn, company).await?;
+    txn.commit().await?;
 
-    Ok(Json("Status updated successfully".into()))
+    Ok((StatusCode::CREATED, Json(response)))
 }
 
-// UPDATE priority
+// READ ALL
 #[utoipa::path(
-    patch,
-    path = "/tickets/{id}/priority",
-    request_body = PriorityInput,
+    get,
+    path = "/companies",
     responses(
-        (status = 200, description = "Ticket priority updated")
+        (status = 200, description = "List of companies", body = [CompanyResponse]),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Ticket"
+    tag = "Company"
 )]
-pub async fn update_ticket_priority(
-    Path(ticket_id): Path<String>,
+pub async fn get_companies(
     State(state): State<AppState>,
     auth: AuthUser,
-    Json(input): Json<PriorityInput>,
-) -> Result<Json<String>, AppError> {
-    let id = uuid::Uuid::parse_str(&ticket_id)
-        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
-
-    let db = &state.db;
-    let ticket = tickets::Entity::find_by_id(id)
-        .one(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?
-        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+) -> Result<Json<Vec<CompanyResponse>>, AppError> {
+    require_staff(&auth)?;
+
+    let db = state.db.as_ref();
+    let list = CompanyEntity::find_in(auth.tenant_id)
+        .order_by_asc(companies::Column::Name)
+        .all(db)
+        .await?;
+
+    let mut response = Vec::new();
+    for company in list {
+        response.push(company_response(db, company).await?);
+    }
+    Ok(Json(response))
+}
 
-    can_edit_ticket(&auth, &ticket)?;
+// UPDATE
+#[utoipa::path(
+    put,
+    path = "/companies/{id}",
+    request_body = CreateCompanyInput,
+    responses(
+        (status = 200, description = "Company updated", body = CompanyResponse),
+        (status = 400, description = "Invalid domain or domain already in use"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Company not found")
+    ),
+    tag = "Company"
+)]
+pub async fn update_company(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateCompanyInput>,
+) -> Result<Json<CompanyResponse>, AppError> {
+    require_staff(&auth)?;
+    let domains = normalise_domains(&input.domains)?;
+
+    let txn = state.db.begin().await?;
+    let company = CompanyEntity::find_by_id_in(id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Company not found".into()))?;
+
+    let mut active = company.into_active_model();
+    active.name = Set(input.name);
+    if let Some(shared) = input.shared_visibility {
+        active.shared_visibility = Set(shared);
+    }
+    let updated = active.update(&txn).await?;
+    set_company_domains(&txn, &updated, &domains).await?;
+    let response = company_response(&txn, updated).await?;
+    txn.commit().await?;
 
-    let mut active = ticket.into_active_model();
-    active.priority = Set(input.priority);
+    Ok(Json(response))
+}
 
-    active
-        .update(db.as_ref())
-        .await
-        .map_err(|_| AppError::Internal("Failed to update priority".into()))?;
+// DELETE
+#[utoipa::path(
+    delete,
+    path = "/companies/{id}",
+    responses(
+        (status = 204, description = "Company deleted; its contacts are kept without a company"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Company not found")
+    ),
+    tag = "Company"
+)]
+pub async fn delete_company(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<StatusCode, AppError> {
+    require_role(&auth, "admin")?;
 
-    Ok(Json("Priority updated successfully".into()))
-}
+    let result = CompanyEntity::delete_many_in(auth.tenant_id)
+        .filter(companies::Column::Id.eq(id))
+        .exec(state.db.as_ref())
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound("Company not found".into()));
+    }
 
-//SEARCH TICKETS 
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct TicketQuery {
-    pub status: Option<String>,
-    pub priority: Option<String>,
-    pub channel: Option<String>,
+    Ok(StatusCode::NO_CONTENT)
 }
 
+// CONTACTS
 #[utoipa::path(
     get,
-    path = "/tickets/search",
-    params(
-        ("status" = Option<String>, Query, description = "Ticket status filter"),
-        ("priority" = Option<String>, Query, description = "Priority filter"),
-        ("channel" = Option<String>, Query, description = "Channel filter")
-    ),
+    path = "/companies/{id}/contacts",
     responses(
-        (status = 200, description = "Filtered list of tickets", body = [TicketResponse])
+        (status = 200, description = "Customers linked to the company", body = [CustomerResponse]),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Ticket"
+    tag = "Company"
 )]
-pub async fn get_filtered_tickets(
+pub async fn get_company_contacts(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
-    Query(params): Query<TicketQuery>,
     auth: AuthUser,
-) -> Result<Json<Vec<TicketResponse>>, AppError> {
-    let mut condition = Condition::all();
+) -> Result<Json<Vec<CustomerResponse>>, AppError> {
+    let db = state.db.as_ref();
+    can_view_company(db, &auth, id).await?;
 
-    if let Some(status) = params.status {
-        condition = condition.add(tickets::Column::Status.eq(status));
-    }
+    let contacts = CustomerEntity::find_in(auth.tenant_id)
+        .filter(customers::Column::CompanyId.eq(id))
+        .order_by_asc(customers::Column::Name)
+        .all(db)
+        .await?;
 
-    if let Some(priority) = params.priority {
-        condition = condition.add(tickets::Column::Priority.eq(priority));
+    Ok(Json(contacts.into_iter().map(CustomerResponse::from).collect()))
+}
+
+// TICKETS of every contact
+#[utoipa::path(
+    get,
+    path = "/companies/{id}/tickets",
+    params(Pagination),
+    responses(
+        (status = 200, description = "Tickets raised by any contact of the company", body = [TicketResponse]),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Company"
+)]
+pub async fn get_company_tickets(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<TicketResponse>>, AppError> {
+    let db = state.db.as_ref();
+    can_view_company(db, &auth, id).await?;
+
+    let contacts = SubQuery::select()
+        .column(customers::Column::Id)
+        .from(customers::Entity)
+        .and_where(customers::Column::TenantId.eq(auth.tenant_id))
+        .and_where(customers::Column::CompanyId.eq(id))
+        .to_owned();
+
+    let list = TicketEntity::find_in(auth.tenant_id)
+        .filter(tickets::Column::CustomerId.in_subquery(contacts))
+        .order_by_desc(tickets::Column::CreatedAt)
+        .limit(pagination.limit.unwrap_or(10))
+        .offset(pagination.offset.unwrap_or(0))
+        .all(db)
+        .await?;
+
+    Ok(Json(list.into_iter().map(TicketResponse::from).collect()))
+}
+
+// LINK a customer
+#[utoipa::path(
+    put,
+    path = "/customers/{id}/company",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    request_body = CustomerCompanyInput,
+    responses(
+        (status = 200, description = "Customer linked to or unlinked from a company", body = CustomerResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Customer or company not found"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Company"
+)]
+pub async fn set_customer_company(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Json(input): Json<CustomerCompanyInput>,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let if_match = IfMatch::from_headers(&headers)?;
+
+    let db = state.db.as_ref();
+    let customer = CustomerEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Customer not found".into()))?;
+    if_match.check(customer.version, || CustomerResponse::from(customer.clone()))?;
+    if let Some(company_id) = input.company_id {
+        CompanyEntity::find_by_id_in(company_id, auth.tenant_id)
+            .one(db)
+            .await?
+            .ok_or(AppError::NotFound("Company not found".into()))?;
+    }
+
+    let version = customer.version;
+    let mut active = customer.into_active_model();
+    active.company_id = Set(input.company_id);
+    let updated = save_versioned(db, active, version)
+        .await?
+        .or_stale(|c| (c.version, CustomerResponse::from(c)))?;
+
+    Ok(with_etag(updated.version, Json(CustomerResponse::from(updated))))
+}
+
+//----------ticket----------------
+const CHANNELS: [&str; 3] = ["Email", "Chat", "Social"];
+const TICKET_STATUSES: [&str; 5] = ["open", "in_progress", "pending", "resolved", "closed"];
+const TICKET_PRIORITIES: [&str; 4] = ["low", "medium", "high", "urgent"];
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTicketInput {
+    pub title: String,
+    pub description: String,
+    pub status: String,
+    pub priority: String,
+    pub channel: String,
+    pub customer_id: Uuid,
+    pub assigned_agent_id: Option<Uuid>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TicketResponse {
+    pub id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub status: String,
+    pub priority: String,
+    pub channel: String,
+    pub customer_id: Uuid,
+    pub assigned_agent_id: Option<Uuid>,
+    pub merged_into_id: Option<Uuid>,
+    pub parent_ticket_id: Option<Uuid>,
+    pub version: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TicketFilter {
+    pub status: Option<String>,
+    pub priority: Option<String>,
+    pub assigned_to: Option<i32>,
+    pub customer_id: Option<i32>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    // pub limit: Option<u64>,
+    // pub offset: Option<u64>,
+}
+
+// CREATE
+#[utoipa::path(
+    post,
+    path = "/tickets",
+    request_body = CreateTicketInput,
+    responses(
+        (status = 201, description = "Ticket created", body = TicketResponse),
+        (status = 400, description = "Unknown customer, status, priority or channel")
+    ),
+    tag = "Ticket"
+)]
+pub async fn create_ticket(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateTicketInput>,
+) -> Result<Json<TicketResponse>, (StatusCode, String)> {
+    let db = &state.db;
+
+    if !TICKET_STATUSES.contains(&input.status.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid status".into()));
+    }
+    if !TICKET_PRIORITIES.contains(&input.priority.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid priority".into()));
+    }
+    if !CHANNELS.contains(&input.channel.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid channel".into()));
+    }
+
+    let customer = CustomerEntity::find_by_id_in(input.customer_id, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    if customer.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Unknown customer".into()));
+    }
+
+    let ticket = tickets::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        title: Set(input.title),
+        description: Set(input.description),
+        status: Set(input.status),
+        priority: Set(input.priority),
+        channel: Set(input.channel),
+        customer_id: Set(input.customer_id),
+        assigned_agent_id: Set(input.assigned_agent_id),
+        merged_into_id: Set(None),
+        parent_ticket_id: Set(None),
+        external_id: Set(None),
+        deleted_at: Set(None),
+        version: Set(1),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+    };
+
+    let saved = ticket.insert(db.as_ref()).await.map_err(|e| {
+        tracing::error!(error = %e, "Failed to insert ticket");
+        (StatusCode::INTERNAL_SERVER_ERROR, "Ticket creation failed".into())
+    })?;
+    metrics::ticket_created(&saved.channel, "api");
+
+    Ok(Json(TicketResponse::from(saved)))
+}
+
+
+//ASSIGNED_AGENT_ID
+#[derive(Deserialize, ToSchema)]
+pub struct AssignInput {
+    pub agent_id: String,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/tickets/{id}/assign",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    request_body = AssignInput,
+    responses(
+        (status = 200, description = "Ticket assigned"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Ticket"
+)]
+pub async fn assign_ticket(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Json(input): Json<AssignInput>,
+) -> Result<Response, AppError> {
+    require_role(&auth, "admin")?;
+    let if_match = IfMatch::from_headers(&headers)?;
+
+    let uuid = uuid::Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".to_string()))?;
+
+    let db = &state.db;
+    let mut ticket = tickets::Entity::find_by_id_in(uuid, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    if_match.check(ticket.version, || TicketResponse::from(ticket.clone()))?;
+
+    let previous_agent = ticket.assigned_agent_id;
+    ticket.assigned_agent_id = Some(uuid::Uuid::parse_str(&input.agent_id)
+        .map_err(|_| AppError::BadRequest("Invalid agent UUID".into()))?);
+
+    let mut active: tickets::ActiveModel = ticket.clone().into();
+    active.assigned_agent_id = Set(ticket.assigned_agent_id);
+
+    let txn = db.begin().await?;
+    let updated = save_versioned(&txn, active, ticket.version)
+        .await?
+        .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+    if previous_agent != updated.assigned_agent_id {
+        record_ticket_event(
+            &txn,
+            &auth,
+            updated.id,
+            "assignment",
+            previous_agent.map(|id| id.to_string()),
+            updated.assigned_agent_id.map(|id| id.to_string()),
+        )
+        .await?;
+    }
+    txn.commit().await?;
+
+    Ok(with_etag(updated.version, Json("Agent assigned successfully")))
+}
+
+// READ ALL
+
+fn can_read_or_edit_ticket(
+    auth: &AuthUser,
+    ticket: &tickets::Model,
+) -> Result<(), AppError> {
+    if auth.is_admin() {
+        return Ok(());
+    }
+
+    if auth.role == "agent"
+        && ticket.assigned_agent_id
+            .as_ref()
+            .map(|id| id.to_string()) == Some(auth.u_id.to_string())
+    {
+        return Ok(());
+    }
+
+   if auth.role == "customer"
+    && Some(auth.u_id.to_string()) == Some(ticket.customer_id.to_string())
+    {
+         return Ok(());
+    }
+
+
+    Err(AppError::Forbidden)
+}
+
+// Same as `can_read_or_edit_ticket`, but also lets contacts of a company with
+// shared visibility read their colleagues' tickets.
+async fn can_read_ticket<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    ticket: &tickets::Model,
+) -> Result<(), AppError> {
+    if can_read_or_edit_ticket(auth, ticket).is_ok() {
+        return Ok(());
+    }
+
+    if let Some(company) = shared_company_of(db, auth).await? {
+        let owner = CustomerEntity::find_by_id_in(ticket.customer_id, auth.tenant_id)
+            .one(db)
+            .await?;
+        if owner.is_some_and(|c| c.company_id == Some(company.id)) {
+            return Ok(());
+        }
+    }
+
+    Err(AppError::Forbidden)
+}
+// READ by ID
+#[utoipa::path(
+    get,
+    path = "/tickets/{id}",
+    responses(
+        (status = 200, description = "Ticket details", body = TicketResponse),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Ticket"
+)]
+pub async fn get_ticket_by_id(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    let id = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = &state.db;
+    let ticket = tickets::Entity::find_by_id_in(id, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|_| AppError::Internal("Database error".into()))?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+
+
+    can_read_ticket(db.as_ref(), &auth, &ticket).await?;
+
+    Ok(with_etag(ticket.version, Json(ticket)))
+}
+// GET /tickets — Admin only
+
+#[utoipa::path(
+    get,
+    path = "/tickets",
+    params(Pagination),
+    responses(
+        (status = 200, description = "List of all tickets", body = [TicketResponse])
+    ),
+    tag = "Ticket"
+)]
+#[debug_handler]
+pub async fn get_all_tickets (
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(pagination): Query<Pagination>,
+    Query(filter): Query<TicketFilter>,
+) -> Result<impl IntoResponse, AppError> {
+    require_role(&auth, "admin")?;
+    
+    let limit = pagination.limit.unwrap_or(10);
+    let offset = pagination.offset.unwrap_or(0);
+
+    let db = &state.db;
+    let mut query = tickets::Entity::find_in(auth.tenant_id);
+    if let Some(ref status) = filter.status {
+        query = query.filter(tickets::Column::Status.eq(status));
+    }
+
+    if let Some(ref priority) = filter.priority {
+        query = query.filter(tickets::Column::Priority.eq(priority));
+    }
+
+    if let Some(assigned_to) = filter.assigned_to {
+        query = query.filter(tickets::Column::AssignedAgentId.eq(assigned_to));
+    }
+
+    if let Some(customer_id) = filter.customer_id {
+        query = query.filter(tickets::Column::CustomerId.eq(customer_id));
+    }
+
+    if let Some(from) = filter.from {
+        query = query.filter(tickets::Column::CreatedAt.gte(from.and_hms_opt(0, 0, 0)));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(tickets::Column::CreatedAt.lte(to.and_hms_opt(23, 59, 59)));
+    }
+
+    let all_tickets = query
+        .limit(limit)
+        .offset(offset)
+        .all(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?;
+
+    Ok(Json(all_tickets))
+}
+
+
+// UPDATE
+#[derive(Deserialize, ToSchema)]
+pub struct StatusInput {
+    pub status: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PriorityInput {
+    pub priority: String,
+}
+
+
+fn can_edit_ticket(auth: &AuthUser, ticket: &tickets::Model) -> Result<(), AppError> {
+    if auth.is_admin() {
+        return Ok(());
+    }
+    if auth.role == "agent" && Some(auth.u_id.clone()) == ticket.assigned_agent_id.map(|id| id.to_string()) {
+        return Ok(());
+    }
+    Err(AppError::Forbidden)
+}
+
+// UPDATE status
+#[utoipa::path(
+    patch,
+    path = "/tickets/{id}/status",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    request_body = StatusInput,
+    responses(
+        (status = 200, description = "Ticket status updated"),
+        (status = 400, description = "Unknown status"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Ticket"
+)]
+pub async fn update_ticket_status(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Json(input): Json<StatusInput>,
+) -> Result<Response, AppError> {
+    let if_match = IfMatch::from_headers(&headers)?;
+    if !TICKET_STATUSES.contains(&input.status.as_str()) {
+        return Err(AppError::BadRequest("Invalid status".into()));
+    }
+    let id = uuid::Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = &state.db;
+    let ticket = tickets::Entity::find_by_id_in(id, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+
+    can_edit_ticket(&auth, &ticket)?;
+    if_match.check(ticket.version, || TicketResponse::from(ticket.clone()))?;
+
+    let was_resolved = ticket.status == "resolved";
+    let previous_status = ticket.status.clone();
+    let version = ticket.version;
+    let mut active = ticket.into_active_model();
+    active.status = Set(input.status);
+
+    // The survey goes out with the status change or not at all.
+    let txn = db.begin().await?;
+    let updated = save_versioned(&txn, active, version)
+        .await?
+        .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+    if updated.status != previous_status {
+        record_ticket_event(&txn, &auth, updated.id, "status", Some(previous_status), Some(updated.status.clone())).await?;
+    }
+
+    if updated.status == "resolved" && !was_resolved {
+        issue_csat_survey(&txn, &state.jwt, &updated, auth_uuid(&auth)?).await?;
+    }
+    txn.commit().await?;
+
+    Ok(with_etag(updated.version, Json("Status updated successfully")))
+}
+
+// UPDATE priority
+#[utoipa::path(
+    patch,
+    path = "/tickets/{id}/priority",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    request_body = PriorityInput,
+    responses(
+        (status = 200, description = "Ticket priority updated"),
+        (status = 400, description = "Unknown priority"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Ticket"
+)]
+pub async fn update_ticket_priority(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Json(input): Json<PriorityInput>,
+) -> Result<Response, AppError> {
+    let if_match = IfMatch::from_headers(&headers)?;
+    if !TICKET_PRIORITIES.contains(&input.priority.as_str()) {
+        return Err(AppError::BadRequest("Invalid priority".into()));
+    }
+    let id = uuid::Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = &state.db;
+    let ticket = tickets::Entity::find_by_id_in(id, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+
+    can_edit_ticket(&auth, &ticket)?;
+    if_match.check(ticket.version, || TicketResponse::from(ticket.clone()))?;
+
+    let previous_priority = ticket.priority.clone();
+    let version = ticket.version;
+    let mut active = ticket.into_active_model();
+    active.priority = Set(input.priority);
+
+    let txn = db.begin().await?;
+    let updated = save_versioned(&txn, active, version)
+        .await?
+        .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+    if updated.priority != previous_priority {
+        record_ticket_event(&txn, &auth, updated.id, "priority", Some(previous_priority), Some(updated.priority.clone())).await?;
+    }
+    txn.commit().await?;
+
+    Ok(with_etag(updated.version, Json("Priority updated successfully")))
+}
+
+//SEARCH TICKETS 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TicketQuery {
+    pub status: Option<String>,
+    pub priority: Option<String>,
+    pub channel: Option<String>,
+    pub tags: Option<String>,
+}
+
+// Tag names from a comma-separated filter, lowercased since tag names match
+// case-insensitively, each once: the filter compares the number of distinct
+// tags found with the number asked for.
+fn tag_filter_names(list: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in list.split(',').map(|t| t.trim().to_lowercase()) {
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Filters shared by ticket search and ticket export. Returns None when the
+// filters can never match, e.g. an unknown tag name.
+async fn ticket_search_condition<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    params: &TicketQuery,
+) -> Result<Option<Condition>, AppError> {
+    let mut condition = Condition::all();
+
+    if let Some(status) = &params.status {
+        condition = condition.add(tickets::Column::Status.eq(status));
+    }
+
+    if let Some(priority) = &params.priority {
+        condition = condition.add(tickets::Column::Priority.eq(priority));
+    }
+
+    if let Some(channel) = &params.channel {
+        condition = condition.add(tickets::Column::Channel.eq(channel));
+    }
+
+    if let Some(tag_list) = &params.tags {
+        let names = tag_filter_names(tag_list);
+        if !names.is_empty() {
+            let tag_ids: Vec<Uuid> = TagEntity::find_in(auth.tenant_id)
+                .filter(Expr::expr(Func::lower(Expr::col(tags::Column::Name))).is_in(names.clone()))
+                .all(db)
+                .await?
+                .into_iter()
+                .map(|t| t.id)
+                .collect();
+
+            // An unknown tag name can never match, so nothing is returned.
+            if tag_ids.len() < names.len() {
+                return Ok(None);
+            }
+
+            let tagged = SubQuery::select()
+                .column(ticket_tags::Column::TicketId)
+                .from(ticket_tags::Entity)
+                .and_where(ticket_tags::Column::TagId.is_in(tag_ids.clone()))
+                .group_by_col(ticket_tags::Column::TicketId)
+                .and_having(Expr::col(ticket_tags::Column::TagId).count_distinct().eq(tag_ids.len() as i64))
+                .to_owned();
+            condition = condition.add(tickets::Column::Id.in_subquery(tagged));
+        }
+    }
+
+    // Role-based visibility
+    if auth.role == "agent" {
+        condition = condition.add(tickets::Column::AssignedAgentId.eq(Uuid::parse_str(&auth.u_id).unwrap()));
+    } else if auth.role == "customer" {
+        match shared_company_of(db, auth).await? {
+            Some(company) => {
+                let colleagues = SubQuery::select()
+                    .column(customers::Column::Id)
+                    .from(customers::Entity)
+                    .and_where(customers::Column::TenantId.eq(auth.tenant_id))
+                    .and_where(customers::Column::CompanyId.eq(company.id))
+                    .to_owned();
+                condition = condition.add(tickets::Column::CustomerId.in_subquery(colleagues));
+            }
+            None => {
+                condition = condition.add(tickets::Column::CustomerId.eq(Uuid::parse_str(&auth.u_id).unwrap()));
+            }
+        }
+    }
+
+    Ok(Some(condition))
+}
+
+#[utoipa::path(
+    get,
+    path = "/tickets/search",
+    params(
+        ("status" = Option<String>, Query, description = "Ticket status filter"),
+        ("priority" = Option<String>, Query, description = "Priority filter"),
+        ("channel" = Option<String>, Query, description = "Channel filter"),
+        ("tags" = Option<String>, Query, description = "Comma-separated tag names; tickets must carry all of them")
+    ),
+    responses(
+        (status = 200, description = "Filtered list of tickets", body = [TicketResponse])
+    ),
+    tag = "Ticket"
+)]
+pub async fn get_filtered_tickets(
+    State(state): State<AppState>,
+    Query(params): Query<TicketQuery>,
+    auth: AuthUser,
+) -> Result<Json<Vec<TicketResponse>>, AppError> {
+    let db = &state.db;
+    let Some(condition) = ticket_search_condition(db.as_ref(), &auth, &params).await? else {
+        return Ok(Json(Vec::new()));
+    };
+
+    let result = tickets::Entity::find_in(auth.tenant_id)
+        .filter(condition)
+        .all(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?;
+
+    let response = result.into_iter().map(TicketResponse::from).collect::<Vec<_>>();
+
+    Ok(Json(response))
+}
+
+// DELETE
+#[utoipa::path(
+    delete,
+    path = "/tickets/{id}",
+    responses(
+        (status = 204, description = "Ticket moved to the trash"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found"),
+        (status = 412, description = "The ticket changed while it was being trashed")
+    ),
+    tag = "Ticket"
+)]
+pub async fn delete_ticket_by_id(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+) -> Result<StatusCode, AppError> {
+    // Customers can no longer remove their tickets; staff move them to the trash.
+    require_staff(&auth)?;
+
+    let uuid = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".to_string()))?;
+
+    let db = &state.db;
+    let ticket = tickets::Entity::find_by_id_in(uuid, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+
+    if auth.role == "agent" && ticket.assigned_agent_id.map(|id| id.to_string()) != Some(auth.u_id.clone()) {
+        return Err(AppError::Forbidden);
+    }
+
+    let version = ticket.version;
+    let mut active = ticket.into_active_model();
+    active.deleted_at = Set(Some(Utc::now()));
+    save_versioned(db.as_ref(), active, version)
+        .await?
+        .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+    audit_trash(db.as_ref(), &auth, "trashed", "ticket", uuid, &ip).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+//MERGE / SPLIT
+// The ids in the order given, each once. A repeated id would otherwise make
+// the lookup come back short and look like a missing record.
+fn unique_ids(ids: &[Uuid]) -> Vec<Uuid> {
+    let mut seen = std::collections::HashSet::new();
+    ids.iter().copied().filter(|id| seen.insert(*id)).collect()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MergeTicketsInput {
+    pub source_ticket_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SplitTicketInput {
+    pub communication_id: Uuid,
+    pub title: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/tickets/{id}/merge",
+    request_body = MergeTicketsInput,
+    responses(
+        (status = 200, description = "Source tickets merged into target", body = TicketResponse),
+        (status = 400, description = "Invalid merge request"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found"),
+        (status = 412, description = "A ticket changed while it was being merged")
+    ),
+    tag = "Ticket"
+)]
+pub async fn merge_tickets(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    Json(input): Json<MergeTicketsInput>,
+) -> Result<Json<TicketResponse>, AppError> {
+    let target_id = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+    let actor = AuditActor::of(&auth)?;
+
+    let source_ids = unique_ids(&input.source_ticket_ids);
+    if source_ids.is_empty() {
+        return Err(AppError::BadRequest("No source tickets given".into()));
+    }
+    if source_ids.contains(&target_id) {
+        return Err(AppError::BadRequest("A ticket cannot be merged into itself".into()));
+    }
+
+    let txn = state.db.begin().await?;
+
+    let target = TicketEntity::find_by_id_in(target_id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_edit_ticket(&auth, &target)?;
+    if target.merged_into_id.is_some() {
+        return Err(AppError::BadRequest("Target ticket has already been merged".into()));
+    }
+
+    let sources = TicketEntity::find_in(auth.tenant_id)
+        .filter(tickets::Column::Id.is_in(source_ids.clone()))
+        .all(&txn)
+        .await?;
+    if sources.len() != source_ids.len() {
+        return Err(AppError::NotFound("Source ticket not found".into()));
+    }
+
+    for source in sources {
+        can_edit_ticket(&auth, &source)?;
+        if source.customer_id != target.customer_id {
+            return Err(AppError::BadRequest("Tickets belong to different customers".into()));
+        }
+        if source.merged_into_id.is_some() {
+            return Err(AppError::BadRequest("Source ticket has already been merged".into()));
+        }
+
+        CommunicationEntity::update_many_in(auth.tenant_id)
+            .col_expr(communications::Column::TicketId, Expr::value(target_id))
+            .filter(communications::Column::TicketId.eq(source.id))
+            .exec(&txn)
+            .await?;
+
+        move_ticket_tags(&txn, ticket_tags::Column::TicketId, source.id, target_id).await?;
+
+        let (source_id, source_status, version) = (source.id, source.status.clone(), source.version);
+        let mut active = source.into_active_model();
+        active.status = Set("closed".into());
+        active.merged_into_id = Set(Some(target_id));
+        active.updated_at = Set(Utc::now());
+        save_versioned(&txn, active, version)
+            .await?
+            .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+        record_audit(&txn, &actor, &format!("merged_into:{}", target_id), "ticket", source_id, &ip).await?;
+        record_audit(&txn, &actor, &format!("merged_from:{}", source_id), "ticket", target_id, &ip).await?;
+        record_ticket_event(&txn, &auth, source_id, "merged_into", None, Some(target_id.to_string())).await?;
+        record_ticket_event(&txn, &auth, target_id, "merged_from", None, Some(source_id.to_string())).await?;
+        if source_status != "closed" {
+            record_ticket_event(&txn, &auth, source_id, "status", Some(source_status), Some("closed".into())).await?;
+        }
+    }
+
+    let merged_list = source_ids
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let locale = customer_locale(&txn, &target).await?;
+    let notice = communications::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        ticket_id: Set(target_id),
+        sender_type: Set("agent".into()),
+        sender_id: Set(actor.user_id),
+        message: Set(Text::TicketsMerged.render(&locale, &[("tickets", &merged_list)])),
+        channel: Set(target.channel.clone()),
+        is_internal: Set(false),
+        is_system: Set(true),
+        external_id: Set(None),
+        timestamp: Set(Utc::now()),
+    };
+    notice.insert(&txn).await?;
+
+    let version = target.version;
+    let mut active = target.into_active_model();
+    active.updated_at = Set(Utc::now());
+    let updated = save_versioned(&txn, active, version)
+        .await?
+        .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+    txn.commit().await?;
+
+    Ok(Json(TicketResponse::from(updated)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/tickets/{id}/split",
+    request_body = SplitTicketInput,
+    responses(
+        (status = 201, description = "Communication split into a new ticket", body = TicketResponse),
+        (status = 400, description = "Invalid split request"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket or communication not found"),
+        (status = 412, description = "The ticket changed while it was being split")
+    ),
+    tag = "Ticket"
+)]
+pub async fn split_ticket(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+    Json(input): Json<SplitTicketInput>,
+) -> Result<(StatusCode, Json<TicketResponse>), AppError> {
+    let original_id = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+    let actor = AuditActor::of(&auth)?;
+
+    let txn = state.db.begin().await?;
+
+    let original = TicketEntity::find_by_id_in(original_id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_edit_ticket(&auth, &original)?;
+
+    let communication = CommunicationEntity::find_by_id_in(input.communication_id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Communication not found".into()))?;
+    if communication.ticket_id != original_id {
+        return Err(AppError::BadRequest("Communication does not belong to this ticket".into()));
+    }
+
+    let now = Utc::now();
+    let new_ticket = tickets::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        title: Set(input.title.unwrap_or_else(|| format!("Split from: {}", original.title))),
+        description: Set(communication.message.clone()),
+        status: Set("open".into()),
+        priority: Set(original.priority.clone()),
+        channel: Set(communication.channel.clone()),
+        customer_id: Set(original.customer_id),
+        assigned_agent_id: Set(original.assigned_agent_id),
+        merged_into_id: Set(None),
+        parent_ticket_id: Set(Some(original_id)),
+        external_id: Set(None),
+        deleted_at: Set(None),
+        version: Set(1),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&txn)
+    .await?;
+
+    let mut moved = communication.into_active_model();
+    moved.ticket_id = Set(new_ticket.id);
+    moved.update(&txn).await?;
+
+    let version = original.version;
+    let mut active = original.into_active_model();
+    active.updated_at = Set(now);
+    save_versioned(&txn, active, version)
+        .await?
+        .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+
+    record_audit(&txn, &actor, &format!("split_to:{}", new_ticket.id), "ticket", original_id, &ip).await?;
+    record_audit(&txn, &actor, &format!("split_from:{}", original_id), "ticket", new_ticket.id, &ip).await?;
+    record_ticket_event(&txn, &auth, original_id, "split_to", None, Some(new_ticket.id.to_string())).await?;
+    record_ticket_event(&txn, &auth, new_ticket.id, "split_from", None, Some(original_id.to_string())).await?;
+
+    txn.commit().await?;
+    metrics::ticket_created(&new_ticket.channel, "split");
+
+    Ok((StatusCode::CREATED, Json(TicketResponse::from(new_ticket))))
+}
+
+// TIMELINE
+#[derive(Serialize, ToSchema)]
+pub struct TimelineActor {
+    pub kind: String,   // "agent", "customer", "api_key" or "system"
+    pub id: Option<Uuid>,
+    pub name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TimelineEntry {
+    pub id: Uuid,
+    // "created", "message", "note", "status", "priority", "assignment",
+    // "tag_added", "tag_removed", "merged_into", "merged_from", "split_to",
+    // "split_from" or "first_response"
+    pub kind: String,
+    pub at: chrono::DateTime<Utc>,
+    pub actor: TimelineActor,
+    pub public: bool,
+    pub message: Option<String>,
+    pub channel: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+// Event kinds a customer may see on their own ticket. Messages are public
+// unless they are internal notes.
+const PUBLIC_TICKET_EVENTS: [&str; 1] = ["status"];
+
+const TIMELINE_PAGE_SIZE: u64 = 50;
+const TIMELINE_MAX_PAGE_SIZE: u64 = 200;
+
+#[derive(FromQueryResult)]
+struct TimelineRow {
+    source: String,     // "created", "first_response", "message" or "event"
+    id: Uuid,
+}
+
+// One page of the timeline, oldest first: the ticket's creation, the first
+// public reply from a staff account (staff only), its messages and its recorded changes.
+// Customers get public messages and PUBLIC_TICKET_EVENTS only.
+fn timeline_statement(tenant_id: Uuid, ticket_id: Uuid, staff: bool, limit: u64, offset: u64) -> Statement {
+    let public_events: Vec<String> = PUBLIC_TICKET_EVENTS.iter().map(|k| k.to_string()).collect();
+    Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"
+SELECT source, id FROM (
+    SELECT 'created' AS source, t.id, t.created_at AS at, 0 AS ord
+    FROM tickets t WHERE t.id = $2 AND t.tenant_id = $1
+    UNION ALL
+    (SELECT 'first_response', c.id, c.timestamp, 1
+     FROM communications c
+     WHERE $3 AND c.ticket_id = $2 AND c.tenant_id = $1 AND c.sender_type = 'agent' AND NOT c.is_internal AND NOT c.is_system
+       AND EXISTS (SELECT 1 FROM users s WHERE s.id = c.sender_id AND s.role IN ('agent', 'admin', 'super_admin'))
+     ORDER BY c.timestamp LIMIT 1)
+    UNION ALL
+    SELECT 'message', c.id, c.timestamp, 2
+    FROM communications c
+    WHERE c.ticket_id = $2 AND c.tenant_id = $1 AND ($3 OR NOT c.is_internal)
+    UNION ALL
+    SELECT 'event', e.id, e.created_at, 3
+    FROM ticket_events e
+    WHERE e.ticket_id = $2 AND e.tenant_id = $1 AND ($3 OR e.kind = ANY($4))
+) timeline
+ORDER BY at, ord, id
+LIMIT $5 OFFSET $6
+"#,
+        [
+            tenant_id.into(),
+            ticket_id.into(),
+            staff.into(),
+            public_events.into(),
+            (limit as i64).into(),
+            (offset as i64).into(),
+        ],
+    )
+}
+
+fn timeline_entry(id: Uuid, kind: &str, at: chrono::DateTime<Utc>, actor: (String, Option<Uuid>), public: bool) -> TimelineEntry {
+    TimelineEntry {
+        id,
+        kind: kind.to_string(),
+        at,
+        actor: TimelineActor { kind: actor.0, id: actor.1, name: String::new() },
+        public,
+        message: None,
+        channel: None,
+        from: None,
+        to: None,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tickets/{id}/timeline",
+    params(Pagination),
+    responses(
+        (status = 200, description = "Messages, notes and changes to the ticket, oldest first", body = [TimelineEntry]),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Ticket"
+)]
+pub async fn get_ticket_timeline(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<TimelineEntry>>, AppError> {
+    let db = state.db.as_ref();
+    let ticket = TicketEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_read_ticket(db, &auth, &ticket).await?;
+    let staff = require_staff(&auth).is_ok();
+
+    let limit = pagination.limit.unwrap_or(TIMELINE_PAGE_SIZE).min(TIMELINE_MAX_PAGE_SIZE);
+    let offset = pagination.offset.unwrap_or(0);
+    let rows = TimelineRow::find_by_statement(timeline_statement(auth.tenant_id, id, staff, limit, offset))
+        .all(db)
+        .await?;
+
+    let ids_from = |source: &str| -> Vec<Uuid> {
+        rows.iter().filter(|r| r.source == source).map(|r| r.id).collect()
+    };
+    let mut message_ids = ids_from("message");
+    message_ids.extend(ids_from("first_response"));
+    let mut messages: HashMap<Uuid, communications::Model> = CommunicationEntity::find_in(auth.tenant_id)
+        .filter(communications::Column::Id.is_in(message_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|m| (m.id, m))
+        .collect();
+    let mut events: HashMap<Uuid, ticket_events::Model> = TicketEventEntity::find_in(auth.tenant_id)
+        .filter(ticket_events::Column::Id.is_in(ids_from("event")))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|e| (e.id, e))
+        .collect();
+
+    let mut page = Vec::with_capacity(rows.len());
+    for row in &rows {
+        match row.source.as_str() {
+            "created" => {
+                let mut created = timeline_entry(ticket.id, "created", ticket.created_at, ("customer".into(), Some(ticket.customer_id)), true);
+                created.message = Some(ticket.title.clone());
+                created.channel = Some(ticket.channel.clone());
+                page.push(created);
+            }
+            // Time to the first public agent reply, the milestone first-response
+            // SLA targets are measured against.
+            "first_response" => {
+                let Some(reply) = messages.get(&row.id) else { continue };
+                let mut milestone = timeline_entry(reply.id, "first_response", reply.timestamp, ("system".into(), None), false);
+                milestone.to = Some(format!("{}m", (reply.timestamp - ticket.created_at).num_minutes()));
+                page.push(milestone);
+            }
+            "message" => {
+                let Some(m) = messages.remove(&row.id) else { continue };
+                let kind = if m.is_internal { "note" } else { "message" };
+                let mut entry = timeline_entry(m.id, kind, m.timestamp, (m.sender_type.clone(), Some(m.sender_id)), !m.is_internal);
+                entry.message = Some(m.message);
+                entry.channel = Some(m.channel);
+                page.push(entry);
+            }
+            _ => {
+                let Some(e) = events.remove(&row.id) else { continue };
+                let actor_kind = if e.actor_type == "user" { "agent".to_string() } else { e.actor_type.clone() };
+                let public = PUBLIC_TICKET_EVENTS.contains(&e.kind.as_str());
+                let mut entry = timeline_entry(e.id, &e.kind, e.created_at, (actor_kind, e.actor_id), public);
+                entry.from = e.from_value;
+                entry.to = e.to_value;
+                page.push(entry);
+            }
+        }
+    }
+
+    // Resolve actor names for this page only.
+    let ids_of = |kind: &str, page: &[TimelineEntry]| -> Vec<Uuid> {
+        page.iter().filter(|e| e.actor.kind == kind).filter_map(|e| e.actor.id).collect()
+    };
+    let agents: HashMap<Uuid, String> = UserEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(users::Column::Id.is_in(ids_of("agent", &page)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|u| (u.id, u.name))
+        .collect();
+    let customers: HashMap<Uuid, String> = CustomerEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(customers::Column::Id.is_in(ids_of("customer", &page)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect();
+
+    let keys: HashMap<Uuid, String> = ApiKeyEntity::find_in(auth.tenant_id)
+        .filter(api_keys::Column::Id.is_in(ids_of("api_key", &page)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|k| (k.id, k.name))
+        .collect();
+
+    for entry in &mut page {
+        let names = match entry.actor.kind.as_str() {
+            "agent" => &agents,
+            "customer" => &customers,
+            "api_key" => &keys,
+            _ => {
+                entry.actor.name = "System".into();
+                continue;
+            }
+        };
+        entry.actor.name = entry
+            .actor
+            .id
+            .and_then(|id| names.get(&id).cloned())
+            .unwrap_or_else(|| "Unknown".into());
+    }
+
+    Ok(Json(page))
+}
+
+//----------communication----------------
+#[derive(Deserialize, ToSchema)]
+pub struct CreateCommunicationInput {
+    pub ticket_id: Uuid,
+    pub message: String,
+    pub channel: String,     // "Email", "Chat", "Social"
+    // Staff only.
+    #[serde(default)]
+    pub is_internal: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CommunicationResponse {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub sender_type: String,
+    pub sender_id: Uuid,
+    pub message: String,
+    pub channel: String,
+    pub is_internal: bool,
+    // Sent by the system (survey invitation, merge notice), not by a person.
+    pub is_system: bool,
+}
+
+// CREATE
+#[utoipa::path(
+    post,
+    path = "/communications",
+    request_body = CreateCommunicationInput,
+    responses(
+        (status = 201, description = "Communication added", body = CommunicationResponse),
+        (status = 400, description = "Invalid channel"),
+        (status = 403, description = "Not allowed to write on this ticket, or an internal note from a customer"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Communication"
+)]
+pub async fn create_communication(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateCommunicationInput>,
+) -> Result<Json<CommunicationResponse>, AppError> {
+    let db = &state.db;
+
+    let ticket = TicketEntity::find_by_id_in(input.ticket_id, auth.tenant_id)
+        .one(db.as_ref())
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+
+    // The sender is whoever is signed in: staff write as agents on tickets
+    // they can edit, customers on tickets they can read.
+    let staff = require_staff(&auth).is_ok();
+    let sender_type = if staff {
+        can_edit_ticket(&auth, &ticket)?;
+        "agent"
+    } else if auth.role == "customer" {
+        can_read_ticket(db.as_ref(), &auth, &ticket).await?;
+        if input.is_internal {
+            return Err(AppError::Forbidden);
+        }
+        "customer"
+    } else {
+        return Err(AppError::Forbidden);
+    };
+    if !CHANNELS.contains(&input.channel.as_str()) {
+        return Err(AppError::BadRequest("Invalid channel".into()));
+    }
+
+    let model = communications::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        ticket_id: Set(input.ticket_id),
+        sender_type: Set(sender_type.into()),
+        sender_id: Set(auth_uuid(&auth)?),
+        message: Set(input.message.clone()),
+        channel: Set(input.channel.clone()),
+        is_internal: Set(input.is_internal),
+        is_system: Set(false),
+        external_id: Set(None),
+        timestamp: Set(Utc::now()),
+    };
+
+    let insert_failed = |e: DbErr| {
+        tracing::error!(error = %e, "Create error");
+        AppError::Internal("Insert failed".into())
+    };
+    let txn = db.begin().await.map_err(insert_failed)?;
+    let saved = model.insert(&txn).await.map_err(insert_failed)?;
+
+    // Only staff internal notes notify; @ in a customer-facing reply is just text.
+    let mentioned = if staff && saved.is_internal {
+        record_mentions(&txn, &auth, &saved).await?
+    } else {
+        Vec::new()
+    };
+
+    // Mentions stay in the inbox either way; only outside delivery can be switched off.
+    let deliver = state.config.features.webhook_notifications;
+    if deliver {
+        for (user, mention, author) in mentioned {
+            let notice = notify::MentionNotice {
+                text: format!(
+                    "{} mentioned you on \"{}\": {}",
+                    author.as_deref().unwrap_or("Someone"),
+                    ticket.title,
+                    excerpt(&saved.message),
+                ),
+                mention_id: mention.id,
+                ticket_id: ticket.id,
+                ticket_title: ticket.title.clone(),
+                mentioned_by: author,
+                note: saved.message.clone(),
+                created_at: mention.created_at,
+            };
+            notify::deliver(&txn, &user, notice).await.map_err(insert_failed)?;
+        }
+    }
+    txn.commit().await.map_err(insert_failed)?;
+    if staff && !saved.is_internal {
+        metrics::reply_sent(&saved.channel);
+    }
+
+    Ok(Json(CommunicationResponse {
+        id: saved.id,
+        ticket_id: saved.ticket_id,
+        sender_type: saved.sender_type,
+        sender_id: saved.sender_id,
+        message: saved.message,
+        channel: saved.channel,
+        is_internal: saved.is_internal,
+        is_system: saved.is_system,
+    }))
+}
+
+// READ ALL
+#[utoipa::path(
+    get,
+    path = "/communications/{ticket_id}",
+    params(Pagination),
+    responses(
+        (status = 200, description = "List of communications", body = [CommunicationResponse])
+    ),
+    tag = "Communication"
+)]
+pub async fn get_communications(
+     Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<CommunicationResponse>>, AppError> {
+    let uuid = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid ticket ID".into()))?;
+
+
+    let db = &state.db;
+    let ticket = TicketEntity::find_by_id_in(uuid, auth.tenant_id)
+        .one(db.as_ref())
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_read_ticket(db.as_ref(), &auth, &ticket).await?;
+
+    // Internal notes are left out before paging, so customers get full pages.
+    let mut query = CommunicationEntity::find_in(auth.tenant_id)
+        .filter(communications::Column::TicketId.eq(uuid));
+    if auth.role == "customer" {
+        query = query.filter(communications::Column::IsInternal.eq(false));
+    }
+    let list = query
+        .order_by_asc(communications::Column::Timestamp)
+        .order_by_asc(communications::Column::Id)
+        .limit(pagination.limit.unwrap_or(10))
+        .offset(pagination.offset.unwrap_or(0))
+        .all(db.as_ref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Fetch error");
+            AppError::Internal("Could not fetch communications".into())
+        })?;
+
+    let response = list.into_iter().map(|c| CommunicationResponse {
+        id: c.id,
+        ticket_id: c.ticket_id,
+        sender_type: c.sender_type,
+        sender_id: c.sender_id,
+        message: c.message,
+        channel: c.channel,
+        is_internal: c.is_internal,
+        is_system: c.is_system,
+    }).collect();   
+    Ok(Json(response))
+}
+
+
+//----------mentions----------------
+// @mentions in internal notes land in the mentioned agent's inbox and are
+// also sent on the channel they picked (see notify.rs).
+const NOTE_EXCERPT_CHARS: usize = 200;
+
+fn excerpt(text: &str) -> String {
+    let mut chars = text.chars();
+    let mut short: String = chars.by_ref().take(NOTE_EXCERPT_CHARS).collect();
+    if chars.next().is_some() {
+        short.push('…');
+    }
+    short
+}
+
+// Stores a mention for every staff member named in `note`, except its author.
+// Returns each mentioned user with their mention and the author's name.
+async fn record_mentions<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    note: &communications::Model,
+) -> Result<Vec<(users::Model, mentions::Model, Option<String>)>, AppError> {
+    let parsed = mention_parser::parse(&note.message);
+    if parsed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let staff = UserEntity::find_in(auth.tenant_id)
+        .filter(users::Column::Role.is_in(["agent", "admin"]))
+        .all(db)
+        .await?;
+    let author_id = Uuid::parse_str(&auth.u_id).ok();
+    let author = staff.iter().find(|u| Some(u.id) == author_id).map(|u| u.name.clone());
+
+    let mut recorded = Vec::new();
+    for user in mention_parser::resolve(&parsed, &staff) {
+        if Some(user.id) == author_id {
+            continue;
+        }
+        let now = Utc::now();
+        let mention = mentions::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            tenant_id: Set(auth.tenant_id),
+            communication_id: Set(note.id),
+            ticket_id: Set(note.ticket_id),
+            user_id: Set(user.id),
+            mentioned_by: Set(author_id),
+            read_at: Set(None),
+            // The inbox is the whole delivery for inbox-only users.
+            delivered_at: Set((user.notification_channel == notify::INBOX).then_some(now)),
+            created_at: Set(now),
+        }
+        .insert(db)
+        .await?;
+        recorded.push((user.clone(), mention, author.clone()));
+    }
+    Ok(recorded)
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct MentionQuery {
+    pub unread: Option<bool>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MentionResponse {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub ticket_title: String,
+    pub communication_id: Uuid,
+    pub note: String,
+    pub mentioned_by: Option<TimelineActor>,
+    pub read: bool,
+    pub read_at: Option<chrono::DateTime<Utc>>,
+    pub delivered_at: Option<chrono::DateTime<Utc>>,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UnreadMentionsResponse {
+    pub unread: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NotificationPreferences {
+    pub channel: String,          // "inbox" or "webhook"
+    pub target: Option<String>,   // webhook URL
+}
+
+// The caller's own mentions. Mentions on trashed tickets are hidden.
+fn my_mentions(auth: &AuthUser, me: Uuid) -> sea_orm::Select<MentionEntity> {
+    MentionEntity::find_in(auth.tenant_id)
+        .filter(mentions::Column::UserId.eq(me))
+        .join(JoinType::InnerJoin, mentions::Relation::Ticket.def())
+        .filter(tickets::Column::DeletedAt.is_null())
+}
+
+async fn mention_responses<C: ConnectionTrait>(
+    db: &C,
+    auth: &AuthUser,
+    rows: Vec<mentions::Model>,
+) -> Result<Vec<MentionResponse>, AppError> {
+    let ticket_ids: Vec<Uuid> = rows.iter().map(|m| m.ticket_id).collect();
+    let note_ids: Vec<Uuid> = rows.iter().map(|m| m.communication_id).collect();
+    let author_ids: Vec<Uuid> = rows.iter().filter_map(|m| m.mentioned_by).collect();
+
+    let titles: HashMap<Uuid, String> = TicketEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(tickets::Column::Id.is_in(ticket_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|t| (t.id, t.title))
+        .collect();
+    let notes: HashMap<Uuid, String> = CommunicationEntity::find_in(auth.tenant_id)
+        .filter(communications::Column::Id.is_in(note_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c.message))
+        .collect();
+    let authors: HashMap<Uuid, String> = UserEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(users::Column::Id.is_in(author_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|u| (u.id, u.name))
+        .collect();
+
+    Ok(rows
+        .into_iter()
+        .map(|m| MentionResponse {
+            id: m.id,
+            ticket_id: m.ticket_id,
+            ticket_title: titles.get(&m.ticket_id).cloned().unwrap_or_default(),
+            communication_id: m.communication_id,
+            note: notes.get(&m.communication_id).cloned().unwrap_or_default(),
+            mentioned_by: m.mentioned_by.map(|id| TimelineActor {
+                kind: "agent".into(),
+                id: Some(id),
+                name: authors.get(&id).cloned().unwrap_or_default(),
+            }),
+            read: m.read_at.is_some(),
+            read_at: m.read_at,
+            delivered_at: m.delivered_at,
+            created_at: m.created_at,
+        })
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/mentions",
+    params(MentionQuery),
+    responses(
+        (status = 200, description = "Mentions of the caller, newest first", body = [MentionResponse]),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Mentions"
+)]
+pub async fn get_my_mentions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<MentionQuery>,
+) -> Result<Json<Vec<MentionResponse>>, AppError> {
+    require_staff(&auth)?;
+    let me = auth_uuid(&auth)?;
+    let db = state.db.as_ref();
+
+    let mut select = my_mentions(&auth, me);
+    match query.unread {
+        Some(true) => select = select.filter(mentions::Column::ReadAt.is_null()),
+        Some(false) => select = select.filter(mentions::Column::ReadAt.is_not_null()),
+        None => {}
+    }
+    let rows = select
+        .order_by_desc(mentions::Column::CreatedAt)
+        .limit(query.limit.unwrap_or(20).min(100))
+        .offset(query.offset.unwrap_or(0))
+        .all(db)
+        .await?;
+
+    Ok(Json(mention_responses(db, &auth, rows).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/mentions/unread-count",
+    responses(
+        (status = 200, description = "Number of unread mentions", body = UnreadMentionsResponse),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Mentions"
+)]
+pub async fn get_unread_mention_count(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<UnreadMentionsResponse>, AppError> {
+    require_staff(&auth)?;
+    let me = auth_uuid(&auth)?;
+    let unread = my_mentions(&auth, me)
+        .filter(mentions::Column::ReadAt.is_null())
+        .count(state.db.as_ref())
+        .await?;
+    Ok(Json(UnreadMentionsResponse { unread }))
+}
+
+async fn set_mention_read(state: &AppState, auth: &AuthUser, id: Uuid, read: bool) -> Result<MentionResponse, AppError> {
+    require_staff(auth)?;
+    let me = auth_uuid(auth)?;
+    let db = state.db.as_ref();
+
+    let mention = my_mentions(auth, me)
+        .filter(mentions::Column::Id.eq(id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Mention not found".into()))?;
+    // Keep the first read time when marking an already read mention.
+    let mention = if read == mention.read_at.is_some() {
+        mention
+    } else {
+        let mut active = mention.into_active_model();
+        active.read_at = Set(read.then(Utc::now));
+        active.update(db).await?
+    };
+
+    let mut responses = mention_responses(db, auth, vec![mention]).await?;
+    Ok(responses.remove(0))
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/mentions/{id}/read",
+    params(("id" = Uuid, Path, description = "Mention id")),
+    responses(
+        (status = 200, description = "Mention marked as read", body = MentionResponse),
+        (status = 404, description = "Mention not found")
+    ),
+    tag = "Mentions"
+)]
+pub async fn mark_mention_read(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<MentionResponse>, AppError> {
+    Ok(Json(set_mention_read(&state, &auth, id, true).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/mentions/{id}/read",
+    params(("id" = Uuid, Path, description = "Mention id")),
+    responses(
+        (status = 200, description = "Mention marked as unread", body = MentionResponse),
+        (status = 404, description = "Mention not found")
+    ),
+    tag = "Mentions"
+)]
+pub async fn mark_mention_unread(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<MentionResponse>, AppError> {
+    Ok(Json(set_mention_read(&state, &auth, id, false).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/mentions/read",
+    responses(
+        (status = 200, description = "All mentions marked as read; returns how many changed", body = UnreadMentionsResponse),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Mentions"
+)]
+pub async fn mark_all_mentions_read(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<UnreadMentionsResponse>, AppError> {
+    require_staff(&auth)?;
+    let me = auth_uuid(&auth)?;
+    let res = MentionEntity::update_many_in(auth.tenant_id)
+        .col_expr(mentions::Column::ReadAt, Expr::value(Utc::now()))
+        .filter(mentions::Column::UserId.eq(me))
+        .filter(mentions::Column::ReadAt.is_null())
+        .exec(state.db.as_ref())
+        .await?;
+    Ok(Json(UnreadMentionsResponse { unread: res.rows_affected }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/notification-preferences",
+    responses(
+        (status = 200, description = "How the caller is notified of mentions", body = NotificationPreferences)
+    ),
+    tag = "Mentions"
+)]
+pub async fn get_notification_preferences(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<NotificationPreferences>, AppError> {
+    let user = UserEntity::find_by_id_in(auth_uuid(&auth)?, auth.tenant_id)
+        .one(state.db.as_ref())
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    Ok(Json(NotificationPreferences {
+        channel: user.notification_channel,
+        target: user.notification_target,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/me/notification-preferences",
+    request_body = NotificationPreferences,
+    responses(
+        (status = 200, description = "Preferences saved", body = NotificationPreferences),
+        (status = 400, description = "Unknown channel or invalid webhook URL")
+    ),
+    tag = "Mentions"
+)]
+pub async fn update_notification_preferences(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<NotificationPreferences>,
+) -> Result<Json<NotificationPreferences>, AppError> {
+    let target = input.target.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    notify::validate(&input.channel, target.as_deref()).await.map_err(AppError::BadRequest)?;
+
+    let db = state.db.as_ref();
+    let user = UserEntity::find_by_id_in(auth_uuid(&auth)?, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+    let mut active = user.into_active_model();
+    active.notification_channel = Set(input.channel);
+    active.notification_target = Set(target);
+    let user = active.update(db).await?;
+
+    Ok(Json(NotificationPreferences {
+        channel: user.notification_channel,
+        target: user.notification_target,
+    }))
+}
+
+
+//----------knowledge_base----------------
+#[derive(Deserialize, ToSchema)]
+pub struct CreateArticleInput {
+    pub title: String,
+    pub content: String,
+    pub category: String,
+    // Language the article is written in. Fixed once the article exists;
+    // other languages are added as translations.
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ArticleResponse {
+    pub id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub category: String,
+    pub created_by: Uuid,
+    pub status: String,
+    pub locale: String,
+    pub translation_of: Option<Uuid>,
+    pub view_count: i64,
+    pub helpful_votes: i32,
+    pub unhelpful_votes: i32,
+    pub version: i32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ArticleStatusInput {
+    pub status: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevisionResponse {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub revision_number: i32,
+    pub title: String,
+    pub content: String,
+    pub category: String,
+    pub author_id: Uuid,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct DiffQuery {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ArticleDiffResponse {
+    pub from: i32,
+    pub to: i32,
+    pub title: Vec<DiffLine>,
+    pub category: Vec<DiffLine>,
+    pub content: Vec<DiffLine>,
+}
+
+impl From<knowledge_base::Model> for ArticleResponse {
+    fn from(article: knowledge_base::Model) -> Self {
+        ArticleResponse {
+            id: article.id,
+            title: article.title,
+            content: article.content,
+            category: article.category,
+            created_by: article.created_by,
+            status: article.status,
+            locale: article.locale,
+            translation_of: article.translation_of,
+            view_count: article.view_count,
+            helpful_votes: article.helpful_votes,
+            unhelpful_votes: article.unhelpful_votes,
+            version: article.version,
+        }
+    }
+}
+
+impl From<article_revisions::Model> for RevisionResponse {
+    fn from(rev: article_revisions::Model) -> Self {
+        RevisionResponse {
+            id: rev.id,
+            article_id: rev.article_id,
+            revision_number: rev.revision_number,
+            title: rev.title,
+            content: rev.content,
+            category: rev.category,
+            author_id: rev.author_id,
+            created_at: rev.created_at,
+        }
+    }
+}
+
+const ARTICLE_STATUSES: [&str; 4] = ["draft", "in_review", "published", "archived"];
+
+fn can_transition_article(from: &str, to: &str) -> bool {
+    matches!(
+        (from, to),
+        ("draft", "in_review")
+            | ("draft", "published")
+            | ("in_review", "draft")
+            | ("in_review", "published")
+            | ("published", "archived")
+            | ("published", "draft")
+            | ("archived", "draft")
+    )
+}
+
+// Snapshots the given text as the next revision of the article. Callers pass
+// a transaction: the article row stays locked until it commits, so concurrent
+// saves take turns instead of both picking the same revision number.
+async fn create_revision<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    article_id: Uuid,
+    title: &str,
+    content: &str,
+    category: &str,
+    author_id: Uuid,
+) -> Result<article_revisions::Model, AppError> {
+    KBEntity::find_by_id_with_trashed_in(article_id, tenant_id)
+        .lock_exclusive()
+        .one(db)
+        .await?;
+
+    let latest = ArticleRevisionEntity::find()
+        .filter(article_revisions::Column::ArticleId.eq(article_id))
+        .order_by_desc(article_revisions::Column::RevisionNumber)
+        .one(db)
+        .await?;
+
+    let revision = article_revisions::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        article_id: Set(article_id),
+        revision_number: Set(latest.map(|r| r.revision_number + 1).unwrap_or(1)),
+        title: Set(title.to_string()),
+        content: Set(content.to_string()),
+        category: Set(category.to_string()),
+        author_id: Set(author_id),
+        created_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(revision)
+}
+
+// Customers only ever see the text of the published revision, never a working draft.
+async fn published_pairs<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    title: Option<&str>,
+    category: Option<&str>,
+) -> Result<Vec<(knowledge_base::Model, article_revisions::Model)>, AppError> {
+    let published_ids = SubQuery::select()
+        .column(knowledge_base::Column::PublishedRevisionId)
+        .from(knowledge_base::Entity)
+        .and_where(knowledge_base::Column::TenantId.eq(tenant_id))
+        .and_where(knowledge_base::Column::PublishedRevisionId.is_not_null())
+        .and_where(knowledge_base::Column::Status.ne("archived"))
+        .to_owned();
+
+    let mut query = ArticleRevisionEntity::find()
+        .filter(article_revisions::Column::Id.in_subquery(published_ids));
+    if let Some(title) = title {
+        query = query.filter(article_revisions::Column::Title.contains(title));
+    }
+    if let Some(category) = category {
+        query = query.filter(article_revisions::Column::Category.eq(category));
+    }
+
+    let revisions = query
+        .order_by_asc(article_revisions::Column::Title)
+        .all(db)
+        .await?;
+    let articles = KBEntity::find_in(tenant_id)
+        .filter(knowledge_base::Column::Id.is_in(revisions.iter().map(|r| r.article_id).collect::<Vec<_>>()))
+        .all(db)
+        .await?;
+
+    Ok(revisions
+        .into_iter()
+        .filter_map(|rev| {
+            let article = articles.iter().find(|a| a.id == rev.article_id)?;
+            Some((article.clone(), rev))
+        })
+        .collect())
+}
+
+// The original an article belongs to; translations share their original's id.
+fn translation_group(article: &knowledge_base::Model) -> Uuid {
+    article.translation_of.unwrap_or(article.id)
+}
+
+// Narrows published articles down to one variant per original: the one in
+// the reader's language when there is one, the original otherwise. When the
+// original is not published itself, its first published translation stands in.
+fn pick_variants(
+    pairs: Vec<(knowledge_base::Model, article_revisions::Model)>,
+    requested: &[String],
+) -> Vec<(knowledge_base::Model, article_revisions::Model)> {
+    let mut groups: HashMap<Uuid, Vec<(knowledge_base::Model, article_revisions::Model)>> = HashMap::new();
+    for pair in pairs {
+        groups.entry(translation_group(&pair.0)).or_default().push(pair);
+    }
+
+    let mut picked: Vec<_> = groups
+        .into_values()
+        .map(|mut variants| {
+            let locales: Vec<&str> = variants.iter().map(|(a, _)| a.locale.as_str()).collect();
+            let index = locale::negotiate(requested, &locales)
+                .and_then(|wanted| locales.iter().position(|l| *l == wanted))
+                .or_else(|| variants.iter().position(|(a, _)| a.translation_of.is_none()))
+                .unwrap_or(0);
+            variants.swap_remove(index)
+        })
+        .collect();
+    picked.sort_by(|a, b| a.1.title.cmp(&b.1.title));
+    picked
+}
+
+async fn published_articles<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    title: Option<&str>,
+    category: Option<&str>,
+    requested: &[String],
+) -> Result<Vec<ArticleResponse>, AppError> {
+    let pairs = published_pairs(db, tenant_id, title, category).await?;
+    Ok(pick_variants(pairs, requested).into_iter().map(published_response).collect())
+}
+
+fn published_response((article, rev): (knowledge_base::Model, article_revisions::Model)) -> ArticleResponse {
+    ArticleResponse {
+        id: article.id,
+        title: rev.title,
+        content: rev.content,
+        category: rev.category,
+        created_by: article.created_by,
+        status: "published".into(),
+        locale: article.locale,
+        translation_of: article.translation_of,
+        view_count: article.view_count,
+        helpful_votes: article.helpful_votes,
+        unhelpful_votes: article.unhelpful_votes,
+        version: article.version,
+    }
+}
+
+fn article_locale(locale: Option<&str>) -> Result<String, AppError> {
+    match locale.map(str::trim) {
+        None | Some("") => Ok(locale::DEFAULT_LOCALE.to_string()),
+        Some(tag) => locale::normalise(tag).ok_or(AppError::BadRequest("Invalid locale".into())),
+    }
+}
+
+async fn find_article<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    article_id: Uuid,
+) -> Result<knowledge_base::Model, AppError> {
+    KBEntity::find_by_id_in(article_id, tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Article not found".into()))
+}
+
+async fn find_revision<C: ConnectionTrait>(
+    db: &C,
+    article_id: Uuid,
+    revision_number: i32,
+) -> Result<article_revisions::Model, AppError> {
+    ArticleRevisionEntity::find()
+        .filter(article_revisions::Column::ArticleId.eq(article_id))
+        .filter(article_revisions::Column::RevisionNumber.eq(revision_number))
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound(format!("Revision {} not found", revision_number)))
+}
+
+// CREATE
+#[utoipa::path(
+    post,
+    path = "/kb",
+    request_body = CreateArticleInput,
+    responses(
+        (status = 201, description = "Article created as a draft", body = ArticleResponse),
+        (status = 400, description = "Invalid locale"),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn create_article(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateArticleInput>,
+) -> Result<(StatusCode, Json<ArticleResponse>), AppError> {
+    require_staff(&auth)?;
+    let author_id = auth_uuid(&auth)?;
+    let locale = article_locale(input.locale.as_deref())?;
+
+    let txn = state.db.begin().await?;
+
+    let now = Utc::now();
+    let article = knowledge_base::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        title: Set(input.title),
+        content: Set(input.content),
+        category: Set(input.category),
+        created_by: Set(author_id),
+        status: Set("draft".into()),
+        published_revision_id: Set(None),
+        view_count: Set(0),
+        helpful_votes: Set(0),
+        unhelpful_votes: Set(0),
+        external_id: Set(None),
+        locale: Set(locale),
+        translation_of: Set(None),
+        deleted_at: Set(None),
+        version: Set(1),
+        created_at: Set(now),
+        updated_at: Set(now),
+    };
+
+    let saved = article.insert(&txn).await.map_err(|e| {
+        tracing::error!(error = %e, "Insert error");
+        AppError::Internal("Could not create article".into())
+    })?;
+    create_revision(&txn, auth.tenant_id, saved.id, &saved.title, &saved.content, &saved.category, author_id).await?;
+
+    txn.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(ArticleResponse::from(saved))))
+}
+
+// GET ALL
+#[utoipa::path(
+    get,
+    path = "/kb",
+    responses(
+        (status = 200, description = "Staff see every article; customers only published ones, in the language negotiated from Accept-Language", body = [ArticleResponse])
+    ),
+    tag = "Knowledge"
+)]
+pub async fn get_all_articles(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ArticleResponse>>, AppError> {
+    let db = state.db.as_ref();
+    if require_staff(&auth).is_err() {
+        let requested = locale::requested(&headers, None);
+        return Ok(Json(published_articles(db, auth.tenant_id, None, None, &requested).await?));
+    }
+
+    let articles = knowledge_base::Entity::find_in(auth.tenant_id)
+        .all(db)
+        .await
+        .map_err(|_| AppError::Db(()))?;
+
+    Ok(Json(articles.into_iter().map(ArticleResponse::from).collect()))
+}
+
+// READ by ID
+#[utoipa::path(
+    get,
+    path = "/kb/{id}",
+    responses(
+        (status = 200, description = "Article, with its version as ETag", body = ArticleResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article not found")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn get_article(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let article = find_article(state.db.as_ref(), auth.tenant_id, id).await?;
+
+    Ok(with_etag(article.version, Json(ArticleResponse::from(article))))
+}
+
+// UPDATE
+#[utoipa::path(
+    put,
+    path = "/kb/{id}",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    request_body = CreateArticleInput,
+    responses(
+        (status = 200, description = "New revision saved", body = ArticleResponse),
+        (status = 400, description = "Locale differs from the article's"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article not found"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Knowledge"
+)]
+#[debug_handler]
+pub async fn update_article(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Json(input): Json<CreateArticleInput>,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let author_id = auth_uuid(&auth)?;
+    let if_match = IfMatch::from_headers(&headers)?;
+
+    let uuid = Uuid::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+    let txn = state.db.begin().await?;
+    let record = KBEntity::find_by_id_in(uuid, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Article not found".into()))?;
+    if_match.check(record.version, || ArticleResponse::from(record.clone()))?;
+    if let Some(locale) = input.locale.as_deref()
+        && article_locale(Some(locale))? != record.locale
+    {
+        return Err(AppError::BadRequest(
+            "The locale of an article cannot be changed; add a translation instead".into(),
+        ));
+    }
+
+    create_revision(&txn, auth.tenant_id, record.id, &input.title, &input.content, &input.category, author_id).await?;
+
+    // Edits to a published or archived article go back through the workflow;
+    // customers keep seeing the last published revision meanwhile.
+    let next_status = if record.status == "in_review" { "in_review" } else { "draft" };
+    let version = record.version;
+    let mut model = record.into_active_model();
+    model.title = Set(input.title);
+    model.content = Set(input.content);
+    model.category = Set(input.category);
+    model.status = Set(next_status.into());
+    model.updated_at = Set(Utc::now());
+
+    let updated = save_versioned(&txn, model, version)
+        .await?
+        .or_stale(|a| (a.version, ArticleResponse::from(a)))?;
+
+    txn.commit().await?;
+
+    Ok(with_etag(updated.version, Json(ArticleResponse::from(updated))))
+}
+
+// WORKFLOW
+#[utoipa::path(
+    patch,
+    path = "/kb/{id}/status",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    request_body = ArticleStatusInput,
+    responses(
+        (status = 200, description = "Article moved to the new state", body = ArticleResponse),
+        (status = 400, description = "Transition not allowed"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article not found"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn update_article_status(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Json(input): Json<ArticleStatusInput>,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let if_match = IfMatch::from_headers(&headers)?;
+    if !ARTICLE_STATUSES.contains(&input.status.as_str()) {
+        return Err(AppError::BadRequest("Unknown article status".into()));
+    }
+    if input.status == "published" {
+        require_role(&auth, "admin")?;
+    }
+
+    let db = state.db.as_ref();
+    let article = KBEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Article not found".into()))?;
+    if_match.check(article.version, || ArticleResponse::from(article.clone()))?;
+
+    if !can_transition_article(&article.status, &input.status) {
+        return Err(AppError::BadRequest(format!(
+            "Cannot move article from {} to {}",
+            article.status, input.status
+        )));
+    }
+
+    let latest = ArticleRevisionEntity::find()
+        .filter(article_revisions::Column::ArticleId.eq(article.id))
+        .order_by_desc(article_revisions::Column::RevisionNumber)
+        .one(db)
+        .await?;
+
+    let version = article.version;
+    let mut model = article.into_active_model();
+    if input.status == "published" {
+        model.published_revision_id = Set(latest.as_ref().map(|r| r.id));
+    }
+    model.status = Set(input.status);
+    model.updated_at = Set(Utc::now());
+    let updated = save_versioned(db, model, version)
+        .await?
+        .or_stale(|a| (a.version, ArticleResponse::from(a)))?;
+
+    {
+        let mut index = kb_index::write(&state.kb_index);
+        match (updated.status.as_str(), latest) {
+            ("published", Some(rev)) => index.upsert(
+                updated.tenant_id,
+                updated.id,
+                &updated.locale,
+                &rev.title,
+                &rev.category,
+                &rev.content,
+            ),
+            ("archived", _) => index.remove(updated.tenant_id, updated.id),
+            _ => {}
+        }
+    }
+
+    Ok(with_etag(updated.version, Json(ArticleResponse::from(updated))))
+}
+
+// REVISIONS
+#[utoipa::path(
+    get,
+    path = "/kb/{id}/revisions",
+    responses(
+        (status = 200, description = "Revision history, newest first", body = [RevisionResponse]),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn get_article_revisions(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<RevisionResponse>>, AppError> {
+    require_staff(&auth)?;
+
+    let db = state.db.as_ref();
+    find_article(db, auth.tenant_id, id).await?;
+    let revisions = ArticleRevisionEntity::find()
+        .filter(article_revisions::Column::ArticleId.eq(id))
+        .order_by_desc(article_revisions::Column::RevisionNumber)
+        .all(db)
+        .await?;
+
+    Ok(Json(revisions.into_iter().map(RevisionResponse::from).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/kb/{id}/diff",
+    params(DiffQuery),
+    responses(
+        (status = 200, description = "Line diff between two revisions", body = ArticleDiffResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Revision not found")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn diff_article_revisions(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<ArticleDiffResponse>, AppError> {
+    require_staff(&auth)?;
+
+    let db = state.db.as_ref();
+    find_article(db, auth.tenant_id, id).await?;
+    let from = find_revision(db, id, query.from).await?;
+    let to = find_revision(db, id, query.to).await?;
+
+    Ok(Json(ArticleDiffResponse {
+        from: from.revision_number,
+        to: to.revision_number,
+        title: line_diff(&from.title, &to.title),
+        category: line_diff(&from.category, &to.category),
+        content: line_diff(&from.content, &to.content),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/kb/{id}/revisions/{revision_number}/restore",
+    params(("If-Match" = String, Header, description = "ETag of the version being edited")),
+    responses(
+        (status = 200, description = "Old revision restored as a new draft revision", body = ArticleResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article or revision not found"),
+        (status = 412, description = "Changed since it was read; body holds the current version"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn restore_article_revision(
+    Path((id, revision_number)): Path<(Uuid, i32)>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let author_id = auth_uuid(&auth)?;
+    let if_match = IfMatch::from_headers(&headers)?;
+
+    let txn = state.db.begin().await?;
+    let article = find_article(&txn, auth.tenant_id, id).await?;
+    if_match.check(article.version, || ArticleResponse::from(article.clone()))?;
+    let old = find_revision(&txn, id, revision_number).await?;
+
+    // Restoring never rewrites history; it appends a copy of the old text.
+    create_revision(&txn, auth.tenant_id, id, &old.title, &old.content, &old.category, author_id).await?;
+
+    let version = article.version;
+    let mut model = article.into_active_model();
+    model.title = Set(old.title);
+    model.content = Set(old.content);
+    model.category = Set(old.category);
+    model.status = Set("draft".into());
+    model.updated_at = Set(Utc::now());
+    let updated = save_versioned(&txn, model, version)
+        .await?
+        .or_stale(|a| (a.version, ArticleResponse::from(a)))?;
+
+    txn.commit().await?;
+
+    Ok(with_etag(updated.version, Json(ArticleResponse::from(updated))))
+}
+
+//SEARCH
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SearchQuery {
+    pub org: Option<String>,
+    pub title: Option<String>,
+    pub category: Option<String>,
+    pub locale: Option<String>,
+}
+#[utoipa::path(
+    get,
+    path = "/kb/search",
+    params(
+        ("org" = Option<String>, Query, description = "Slug of the organization whose articles are searched; the default organization when left out"),
+        ("title" = Option<String>, Query, description = "Words to look for in the title, category and text; results are ranked best first"),
+        ("category" = Option<String>, Query, description = "Exact category"),
+        ("locale" = Option<String>, Query, description = "Language wanted; overrides Accept-Language")
+    ),
+    responses(
+        (status = 200, description = "Search published articles, one language variant of each", body = [ArticleResponse])
+    ),
+    tag = "Knowledge"
+)]
+#[debug_handler]
+pub async fn search_articles(
+    Query(params): Query<SearchQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let db = state.db.as_ref();
+    let requested = locale::requested(&headers, params.locale.as_deref());
+    // Callers from before organizations existed send no slug; their articles
+    // were moved into the default organization.
+    let slug = params.org.as_deref().unwrap_or(DEFAULT_ORGANIZATION);
+    let org = find_organization(db, slug, &requested).await?;
+    let Some(text) = params.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) else {
+        let articles = published_articles(db, org.id, None, params.category.as_deref(), &requested).await?;
+        return Ok(locale::content_language(Json(articles).into_response(), None));
+    };
+
+    // Ranked by the same index as suggestions, in the best language the
+    // organization has articles in, so stop words and accents are handled
+    // per language. Like suggestions, the default language is tried when
+    // nothing matches in the reader's.
+    let pairs = published_pairs(db, org.id, None, params.category.as_deref()).await?;
+    let mut available: Vec<&str> = pairs.iter().map(|(a, _)| a.locale.as_str()).collect();
+    available.sort_unstable();
+    available.dedup();
+    let mut search_locale = locale::negotiate(&requested, &available).unwrap_or(locale::DEFAULT_LOCALE).to_string();
+    let limit = pairs.len().max(1);
+    let ranked = {
+        let index = kb_index::read(&state.kb_index);
+        let ranked = index.search(org.id, &search_locale, text, limit);
+        if ranked.is_empty() && locale::language(&search_locale) != locale::DEFAULT_LOCALE {
+            search_locale = locale::DEFAULT_LOCALE.to_string();
+            index.search(org.id, &search_locale, text, limit)
+        } else {
+            ranked
+        }
+    };
+
+    let mut groups = std::collections::HashSet::new();
+    let articles: Vec<ArticleResponse> = ranked
+        .into_iter()
+        .filter_map(|(article_id, _)| pairs.iter().find(|(a, _)| a.id == article_id))
+        .filter(|(article, _)| groups.insert(translation_group(article)))
+        .map(|pair| published_response(pair.clone()))
+        .collect();
+
+    Ok(locale::content_language(Json(articles).into_response(), Some(&search_locale)))
+}
+
+// DELETE
+#[utoipa::path(
+    delete,
+    path = "/kb/{id}",
+    responses(
+        (status = 204, description = "Article moved to the trash"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article not found")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn delete_article(
+      Path(id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if !auth.is_admin() && auth.role != "agent" {
+        return Err((StatusCode::FORBIDDEN, "Only agents or admins can delete".into()));
+    }
+    let uuid = Uuid::parse_str(&id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid UUID".into()))?;
+    let db = &state.db;
+    let trashed = KBEntity::update_many_in(auth.tenant_id)
+        .col_expr(knowledge_base::Column::DeletedAt, Expr::value(Utc::now()))
+        .filter(knowledge_base::Column::Id.eq(uuid))
+        .filter(knowledge_base::Column::DeletedAt.is_null())
+        .exec(db.as_ref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Deletion error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Could not delete article".into())
+        })?;
+    if trashed.rows_affected == 0 {
+        return Err((StatusCode::NOT_FOUND, "Article not found".into()));
+    }
+    kb_index::write(&state.kb_index).remove(auth.tenant_id, uuid);
+
+    audit_trash(db.as_ref(), &auth, "trashed", "article", uuid, &ip)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Could not record audit log".into()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+//TRANSLATIONS
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTranslationInput {
+    pub locale: String,
+    pub title: String,
+    pub content: String,
+    pub category: String,
+}
+
+// The original and all of its translations.
+fn variants_of(original_id: Uuid) -> Condition {
+    Condition::any()
+        .add(knowledge_base::Column::Id.eq(original_id))
+        .add(knowledge_base::Column::TranslationOf.eq(original_id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/kb/{id}/translations",
+    request_body = CreateTranslationInput,
+    responses(
+        (status = 201, description = "Translation created as a draft", body = ArticleResponse),
+        (status = 400, description = "Invalid locale"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article not found"),
+        (status = 409, description = "The article already exists in this locale")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn create_article_translation(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateTranslationInput>,
+) -> Result<(StatusCode, Json<ArticleResponse>), AppError> {
+    require_staff(&auth)?;
+    let author_id = auth_uuid(&auth)?;
+    let locale = article_locale(Some(&input.locale))?;
+
+    let txn = state.db.begin().await?;
+    // Translations always hang off the original, even when added through
+    // another translation.
+    let article = find_article(&txn, auth.tenant_id, id).await?;
+    let original_id = translation_group(&article);
+
+    // Trashed variants count too: restoring one must not clash.
+    let taken = KBEntity::find_with_trashed_in(auth.tenant_id)
+        .filter(variants_of(original_id))
+        .filter(knowledge_base::Column::Locale.eq(locale.as_str()))
+        .count(&txn)
+        .await?;
+    let exists = format!("The article already exists in {}", locale);
+    if taken > 0 {
+        return Err(AppError::Conflict(exists));
+    }
+
+    let now = Utc::now();
+    // The check above can race another request for the same locale; the
+    // unique index settles it.
+    let saved = knowledge_base::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        title: Set(input.title),
+        content: Set(input.content),
+        category: Set(input.category),
+        created_by: Set(author_id),
+        status: Set("draft".into()),
+        published_revision_id: Set(None),
+        view_count: Set(0),
+        helpful_votes: Set(0),
+        unhelpful_votes: Set(0),
+        external_id: Set(None),
+        locale: Set(locale),
+        translation_of: Set(Some(original_id)),
+        deleted_at: Set(None),
+        version: Set(1),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&txn)
+    .await
+    .map_err(conflict_on_unique(&exists))?;
+    create_revision(&txn, auth.tenant_id, saved.id, &saved.title, &saved.content, &saved.category, author_id).await?;
+
+    txn.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(ArticleResponse::from(saved))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/kb/{id}/translations",
+    responses(
+        (status = 200, description = "The original article and all its translations, by locale", body = [ArticleResponse]),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Article not found")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn get_article_translations(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<ArticleResponse>>, AppError> {
+    require_staff(&auth)?;
+
+    let db = state.db.as_ref();
+    let article = find_article(db, auth.tenant_id, id).await?;
+    let variants = KBEntity::find_in(auth.tenant_id)
+        .filter(variants_of(translation_group(&article)))
+        .order_by_asc(knowledge_base::Column::Locale)
+        .all(db)
+        .await?;
+
+    Ok(Json(variants.into_iter().map(ArticleResponse::from).collect()))
+}
+
+
+//----------help center----------------
+// Public, read-only view of the knowledge base. No login; rate limited per IP in routes.rs.
+// The organization is named by its slug in the path, e.g. /help/acme/articles.
+// Each article is served in the language negotiated from Accept-Language (or
+// ?locale=), falling back to the original when it has not been translated.
+#[derive(Serialize, ToSchema)]
+pub struct HelpArticle {
+    pub id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub category: String,
+    pub locale: String,
+    // Every locale the article can be read in, for a language switcher.
+    pub translations: Vec<String>,
+    pub helpful_votes: i32,
+    pub unhelpful_votes: i32,
+    pub updated_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HelpArticleSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub category: String,
+    pub locale: String,
+}
+#[derive(Serialize, ToSchema)]
+pub struct CategoryNode {
+    pub name: String,
+    pub path: String,
+    pub article_count: u64,
+    #[schema(no_recursion)]
+    pub children: Vec<CategoryNode>,
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct HelpArticleQuery {
+    pub category: Option<String>,
+    pub q: Option<String>,
+    pub locale: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct LocaleQuery {
+    // Overrides Accept-Language, e.g. for a language switcher.
+    pub locale: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct VoteInput {
+    pub helpful: bool,
+}
+
+const CATEGORY_SEPARATOR: char = '/';
+
+fn category_segments(category: &str) -> Vec<&str> {
+    category
+        .split(CATEGORY_SEPARATOR)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn in_category(article_category: &str, wanted: &str) -> bool {
+    let article = category_segments(article_category);
+    let wanted = category_segments(wanted);
+    article.len() >= wanted.len()
+        && article.iter().zip(wanted.iter()).all(|(a, w)| a.eq_ignore_ascii_case(w))
+}
+
+// Builds the category hierarchy from "Parent / Child" style category names.
+fn build_category_tree(categories: &[String]) -> Vec<CategoryNode> {
+    fn insert(nodes: &mut Vec<CategoryNode>, segments: &[&str], parent_path: &str) {
+        let Some((first, rest)) = segments.split_first() else {
+            return;
+        };
+        let path = if parent_path.is_empty() {
+            first.to_string()
+        } else {
+            format!("{}{}{}", parent_path, CATEGORY_SEPARATOR, first)
+        };
+
+        let index = match nodes.iter().position(|n| n.name.eq_ignore_ascii_case(first)) {
+            Some(index) => index,
+            None => {
+                nodes.push(CategoryNode {
+                    name: first.to_string(),
+                    path: path.clone(),
+                    article_count: 0,
+                    children: Vec::new(),
+                });
+                nodes.len() - 1
+            }
+        };
+
+        let node = &mut nodes[index];
+        node.article_count += 1;
+        insert(&mut node.children, rest, &node.path.clone());
+    }
+
+    fn sort(nodes: &mut [CategoryNode]) {
+        nodes.sort_by_key(|n| n.name.to_lowercase());
+        for node in nodes.iter_mut() {
+            sort(&mut node.children);
+        }
+    }
+
+    let mut roots = Vec::new();
+    for category in categories {
+        insert(&mut roots, &category_segments(category), "");
+    }
+    sort(&mut roots);
+    roots
+}
+
+async fn find_organization<C: ConnectionTrait>(
+    db: &C,
+    slug: &str,
+    requested: &[String],
+) -> Result<organizations::Model, AppError> {
+    OrganizationEntity::find()
+        .filter(organizations::Column::Slug.eq(slug))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Text::OrganizationNotFound.pick(requested)))
+}
+
+// Slug of the organization that rows from before multi-tenancy were put in.
+const DEFAULT_ORGANIZATION: &str = "default";
+
+// The revision an article publishes. Revisions carry no tenant_id, so they are
+// only looked up through an article that was itself found in the tenant.
+async fn published_revision<C: ConnectionTrait>(
+    db: &C,
+    article: &knowledge_base::Model,
+) -> Result<Option<article_revisions::Model>, AppError> {
+    let Some(revision_id) = article.published_revision_id else {
+        return Ok(None);
+    };
+    Ok(ArticleRevisionEntity::find()
+        .filter(article_revisions::Column::Id.eq(revision_id))
+        .filter(article_revisions::Column::ArticleId.eq(article.id))
+        .one(db)
+        .await?)
+}
+
+async fn find_published<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    id: Uuid,
+    requested: &[String],
+) -> Result<(knowledge_base::Model, article_revisions::Model), AppError> {
+    let not_found = || AppError::NotFound(Text::ArticleNotFound.pick(requested));
+    let article = KBEntity::find_by_id_in(id, tenant_id)
+        .filter(knowledge_base::Column::Status.ne("archived"))
+        .one(db)
+        .await?
+        .ok_or_else(not_found)?;
+    let revision = published_revision(db, &article).await?.ok_or_else(not_found)?;
+
+    Ok((article, revision))
+}
+
+// Published variants of the article `article` belongs to, as (id, locale).
+async fn published_variants<C: ConnectionTrait>(
+    db: &C,
+    article: &knowledge_base::Model,
+) -> Result<Vec<(Uuid, String)>, AppError> {
+    let variants = KBEntity::find_in(article.tenant_id)
+        .filter(variants_of(translation_group(article)))
+        .filter(knowledge_base::Column::PublishedRevisionId.is_not_null())
+        .filter(knowledge_base::Column::Status.ne("archived"))
+        .order_by_asc(knowledge_base::Column::Locale)
+        .all(db)
+        .await?;
+    Ok(variants.into_iter().map(|a| (a.id, a.locale)).collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/help/{org}/articles",
+    params(HelpArticleQuery),
+    responses(
+        (status = 200, description = "Published articles", body = [HelpArticleSummary]),
+        (status = 304, description = "Not modified"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Help Center",
+    security(())
+)]
+pub async fn list_help_articles(
+    Path(org): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<HelpArticleQuery>,
+) -> Result<Response, AppError> {
+    let db = state.db.as_ref();
+    let requested = locale::requested(&headers, query.locale.as_deref());
+    let org = find_organization(db, &org, &requested).await?;
+    let pairs = published_pairs(db, org.id, query.q.as_deref(), None).await?;
+
+    let last_modified = pairs.iter().map(|(_, rev)| rev.created_at).max();
+    let summaries: Vec<HelpArticleSummary> = pick_variants(pairs, &requested)
+        .into_iter()
+        .filter(|(_, rev)| query.category.as_deref().is_none_or(|c| in_category(&rev.category, c)))
+        .map(|(article, rev)| HelpArticleSummary {
+            id: article.id,
+            title: rev.title,
+            category: rev.category,
+            locale: article.locale,
+        })
+        .collect();
+
+    Ok(locale::content_language(cached_json(&headers, &summaries, last_modified), None))
+}
+
+#[utoipa::path(
+    get,
+    path = "/help/{org}/articles/{id}",
+    params(LocaleQuery),
+    responses(
+        (status = 200, description = "Published article", body = HelpArticle),
+        (status = 304, description = "Not modified"),
+        (status = 404, description = "Article not found"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Help Center",
+    security(())
+)]
+pub async fn get_help_article(
+    Path((org, id)): Path<(String, Uuid)>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<LocaleQuery>,
+) -> Result<Response, AppError> {
+    let db = state.db.as_ref();
+    let requested = locale::requested(&headers, query.locale.as_deref());
+    let org = find_organization(db, &org, &requested).await?;
+    let (mut article, mut revision) = find_published(db, org.id, id, &requested).await?;
+
+    // Links may point at any variant; serve the one in the reader's language
+    // if there is one, and the variant asked for otherwise.
+    let variants = published_variants(db, &article).await?;
+    let locales: Vec<&str> = variants.iter().map(|(_, l)| l.as_str()).collect();
+    if let Some(wanted) = locale::negotiate(&requested, &locales)
+        && wanted != article.locale
+        && let Some((variant_id, _)) = variants.iter().find(|(_, l)| l == wanted)
+    {
+        (article, revision) = find_published(db, org.id, *variant_id, &requested).await?;
+    }
+    let translations: Vec<String> = variants.iter().map(|(_, l)| l.clone()).collect();
+
+    KBEntity::update_many_in(org.id)
+        .col_expr(knowledge_base::Column::ViewCount, Expr::col(knowledge_base::Column::ViewCount).add(1))
+        .filter(knowledge_base::Column::Id.eq(article.id))
+        .exec(db)
+        .await?;
+
+    let body = HelpArticle {
+        id: article.id,
+        title: revision.title,
+        content: revision.content,
+        category: revision.category,
+        locale: article.locale.clone(),
+        translations,
+        helpful_votes: article.helpful_votes,
+        unhelpful_votes: article.unhelpful_votes,
+        updated_at: revision.created_at,
+    };
+
+    Ok(locale::content_language(
+        cached_json(&headers, &body, Some(revision.created_at)),
+        Some(&article.locale),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/help/{org}/categories",
+    params(LocaleQuery),
+    responses(
+        (status = 200, description = "Category tree of published articles", body = [CategoryNode]),
+        (status = 304, description = "Not modified"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Help Center",
+    security(())
+)]
+pub async fn get_help_categories(
+    Path(org): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<LocaleQuery>,
+) -> Result<Response, AppError> {
+    let db = state.db.as_ref();
+    let requested = locale::requested(&headers, query.locale.as_deref());
+    let org = find_organization(db, &org, &requested).await?;
+    let pairs = published_pairs(db, org.id, None, None).await?;
+
+    let last_modified = pairs.iter().map(|(_, rev)| rev.created_at).max();
+    let categories: Vec<String> = pick_variants(pairs, &requested)
+        .into_iter()
+        .map(|(_, rev)| rev.category)
+        .collect();
+
+    Ok(locale::content_language(
+        cached_json(&headers, &build_category_tree(&categories), last_modified),
+        None,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/help/{org}/articles/{id}/related",
+    responses(
+        (status = 200, description = "Published articles similar to this one", body = [HelpArticleSummary]),
+        (status = 404, description = "Article not found"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Help Center",
+    security(())
+)]
+pub async fn get_related_articles(
+    Path((org, id)): Path<(String, Uuid)>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let db = state.db.as_ref();
+    let requested = locale::requested(&headers, None);
+    let org = find_organization(db, &org, &requested).await?;
+    let (article, revision) = find_published(db, org.id, id, &requested).await?;
+
+    // Related articles are looked for among those in the same language.
+    let query = format!("{} {} {}", revision.title, revision.category, revision.content);
+    let ranked: Vec<Uuid> = kb_index::read(&state.kb_index)
+        .search(org.id, &article.locale, &query, 6)
+        .into_iter()
+        .map(|(article_id, _)| article_id)
+        .filter(|article_id| *article_id != id)
+        .take(5)
+        .collect();
+
+    let pairs = published_pairs(db, org.id, None, None).await?;
+    let related: Vec<HelpArticleSummary> = ranked
+        .iter()
+        .filter_map(|article_id| pairs.iter().find(|(a, _)| a.id == *article_id))
+        .map(|(related, rev)| HelpArticleSummary {
+            id: related.id,
+            title: rev.title.clone(),
+            category: rev.category.clone(),
+            locale: related.locale.clone(),
+        })
+        .collect();
+
+    Ok(locale::content_language(
+        cached_json(&headers, &related, Some(revision.created_at)),
+        Some(&article.locale),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/help/{org}/articles/{id}/vote",
+    request_body = VoteInput,
+    responses(
+        (status = 204, description = "Vote recorded"),
+        (status = 404, description = "Article not found"),
+        (status = 429, description = "Too many requests")
+    ),
+    tag = "Help Center",
+    security(())
+)]
+pub async fn vote_help_article(
+    Path((org, id)): Path<(String, Uuid)>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(input): Json<VoteInput>,
+) -> Result<StatusCode, AppError> {
+    let db = state.db.as_ref();
+    let requested = locale::requested(&headers, None);
+    let org = find_organization(db, &org, &requested).await?;
+    let (article, _) = find_published(db, org.id, id, &requested).await?;
+
+    let column = if input.helpful {
+        knowledge_base::Column::HelpfulVotes
+    } else {
+        knowledge_base::Column::UnhelpfulVotes
+    };
+    KBEntity::update_many_in(org.id)
+        .col_expr(column, Expr::col(column).add(1))
+        .filter(knowledge_base::Column::Id.eq(article.id))
+        .exec(db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+//----------suggestions----------------
+const SUGGESTION_MESSAGES: u64 = 3;
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct SuggestionQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SuggestedArticle {
+    pub article_id: Uuid,
+    pub title: String,
+    pub category: String,
+    pub score: f64,
+    pub rank: i32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SuggestionFeedbackInput {
+    pub action: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SuggestionQualityResponse {
+    pub impressions: u64,
+    pub linked: u64,
+    pub sent: u64,
+    pub acceptance_rate: f64,
+    pub mean_reciprocal_rank: f64,
+}
+
+// Published articles ranked against the ticket's title, description and the
+// customer's latest messages. Titles and categories are those of the published
+// revision, the text that was indexed.
+async fn rank_suggestions(
+    state: &AppState,
+    auth: &AuthUser,
+    ticket_id: &str,
+    limit: Option<usize>,
+) -> Result<(tickets::Model, Vec<SuggestedArticle>), AppError> {
+    require_staff(auth)?;
+    let id = Uuid::parse_str(ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = state.db.as_ref();
+    let ticket = TicketEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_read_or_edit_ticket(auth, &ticket)?;
+
+    let latest_messages = CommunicationEntity::find_in(auth.tenant_id)
+        .filter(communications::Column::TicketId.eq(ticket.id))
+        .filter(communications::Column::SenderType.eq("customer"))
+        .filter(communications::Column::IsInternal.eq(false))
+        .order_by_desc(communications::Column::Timestamp)
+        .limit(SUGGESTION_MESSAGES)
+        .all(db)
+        .await?;
+
+    let mut text = format!("{} {}", ticket.title, ticket.description);
+    for message in latest_messages {
+        text.push(' ');
+        text.push_str(&message.message);
+    }
+
+    // Articles in the customer's language first; the default-language ones
+    // when nothing has been written in theirs.
+    let customer_locale = customer_locale(db, &ticket).await?;
+    let limit = limit.unwrap_or(5).clamp(1, 20);
+    let ranked = {
+        let index = kb_index::read(&state.kb_index);
+        let ranked = index.search(auth.tenant_id, &customer_locale, &text, limit);
+        if ranked.is_empty() {
+            index.search(auth.tenant_id, locale::DEFAULT_LOCALE, &text, limit)
+        } else {
+            ranked
+        }
+    };
+    if ranked.is_empty() {
+        return Ok((ticket, Vec::new()));
+    }
+
+    let ids: Vec<Uuid> = ranked.iter().map(|(id, _)| *id).collect();
+    let published = published_pairs(db, auth.tenant_id, None, None)
+        .await?
+        .into_iter()
+        .filter(|(article, _)| ids.contains(&article.id))
+        .collect::<Vec<_>>();
+
+    let mut suggestions = Vec::new();
+    for (article_id, score) in ranked {
+        let Some((_, revision)) = published.iter().find(|(a, _)| a.id == article_id) else {
+            continue;
+        };
+        suggestions.push(SuggestedArticle {
+            article_id,
+            title: revision.title.clone(),
+            category: revision.category.clone(),
+            score,
+            rank: suggestions.len() as i32 + 1,
+        });
+    }
+
+    Ok((ticket, suggestions))
+}
+
+// READ suggestions for a ticket
+#[utoipa::path(
+    get,
+    path = "/tickets/{id}/suggested-articles",
+    params(SuggestionQuery),
+    responses(
+        (status = 200, description = "Published articles ranked by relevance to the ticket", body = [SuggestedArticle]),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn get_suggested_articles(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<SuggestionQuery>,
+) -> Result<Json<Vec<SuggestedArticle>>, AppError> {
+    let (_, suggestions) = rank_suggestions(&state, &auth, &ticket_id, query.limit).await?;
+    Ok(Json(suggestions))
+}
+
+// SHOW suggestions for a ticket. Same ranking as the GET, and each suggestion
+// is stored as an impression; call this when the list is put in front of the
+// agent so link and send rates in /analytics/suggestions are per rank shown.
+#[utoipa::path(
+    post,
+    path = "/tickets/{id}/suggested-articles",
+    params(SuggestionQuery),
+    responses(
+        (status = 200, description = "Suggestions shown, with their impressions recorded", body = [SuggestedArticle]),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn record_suggested_articles(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<SuggestionQuery>,
+) -> Result<Json<Vec<SuggestedArticle>>, AppError> {
+    let agent_id = auth_uuid(&auth)?;
+    let (ticket, suggestions) = rank_suggestions(&state, &auth, &ticket_id, query.limit).await?;
+
+    let now = Utc::now();
+    let impressions: Vec<article_suggestions::ActiveModel> = suggestions
+        .iter()
+        .map(|s| article_suggestions::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            tenant_id: Set(auth.tenant_id),
+            ticket_id: Set(ticket.id),
+            article_id: Set(s.article_id),
+            agent_id: Set(agent_id),
+            rank: Set(s.rank),
+            score: Set(s.score),
+            action: Set(None),
+            shown_at: Set(now),
+            acted_at: Set(None),
+        })
+        .collect();
+    if !impressions.is_empty() {
+        ArticleSuggestionEntity::insert_many(impressions).exec(state.db.as_ref()).await?;
+    }
+
+    Ok(Json(suggestions))
+}
+
+// FEEDBACK
+#[utoipa::path(
+    post,
+    path = "/tickets/{id}/suggested-articles/{article_id}/feedback",
+    request_body = SuggestionFeedbackInput,
+    responses(
+        (status = 204, description = "Suggestion marked as used"),
+        (status = 400, description = "Unknown action"),
+        (status = 404, description = "Article was never suggested for this ticket")
+    ),
+    tag = "Knowledge"
+)]
+pub async fn record_suggestion_feedback(
+    Path((ticket_id, article_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<SuggestionFeedbackInput>,
+) -> Result<StatusCode, AppError> {
+    require_staff(&auth)?;
+    if input.action != "linked" && input.action != "sent" {
+        return Err(AppError::BadRequest("Action must be linked or sent".into()));
+    }
+
+    let db = state.db.as_ref();
+    let shown = ArticleSuggestionEntity::find_in(auth.tenant_id)
+        .filter(article_suggestions::Column::TicketId.eq(ticket_id))
+        .filter(article_suggestions::Column::ArticleId.eq(article_id))
+        .order_by_desc(article_suggestions::Column::ShownAt)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Article was not suggested for this ticket".into()))?;
+
+    let mut active = shown.into_active_model();
+    active.action = Set(Some(input.action));
+    active.acted_at = Set(Some(Utc::now()));
+    active.update(db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// QUALITY
+#[utoipa::path(
+    get,
+    path = "/analytics/suggestions",
+    responses(
+        (status = 200, description = "How often suggested articles are used", body = SuggestionQualityResponse),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Analytics"
+)]
+pub async fn get_suggestion_quality(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<SuggestionQualityResponse>, AppError> {
+    require_role(&auth, "admin")?;
+
+    let db = state.db.as_ref();
+    let impressions = ArticleSuggestionEntity::find_in(auth.tenant_id).count(db).await?;
+    let used = ArticleSuggestionEntity::find_in(auth.tenant_id)
+        .filter(article_suggestions::Column::Action.is_not_null())
+        .all(db)
+        .await?;
+
+    let linked = used.iter().filter(|s| s.action.as_deref() == Some("linked")).count() as u64;
+    let sent = used.len() as u64 - linked;
+    let reciprocal_sum: f64 = used.iter().map(|s| 1.0 / s.rank as f64).sum();
+
+    Ok(Json(SuggestionQualityResponse {
+        impressions,
+        linked,
+        sent,
+        acceptance_rate: if impressions > 0 { used.len() as f64 / impressions as f64 } else { 0.0 },
+        mean_reciprocal_rank: if used.is_empty() { 0.0 } else { reciprocal_sum / used.len() as f64 },
+    }))
+}
+
+
+//----------tag----------------
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTagInput {
+    pub name: String,
+    pub colour: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TagResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub colour: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TicketTagInput {
+    pub tag_id: Uuid,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MergeTagInput {
+    pub into_tag_id: Uuid,
+}
+
+#[derive(Serialize, ToSchema, FromQueryResult)]
+pub struct TagUsageResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub ticket_count: i64,
+}
+
+impl From<tags::Model> for TagResponse {
+    fn from(tag: tags::Model) -> Self {
+        TagResponse {
+            id: tag.id,
+            name: tag.name,
+            colour: tag.colour,
+            description: tag.description,
+        }
+    }
+}
+
+fn require_staff(auth: &AuthUser) -> Result<(), AppError> {
+    if !auth.is_admin() && auth.role != "agent" {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+fn validate_tag_input(input: &CreateTagInput) -> Result<String, AppError> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Tag name is required".into()));
+    }
+    if let Some(colour) = &input.colour {
+        let hex = colour.strip_prefix('#').unwrap_or("");
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AppError::BadRequest("Colour must look like #RRGGBB".into()));
+        }
+    }
+    Ok(name.to_string())
+}
+
+// Case-insensitive lookup, so "Billing" and "billing" are the same tag.
+async fn find_tag_by_name<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    name: &str,
+) -> Result<Option<tags::Model>, AppError> {
+    let tag = TagEntity::find_in(tenant_id)
+        .filter(Expr::expr(Func::lower(Expr::col(tags::Column::Name))).eq(name.to_lowercase()))
+        .one(db)
+        .await?;
+    Ok(tag)
+}
+
+// CREATE
+#[utoipa::path(
+    post,
+    path = "/tags",
+    request_body = CreateTagInput,
+    responses(
+        (status = 201, description = "Tag added to catalog", body = TagResponse),
+        (status = 400, description = "Invalid tag or name already in use"),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Tag"
+)]
+pub async fn create_tag(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateTagInput>,
+) -> Result<(StatusCode, Json<TagResponse>), AppError> {
+    require_staff(&auth)?;
+    let name = validate_tag_input(&input)?;
+
+    let db = state.db.as_ref();
+    if find_tag_by_name(db, auth.tenant_id, &name).await?.is_some() {
+        return Err(AppError::BadRequest("A tag with this name already exists".into()));
+    }
+
+    let tag = tags::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        name: Set(name),
+        colour: Set(input.colour),
+        description: Set(input.description),
+        created_at: Set(Utc::now()),
+    };
+
+    let saved = tag.insert(db).await.map_err(|e| {
+        tracing::error!(error = %e, "Insert error");
+        AppError::Internal("Could not create tag".into())
+    })?;
+
+    Ok((StatusCode::CREATED, Json(TagResponse::from(saved))))
+}
+
+// READ catalog
+#[utoipa::path(
+    get,
+    path = "/tags",
+    responses(
+        (status = 200, description = "Tag catalog", body = [TagResponse])
+    ),
+    tag = "Tag"
+)]
+pub async fn get_tags(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<TagResponse>>, AppError> {
+    let all_tags = TagEntity::find_in(auth.tenant_id)
+        .order_by_asc(tags::Column::Name)
+        .all(state.db.as_ref())
+        .await?;
+
+    Ok(Json(all_tags.into_iter().map(TagResponse::from).collect()))
+}
+
+// USAGE
+#[utoipa::path(
+    get,
+    path = "/tags/stats",
+    responses(
+        (status = 200, description = "Number of tickets carrying each tag", body = [TagUsageResponse])
+    ),
+    tag = "Tag"
+)]
+pub async fn get_tag_stats(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<TagUsageResponse>>, AppError> {
+    require_staff(&auth)?;
+
+    let stats = TagEntity::find_in(auth.tenant_id)
+        .select_only()
+        .column(tags::Column::Id)
+        .column(tags::Column::Name)
+        .column_as(ticket_tags::Column::TicketId.count(), "ticket_count")
+        .join(JoinType::LeftJoin, tags::Relation::TicketTags.def())
+        .group_by(tags::Column::Id)
+        .group_by(tags::Column::Name)
+        .order_by_desc(Expr::col(Alias::new("ticket_count")))
+        .into_model::<TagUsageResponse>()
+        .all(state.db.as_ref())
+        .await?;
+
+    Ok(Json(stats))
+}
+
+// RENAME / UPDATE
+#[utoipa::path(
+    put,
+    path = "/tags/{id}",
+    request_body = CreateTagInput,
+    responses(
+        (status = 200, description = "Tag updated on every ticket", body = TagResponse),
+        (status = 400, description = "Invalid tag or name already in use"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Tag not found")
+    ),
+    tag = "Tag"
+)]
+pub async fn update_tag(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateTagInput>,
+) -> Result<Json<TagResponse>, AppError> {
+    require_staff(&auth)?;
+    let name = validate_tag_input(&input)?;
+
+    let db = state.db.as_ref();
+    let tag = TagEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Tag not found".into()))?;
+
+    if let Some(other) = find_tag_by_name(db, auth.tenant_id, &name).await?
+        && other.id != tag.id
+    {
+        return Err(AppError::BadRequest("A tag with this name already exists".into()));
+    }
+
+    let mut active = tag.into_active_model();
+    active.name = Set(name);
+    active.colour = Set(input.colour);
+    active.description = Set(input.description);
+    let updated = active.update(db).await?;
+
+    Ok(Json(TagResponse::from(updated)))
+}
+
+// MERGE
+#[utoipa::path(
+    post,
+    path = "/tags/{id}/merge",
+    request_body = MergeTagInput,
+    responses(
+        (status = 200, description = "Tag merged; its tickets now carry the target tag", body = TagResponse),
+        (status = 400, description = "Invalid merge request"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Tag not found")
+    ),
+    tag = "Tag"
+)]
+pub async fn merge_tags(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<MergeTagInput>,
+) -> Result<Json<TagResponse>, AppError> {
+    require_staff(&auth)?;
+    if id == input.into_tag_id {
+        return Err(AppError::BadRequest("A tag cannot be merged into itself".into()));
+    }
+
+    let txn = state.db.begin().await?;
+
+    let source = TagEntity::find_by_id_in(id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Tag not found".into()))?;
+    let target = TagEntity::find_by_id_in(input.into_tag_id, auth.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Target tag not found".into()))?;
+
+    move_ticket_tags(&txn, ticket_tags::Column::TagId, source.id, target.id).await?;
+    source.delete(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(Json(TagResponse::from(target)))
+}
+
+// Re-points join rows from `from` to `to` on the given column, dropping rows
+// that would duplicate an existing (ticket, tag) pair.
+async fn move_ticket_tags<C: ConnectionTrait>(
+    db: &C,
+    column: ticket_tags::Column,
+    from: Uuid,
+    to: Uuid,
+) -> Result<(), AppError> {
+    let rows = TicketTagEntity::find()
+        .filter(column.eq(from))
+        .all(db)
+        .await?;
+
+    for row in rows {
+        let (ticket_id, tag_id) = match column {
+            ticket_tags::Column::TicketId => (to, row.tag_id),
+            _ => (row.ticket_id, to),
+        };
+        let exists = TicketTagEntity::find_by_id((ticket_id, tag_id))
+            .one(db)
+            .await?
+            .is_some();
+
+        TicketTagEntity::delete_by_id((row.ticket_id, row.tag_id))
+            .exec(db)
+            .await?;
+        if !exists {
+            ticket_tags::ActiveModel {
+                ticket_id: Set(ticket_id),
+                tag_id: Set(tag_id),
+                created_at: Set(row.created_at),
+            }
+            .insert(db)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// DELETE
+#[utoipa::path(
+    delete,
+    path = "/tags/{id}",
+    params(
+        ("id" = String, Path, description = "UUID of the tag")
+    ),
+    responses(
+        (status = 204, description = "Tag removed from catalog and all tickets"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Tag not found")
+    ),
+    tag = "Tag"
+)]
+pub async fn delete_tag(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    require_staff(&auth)?;
+
+    let result = TagEntity::delete_many_in(auth.tenant_id)
+        .filter(tags::Column::Id.eq(id))
+        .exec(state.db.as_ref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Delete error");
+            AppError::Internal("Could not delete tag".into())
+        })?;
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound("Tag not found".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// READ tags of a ticket
+#[utoipa::path(
+    get,
+    path = "/tickets/{id}/tags",
+    params(
+        ("id" = String, Path, description = "UUID of the ticket")
+    ),
+    responses(
+        (status = 200, description = "List of tags for a ticket", body = [TagResponse]),
+        (status = 400, description = "Invalid ticket ID"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Tag"
+)]
+pub async fn get_tags_by_id(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<TagResponse>>, AppError> {
+    let ticket_uuid = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = state.db.as_ref();
+    let ticket = TicketEntity::find_by_id_in(ticket_uuid, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_read_ticket(db, &auth, &ticket).await?;
+
+    let all_tags = ticket
+        .find_related(TagEntity)
+        .order_by_asc(tags::Column::Name)
+        .all(db)
+        .await?;
+
+    Ok(Json(all_tags.into_iter().map(TagResponse::from).collect()))
+}
+
+// TAG a ticket
+#[utoipa::path(
+    post,
+    path = "/tickets/{id}/tags",
+    request_body = TicketTagInput,
+    responses(
+        (status = 201, description = "Tag attached to ticket", body = TagResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket or tag not found")
+    ),
+    tag = "Tag"
+)]
+pub async fn add_ticket_tag(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<TicketTagInput>,
+) -> Result<(StatusCode, Json<TagResponse>), AppError> {
+    let ticket_uuid = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = state.db.as_ref();
+    let ticket = TicketEntity::find_by_id_in(ticket_uuid, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_edit_ticket(&auth, &ticket)?;
+
+    let tag = TagEntity::find_by_id_in(input.tag_id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Tag not found".into()))?;
+
+    let exists = TicketTagEntity::find_by_id((ticket.id, tag.id))
+        .one(db)
+        .await?
+        .is_some();
+    if !exists {
+        ticket_tags::ActiveModel {
+            ticket_id: Set(ticket.id),
+            tag_id: Set(tag.id),
+            created_at: Set(Utc::now()),
+        }
+        .insert(db)
+        .await?;
+        record_ticket_event(db, &auth, ticket.id, "tag_added", None, Some(tag.name.clone())).await?;
+    }
+
+    Ok((StatusCode::CREATED, Json(TagResponse::from(tag))))
+}
+
+// UNTAG a ticket
+#[utoipa::path(
+    delete,
+    path = "/tickets/{id}/tags/{tag_id}",
+    responses(
+        (status = 204, description = "Tag removed from ticket"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Tag"
+)]
+pub async fn remove_ticket_tag(
+    Path((ticket_id, tag_id)): Path<(String, Uuid)>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<StatusCode, AppError> {
+    let ticket_uuid = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = state.db.as_ref();
+    let ticket = TicketEntity::find_by_id_in(ticket_uuid, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_edit_ticket(&auth, &ticket)?;
+
+    let removed = TicketTagEntity::delete_by_id((ticket.id, tag_id))
+        .exec(db)
+        .await?;
+
+    if removed.rows_affected > 0 {
+        let name = TagEntity::find_by_id_in(tag_id, auth.tenant_id).one(db).await?.map(|t| t.name);
+        record_ticket_event(db, &auth, ticket.id, "tag_removed", name, None).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+//----------analytics----------------
+#[derive(Deserialize, ToSchema)]
+pub struct CreateAnalyticsInput {
+    pub date: NaiveDate,
+    pub total_tickets: i32,
+    pub resolved_tickets: i32,
+    pub avg_response_time: i64,
+    pub agent_id: Uuid,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AnalyticsResponse {
+    pub id: Uuid,
+    pub date: NaiveDate,
+    pub total_tickets: i32,
+    pub resolved_tickets: i32,
+    pub avg_response_time: i64,
+    pub agent_id: Uuid,
+    pub csat_responses: i32,
+    pub csat_average: Option<f64>,
+}
+
+impl From<analytics::Model> for AnalyticsResponse {
+    fn from(a: analytics::Model) -> Self {
+        AnalyticsResponse {
+            id: a.id,
+            date: a.date,
+            total_tickets: a.total_tickets,
+            resolved_tickets: a.resolved_tickets,
+            avg_response_time: a.avg_response_time,
+            agent_id: a.agent_id,
+            csat_responses: a.csat_responses,
+            csat_average: (a.csat_responses > 0)
+                .then(|| a.csat_score_total as f64 / a.csat_responses as f64),
+        }
+    }
+}
+
+// CREATE
+#[utoipa::path(
+    post,
+    path = "/analytics",
+    request_body = CreateAnalyticsInput,
+    responses(
+        (status = 201, description = "Analytics entry created", body = AnalyticsResponse)
+    ),
+    tag = "Analytics"
+)]
+pub async fn create_analytics(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(input): Json<CreateAnalyticsInput>,
+) -> Result<Json<AnalyticsResponse>, (StatusCode, String)> {
+    if !auth.is_admin() {
+        return Err((StatusCode::FORBIDDEN, "Access denied".into()));
+    }
+    let db = &state.db;
+
+    let analytics = analytics::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        date: Set(input.date),
+        total_tickets: Set(input.total_tickets),
+        resolved_tickets: Set(input.resolved_tickets),
+        avg_response_time: Set(input.avg_response_time),
+        agent_id: Set(input.agent_id),
+        csat_responses: Set(0),
+        csat_score_total: Set(0),
+    };
+
+    let saved = analytics.insert(db.as_ref()).await.map_err(|e| {
+        if let Some(SqlErr::UniqueConstraintViolation(_)) = e.sql_err() {
+            return (StatusCode::CONFLICT, "The agent already has an entry for this date".into());
+        }
+        tracing::error!(error = %e, "Error creating analytics");
+        (StatusCode::INTERNAL_SERVER_ERROR, "Insert failed".into())
+    })?;
+
+    Ok(Json(AnalyticsResponse::from(saved)))
+}
+
+// READ ALL
+#[utoipa::path(
+    get,
+    path = "/analytics",
+    params(Pagination),
+    responses(
+        (status = 200, description = "All analytics data", body = [AnalyticsResponse])
+    ),
+    tag = "Analytics"
+)]
+pub async fn get_analytics(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<AnalyticsResponse>>, AppError> {
+    let db = &state.db;
+
+    let list = AnalyticsEntity::find_in(auth.tenant_id)
+        .limit(pagination.limit.unwrap_or(10))
+        .offset(pagination.offset.unwrap_or(0))
+        .all(db.as_ref())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Fetch error");
+        AppError::Internal("Could not fetch analytics".into())
+    })?;
+
+    let response = list.into_iter().map(AnalyticsResponse::from).collect();
+
+    Ok(Json(response))
+}
+//READ by ID
+#[utoipa::path(
+    get,
+    path = "/analytics/{id}",
+    responses(
+        (status = 200, description = "Analytics by ID", body = AnalyticsResponse),
+        (status = 404, description = "Not found")
+    ),
+    tag = "Analytics"
+)]
+#[debug_handler]
+pub async fn get_analytics_by_id(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<AnalyticsResponse>, AppError> {
+    require_role(&auth, "admin")?;
+
+    let uuid = Uuid::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid ID".into()))?;
+    let db = &state.db;
+    let found = analytics::Entity::find_by_id_in(uuid, auth.tenant_id)
+        .one(db.as_ref())
+        .await
+        .map_err(|_| AppError::Db(()))?
+        .ok_or(AppError::NotFound("Not found".into()))?;
+
+    Ok(Json(AnalyticsResponse::from(found)))
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct PerformanceQuery {
+    // First and last day of the period, both included (UTC).
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub group_by: GroupBy,
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PerformanceReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub group_by: GroupBy,
+    pub rows: Vec<PerformanceRow>,
+    pub total: PerformanceRow,
+}
+
+// Tickets received, solved and reopened, response and resolution time
+// percentiles, end-of-period backlog and SLA compliance, per group. SLA
+// targets come from the [sla] config section, per priority.
+#[utoipa::path(
+    get,
+    path = "/analytics/performance",
+    params(PerformanceQuery),
+    responses(
+        (status = 200, description = "Performance report, as JSON or CSV", body = PerformanceReport),
+        (status = 400, description = "Invalid date range")
+    ),
+    tag = "Analytics"
+)]
+pub async fn get_performance_report(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<PerformanceQuery>,
+) -> Result<Response, AppError> {
+    require_role(&auth, "admin")?;
+    if query.from > query.to {
+        return Err(AppError::BadRequest("from must not be after to".into()));
+    }
+    let start = query.from.and_hms_opt(0, 0, 0).map(|d| d.and_utc());
+    let end = query
+        .to
+        .succ_opt()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc());
+    let (Some(start), Some(end)) = (start, end) else {
+        return Err(AppError::BadRequest("Date out of range".into()));
+    };
+
+    let sla = &state.config.sla;
+    let statement = performance_statement(auth.tenant_id, start, end, Some(query.group_by), sla);
+
+    if let ReportFormat::Csv = query.format {
+        let filename = format!("performance-{}-{}.csv", query.from, query.to);
+        return Ok(download(&filename, Format::Csv.content_type(), reports::csv_body(state.db.clone(), statement)));
     }
 
-    if let Some(channel) = params.channel {
-        condition = condition.add(tickets::Column::Channel.eq(channel));
+    let rows = PerformanceRow::find_by_statement(statement).all(state.db.as_ref()).await?;
+    let total = PerformanceRow::find_by_statement(performance_statement(auth.tenant_id, start, end, None, sla))
+        .one(state.db.as_ref())
+        .await?
+        .unwrap_or_default();
+
+    Ok(Json(PerformanceReport {
+        from: query.from,
+        to: query.to,
+        group_by: query.group_by,
+        rows,
+        total,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct ForecastQuery {
+    // Weeks to forecast, 1 to 12 (default 4).
+    pub weeks: Option<usize>,
+    // Weeks of history to fit on, 2 to 52 (default 12).
+    pub history_weeks: Option<usize>,
+    // Forecast one channel only. By default every channel is forecast, and
+    // all of them together.
+    pub channel: Option<String>,
+    // Override the [staffing] config for this forecast.
+    pub handle_secs: Option<u64>,
+    pub service_level: Option<f64>,
+    pub answer_secs: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StaffingTarget {
+    pub handle_secs: u64,
+    pub service_level: f64,
+    pub answer_secs: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DailyForecast {
+    pub date: NaiveDate,
+    pub forecast: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HourlyForecast {
+    pub hour: chrono::DateTime<Utc>,
+    pub forecast: f64,
+    pub lower: f64,
+    pub upper: f64,
+    // Agents to schedule for the forecast volume to meet the staffing target,
+    // at most 10 000.
+    pub agents: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChannelForecast {
+    // Null for all channels together.
+    pub channel: Option<String>,
+    pub history_tickets: u64,
+    pub daily: Vec<DailyForecast>,
+    pub hourly: Vec<HourlyForecast>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ForecastResponse {
+    // Days the model was fitted on, both included (UTC).
+    pub history_from: NaiveDate,
+    pub history_to: NaiveDate,
+    pub weeks: usize,
+    pub staffing: StaffingTarget,
+    pub series: Vec<ChannelForecast>,
+}
+
+// Forecasts a series of hourly arrivals by hour and by day, with 95%
+// intervals, and the agents each forecast hour needs.
+fn forecast_channel(
+    channel: Option<String>,
+    hourly: &[f64],
+    first_day: NaiveDate,
+    weeks: usize,
+    staffing: &StaffingTarget,
+) -> ChannelForecast {
+    let daily: Vec<f64> = hourly.chunks(24).map(|day| day.iter().sum()).collect();
+    let forecast_start = first_day + chrono::Days::new(daily.len() as u64);
+    let forecast_start_hour = forecast_start.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+
+    let daily_forecast = forecast::holt_winters(&daily, forecast::DAYS_PER_WEEK, weeks * forecast::DAYS_PER_WEEK)
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| DailyForecast {
+            date: forecast_start + chrono::Days::new(i as u64),
+            forecast: f.forecast,
+            lower: f.lower,
+            upper: f.upper,
+        })
+        .collect();
+
+    let hourly_forecast = forecast::holt_winters(hourly, forecast::HOURS_PER_WEEK, weeks * forecast::HOURS_PER_WEEK)
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| HourlyForecast {
+            hour: forecast_start_hour + chrono::Duration::hours(i as i64),
+            forecast: f.forecast,
+            lower: f.lower,
+            upper: f.upper,
+            agents: forecast::agents_needed(
+                f.forecast,
+                staffing.handle_secs as f64,
+                staffing.answer_secs as f64,
+                staffing.service_level,
+            ),
+        })
+        .collect();
+
+    ChannelForecast {
+        channel,
+        history_tickets: hourly.iter().sum::<f64>() as u64,
+        daily: daily_forecast,
+        hourly: hourly_forecast,
     }
+}
 
-    // Role-based visibility
-    if auth.role == "agent" {
-        condition = condition.add(tickets::Column::AssignedAgentId.eq(Uuid::parse_str(&auth.u_id).unwrap()));
-    } else if auth.role == "customer" {
-        condition = condition.add(tickets::Column::CustomerId.eq(Uuid::parse_str(&auth.u_id).unwrap()));
+// Ticket volume for the coming weeks, per channel, from the arrivals of the
+// past weeks (Holt-Winters with a weekly season), and the agents needed per
+// hour to pick up tickets within the staffing target (Erlang C on the point
+// forecast). History ends with yesterday and starts no earlier than the first
+// ticket, of which there must be at least two weeks.
+#[utoipa::path(
+    get,
+    path = "/analytics/forecast",
+    params(ForecastQuery),
+    responses(
+        (status = 200, description = "Volume forecast and staffing", body = ForecastResponse),
+        (status = 400, description = "Invalid parameters or not enough history")
+    ),
+    tag = "Analytics"
+)]
+pub async fn get_forecast(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<ForecastQuery>,
+) -> Result<Json<ForecastResponse>, AppError> {
+    require_role(&auth, "admin")?;
+
+    let weeks = query.weeks.unwrap_or(4);
+    let history_weeks = query.history_weeks.unwrap_or(12);
+    if !(1..=12).contains(&weeks) || !(2..=52).contains(&history_weeks) {
+        return Err(AppError::BadRequest("weeks must be 1 to 12 and history_weeks 2 to 52".into()));
+    }
+    let defaults = &state.config.staffing;
+    let staffing = StaffingTarget {
+        handle_secs: query.handle_secs.unwrap_or(defaults.handle_secs),
+        service_level: query.service_level.unwrap_or(defaults.service_level),
+        answer_secs: query.answer_secs.unwrap_or(defaults.answer_secs),
+    };
+    let secs = 1..=forecast::MAX_STAFFING_SECS;
+    if !secs.contains(&staffing.handle_secs) || !secs.contains(&staffing.answer_secs) {
+        return Err(AppError::BadRequest(format!(
+            "handle_secs and answer_secs must be 1 to {}",
+            forecast::MAX_STAFFING_SECS
+        )));
+    }
+    if !(staffing.service_level > 0.0 && staffing.service_level < 1.0) {
+        return Err(AppError::BadRequest("service_level must be between 0 and 1".into()));
+    }
+
+    let today = Utc::now().date_naive();
+    let earliest = today - chrono::Days::new(7 * history_weeks as u64);
+    let midnight = |day: NaiveDate| day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+
+    let mut arrivals = ArrivalRow::find_by_statement(arrivals_statement(auth.tenant_id, midnight(earliest), midnight(today)))
+        .all(state.db.as_ref())
+        .await?;
+    if let Some(channel) = &query.channel {
+        arrivals.retain(|a| &a.channel == channel);
+    }
+
+    // A newer organization has no tickets at the start of the window; fitting
+    // on those empty weeks would drag the forecast down.
+    let first_day = arrivals.iter().map(|a| a.hour.date()).min().map_or(today, |day| day.max(earliest));
+    let days = (today - first_day).num_days() as usize;
+    if days < 2 * forecast::DAYS_PER_WEEK {
+        return Err(AppError::BadRequest("At least two weeks of ticket history are needed".into()));
+    }
+
+    let mut by_channel: std::collections::BTreeMap<String, Vec<f64>> = std::collections::BTreeMap::new();
+    let mut all = vec![0.0; days * 24];
+    for a in &arrivals {
+        let Ok(i) = usize::try_from((a.hour.and_utc() - midnight(first_day)).num_hours()) else {
+            continue;
+        };
+        by_channel.entry(a.channel.clone()).or_insert_with(|| vec![0.0; days * 24])[i] += a.tickets as f64;
+        all[i] += a.tickets as f64;
+    }
+
+    // Fitting tries every smoothing parameter combination on up to a year of
+    // hours per channel; keep it off the request threads.
+    let (series, staffing) = tokio::task::spawn_blocking(move || {
+        let mut series = Vec::with_capacity(by_channel.len() + 1);
+        if query.channel.is_none() {
+            series.push(forecast_channel(None, &all, first_day, weeks, &staffing));
+        }
+        for (channel, hourly) in by_channel {
+            series.push(forecast_channel(Some(channel), &hourly, first_day, weeks, &staffing));
+        }
+        (series, staffing)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Forecast failed: {}", e)))?;
+
+    Ok(Json(ForecastResponse {
+        history_from: first_day,
+        history_to: today - chrono::Days::new(1),
+        weeks,
+        staffing,
+        series,
+    }))
+}
+
+//----------csat----------------
+const SURVEY_VALID_DAYS: i64 = 14;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SurveyAnswerInput {
+    pub rating: i16,
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SurveyResponse {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub agent_id: Option<Uuid>,
+    pub rating: Option<i16>,
+    pub comment: Option<String>,
+    pub expires_at: chrono::DateTime<Utc>,
+    pub responded_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl From<csat_surveys::Model> for SurveyResponse {
+    fn from(s: csat_surveys::Model) -> Self {
+        SurveyResponse {
+            id: s.id,
+            ticket_id: s.ticket_id,
+            agent_id: s.agent_id,
+            rating: s.rating,
+            comment: s.comment,
+            expires_at: s.expires_at,
+            responded_at: s.responded_at,
+        }
+    }
+}
+
+// Language customer-facing messages on the ticket are written in.
+async fn customer_locale<C: ConnectionTrait>(db: &C, ticket: &tickets::Model) -> Result<String, DbErr> {
+    let customer = CustomerEntity::find_by_id_with_trashed_in(ticket.customer_id, ticket.tenant_id)
+        .one(db)
+        .await?;
+    Ok(customer
+        .and_then(|c| c.preferred_locale)
+        .unwrap_or_else(|| locale::DEFAULT_LOCALE.to_string()))
+}
+
+// Issues a survey for a freshly resolved ticket and sends the customer the link
+// as a public message on the ticket.
+async fn issue_csat_survey<C: ConnectionTrait>(
+    db: &C,
+    jwt: &auth::JwtKeys,
+    ticket: &tickets::Model,
+    sender_id: Uuid,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let pending = CsatSurveyEntity::find_in(ticket.tenant_id)
+        .filter(csat_surveys::Column::TicketId.eq(ticket.id))
+        .filter(csat_surveys::Column::RespondedAt.is_null())
+        .filter(csat_surveys::Column::ExpiresAt.gt(now))
+        .one(db)
+        .await?;
+    if pending.is_some() {
+        return Ok(());
+    }
+
+    let expires_at = now + chrono::Duration::days(SURVEY_VALID_DAYS);
+    let survey = csat_surveys::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(ticket.tenant_id),
+        ticket_id: Set(ticket.id),
+        customer_id: Set(ticket.customer_id),
+        agent_id: Set(ticket.assigned_agent_id),
+        rating: Set(None),
+        comment: Set(None),
+        expires_at: Set(expires_at),
+        responded_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(db)
+    .await?;
+
+    let token = auth::generate_survey_token(jwt, &survey.id.to_string(), expires_at)?;
+    let link = format!("/surveys/{}", token);
+    let locale = customer_locale(db, ticket).await?;
+
+    communications::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(ticket.tenant_id),
+        ticket_id: Set(ticket.id),
+        sender_type: Set("agent".into()),
+        sender_id: Set(sender_id),
+        message: Set(Text::SurveyInvitation.render(&locale, &[("link", &link)])),
+        channel: Set(ticket.channel.clone()),
+        is_internal: Set(false),
+        is_system: Set(true),
+        external_id: Set(None),
+        timestamp: Set(now),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+// Adds an answer to the agent's row for the day in one statement, so answers
+// submitted at the same time are all counted.
+async fn record_csat_in_analytics<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    agent_id: Uuid,
+    date: NaiveDate,
+    rating: i16,
+) -> Result<(), AppError> {
+    let row = analytics::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(tenant_id),
+        date: Set(date),
+        total_tickets: Set(0),
+        resolved_tickets: Set(0),
+        avg_response_time: Set(0),
+        agent_id: Set(agent_id),
+        csat_responses: Set(1),
+        csat_score_total: Set(rating as i32),
+    };
+    AnalyticsEntity::insert(row)
+        .on_conflict(
+            OnConflict::columns([analytics::Column::TenantId, analytics::Column::AgentId, analytics::Column::Date])
+                .value(
+                    analytics::Column::CsatResponses,
+                    Expr::col((AnalyticsEntity, analytics::Column::CsatResponses)).add(1),
+                )
+                .value(
+                    analytics::Column::CsatScoreTotal,
+                    Expr::col((AnalyticsEntity, analytics::Column::CsatScoreTotal)).add(rating as i32),
+                )
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SurveyQuestion {
+    pub ticket_title: String,
+    pub question: String,
+    pub min_rating: i16,
+    pub max_rating: i16,
+    pub expires_at: chrono::DateTime<Utc>,
+    pub answered: bool,
+}
+
+// VIEW (no login). The link in the resolution message is opened in a browser,
+// so it answers GET with what the survey asks; answers are POSTed to the same
+// URL.
+#[utoipa::path(
+    get,
+    path = "/surveys/{token}",
+    params(
+        ("token" = String, Path, description = "Signed survey token from the resolution message"),
+        LocaleQuery
+    ),
+    responses(
+        (status = 200, description = "The survey question", body = SurveyQuestion),
+        (status = 400, description = "Expired or invalid token"),
+        (status = 404, description = "Survey not found")
+    ),
+    tag = "Survey",
+    security(())
+)]
+pub async fn get_survey(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<LocaleQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let requested = locale::requested(&headers, query.locale.as_deref());
+    let claims = auth::decode_survey_token(&state.jwt, &token, &requested)?;
+    let survey_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::BadRequest(Text::SurveyInvalidToken.pick(&requested)))?;
+
+    let db = state.db.as_ref();
+    // cross-tenant: the token is the credential, so the survey itself decides
+    // the tenant.
+    let survey = CsatSurveyEntity::find_by_id(survey_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Text::SurveyNotFound.pick(&requested)))?;
+    let ticket = TicketEntity::find_by_id_with_trashed_in(survey.ticket_id, survey.tenant_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Text::SurveyNotFound.pick(&requested)))?;
+
+    // The customer's own language unless the browser asks for another.
+    let language = match locale::negotiate(&requested, &locale::TRANSLATED) {
+        Some(language) => language.to_string(),
+        None => customer_locale(db, &ticket).await?,
+    };
+
+    let body = Json(SurveyQuestion {
+        ticket_title: ticket.title,
+        question: Text::SurveyQuestion.get(&language).to_string(),
+        min_rating: 1,
+        max_rating: 5,
+        expires_at: survey.expires_at,
+        answered: survey.responded_at.is_some(),
+    });
+    Ok(locale::content_language(body.into_response(), Some(locale::language(&language))))
+}
+
+// ANSWER (no login, the signed token is the credential)
+#[utoipa::path(
+    post,
+    path = "/surveys/{token}",
+    request_body = SurveyAnswerInput,
+    params(
+        ("token" = String, Path, description = "Signed survey token from the resolution message")
+    ),
+    responses(
+        (status = 200, description = "Survey answered", body = SurveyResponse),
+        (status = 400, description = "Invalid rating, expired or already used token"),
+        (status = 404, description = "Survey not found")
+    ),
+    tag = "Survey",
+    security(())
+)]
+pub async fn submit_survey(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(input): Json<SurveyAnswerInput>,
+) -> Result<Json<SurveyResponse>, AppError> {
+    let requested = locale::requested(&headers, None);
+    if !(1..=5).contains(&input.rating) {
+        return Err(AppError::BadRequest(Text::SurveyInvalidRating.pick(&requested)));
+    }
+
+    let claims = auth::decode_survey_token(&state.jwt, &token, &requested)?;
+    let survey_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::BadRequest(Text::SurveyInvalidToken.pick(&requested)))?;
+
+    let txn = state.db.begin().await?;
+
+    // cross-tenant: the token is the credential, so the survey itself decides
+    // the tenant.
+    let survey = CsatSurveyEntity::find_by_id(survey_id)
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Text::SurveyNotFound.pick(&requested)))?;
+
+    let now = Utc::now();
+    if survey.expires_at <= now {
+        return Err(AppError::BadRequest(Text::SurveyExpired.pick(&requested)));
+    }
+
+    // Guarded update so two concurrent submissions cannot both succeed.
+    let claimed = CsatSurveyEntity::update_many_in(survey.tenant_id)
+        .col_expr(csat_surveys::Column::Rating, Expr::value(input.rating))
+        .col_expr(csat_surveys::Column::Comment, Expr::value(input.comment.clone()))
+        .col_expr(csat_surveys::Column::RespondedAt, Expr::value(now))
+        .filter(csat_surveys::Column::Id.eq(survey.id))
+        .filter(csat_surveys::Column::RespondedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if claimed.rows_affected == 0 {
+        return Err(AppError::BadRequest(Text::SurveyAnswered.pick(&requested)));
+    }
+
+    if let Some(agent_id) = survey.agent_id {
+        record_csat_in_analytics(&txn, survey.tenant_id, agent_id, now.date_naive(), input.rating).await?;
+    }
+
+    let answered = CsatSurveyEntity::find_by_id_in(survey.id, survey.tenant_id)
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Text::SurveyNotFound.pick(&requested)))?;
+
+    txn.commit().await?;
+
+    Ok(Json(SurveyResponse::from(answered)))
+}
+
+// READ surveys of a ticket
+#[utoipa::path(
+    get,
+    path = "/tickets/{id}/surveys",
+    responses(
+        (status = 200, description = "CSAT surveys issued for the ticket", body = [SurveyResponse]),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Ticket not found")
+    ),
+    tag = "Survey"
+)]
+pub async fn get_ticket_surveys(
+    Path(ticket_id): Path<String>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<Vec<SurveyResponse>>, AppError> {
+    require_staff(&auth)?;
+    let id = Uuid::parse_str(&ticket_id)
+        .map_err(|_| AppError::BadRequest("Invalid UUID".into()))?;
+
+    let db = state.db.as_ref();
+    let ticket = TicketEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Ticket not found".into()))?;
+    can_read_or_edit_ticket(&auth, &ticket)?;
+
+    let surveys = CsatSurveyEntity::find_in(auth.tenant_id)
+        .filter(csat_surveys::Column::TicketId.eq(ticket.id))
+        .order_by_desc(csat_surveys::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    Ok(Json(surveys.into_iter().map(SurveyResponse::from).collect()))
+}
+
+//----------import / export----------------
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct FormatQuery {
+    pub format: Option<Format>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CustomerImportRow {
+    pub external_id: String,
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub preferred_locale: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CommunicationImportRow {
+    pub external_id: String,
+    pub ticket_external_id: Option<String>,
+    pub sender_type: String,
+    pub sender_email: Option<String>,
+    pub message: String,
+    pub channel: String,
+    pub is_internal: Option<bool>,
+    pub timestamp: Option<chrono::DateTime<Utc>>,
+}
+
+// In JSON Lines a ticket may carry its conversation inline; CSV files import
+// communications separately, linked by ticket_external_id.
+#[derive(Deserialize, ToSchema)]
+pub struct TicketImportRow {
+    pub external_id: String,
+    pub customer_external_id: String,
+    pub title: String,
+    pub description: String,
+    pub status: String,
+    pub priority: String,
+    pub channel: String,
+    pub assigned_agent_email: Option<String>,
+    pub created_at: Option<chrono::DateTime<Utc>>,
+    #[serde(default)]
+    pub communications: Vec<CommunicationImportRow>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ArticleImportRow {
+    pub external_id: String,
+    pub title: String,
+    pub content: String,
+    pub category: String,
+    pub status: Option<String>,
+    pub author_email: Option<String>,
+    // Only used when the article is created; defaults to English.
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportSummary {
+    pub id: Uuid,
+    pub kind: String,
+    pub format: String,
+    // "queued", "running" or "finished".
+    pub status: String,
+    pub processed: i32,
+    pub created: i32,
+    pub updated: i32,
+    pub failed: i32,
+    pub finished_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl From<import_jobs::Model> for ImportSummary {
+    fn from(job: import_jobs::Model) -> Self {
+        ImportSummary {
+            id: job.id,
+            kind: job.kind,
+            format: job.format,
+            status: job.status,
+            processed: job.processed,
+            created: job.created,
+            updated: job.updated,
+            failed: job.failed,
+            finished_at: job.finished_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CustomerExportRow {
+    id: Uuid,
+    external_id: Option<String>,
+    name: String,
+    email: String,
+    phone: String,
+    company_id: Option<Uuid>,
+    preferred_locale: Option<String>,
+    created_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct TicketExportRow {
+    id: Uuid,
+    external_id: Option<String>,
+    title: String,
+    description: String,
+    status: String,
+    priority: String,
+    channel: String,
+    customer_id: Uuid,
+    assigned_agent_id: Option<Uuid>,
+    created_at: chrono::DateTime<Utc>,
+    updated_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ArticleExportRow {
+    id: Uuid,
+    external_id: Option<String>,
+    title: String,
+    content: String,
+    category: String,
+    status: String,
+    locale: String,
+    translation_of: Option<Uuid>,
+    created_at: chrono::DateTime<Utc>,
+    updated_at: chrono::DateTime<Utc>,
+}
+
+impl Keyed for CustomerExportRow {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Keyed for TicketExportRow {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Keyed for ArticleExportRow {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl From<customers::Model> for CustomerExportRow {
+    fn from(c: customers::Model) -> Self {
+        CustomerExportRow {
+            id: c.id,
+            external_id: c.external_id,
+            name: c.name,
+            email: c.email,
+            phone: c.phone,
+            company_id: c.company_id,
+            preferred_locale: c.preferred_locale,
+            created_at: c.created_at,
+        }
+    }
+}
+
+impl From<tickets::Model> for TicketExportRow {
+    fn from(t: tickets::Model) -> Self {
+        TicketExportRow {
+            id: t.id,
+            external_id: t.external_id,
+            title: t.title,
+            description: t.description,
+            status: t.status,
+            priority: t.priority,
+            channel: t.channel,
+            customer_id: t.customer_id,
+            assigned_agent_id: t.assigned_agent_id,
+            created_at: t.created_at,
+            updated_at: t.updated_at,
+        }
+    }
+}
+
+impl From<knowledge_base::Model> for ArticleExportRow {
+    fn from(a: knowledge_base::Model) -> Self {
+        ArticleExportRow {
+            id: a.id,
+            external_id: a.external_id,
+            title: a.title,
+            content: a.content,
+            category: a.category,
+            status: a.status,
+            locale: a.locale,
+            translation_of: a.translation_of,
+            created_at: a.created_at,
+            updated_at: a.updated_at,
+        }
+    }
+}
+
+enum Outcome {
+    Created,
+    Updated,
+}
+
+// Message written to the error report for a row that failed.
+fn row_error(err: AppError) -> String {
+    match err {
+        AppError::NotFound(msg) | AppError::BadRequest(msg) | AppError::Conflict(msg) | AppError::Internal(msg) => msg,
+        AppError::Db(()) => "Database error".into(),
+        AppError::Unauthorized | AppError::Forbidden => "Not allowed".into(),
+        AppError::PreconditionRequired | AppError::PreconditionFailed { .. } => "Record changed during import".into(),
+    }
+}
+
+// A trashed record keeps its external_id; importing over it would bring it
+// back without anyone restoring it.
+fn not_trashed(deleted_at: Option<chrono::DateTime<Utc>>) -> Result<(), AppError> {
+    if deleted_at.is_some() {
+        return Err(AppError::BadRequest("Record is in the trash; restore it first".into()));
+    }
+    Ok(())
+}
+
+fn required(external_id: &str) -> Result<(), AppError> {
+    if external_id.trim().is_empty() {
+        return Err(AppError::BadRequest("external_id is required".into()));
+    }
+    Ok(())
+}
+
+// One import kind. Rows are applied one at a time and independently, so a bad
+// row is reported without affecting the rest of the file.
+trait ImportRow: serde::de::DeserializeOwned + Send + 'static {
+    const KIND: &'static str;
+
+    fn external_id(&self) -> &str;
+
+    async fn apply(self, state: &AppState, auth: &AuthUser) -> Result<Outcome, AppError>;
+}
+
+impl ImportRow for CustomerImportRow {
+    const KIND: &'static str = "customers";
+
+    fn external_id(&self) -> &str {
+        &self.external_id
+    }
+
+    async fn apply(self, state: &AppState, auth: &AuthUser) -> Result<Outcome, AppError> {
+        required(&self.external_id)?;
+        let db = state.db.as_ref();
+        let input = CreateCustomerInput {
+            name: self.name,
+            email: self.email,
+            phone: self.phone,
+            preferred_locale: self.preferred_locale,
+        };
+        let (email, phone) = normalise_contact(&input).map_err(|(_, msg)| AppError::BadRequest(msg))?;
+        let preferred_locale = preferred_locale(&input).map_err(|(_, msg)| AppError::BadRequest(msg))?;
+
+        let existing = CustomerEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(customers::Column::ExternalId.eq(self.external_id.as_str()))
+            .one(db)
+            .await?;
+        not_trashed(existing.as_ref().and_then(|c| c.deleted_at))?;
+        let in_use = email_in_use(db, auth.tenant_id, &email, existing.as_ref().map(|c| c.id))
+            .await
+            .map_err(|(_, msg)| AppError::Internal(msg))?;
+        if in_use {
+            return Err(AppError::BadRequest("Another customer already has this email".into()));
+        }
+
+        match existing {
+            Some(customer) => {
+                let mut active = customer.into_active_model();
+                active.name = Set(input.name);
+                active.email = Set(email);
+                active.phone = Set(phone);
+                // Files without the column leave the stored preference alone.
+                if preferred_locale.is_some() {
+                    active.preferred_locale = Set(preferred_locale);
+                }
+                active
+                    .update(db)
+                    .await
+                    .map_err(conflict_on_unique("Another customer already has this email"))?;
+                Ok(Outcome::Updated)
+            }
+            None => {
+                let company_id = company_for_email(db, auth.tenant_id, &email).await?;
+                customers::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    tenant_id: Set(auth.tenant_id),
+                    name: Set(input.name),
+                    email: Set(email),
+                    phone: Set(phone),
+                    company_id: Set(company_id),
+                    merged_into_id: Set(None),
+                    external_id: Set(Some(self.external_id)),
+                    preferred_locale: Set(preferred_locale),
+                    erased_at: Set(None),
+                    deleted_at: Set(None),
+                    version: Set(1),
+                    created_at: Set(Utc::now()),
+                }
+                .insert(db)
+                .await
+                .map_err(conflict_on_unique("Another customer already has this email"))?;
+                Ok(Outcome::Created)
+            }
+        }
+    }
+}
+
+async fn find_user_by_email<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    email: &str,
+) -> Result<Option<users::Model>, AppError> {
+    let user = UserEntity::find_in(tenant_id)
+        .filter(users::Column::Email.eq(normalise_email(email)))
+        .one(db)
+        .await?;
+    Ok(user)
+}
+
+async fn import_communication<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    ticket: &tickets::Model,
+    row: CommunicationImportRow,
+) -> Result<Outcome, AppError> {
+    required(&row.external_id)?;
+    if !CHANNELS.contains(&row.channel.as_str()) {
+        return Err(AppError::BadRequest("Invalid channel".into()));
+    }
+
+    let sender_id = match (row.sender_type.as_str(), row.sender_email.as_deref()) {
+        ("customer", Some(email)) => CustomerEntity::find_in(tenant_id)
+            .filter(customers::Column::Email.eq(normalise_email(email)))
+            .filter(customers::Column::MergedIntoId.is_null())
+            .one(db)
+            .await?
+            .map(|c| c.id)
+            .ok_or(AppError::BadRequest(format!("Unknown customer {}", email)))?,
+        ("customer", None) => ticket.customer_id,
+        ("agent", Some(email)) => find_user_by_email(db, tenant_id, email)
+            .await?
+            .map(|u| u.id)
+            .ok_or(AppError::BadRequest(format!("Unknown agent {}", email)))?,
+        ("agent", None) => ticket
+            .assigned_agent_id
+            .ok_or(AppError::BadRequest("sender_email is required for agent messages".into()))?,
+        _ => return Err(AppError::BadRequest("Invalid sender_type".into())),
+    };
+
+    let existing = CommunicationEntity::find_in(tenant_id)
+        .filter(communications::Column::ExternalId.eq(row.external_id.as_str()))
+        .one(db)
+        .await?;
+
+    match existing {
+        Some(communication) => {
+            let mut active = communication.into_active_model();
+            active.ticket_id = Set(ticket.id);
+            active.sender_type = Set(row.sender_type);
+            active.sender_id = Set(sender_id);
+            active.message = Set(row.message);
+            active.channel = Set(row.channel);
+            active.is_internal = Set(row.is_internal.unwrap_or(false));
+            if let Some(timestamp) = row.timestamp {
+                active.timestamp = Set(timestamp);
+            }
+            active.update(db).await?;
+            Ok(Outcome::Updated)
+        }
+        None => {
+            communications::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                tenant_id: Set(tenant_id),
+                ticket_id: Set(ticket.id),
+                sender_type: Set(row.sender_type),
+                sender_id: Set(sender_id),
+                message: Set(row.message),
+                channel: Set(row.channel),
+                is_internal: Set(row.is_internal.unwrap_or(false)),
+                is_system: Set(false),
+                external_id: Set(Some(row.external_id)),
+                timestamp: Set(row.timestamp.unwrap_or_else(Utc::now)),
+            }
+            .insert(db)
+            .await?;
+            Ok(Outcome::Created)
+        }
+    }
+}
+
+impl ImportRow for TicketImportRow {
+    const KIND: &'static str = "tickets";
+
+    fn external_id(&self) -> &str {
+        &self.external_id
+    }
+
+    async fn apply(self, state: &AppState, auth: &AuthUser) -> Result<Outcome, AppError> {
+        required(&self.external_id)?;
+        // Values from the old helpdesk have to be mapped first; reports and SLA
+        // targets only know these.
+        for (field, value, allowed) in [
+            ("status", &self.status, &TICKET_STATUSES[..]),
+            ("priority", &self.priority, &TICKET_PRIORITIES[..]),
+            ("channel", &self.channel, &CHANNELS[..]),
+        ] {
+            if !allowed.contains(&value.as_str()) {
+                return Err(AppError::BadRequest(format!("Invalid {} {:?}; expected one of {}", field, value, allowed.join(", "))));
+            }
+        }
+        let txn = state.db.begin().await?;
+
+        let customer = CustomerEntity::find_in(auth.tenant_id)
+            .filter(customers::Column::ExternalId.eq(self.customer_external_id.as_str()))
+            .one(&txn)
+            .await?
+            .ok_or(AppError::BadRequest(format!("Unknown customer_external_id {}", self.customer_external_id)))?;
+        // Tickets follow a customer that has since been merged into another.
+        let customer_id = customer.merged_into_id.unwrap_or(customer.id);
+
+        let agent_id = match self.assigned_agent_email.as_deref() {
+            Some(email) if !email.is_empty() => Some(
+                find_user_by_email(&txn, auth.tenant_id, email)
+                    .await?
+                    .ok_or(AppError::BadRequest(format!("Unknown agent {}", email)))?
+                    .id,
+            ),
+            _ => None,
+        };
+
+        let existing = TicketEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(tickets::Column::ExternalId.eq(self.external_id.as_str()))
+            .one(&txn)
+            .await?;
+        not_trashed(existing.as_ref().and_then(|t| t.deleted_at))?;
+
+        let now = Utc::now();
+        let (ticket, outcome) = match existing {
+            Some(ticket) => {
+                // Reports read status changes from the events.
+                if ticket.status != self.status {
+                    let from = Some(ticket.status.clone());
+                    record_ticket_event(&txn, auth, ticket.id, "status", from, Some(self.status.clone())).await?;
+                }
+                let mut active = ticket.into_active_model();
+                active.title = Set(self.title);
+                active.description = Set(self.description);
+                active.status = Set(self.status);
+                active.priority = Set(self.priority);
+                active.channel = Set(self.channel);
+                active.customer_id = Set(customer_id);
+                active.assigned_agent_id = Set(agent_id);
+                active.updated_at = Set(now);
+                (active.update(&txn).await?, Outcome::Updated)
+            }
+            None => {
+                let ticket = tickets::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    tenant_id: Set(auth.tenant_id),
+                    title: Set(self.title),
+                    description: Set(self.description),
+                    status: Set(self.status),
+                    priority: Set(self.priority),
+                    channel: Set(self.channel),
+                    customer_id: Set(customer_id),
+                    assigned_agent_id: Set(agent_id),
+                    merged_into_id: Set(None),
+                    parent_ticket_id: Set(None),
+                    external_id: Set(Some(self.external_id)),
+                    deleted_at: Set(None),
+                    version: Set(1),
+                    created_at: Set(self.created_at.unwrap_or(now)),
+                    updated_at: Set(now),
+                }
+                .insert(&txn)
+                .await?;
+                (ticket, Outcome::Created)
+            }
+        };
+
+        for communication in self.communications {
+            let external_id = communication.external_id.clone();
+            import_communication(&txn, auth.tenant_id, &ticket, communication)
+                .await
+                .map_err(|e| AppError::BadRequest(format!("Communication {}: {}", external_id, row_error(e))))?;
+        }
+
+        txn.commit().await?;
+        if matches!(outcome, Outcome::Created) {
+            metrics::ticket_created(&ticket.channel, "import");
+        }
+        Ok(outcome)
     }
+}
 
-    let db = &state.db;
-    let result = tickets::Entity::find()
-        .filter(condition)
-        .all(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?;
+impl ImportRow for CommunicationImportRow {
+    const KIND: &'static str = "communications";
 
-    let response = result.into_iter().map(|ticket| TicketResponse::from(ticket)).collect::<Vec<_>>();
+    fn external_id(&self) -> &str {
+        &self.external_id
+    }
 
-    Ok(Json(response))
+    async fn apply(self, state: &AppState, auth: &AuthUser) -> Result<Outcome, AppError> {
+        let db = state.db.as_ref();
+        let ticket_external_id = self
+            .ticket_external_id
+            .clone()
+            .ok_or(AppError::BadRequest("ticket_external_id is required".into()))?;
+        let ticket = TicketEntity::find_in(auth.tenant_id)
+            .filter(tickets::Column::ExternalId.eq(ticket_external_id.as_str()))
+            .one(db)
+            .await?
+            .ok_or(AppError::BadRequest(format!("Unknown ticket_external_id {}", ticket_external_id)))?;
+
+        import_communication(db, auth.tenant_id, &ticket, self).await
+    }
 }
 
-// DELETE
-fn can_delete_ticket(
-    auth: &AuthUser,
-    ticket: &tickets::Model,
-) -> Result<(), AppError> {
-    if auth.role == "admin" {
-        return Ok(());
+impl ImportRow for ArticleImportRow {
+    const KIND: &'static str = "articles";
+
+    fn external_id(&self) -> &str {
+        &self.external_id
     }
 
-    if auth.role == "agent"
-        && Some(auth.u_id.clone()) == ticket.assigned_agent_id.map(|id| id.to_string())
-    {
-        return Ok(());
+    async fn apply(self, state: &AppState, auth: &AuthUser) -> Result<Outcome, AppError> {
+        required(&self.external_id)?;
+        let status = self.status.unwrap_or_else(|| "draft".into());
+        if status != "draft" && status != "published" {
+            return Err(AppError::BadRequest("Imported articles must be draft or published".into()));
+        }
+        let locale = article_locale(self.locale.as_deref())?;
+
+        let txn = state.db.begin().await?;
+        let author_id = match self.author_email.as_deref() {
+            Some(email) if !email.is_empty() => find_user_by_email(&txn, auth.tenant_id, email)
+                .await?
+                .ok_or(AppError::BadRequest(format!("Unknown author {}", email)))?
+                .id,
+            _ => auth_uuid(auth)?,
+        };
+
+        let existing = KBEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(knowledge_base::Column::ExternalId.eq(self.external_id.as_str()))
+            .one(&txn)
+            .await?;
+        not_trashed(existing.as_ref().and_then(|a| a.deleted_at))?;
+
+        let now = Utc::now();
+        let (article, outcome) = match existing {
+            Some(article) => {
+                let mut active = article.into_active_model();
+                active.title = Set(self.title.clone());
+                active.content = Set(self.content.clone());
+                active.category = Set(self.category.clone());
+                active.updated_at = Set(now);
+                (active.update(&txn).await?, Outcome::Updated)
+            }
+            None => {
+                let article = knowledge_base::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    tenant_id: Set(auth.tenant_id),
+                    title: Set(self.title.clone()),
+                    content: Set(self.content.clone()),
+                    category: Set(self.category.clone()),
+                    created_by: Set(author_id),
+                    status: Set("draft".into()),
+                    published_revision_id: Set(None),
+                    view_count: Set(0),
+                    helpful_votes: Set(0),
+                    unhelpful_votes: Set(0),
+                    external_id: Set(Some(self.external_id)),
+                    locale: Set(locale),
+                    translation_of: Set(None),
+                    deleted_at: Set(None),
+                    version: Set(1),
+                    created_at: Set(now),
+                    updated_at: Set(now),
+                }
+                .insert(&txn)
+                .await?;
+                (article, Outcome::Created)
+            }
+        };
+
+        let revision = create_revision(&txn, auth.tenant_id, article.id, &self.title, &self.content, &self.category, author_id).await?;
+        let mut active = article.into_active_model();
+        active.status = Set(status.clone());
+        if status == "published" {
+            active.published_revision_id = Set(Some(revision.id));
+        }
+        let article = active.update(&txn).await?;
+
+        txn.commit().await?;
+
+        if status == "published" {
+            kb_index::write(&state.kb_index).upsert(
+                article.tenant_id,
+                article.id,
+                &article.locale,
+                &revision.title,
+                &revision.category,
+                &revision.content,
+            );
+        }
+        Ok(outcome)
     }
+}
 
-    if auth.role == "customer"
-        && Some(auth.u_id.to_string()) == Some(ticket.customer_id.to_string())
+// Largest file accepted for import. It is kept in the database until the
+// import job has gone through it.
+const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+// How long one import job runs before handing the rest of the file to a new
+// job, well inside the job lease. Progress is saved at that point.
+const IMPORT_SLICE: std::time::Duration = std::time::Duration::from_secs(60);
+
+pub const IMPORT_QUEUED: &str = "queued";
+const IMPORT_RUNNING: &str = "running";
+const IMPORT_FINISHED: &str = "finished";
+
+const IMPORT_KINDS: [&str; 4] = [
+    CustomerImportRow::KIND,
+    TicketImportRow::KIND,
+    CommunicationImportRow::KIND,
+    ArticleImportRow::KIND,
+];
+
+// Works through a queued import (see import_records). Rows already counted in
+// the import are skipped, so a job that is retried, or picks up where the
+// previous slice stopped, does not apply them twice.
+#[derive(Serialize, Deserialize)]
+pub struct RunImport {
+    pub import_id: Uuid,
+    pub tenant_id: Uuid,
+}
 
-    {
-        return Ok(());
+impl Job for RunImport {
+    const KIND: &'static str = "run_import";
+
+    async fn run(self, state: &AppState) -> Result<(), String> {
+        let db = state.db.as_ref();
+        let import = ImportJobEntity::find_by_id_in(self.import_id, self.tenant_id)
+            .one(db)
+            .await
+            .map_err(|e| e.to_string())?;
+        let Some(import) = import.filter(|i| i.status != IMPORT_FINISHED) else {
+            return Ok(());
+        };
+        let upload = ImportUploadEntity::find_by_id(import.id)
+            .one(db)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("the uploaded file is gone")?;
+        let format = Format::parse(&import.format).ok_or("unknown import format")?;
+
+        let body = Body::from(upload.data);
+        let done = match import.kind.as_str() {
+            CustomerImportRow::KIND => import_slice::<CustomerImportRow>(state, &import, format, body).await,
+            TicketImportRow::KIND => import_slice::<TicketImportRow>(state, &import, format, body).await,
+            CommunicationImportRow::KIND => import_slice::<CommunicationImportRow>(state, &import, format, body).await,
+            ArticleImportRow::KIND => import_slice::<ArticleImportRow>(state, &import, format, body).await,
+            other => return Err(format!("unknown import kind {:?}", other)),
+        }
+        .map_err(|e| e.to_string())?;
+
+        if done {
+            ImportUploadEntity::delete_by_id(import.id).exec(db).await.map_err(|e| e.to_string())?;
+        } else {
+            let options = EnqueueOptions { tenant_id: Some(self.tenant_id), ..Default::default() };
+            crate::jobs::enqueue(db, &self, options).await.map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+// Applies the rows after those already processed, for up to IMPORT_SLICE,
+// then saves the counts and the error report. True once the file is done.
+async fn import_slice<T: ImportRow>(
+    state: &AppState,
+    import: &import_jobs::Model,
+    format: Format,
+    body: Body,
+) -> Result<bool, DbErr> {
+    // Imports act with the rights of the admin who started them.
+    let auth = AuthUser {
+        u_id: import.created_by.to_string(),
+        role: "admin".into(),
+        tenant_id: import.tenant_id,
+        api_key_id: None,
+    };
+    let started = std::time::Instant::now();
+
+    let mut rows = read_rows::<T>(body, format);
+    let mut report = ErrorReport::default();
+    let (mut processed, mut created, mut updated, mut failed) =
+        (import.processed, import.created, import.updated, import.failed);
+    let mut skip = import.processed;
+    let mut done = true;
+
+    while let Some(Row { row, value }) = rows.recv().await {
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        if processed > import.processed && started.elapsed() > IMPORT_SLICE {
+            done = false;
+            break;
+        }
+        processed += 1;
+        let result = match value {
+            Ok(record) => {
+                let external_id = record.external_id().to_string();
+                record.apply(state, &auth).await.map_err(|e| (external_id, row_error(e)))
+            }
+            Err(e) => Err((String::new(), e)),
+        };
+        match result {
+            Ok(Outcome::Created) => created += 1,
+            Ok(Outcome::Updated) => updated += 1,
+            Err((external_id, error)) => {
+                failed += 1;
+                report.push(row, &external_id, error);
+            }
+        }
     }
 
-    Err(AppError::Forbidden)
+    let mut active = import.clone().into_active_model();
+    active.status = Set(if done { IMPORT_FINISHED } else { IMPORT_RUNNING }.into());
+    active.processed = Set(processed);
+    active.created = Set(created);
+    active.updated = Set(updated);
+    active.failed = Set(failed);
+    active.error_report = Set(report.append_to(&import.error_report));
+    active.finished_at = Set(done.then(Utc::now));
+    active.update(state.db.as_ref()).await?;
+    Ok(done)
 }
+
 #[utoipa::path(
-    delete,
-    path = "/tickets/{id}",
+    post,
+    path = "/import/{kind}",
+    params(
+        ("kind" = String, Path, description = "customers, tickets, communications or articles"),
+        FormatQuery
+    ),
+    request_body(content = String, description = "CSV with a header row, or one JSON object per line", content_type = "text/csv"),
     responses(
-        (status = 204, description = "Ticket deleted")
+        (status = 202, description = "Import queued; poll GET /imports/{id} until finished_at is set", body = ImportSummary),
+        (status = 400, description = "Unknown import kind, or the file is too large"),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Ticket"
+    tag = "Import/Export"
 )]
-pub async fn delete_ticket_by_id(
-    Path(ticket_id): Path<String>,
+pub async fn import_records(
+    Path(kind): Path<String>,
     State(state): State<AppState>,
     auth: AuthUser,
-) -> Result<StatusCode, AppError> {
-    let uuid = Uuid::parse_str(&ticket_id)
-        .map_err(|_| AppError::BadRequest("Invalid UUID".to_string()))?;
-
-    let db = &state.db;
-    let ticket = tickets::Entity::find_by_id(uuid)
-        .one(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?
-        .ok_or(AppError::NotFound("Ticket not found".into()))?;
-
-    can_delete_ticket(&auth, &ticket)?; 
-
-    ticket
-        .delete(db.as_ref())
+    Query(query): Query<FormatQuery>,
+    body: Body,
+) -> Result<(StatusCode, Json<ImportSummary>), AppError> {
+    require_role(&auth, "admin")?;
+    let format = query.format.unwrap_or_default();
+    if !IMPORT_KINDS.contains(&kind.as_str()) {
+        return Err(AppError::BadRequest("Unknown import kind".into()));
+    }
+    let data = axum::body::to_bytes(body, MAX_IMPORT_BYTES)
         .await
-        .map_err(|_| AppError::Internal("Failed to delete".into()))?;
+        .map_err(|_| AppError::BadRequest(format!("Files over {} MB cannot be imported", MAX_IMPORT_BYTES >> 20)))?;
 
-    Ok(StatusCode::NO_CONTENT)
+    let txn = state.db.begin().await?;
+    let import = import_jobs::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        kind: Set(kind),
+        format: Set(format.as_str().into()),
+        status: Set(IMPORT_QUEUED.into()),
+        created_by: Set(auth_uuid(&auth)?),
+        processed: Set(0),
+        created: Set(0),
+        updated: Set(0),
+        failed: Set(0),
+        error_report: Set(String::new()),
+        created_at: Set(Utc::now()),
+        finished_at: Set(None),
+    }
+    .insert(&txn)
+    .await?;
+    import_uploads::ActiveModel { import_id: Set(import.id), data: Set(data.to_vec()) }
+        .insert(&txn)
+        .await?;
+    let job = RunImport { import_id: import.id, tenant_id: auth.tenant_id };
+    crate::jobs::enqueue(&txn, &job, EnqueueOptions { tenant_id: Some(auth.tenant_id), ..Default::default() }).await?;
+    txn.commit().await?;
+
+    Ok((StatusCode::ACCEPTED, Json(ImportSummary::from(import))))
 }
 
+#[utoipa::path(
+    get,
+    path = "/imports/{id}",
+    responses(
+        (status = 200, description = "Import summary", body = ImportSummary),
+        (status = 404, description = "Import not found")
+    ),
+    tag = "Import/Export"
+)]
+pub async fn get_import(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<ImportSummary>, AppError> {
+    require_role(&auth, "admin")?;
 
-//----------communication----------------
-#[derive(Deserialize, ToSchema)]
-pub struct CreateCommunicationInput {
-    pub ticket_id: Uuid,
-    pub sender_type: String, // "agent" or "customer"
-    pub sender_id: Uuid,
-    pub message: String,
-    pub channel: String,     // "Email", "Chat", "Social"
-    pub is_internal: bool,
-}
+    let job = ImportJobEntity::find_by_id_in(id, auth.tenant_id)
+        .one(state.db.as_ref())
+        .await?
+        .ok_or(AppError::NotFound("Import not found".into()))?;
 
-#[derive(Serialize, ToSchema)]
-pub struct CommunicationResponse {
-    pub id: Uuid,
-    pub ticket_id: Uuid,
-    pub sender_type: String,
-    pub sender_id: Uuid,
-    pub message: String,
-    pub channel: String,
-    pub is_internal: bool,
+    Ok(Json(ImportSummary::from(job)))
 }
 
-// CREATE
 #[utoipa::path(
-    post,
-    path = "/communications",
-    request_body = CreateCommunicationInput,
+    get,
+    path = "/imports/{id}/errors",
     responses(
-        (status = 201, description = "Communication added", body = CommunicationResponse)
+        (status = 200, description = "CSV report of the rows that failed", body = String, content_type = "text/csv"),
+        (status = 404, description = "Import not found")
     ),
-    tag = "Communication"
+    tag = "Import/Export"
 )]
-pub async fn create_communication(
+pub async fn get_import_errors(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
-    Json(input): Json<CreateCommunicationInput>,
-) -> Result<Json<CommunicationResponse>, (StatusCode, String)> {
-    let db = &state.db;
-
-    if !["agent", "customer"].contains(&input.sender_type.as_str()) {
-        return Err((StatusCode::BAD_REQUEST, "Invalid sender_type".into()));
-    }
-    if !["Email", "Chat", "Social"].contains(&input.channel.as_str()) {
-        return Err((StatusCode::BAD_REQUEST, "Invalid channel".into()));
-    }
+    auth: AuthUser,
+) -> Result<Response, AppError> {
+    require_role(&auth, "admin")?;
 
-    let model = communications::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        ticket_id: Set(input.ticket_id),
-        sender_type: Set(input.sender_type.clone()),
-        sender_id: Set(input.sender_id),
-        message: Set(input.message.clone()),
-        channel: Set(input.channel.clone()),
-        is_internal: Set(input.is_internal),
-        timestamp: Set(Utc::now().into()),
-    };
+    let job = ImportJobEntity::find_by_id_in(id, auth.tenant_id)
+        .one(state.db.as_ref())
+        .await?
+        .ok_or(AppError::NotFound("Import not found".into()))?;
 
-    let saved = model.insert(db.as_ref()).await.map_err(|e| {
-        eprintln!("Create error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Insert failed".into())
-    })?;
+    Ok(download(&format!("import-{}-errors.csv", job.id), Format::Csv.content_type(), Body::from(job.error_report)))
+}
 
-    Ok(Json(CommunicationResponse {
-        id: saved.id,
-        ticket_id: saved.ticket_id,
-        sender_type: saved.sender_type,
-        sender_id: saved.sender_id,
-        message: saved.message,
-        channel: saved.channel,
-        is_internal: saved.is_internal,
-    }))
+fn download(filename: &str, content_type: &str, body: Body) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response()
 }
 
-// READ ALL
 #[utoipa::path(
     get,
-    path = "/communications/{ticket_id}",
-    params(Pagination),
+    path = "/export/customers",
+    params(FormatQuery),
     responses(
-        (status = 200, description = "List of communications", body = [CommunicationResponse])
+        (status = 200, description = "All active customers, streamed", body = String),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Communication"
+    tag = "Import/Export"
 )]
-pub async fn get_communications(
-     Path(ticket_id): Path<String>,
+pub async fn export_customers(
     State(state): State<AppState>,
     auth: AuthUser,
-    Query(pagination): Query<Pagination>,
-) -> Result<Json<Vec<CommunicationResponse>>, AppError> {
-    let uuid = Uuid::parse_str(&ticket_id)
-        .map_err(|_| AppError::BadRequest("Invalid ticket ID".into()))?;
-
+    Query(query): Query<FormatQuery>,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let format = query.format.unwrap_or_default();
+
+    let db = state.db.clone();
+    let tenant_id = auth.tenant_id;
+    let body = export_body(format, move |after| {
+        let db = db.clone();
+        async move {
+            let mut page = CustomerEntity::find_in(tenant_id)
+                .filter(customers::Column::MergedIntoId.is_null())
+                .order_by_asc(customers::Column::Id)
+                .limit(EXPORT_PAGE_SIZE);
+            if let Some(after) = after {
+                page = page.filter(customers::Column::Id.gt(after));
+            }
+            Ok(page.all(db.as_ref()).await?.into_iter().map(CustomerExportRow::from).collect())
+        }
+    });
+
+    Ok(download(&format!("customers.{}", format.as_str()), format.content_type(), body))
+}
 
-    let db = &state.db;
-    let list = CommunicationEntity::find()
-        .filter(communications::Column::TicketId.eq(uuid))
-        .limit(pagination.limit.unwrap_or(10))
-        .offset(pagination.offset.unwrap_or(0))
-        .all(db.as_ref())
-        .await
-        .map_err(|e| {
-            eprintln!("Fetch error: {}", e);
-            AppError::Internal("Could not fetch communications".into())
-        })?;
+#[utoipa::path(
+    get,
+    path = "/export/tickets",
+    params(
+        FormatQuery,
+        ("status" = Option<String>, Query, description = "Ticket status filter"),
+        ("priority" = Option<String>, Query, description = "Priority filter"),
+        ("channel" = Option<String>, Query, description = "Channel filter"),
+        ("tags" = Option<String>, Query, description = "Comma-separated tag names; tickets must carry all of them")
+    ),
+    responses(
+        (status = 200, description = "Tickets matching the search filters, streamed", body = String),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Import/Export"
+)]
+pub async fn export_tickets(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<FormatQuery>,
+    Query(params): Query<TicketQuery>,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let format = query.format.unwrap_or_default();
+    let filename = format!("tickets.{}", format.as_str());
 
-    let visible = if auth.role == "customer" {
-        list.into_iter().filter(|c| !c.is_internal).collect()
-    } else {
-        list
+    let Some(condition) = ticket_search_condition(state.db.as_ref(), &auth, &params).await? else {
+        return Ok(download(&filename, format.content_type(), Body::empty()));
     };
 
-    let response = visible.into_iter().map(|c| CommunicationResponse {
-        id: c.id,
-        ticket_id: c.ticket_id,
-        sender_type: c.sender_type,
-        sender_id: c.sender_id,
-        message: c.message,
-        channel: c.channel,
-        is_internal: c.is_internal,
-    }).collect();   
-    Ok(Json(response))
+    let db = state.db.clone();
+    let tenant_id = auth.tenant_id;
+    let body = export_body(format, move |after| {
+        let db = db.clone();
+        let condition = condition.clone();
+        async move {
+            let mut page = TicketEntity::find_in(tenant_id)
+                .filter(condition)
+                .order_by_asc(tickets::Column::Id)
+                .limit(EXPORT_PAGE_SIZE);
+            if let Some(after) = after {
+                page = page.filter(tickets::Column::Id.gt(after));
+            }
+            Ok(page.all(db.as_ref()).await?.into_iter().map(TicketExportRow::from).collect())
+        }
+    });
+
+    Ok(download(&filename, format.content_type(), body))
 }
 
+#[utoipa::path(
+    get,
+    path = "/export/articles",
+    params(FormatQuery),
+    responses(
+        (status = 200, description = "All knowledge base articles, streamed", body = String),
+        (status = 403, description = "Forbidden")
+    ),
+    tag = "Import/Export"
+)]
+pub async fn export_articles(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(query): Query<FormatQuery>,
+) -> Result<Response, AppError> {
+    require_staff(&auth)?;
+    let format = query.format.unwrap_or_default();
+
+    let db = state.db.clone();
+    let tenant_id = auth.tenant_id;
+    let body = export_body(format, move |after| {
+        let db = db.clone();
+        async move {
+            let mut page = KBEntity::find_in(tenant_id)
+                .order_by_asc(knowledge_base::Column::Id)
+                .limit(EXPORT_PAGE_SIZE);
+            if let Some(after) = after {
+                page = page.filter(knowledge_base::Column::Id.gt(after));
+            }
+            Ok(page.all(db.as_ref()).await?.into_iter().map(ArticleExportRow::from).collect())
+        }
+    });
+
+    Ok(download(&format!("articles.{}", format.as_str()), format.content_type(), body))
+}
 
-//----------knowledge_base----------------
-#[derive(Deserialize, ToSchema)]
-pub struct CreateArticleInput {
-    pub title: String,
-    pub content: String,
-    pub category: String,
-    pub created_by: Uuid,
+//----------trash----------------
+// Deleted users, customers, tickets and articles stay here until restored or
+// purged by the retention job in retention.rs.
+const TRASH_KINDS: [&str; 4] = ["users", "customers", "tickets", "articles"];
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct TrashQuery {
+    pub kind: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
-pub struct ArticleResponse {
+#[derive(Serialize, ToSchema)]
+pub struct TrashItem {
+    pub kind: String,
     pub id: Uuid,
-    pub title: String,
-    pub content: String,
-    pub category: String,
-    pub created_by: Uuid,
+    pub label: String,
+    pub deleted_at: chrono::DateTime<Utc>,
+}
+
+impl TrashItem {
+    fn new(kind: &str, id: Uuid, label: String, deleted_at: Option<chrono::DateTime<Utc>>) -> Self {
+        TrashItem {
+            kind: kind.to_string(),
+            id,
+            label,
+            deleted_at: deleted_at.unwrap_or_default(),
+        }
+    }
 }
 
-// CREATE
 #[utoipa::path(
-    post,
-    path = "/kb",
-    request_body = CreateArticleInput,
+    get,
+    path = "/admin/trash",
+    params(TrashQuery),
     responses(
-        (status = 201, description = "Article created", body = ArticleResponse)
+        (status = 200, description = "Trashed records, most recently deleted first", body = [TrashItem]),
+        (status = 400, description = "Unknown kind"),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Knowledge"
+    tag = "Trash"
 )]
-pub async fn create_article(
+pub async fn get_trash(
     State(state): State<AppState>,
     auth: AuthUser,
-    Json(input): Json<CreateArticleInput>,
-
-) -> Result<Json<ArticleResponse>, (StatusCode, String)> {
-    
-     if auth.role != "admin" && auth.role != "agent" {
-        return Err((StatusCode::FORBIDDEN, "Only agents/admins can create articles.".into()));
+    Query(query): Query<TrashQuery>,
+) -> Result<Json<Vec<TrashItem>>, AppError> {
+    require_role(&auth, "admin")?;
+    if let Some(kind) = query.kind.as_deref()
+        && !TRASH_KINDS.contains(&kind)
+    {
+        return Err(AppError::BadRequest("Unknown kind".into()));
     }
-    let article = knowledge_base::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        title: Set(input.title),
-        content: Set(input.content),
-        category: Set(input.category),
-        created_by: Set(input.created_by),
-        created_at: Set(Utc::now().into()),
-    };
-
-    let db = &state.db;
-    let saved = article.insert(db.as_ref()).await.map_err(|e| {
-        eprintln!("Insert error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not create article".into())
-    })?;
+    let wanted = |kind: &str| query.kind.as_deref().is_none_or(|k| k == kind);
+
+    let db = state.db.as_ref();
+    let mut items = Vec::new();
+    if wanted("users") {
+        let rows = UserEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(users::Column::DeletedAt.is_not_null())
+            .all(db)
+            .await?;
+        items.extend(rows.into_iter().map(|u| TrashItem::new("users", u.id, u.email, u.deleted_at)));
+    }
+    if wanted("customers") {
+        let rows = CustomerEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(customers::Column::DeletedAt.is_not_null())
+            .all(db)
+            .await?;
+        items.extend(rows.into_iter().map(|c| TrashItem::new("customers", c.id, c.name, c.deleted_at)));
+    }
+    if wanted("tickets") {
+        let rows = TicketEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(tickets::Column::DeletedAt.is_not_null())
+            .all(db)
+            .await?;
+        items.extend(rows.into_iter().map(|t| TrashItem::new("tickets", t.id, t.title, t.deleted_at)));
+    }
+    if wanted("articles") {
+        let rows = KBEntity::find_with_trashed_in(auth.tenant_id)
+            .filter(knowledge_base::Column::DeletedAt.is_not_null())
+            .all(db)
+            .await?;
+        items.extend(rows.into_iter().map(|a| TrashItem::new("articles", a.id, a.title, a.deleted_at)));
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
 
-    Ok(Json(ArticleResponse {
-        id: saved.id,
-        title: saved.title,
-        content: saved.content,
-        category: saved.category,
-        created_by: saved.created_by,
-    }))
+    Ok(Json(items))
 }
 
-// GET ALL
 #[utoipa::path(
-    get,
-    path = "/kb",
+    post,
+    path = "/admin/trash/{kind}/{id}/restore",
+    params(
+        ("kind" = String, Path, description = "users, customers, tickets or articles"),
+        ("id" = Uuid, Path, description = "Id of the trashed record")
+    ),
     responses(
-        (status = 200, description = "List of all articles", body = [ArticleResponse])
+        (status = 200, description = "Record restored", body = TrashItem),
+        (status = 400, description = "Unknown kind"),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Not in the trash"),
+        (status = 409, description = "A live customer now has the same email, or the ticket's customer is still trashed"),
+        (status = 412, description = "The record changed while it was being restored")
     ),
-    tag = "Knowledge"
+    tag = "Trash"
 )]
-pub async fn get_all_articles(
+pub async fn restore_from_trash(
+    Path((kind, id)): Path<(String, Uuid)>,
     State(state): State<AppState>,
-    _auth: AuthUser,
-) -> Result<Json<Vec<ArticleResponse>>, AppError> {
-    let db = &state.db;
-    let articles = knowledge_base::Entity::find()
-        .all(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?;
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+) -> Result<Json<TrashItem>, AppError> {
+    require_role(&auth, "admin")?;
+    let db = state.db.as_ref();
+    let not_found = || AppError::NotFound("Not in the trash".into());
+
+    let item = match kind.as_str() {
+        "users" => {
+            let user = UserEntity::find_by_id_with_trashed_in(id, auth.tenant_id)
+                .filter(users::Column::DeletedAt.is_not_null())
+                .one(db)
+                .await?
+                .ok_or_else(not_found)?;
+            let item = TrashItem::new("users", user.id, user.email.clone(), user.deleted_at);
+            let mut active = user.into_active_model();
+            active.deleted_at = Set(None);
+            active.update(db).await?;
+            item
+        }
+        "customers" => {
+            let customer = CustomerEntity::find_by_id_with_trashed_in(id, auth.tenant_id)
+                .filter(customers::Column::DeletedAt.is_not_null())
+                .one(db)
+                .await?
+                .ok_or_else(not_found)?;
+            // The email was released when the customer was trashed.
+            let in_use = email_in_use(db, auth.tenant_id, &customer.email, Some(customer.id))
+                .await
+                .map_err(|(_, msg)| AppError::Internal(msg))?;
+            if in_use && customer.merged_into_id.is_none() {
+                return Err(AppError::Conflict("Another customer now has this email".into()));
+            }
+            let item = TrashItem::new("customers", customer.id, customer.name.clone(), customer.deleted_at);
+            let version = customer.version;
+            let mut active = customer.into_active_model();
+            active.deleted_at = Set(None);
+            save_versioned(db, active, version)
+                .await
+                .map_err(conflict_on_unique("Another customer now has this email"))?
+                .or_stale(|c| (c.version, CustomerResponse::from(c)))?;
+            item
+        }
+        "tickets" => {
+            let ticket = TicketEntity::find_by_id_with_trashed_in(id, auth.tenant_id)
+                .filter(tickets::Column::DeletedAt.is_not_null())
+                .one(db)
+                .await?
+                .ok_or_else(not_found)?;
+            // A ticket back in the list must not point at a customer still in the trash.
+            let customer_trashed = CustomerEntity::find_by_id_with_trashed_in(ticket.customer_id, auth.tenant_id)
+                .filter(customers::Column::DeletedAt.is_not_null())
+                .one(db)
+                .await?
+                .is_some();
+            if customer_trashed {
+                return Err(AppError::Conflict("The ticket's customer is in the trash; restore the customer first".into()));
+            }
+            let item = TrashItem::new("tickets", ticket.id, ticket.title.clone(), ticket.deleted_at);
+            let version = ticket.version;
+            let mut active = ticket.into_active_model();
+            active.deleted_at = Set(None);
+            save_versioned(db, active, version)
+                .await?
+                .or_stale(|t| (t.version, TicketResponse::from(t)))?;
+            item
+        }
+        "articles" => {
+            let article = KBEntity::find_by_id_with_trashed_in(id, auth.tenant_id)
+                .filter(knowledge_base::Column::DeletedAt.is_not_null())
+                .one(db)
+                .await?
+                .ok_or_else(not_found)?;
+            let item = TrashItem::new("articles", article.id, article.title.clone(), article.deleted_at);
+            let version = article.version;
+            let mut active = article.into_active_model();
+            active.deleted_at = Set(None);
+            let restored = save_versioned(db, active, version)
+                .await?
+                .or_stale(|a| (a.version, ArticleResponse::from(a)))?;
+
+            if restored.status == "published"
+                && let Some(rev) = published_revision(db, &restored).await?
+            {
+                kb_index::write(&state.kb_index).upsert(
+                    restored.tenant_id,
+                    restored.id,
+                    &restored.locale,
+                    &rev.title,
+                    &rev.category,
+                    &rev.content,
+                );
+            }
+            item
+        }
+        _ => return Err(AppError::BadRequest("Unknown kind".into())),
+    };
 
-    let response = articles
-        .into_iter()
-        .map(|article| ArticleResponse {
-            id: article.id,
-            title: article.title,
-            content: article.content,
-            category: article.category,
-            created_by: article.created_by,
-        })
-        .collect();
+    let entity = item.kind.trim_end_matches('s');
+    audit_trash(db, &auth, "restored", entity, id, &ip).await?;
+
+    Ok(Json(item))
+}
+
+//----------api keys----------------
+// Keys for internal tools and other services, minted by admins. The key is
+// returned once at creation; only its hash is kept (see api_keys.rs).
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKeyInput {
+    pub name: String,
+    pub scopes: Vec<String>,                       // e.g. ["tickets:write", "customers:read"]
+    pub expires_at: Option<chrono::DateTime<Utc>>,
+    pub allowed_ips: Option<Vec<String>>,          // addresses or CIDR ranges
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub allowed_ips: Option<Vec<String>>,
+    pub created_by: Uuid,
+    pub expires_at: Option<chrono::DateTime<Utc>>,
+    pub last_used_at: Option<chrono::DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<chrono::DateTime<Utc>>,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+impl From<api_keys::Model> for ApiKeyResponse {
+    fn from(k: api_keys::Model) -> Self {
+        let words = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        ApiKeyResponse {
+            id: k.id,
+            name: k.name,
+            prefix: k.prefix,
+            scopes: words(&k.scopes),
+            allowed_ips: k.allowed_ips.as_deref().map(words),
+            created_by: k.created_by,
+            expires_at: k.expires_at,
+            last_used_at: k.last_used_at,
+            last_used_ip: k.last_used_ip,
+            revoked_at: k.revoked_at,
+            created_at: k.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    // The key itself. It cannot be retrieved again.
+    pub key: String,
+    pub api_key: ApiKeyResponse,
+}
 
-    Ok(Json(response))
+// Key management is for people: a key cannot mint or revoke keys.
+fn require_key_admin(auth: &AuthUser) -> Result<(), AppError> {
+    require_role(auth, "admin")?;
+    if auth.api_key_id.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
 }
 
-// UPDATE
 #[utoipa::path(
-    put,
-    path = "/kb/{id}",
-    request_body = ArticleResponse,
+    post,
+    path = "/admin/api-keys",
+    request_body = CreateApiKeyInput,
     responses(
-        (status = 200, description = "Article updated", body = ArticleResponse),
-        (status = 404, description = "Article not found")
+        (status = 201, description = "Key created; the key is only shown in this response", body = CreatedApiKey),
+        (status = 400, description = "Invalid name, scope, expiry or IP rule"),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Knowledge"
+    tag = "API Keys"
 )]
-#[debug_handler]
-pub async fn update_article(
-     Path(id): Path<String>,
+pub async fn create_api_key(
     State(state): State<AppState>,
     auth: AuthUser,
-    Json(input): Json<ArticleResponse>,
-) -> Result<Json<ArticleResponse>, (StatusCode, String)> {
-     if auth.role != "admin" && auth.role != "agent" {
-        return Err((StatusCode::FORBIDDEN, "Access denied".into()));
+    ClientIp(ip): ClientIp,
+    Json(input): Json<CreateApiKeyInput>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), AppError> {
+    require_key_admin(&auth)?;
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Name is required".into()));
     }
+    let scopes = crate::api_keys::validate_scopes(&input.scopes).map_err(AppError::BadRequest)?;
+    if input.expires_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(AppError::BadRequest("expires_at must be in the future".into()));
+    }
+    let allowed_ips = match input.allowed_ips {
+        Some(rules) if !rules.is_empty() => {
+            for rule in &rules {
+                crate::api_keys::validate_ip_rule(rule).map_err(AppError::BadRequest)?;
+            }
+            Some(rules.join(" "))
+        }
+        _ => None,
+    };
 
-    let uuid = Uuid::parse_str(&id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid UUID".into()))?;
-    let db = &state.db;
-    let record = KBEntity::find_by_id(uuid).one(db.as_ref()).await.map_err(|e| {
-        eprintln!("Find error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not find article".into())
-    })?;
-
-    let mut model = record.ok_or((StatusCode::NOT_FOUND, "Article not found".into()))?.into_active_model();
-
-    model.title = Set(input.title);
-    model.content = Set(input.content);
-    model.category = Set(input.category);
-    model.created_by = Set(input.created_by);
-
-    let updated = model.update(db.as_ref()).await.map_err(|e| {
-        eprintln!("Update error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not update article".into())
-    })?;
-
-    Ok(Json(ArticleResponse {
-        id: updated.id,
-        title: updated.title,
-        content: updated.content,
-        category: updated.category,
-        created_by: updated.created_by,
-    }))
+    let db = state.db.as_ref();
+    let actor = AuditActor::of(&auth)?;
+    let new_key = crate::api_keys::generate();
+    let saved = api_keys::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(auth.tenant_id),
+        name: Set(name.to_string()),
+        prefix: Set(new_key.prefix),
+        key_hash: Set(new_key.hash),
+        scopes: Set(scopes),
+        allowed_ips: Set(allowed_ips),
+        created_by: Set(actor.user_id),
+        expires_at: Set(input.expires_at),
+        last_used_at: Set(None),
+        last_used_ip: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await?;
+    record_audit(db, &actor, "api_key_created", "api_key", saved.id, &ip).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiKey {
+            key: new_key.key,
+            api_key: saved.into(),
+        }),
+    ))
 }
 
-//SEARCH
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct SearchQuery {
-    pub title: Option<String>,
-    pub category: Option<String>,
-}
 #[utoipa::path(
     get,
-    path = "/kb/search",
+    path = "/admin/api-keys",
     responses(
-        (status = 200, description = "Search articles", body = [ArticleResponse])
+        (status = 200, description = "All keys of the organization, including revoked ones", body = [ApiKeyResponse]),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Knowledge"
+    tag = "API Keys"
 )]
-#[debug_handler]
-pub async fn search_articles(
-    Query(params): Query<SearchQuery>,
+pub async fn get_api_keys(
     State(state): State<AppState>,
-) -> Result<Json<Vec<ArticleResponse>>, (StatusCode, String)> {
-    let mut query = KBEntity::find();
-
-    if let Some(title) = &params.title {
-        query = query.filter(knowledge_base::Column::Title.contains(title));
-    }
-
-    if let Some(category) = &params.category {
-        query = query.filter(knowledge_base::Column::Category.eq(category));
-    }
-
-    let db = &state.db;
-    let articles = query
-        .all(db.as_ref())
-        .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Query failed".into()))?;
-
-    Ok(Json(articles.into_iter().map(|article| ArticleResponse {
-        id: article.id,
-        title: article.title,
-        content: article.content,
-        category: article.category,
-        created_by: article.created_by,
-    }).collect()))
+    auth: AuthUser,
+) -> Result<Json<Vec<ApiKeyResponse>>, AppError> {
+    require_key_admin(&auth)?;
+    let keys = ApiKeyEntity::find_in(auth.tenant_id)
+        .order_by_desc(api_keys::Column::CreatedAt)
+        .all(state.db.as_ref())
+        .await?;
+    Ok(Json(keys.into_iter().map(ApiKeyResponse::from).collect()))
 }
 
-// DELETE
 #[utoipa::path(
-    get,
-    path = "/kb/{id}",
+    delete,
+    path = "/admin/api-keys/{id}",
+    params(("id" = Uuid, Path, description = "API key id")),
     responses(
-        (status = 200, description = "Search articles", body = [ArticleResponse])
+        (status = 200, description = "Key revoked; requests using it are rejected from now on", body = ApiKeyResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "API key not found")
     ),
-    tag = "Knowledge"
+    tag = "API Keys"
 )]
-pub async fn delete_article(
-      Path(id): Path<String>,
+pub async fn revoke_api_key(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
     auth: AuthUser,
-) -> Result<StatusCode, (StatusCode, String)> {
-    if auth.role != "admin" && auth.role != "agent" {
-        return Err((StatusCode::FORBIDDEN, "Only agents or admins can delete".into()));
+    ClientIp(ip): ClientIp,
+) -> Result<Json<ApiKeyResponse>, AppError> {
+    require_key_admin(&auth)?;
+    let db = state.db.as_ref();
+    let key = ApiKeyEntity::find_by_id_in(id, auth.tenant_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("API key not found".into()))?;
+    if key.revoked_at.is_some() {
+        return Ok(Json(key.into()));
     }
-    let uuid = Uuid::parse_str(&id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid UUID".into()))?;
-    let db = &state.db;
-    KBEntity::delete_by_id(uuid).exec(db.as_ref()).await.map_err(|e| {
-        eprintln!("Deletion error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not delete article".into())
-    })?;
 
-    Ok(StatusCode::NO_CONTENT)
-}
+    let mut active = key.into_active_model();
+    active.revoked_at = Set(Some(Utc::now()));
+    let key = active.update(db).await?;
+    let actor = AuditActor::of(&auth)?;
+    record_audit(db, &actor, "api_key_revoked", "api_key", id, &ip).await?;
 
+    Ok(Json(key.into()))
+}
 
-//----------tag----------------
+//----------organizations----------------
+// Platform-level tooling for super admins; everything else in this file works
+// inside the caller's own organization.
 #[derive(Deserialize, ToSchema)]
-pub struct CreateTagInput {
-    pub ticket_id: Uuid,
-    pub tag_name: String,
+pub struct CreateOrganizationInput {
+    pub name: String,
+    pub slug: String,
+    pub admin_email: String,
+    pub admin_name: String,
+    pub admin_password: String,
 }
 
 #[derive(Serialize, ToSchema)]
-pub struct TagResponse {
+pub struct OrganizationResponse {
     pub id: Uuid,
-    pub ticket_id: Uuid,
-    pub tag_name: String,
+    pub name: String,
+    pub slug: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub user_count: u64,
+    pub ticket_count: u64,
+}
+
+fn valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 63
+        && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
 }
 
 // CREATE
 #[utoipa::path(
     post,
-    path = "/tags",
-    request_body = CreateTagInput,
+    path = "/admin/organizations",
+    request_body = CreateOrganizationInput,
     responses(
-        (status = 201, description = "Tag added to ticket", body = TagResponse),
-        (status = 400, description = "Invalid ticket ID"),
-        (status = 500, description = "Database error")
+        (status = 201, description = "Organization created with its first admin", body = OrganizationResponse),
+        (status = 400, description = "Invalid or duplicate slug"),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Tag"
+    tag = "Organization"
 )]
-pub async fn create_tag(
+pub async fn create_organization(
     State(state): State<AppState>,
-    Json(input): Json<CreateTagInput>,
-) -> Result<Json<TagResponse>, (StatusCode, String)> {
-    let db = &state.db;
+    auth: AuthUser,
+    Json(input): Json<CreateOrganizationInput>,
+) -> Result<(StatusCode, Json<OrganizationResponse>), AppError> {
+    require_role(&auth, auth::SUPER_ADMIN)?;
+    if !valid_slug(&input.slug) {
+        return Err(AppError::BadRequest("Slug may only contain lowercase letters, digits and dashes".into()));
+    }
 
-    let tag = tags::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        ticket_id: Set(input.ticket_id),
-        tag_name: Set(input.tag_name.clone()),
-    };
+    let txn = state.db.begin().await?;
 
-    let saved = tag.insert(db.as_ref()).await.map_err(|e| {
-        eprintln!("Insert error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not create tag".into())
-    })?;
+    let taken = OrganizationEntity::find()
+        .filter(organizations::Column::Slug.eq(input.slug.as_str()))
+        .one(&txn)
+        .await?;
+    if taken.is_some() {
+        return Err(AppError::BadRequest("An organization with this slug already exists".into()));
+    }
 
-    Ok(Json(TagResponse {
-        id: saved.id,
-        ticket_id: saved.ticket_id,
-        tag_name: saved.tag_name,
-    }))
+    let now = Utc::now();
+    let org = organizations::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        name: Set(input.name),
+        slug: Set(input.slug),
+        created_at: Set(now),
+    }
+    .insert(&txn)
+    .await?;
+
+    users::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        tenant_id: Set(org.id),
+        email: Set(input.admin_email),
+        name: Set(input.admin_name),
+        password_hash: Set(input.admin_password),
+        role: Set("admin".into()),
+        team: Set(None),
+        notification_channel: Set(crate::notify::INBOX.into()),
+        notification_target: Set(None),
+        deleted_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&txn)
+    .await?;
+
+    txn.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(OrganizationResponse {
+            id: org.id,
+            name: org.name,
+            slug: org.slug,
+            created_at: org.created_at,
+            user_count: 1,
+            ticket_count: 0,
+        }),
+    ))
 }
 
-// READ 
+// READ ALL
 #[utoipa::path(
     get,
-    path = "/tags/{ticket_id}",
-    params(
-        ("ticket_id" = String, Path, description = "UUID of the ticket")
-    ),
+    path = "/admin/organizations",
     responses(
-        (status = 200, description = "List of tags for a ticket", body = [TagResponse]),
-        (status = 400, description = "Invalid ticket ID"),
-        (status = 500, description = "Database error")
+        (status = 200, description = "All organizations with usage counts", body = [OrganizationResponse]),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Tag"
+    tag = "Organization"
 )]
-pub async fn get_tags_by_id(
-    Path(ticket_id): Path<String>,
+pub async fn get_organizations(
     State(state): State<AppState>,
-) -> Result<Json<Vec<TagResponse>>, (StatusCode, String)> {
-    let ticket_uuid = Uuid::parse_str(&ticket_id)
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid UUID".into()))?;
-    
-    let db = &state.db;
-    let all_tags = tags::Entity::find()
-        .filter(tags::Column::TicketId.eq(ticket_uuid))
-        .all(db.as_ref())
-        .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error".into()))?;
-
-    let response = all_tags
-        .into_iter()
-        .map(|tag| TagResponse {
-            id: tag.id,
-            tag_name: tag.tag_name,
-            ticket_id: tag.ticket_id,
-        })
-        .collect();
+    auth: AuthUser,
+) -> Result<Json<Vec<OrganizationResponse>>, AppError> {
+    require_role(&auth, auth::SUPER_ADMIN)?;
+
+    let db = state.db.as_ref();
+    let orgs = OrganizationEntity::find()
+        .order_by_asc(organizations::Column::Name)
+        .all(db)
+        .await?;
+
+    let mut response = Vec::new();
+    for org in orgs {
+        response.push(OrganizationResponse {
+            user_count: UserEntity::find_in(org.id).count(db).await?,
+            ticket_count: TicketEntity::find_in(org.id).count(db).await?,
+            id: org.id,
+            name: org.name,
+            slug: org.slug,
+            created_at: org.created_at,
+        });
+    }
 
     Ok(Json(response))
 }
 
-// DELETE
-#[utoipa::path(
-    delete,
-    path = "/tags/{tag_id}",
-    params(
-        ("tag_id" = String, Path, description = "UUID of the tag")
-    ),
-    responses(
-        (status = 204, description = "Tag deleted"),
-        (status = 404, description = "Tag not found"),
-        (status = 500, description = "Database error")
-    ),
-    tag = "Tag"
-)]
-pub async fn delete_tag(
-    State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    let db = &state.db;
+//----------jobs----------------
+// The background job queue (see jobs.rs). Admins see their organization's
+// jobs; super admins also see the platform's own, such as the trash purge.
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct JobQuery {
+    pub status: Option<String>,   // "queued", "running", "succeeded" or "dead"
+    pub kind: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
 
-    TagEntity::delete_by_id(id).exec(db.as_ref()).await.map_err(|e| {
-        eprintln!("Delete error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Could not delete tag".into())
-    })?;
+#[derive(Serialize, ToSchema)]
+pub struct JobResponse {
+    pub id: Uuid,
+    pub tenant_id: Option<Uuid>,
+    pub kind: String,
+    // Ids, numbers and times only; text is replaced (see `redact_payload`).
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: chrono::DateTime<Utc>,
+    pub locked_by: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub finished_at: Option<chrono::DateTime<Utc>>,
+}
 
-    Ok(StatusCode::NO_CONTENT)
+impl From<jobs::Model> for JobResponse {
+    fn from(j: jobs::Model) -> Self {
+        JobResponse {
+            id: j.id,
+            tenant_id: j.tenant_id,
+            kind: j.kind,
+            payload: redact_payload(j.payload),
+            status: j.status,
+            attempts: j.attempts,
+            max_attempts: j.max_attempts,
+            run_at: j.run_at,
+            locked_by: j.locked_by,
+            last_error: j.last_error,
+            created_at: j.created_at,
+            finished_at: j.finished_at,
+        }
+    }
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct JobScheduleResponse {
+    pub name: String,
+    pub kind: String,
+    pub cron: String,
+    pub next_run_at: chrono::DateTime<Utc>,
+    pub last_run_at: Option<chrono::DateTime<Utc>>,
+}
 
-//----------analytics----------------
-#[derive(Deserialize, ToSchema)]
-pub struct CreateAnalyticsInput {
-    pub date: NaiveDate,
-    pub total_tickets: i32,
-    pub resolved_tickets: i32,
-    pub avg_response_time: i64,
-    pub agent_id: Uuid,
+// Payloads carry whatever the job needs, including message text such as a
+// mention's note. The admin view keeps the shape and the ids, which is what
+// debugging a stuck job needs, and blanks every other string.
+fn redact_payload(payload: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match payload {
+        Value::String(s) if Uuid::parse_str(&s).is_ok() || chrono::DateTime::parse_from_rfc3339(&s).is_ok() => {
+            Value::String(s)
+        }
+        Value::String(_) => Value::String("[redacted]".into()),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact_payload).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(|(k, v)| (k, redact_payload(v))).collect()),
+        other => other,
+    }
 }
 
-#[derive(Serialize, ToSchema)]
-pub struct AnalyticsResponse {
-    pub id: Uuid,
-    pub date: NaiveDate,
-    pub total_tickets: i32,
-    pub resolved_tickets: i32,
-    pub avg_response_time: i64,
-    pub agent_id: Uuid,
+fn visible_jobs(auth: &AuthUser) -> Result<sea_orm::Select<JobEntity>, AppError> {
+    require_key_admin(auth)?;
+    Ok(if auth.role == auth::SUPER_ADMIN {
+        // cross-tenant: super admins look after the whole queue, including
+        // platform jobs that belong to no organization.
+        JobEntity::find()
+    } else {
+        JobEntity::find_in(auth.tenant_id)
+    })
 }
 
-// CREATE
 #[utoipa::path(
-    post,
-    path = "/analytics",
-    request_body = CreateAnalyticsInput,
+    get,
+    path = "/admin/jobs",
+    params(JobQuery),
     responses(
-        (status = 201, description = "Analytics entry created", body = AnalyticsResponse)
+        (status = 200, description = "Jobs, newest first", body = [JobResponse]),
+        (status = 400, description = "Unknown status"),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Analytics"
+    tag = "Jobs"
 )]
-pub async fn create_analytics(
+pub async fn get_jobs(
     State(state): State<AppState>,
-    Json(input): Json<CreateAnalyticsInput>,
-) -> Result<Json<AnalyticsResponse>, (StatusCode, String)> {
-    let db = &state.db;
-
-    let analytics = analytics::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        date: Set(input.date),
-        total_tickets: Set(input.total_tickets),
-        resolved_tickets: Set(input.resolved_tickets),
-        avg_response_time: Set(input.avg_response_time),
-        agent_id: Set(input.agent_id),
-    };
-
-    let saved = analytics.insert(db.as_ref()).await.map_err(|e| {
-        eprintln!("Error creating analytics: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Insert failed".into())
-    })?;
-
-    Ok(Json(AnalyticsResponse {
-        id: saved.id,
-        date: saved.date,
-        total_tickets: saved.total_tickets,
-        resolved_tickets: saved.resolved_tickets,
-        avg_response_time: saved.avg_response_time,
-        agent_id: saved.agent_id,
-    }))
+    auth: AuthUser,
+    Query(query): Query<JobQuery>,
+) -> Result<Json<Vec<JobResponse>>, AppError> {
+    let mut select = visible_jobs(&auth)?;
+    if let Some(status) = &query.status {
+        if !crate::jobs::STATUSES.contains(&status.as_str()) {
+            return Err(AppError::BadRequest(format!(
+                "Unknown status; expected one of {}",
+                crate::jobs::STATUSES.join(", ")
+            )));
+        }
+        select = select.filter(jobs::Column::Status.eq(status.as_str()));
+    }
+    if let Some(kind) = &query.kind {
+        select = select.filter(jobs::Column::Kind.eq(kind.as_str()));
+    }
+    let rows = select
+        .order_by_desc(jobs::Column::CreatedAt)
+        .limit(query.limit.unwrap_or(50).min(200))
+        .offset(query.offset.unwrap_or(0))
+        .all(state.db.as_ref())
+        .await?;
+    Ok(Json(rows.into_iter().map(JobResponse::from).collect()))
 }
 
-// READ ALL
 #[utoipa::path(
     get,
-    path = "/analytics",
-    params(Pagination),
+    path = "/admin/jobs/{id}",
+    params(("id" = Uuid, Path, description = "Job id")),
     responses(
-        (status = 200, description = "All analytics data", body = [AnalyticsResponse])
+        (status = 200, description = "The job, with its payload and last error", body = JobResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Job not found")
     ),
-    tag = "Analytics"
+    tag = "Jobs"
 )]
-pub async fn get_analytics(
+pub async fn get_job(
+    Path(id): Path<Uuid>,
     State(state): State<AppState>,
-    // auth: AuthUser,
-    Query(pagination): Query<Pagination>,
-) -> Result<Json<Vec<AnalyticsResponse>>, AppError> {
-    let db = &state.db;
-
-    let list = AnalyticsEntity::find()
-        .limit(pagination.limit.unwrap_or(10))
-        .offset(pagination.offset.unwrap_or(0))
-        .all(db.as_ref())
-        .await
-        .map_err(|e| {
-            eprintln!("Fetch error: {}", e);
-        AppError::Internal("Could not fetch analytics".into())
-    })?;
+    auth: AuthUser,
+) -> Result<Json<JobResponse>, AppError> {
+    let job = visible_jobs(&auth)?
+        .filter(jobs::Column::Id.eq(id))
+        .one(state.db.as_ref())
+        .await?
+        .ok_or_else(|| AppError::NotFound("Job not found".into()))?;
+    Ok(Json(job.into()))
+}
 
-    let response = list.into_iter().map(|a| AnalyticsResponse {
-        id: a.id,
-        date: a.date,
-        total_tickets: a.total_tickets,
-        resolved_tickets: a.resolved_tickets,
-        avg_response_time: a.avg_response_time,
-        agent_id: a.agent_id,
-    }).collect();
+// A dead job gets a fresh set of attempts; a queued one waiting out a retry
+// delay runs now.
+#[utoipa::path(
+    post,
+    path = "/admin/jobs/{id}/retry",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "Job queued to run now", body = JobResponse),
+        (status = 403, description = "Forbidden"),
+        (status = 404, description = "Job not found"),
+        (status = 409, description = "Job is running or has succeeded")
+    ),
+    tag = "Jobs"
+)]
+pub async fn retry_job(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    auth: AuthUser,
+    ClientIp(ip): ClientIp,
+) -> Result<Json<JobResponse>, AppError> {
+    let actor = AuditActor::of(&auth)?;
+    let txn = state.db.begin().await?;
+    let job = visible_jobs(&auth)?
+        .filter(jobs::Column::Id.eq(id))
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Job not found".into()))?;
+    let (status, job_tenant) = (job.status.clone(), job.tenant_id);
+    if status != crate::jobs::DEAD && status != crate::jobs::QUEUED {
+        return Err(AppError::Conflict(format!("Job is {}", status)));
+    }
 
-    Ok(Json(response))
+    let now = Utc::now();
+    let mut active = jobs::ActiveModel::from(job);
+    if status == crate::jobs::DEAD {
+        active.status = Set(crate::jobs::QUEUED.into());
+        active.attempts = Set(0);
+        active.finished_at = Set(None);
+    }
+    active.run_at = Set(now);
+    active.updated_at = Set(now);
+    // Only if a worker has not claimed it in the meantime.
+    let update = match job_tenant {
+        Some(tenant_id) => JobEntity::update_many_in(tenant_id),
+        // cross-tenant: a platform job, found through visible_jobs, which
+        // only shows those to super admins.
+        None => JobEntity::update_many(),
+    };
+    let updated = update
+        .set(active)
+        .filter(jobs::Column::Id.eq(id))
+        .filter(jobs::Column::Status.eq(status.as_str()))
+        .exec_with_returning(&txn)
+        .await?
+        .pop()
+        .ok_or_else(|| AppError::Conflict("Job changed while retrying; try again".into()))?;
+
+    record_audit(&txn, &actor, "job_retried", "job", id, &ip).await?;
+    txn.commit().await?;
+    Ok(Json(updated.into()))
 }
-//READ by ID
+
 #[utoipa::path(
     get,
-    path = "/analytics/{id}",
+    path = "/admin/job-schedules",
     responses(
-        (status = 200, description = "Analytics by ID", body = AnalyticsResponse),
-        (status = 404, description = "Not found")
+        (status = 200, description = "Recurring jobs and when they next run", body = [JobScheduleResponse]),
+        (status = 403, description = "Forbidden")
     ),
-    tag = "Analytics"
+    tag = "Jobs"
 )]
-#[debug_handler]
-pub async fn get_analytics_by_id(
-    Path(id): Path<String>,
+pub async fn get_job_schedules(
     State(state): State<AppState>,
     auth: AuthUser,
-) -> Result<Json<AnalyticsResponse>, AppError> {
-    require_role(&auth, "admin")?;
-
-    let uuid = Uuid::parse_str(&id).map_err(|_| AppError::BadRequest("Invalid ID".into()))?;
-    let db = &state.db;
-    let found = analytics::Entity::find_by_id(uuid)
-        .one(db.as_ref())
-        .await
-        .map_err(|_| AppError::Db(()))?
-        .ok_or(AppError::NotFound("Not found".into()))?;
-
-    Ok(Json(AnalyticsResponse {
-        id: found.id,
-        date: found.date,
-        total_tickets: found.total_tickets,
-        resolved_tickets: found.resolved_tickets,
-        avg_response_time: found.avg_response_time,
-        agent_id: found.agent_id,
-    }))
+) -> Result<Json<Vec<JobScheduleResponse>>, AppError> {
+    require_role(&auth, auth::SUPER_ADMIN)?;
+    let rows = JobScheduleEntity::find()
+        .order_by_asc(job_schedules::Column::Name)
+        .all(state.db.as_ref())
+        .await?;
+    Ok(Json(
+        rows.into_iter()
+            .map(|s| JobScheduleResponse {
+                name: s.name,
+                kind: s.kind,
+                cron: s.cron,
+                next_run_at: s.next_run_at,
+                last_run_at: s.last_run_at,
+            })
+            .collect(),
+    ))
 }
 
 // //----------audit_logs----------------
@@ -1641,4 +8073,57 @@ pub async fn get_analytics_by_id(
 //         .map_err(|e| e.to_string())?;
 
 //     Ok(Json("Reply added".to_string()))
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_ids_keeps_first_occurrence_in_order() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(unique_ids(&[b, a, b, c, a]), vec![b, a, c]);
+        assert!(unique_ids(&[]).is_empty());
+    }
+
+    #[test]
+    fn tag_filter_names_fold_case_and_repeats() {
+        assert_eq!(tag_filter_names("billing, Billing,,BILLING , vip"), vec!["billing", "vip"]);
+        assert!(tag_filter_names(" , ").is_empty());
+    }
+
+    #[test]
+    fn email_domain_is_the_folded_part_after_the_last_at() {
+        assert_eq!(email_domain("Jo@Example.COM").as_deref(), Some("example.com"));
+        assert_eq!(email_domain("\"a@b\"@corp.io").as_deref(), Some("corp.io"));
+        assert_eq!(email_domain("nobody@"), None);
+        assert_eq!(email_domain("no-at-sign"), None);
+    }
+
+    #[test]
+    fn job_payloads_keep_ids_and_times_but_not_text() {
+        let id = Uuid::new_v4();
+        let payload = serde_json::json!({
+            "user_id": id,
+            "notice": {
+                "text": "Sam mentioned you: the customer's card ends 4242",
+                "created_at": "2026-03-01T09:30:00Z",
+                "mentioned_by": null,
+                "tags": ["vip"],
+            },
+            "rows": 12,
+        });
+        assert_eq!(
+            redact_payload(payload),
+            serde_json::json!({
+                "user_id": id,
+                "notice": {
+                    "text": "[redacted]",
+                    "created_at": "2026-03-01T09:30:00Z",
+                    "mentioned_by": null,
+                    "tags": ["[redacted]"],
+                },
+                "rows": 12,
+            })
+        );
+    }
+}
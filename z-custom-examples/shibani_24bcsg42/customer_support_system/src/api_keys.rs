@@ -0,0 +1,258 @@
+use axum::http::{HeaderMap, Method};
+use chrono::Utc;
+use rand::RngCore;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use crate::auth::{self, AuthUser};
+use crate::entity::{api_keys, users};
+use crate::error_handle::AppError;
+use crate::tenant::TenantScoped;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+const KEY_PREFIX: &str = "csk_";
+// Characters of the key kept in clear so admins can tell keys apart.
+const SHOWN_PREFIX_LEN: usize = 12;
+
+// Resources a key can be scoped to, each as "<resource>:read" or
+// "<resource>:write"; write includes read. The route groups they cover are in
+// `resource_of`.
+pub const SCOPE_RESOURCES: [&str; 6] = ["tickets", "customers", "kb", "analytics", "users", "bulk"];
+
+// A new key: what to hand to the caller once, and what to store.
+pub struct NewKey {
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+pub fn generate() -> NewKey {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let key = format!("{}{}", KEY_PREFIX, hex::encode(secret));
+    NewKey {
+        prefix: key[..SHOWN_PREFIX_LEN].to_string(),
+        hash: hash(&key),
+        key,
+    }
+}
+
+pub fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+// The key sent with a request, from `X-API-Key: <key>` or
+// `Authorization: ApiKey <key>`.
+pub fn from_headers(headers: &HeaderMap) -> Option<&str> {
+    if let Some(value) = headers.get(API_KEY_HEADER) {
+        return value.to_str().ok().map(str::trim);
+    }
+    headers
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("ApiKey ")
+        .map(str::trim)
+}
+
+// Checks requested scopes and returns them in stored form.
+pub fn validate_scopes(scopes: &[String]) -> Result<String, String> {
+    if scopes.is_empty() {
+        return Err("At least one scope is required".into());
+    }
+    for scope in scopes {
+        let valid = scope
+            .split_once(':')
+            .is_some_and(|(resource, access)| SCOPE_RESOURCES.contains(&resource) && matches!(access, "read" | "write"));
+        if !valid {
+            return Err(format!("Unknown scope {}", scope));
+        }
+    }
+    Ok(scopes.join(" "))
+}
+
+// Checks an allow-list entry: an address or a CIDR range.
+pub fn validate_ip_rule(rule: &str) -> Result<(), String> {
+    let invalid = || format!("Invalid address or range {}", rule);
+    let (addr, bits) = match rule.split_once('/') {
+        Some((addr, bits)) => (addr, Some(bits)),
+        None => (rule, None),
+    };
+    let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    match bits.map(str::parse::<u32>) {
+        None => Ok(()),
+        Some(Ok(bits)) if bits <= max => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn ip_matches(rule: &str, ip: IpAddr) -> bool {
+    let (addr, bits) = match rule.split_once('/') {
+        Some((addr, bits)) => (addr, bits.parse::<u32>().ok()),
+        None => (rule, None),
+    };
+    match (addr.parse::<IpAddr>(), ip) {
+        (Ok(IpAddr::V4(net)), IpAddr::V4(ip)) => {
+            let bits = bits.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (Ok(IpAddr::V6(net)), IpAddr::V6(ip)) => {
+            let bits = bits.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+// Which scope resource a route belongs to, by its first path segment. Routes
+// outside these groups (admin tools, personal inboxes, key management) are
+// closed to keys.
+fn resource_of(path: &str) -> Option<&'static str> {
+    let segment = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    match segment {
+        "tickets" | "communications" | "tags" => Some("tickets"),
+        "customers" | "companies" => Some("customers"),
+        "kb" => Some("kb"),
+        "analytics" => Some("analytics"),
+        "users" => Some("users"),
+        "import" | "imports" | "export" => Some("bulk"),
+        _ => None,
+    }
+}
+
+fn scope_allows(scopes: &str, method: &Method, path: &str) -> bool {
+    let Some(resource) = resource_of(path) else {
+        return false;
+    };
+    // Creating users is how roles are handed out; a leaked key must not be
+    // able to mint admins, whatever its scopes.
+    if *method == Method::POST && path.trim_end_matches('/') == "/users" {
+        return false;
+    }
+    let read_only = matches!(*method, Method::GET | Method::HEAD);
+    scopes.split_whitespace().any(|scope| match scope.split_once(':') {
+        Some((r, "write")) => r == resource,
+        Some((r, "read")) => r == resource && read_only,
+        _ => false,
+    })
+}
+
+// Resolves a key to the caller it acts as. Keys act with the admin role in
+// their organization, narrowed to their scopes, and on behalf of the admin who
+// created them. The creator is looked up on every request: a key stops working
+// once they are deleted or are no longer an admin.
+//
+// The allow-list is checked against the connecting address, not
+// X-Forwarded-For, which any client can set.
+pub async fn authenticate(
+    db: &DatabaseConnection,
+    key: &str,
+    peer: Option<IpAddr>,
+    method: &Method,
+    path: &str,
+) -> Result<AuthUser, AppError> {
+    // cross-tenant: the key is the credential and decides the tenant.
+    let record = api_keys::Entity::find()
+        .filter(api_keys::Column::KeyHash.eq(hash(key)))
+        .one(db)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    let now = Utc::now();
+    if record.revoked_at.is_some() || record.expires_at.is_some_and(|at| at <= now) {
+        return Err(AppError::Unauthorized);
+    }
+    if let Some(rules) = &record.allowed_ips {
+        let allowed = peer.is_some_and(|ip| rules.split_whitespace().any(|rule| ip_matches(rule, ip)));
+        if !allowed {
+            return Err(AppError::Forbidden);
+        }
+    }
+    if !scope_allows(&record.scopes, method, path) {
+        return Err(AppError::Forbidden);
+    }
+
+    let creator = users::Entity::find_by_id_in(record.created_by, record.tenant_id)
+        .one(db)
+        .await?;
+    if !creator.is_some_and(|u| matches!(u.role.as_str(), "admin" | auth::SUPER_ADMIN)) {
+        return Err(AppError::Unauthorized);
+    }
+
+    api_keys::Entity::update_many_in(record.tenant_id)
+        .col_expr(api_keys::Column::LastUsedAt, Expr::value(now))
+        .col_expr(api_keys::Column::LastUsedIp, Expr::value(peer.map(|ip| ip.to_string())))
+        .filter(api_keys::Column::Id.eq(record.id))
+        .exec(db)
+        .await?;
+
+    Ok(AuthUser {
+        u_id: record.created_by.to_string(),
+        role: "admin".into(),
+        tenant_id: record.tenant_id,
+        api_key_id: Some(record.id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn scopes_must_name_a_resource_and_an_access() {
+        let scopes = |list: &[&str]| validate_scopes(&list.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        assert_eq!(scopes(&["tickets:read", "kb:write"]), Ok("tickets:read kb:write".into()));
+        assert!(scopes(&[]).is_err());
+        assert!(scopes(&["tickets"]).is_err());
+        assert!(scopes(&["tickets:admin"]).is_err());
+        assert!(scopes(&["admin:write"]).is_err());
+        assert!(scopes(&["Tickets:read"]).is_err());
+    }
+
+    #[test]
+    fn ip_rules_match_addresses_and_ranges() {
+        assert!(ip_matches("203.0.113.7", ip("203.0.113.7")));
+        assert!(!ip_matches("203.0.113.7", ip("203.0.113.8")));
+        assert!(ip_matches("10.0.0.0/8", ip("10.200.3.4")));
+        assert!(!ip_matches("10.0.0.0/8", ip("11.0.0.1")));
+        assert!(ip_matches("0.0.0.0/0", ip("198.51.100.1")));
+        assert!(ip_matches("2001:db8::/32", ip("2001:db8:1::5")));
+        assert!(!ip_matches("2001:db8::/32", ip("2001:db9::5")));
+        // Families never match each other, and bad rules match nothing.
+        assert!(!ip_matches("10.0.0.0/8", ip("::ffff:10.0.0.1")));
+        assert!(!ip_matches("junk", ip("10.0.0.1")));
+
+        assert!(validate_ip_rule("10.0.0.0/8").is_ok());
+        assert!(validate_ip_rule("::1").is_ok());
+        assert!(validate_ip_rule("10.0.0.0/33").is_err());
+        assert!(validate_ip_rule("10.0.0/8").is_err());
+    }
+
+    #[test]
+    fn scopes_allow_their_routes_and_write_includes_read() {
+        assert!(scope_allows("tickets:read", &Method::GET, "/tickets/1"));
+        assert!(!scope_allows("tickets:read", &Method::PATCH, "/tickets/1/status"));
+        assert!(scope_allows("tickets:write", &Method::PATCH, "/tickets/1/status"));
+        assert!(scope_allows("tickets:write", &Method::GET, "/communications"));
+        assert!(!scope_allows("tickets:write", &Method::GET, "/customers"));
+        assert!(scope_allows("kb:read customers:write", &Method::POST, "/customers"));
+    }
+
+    #[test]
+    fn keys_cannot_reach_ungrouped_routes_or_create_users() {
+        let all = SCOPE_RESOURCES.map(|r| format!("{}:write", r)).join(" ");
+        assert!(!scope_allows(&all, &Method::GET, "/admin/jobs"));
+        assert!(!scope_allows(&all, &Method::POST, "/admin/api-keys"));
+        assert!(!scope_allows(&all, &Method::POST, "/users"));
+        assert!(!scope_allows(&all, &Method::POST, "/users/"));
+        assert!(scope_allows(&all, &Method::PUT, "/users/1"));
+    }
+}
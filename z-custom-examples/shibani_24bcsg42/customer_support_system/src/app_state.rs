@@ -1,7 +1,15 @@
 use sea_orm::DatabaseConnection;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use crate::auth::JwtKeys;
+use crate::config::Config;
+use crate::kb_index::KbIndex;
+use crate::workers::Workers;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<DatabaseConnection>,
+    pub kb_index: Arc<RwLock<KbIndex>>,
+    pub config: Arc<Config>,
+    pub jwt: Arc<JwtKeys>,
+    pub workers: Workers,
 }
@@ -1,18 +1,66 @@
 use axum::{
     extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    extract::ConnectInfo,
+    http::{request::Parts, HeaderMap, StatusCode},
 };
+use std::net::SocketAddr;
+use crate::api_keys;
+use crate::app_state::AppState;
 use jsonwebtoken::{decode, DecodingKey, EncodingKey, Validation, Header, encode};
-use serde::{Deserialize, Serialize};
-use std::env;
-use chrono::{Duration, Utc};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use chrono::{DateTime, Duration, Utc};
+use crate::config::AuthConfig;
 use crate::error_handle::{AppError};
+use crate::locale::Text;
+use uuid::Uuid;
+
+// Signing and verification keys, built once from the config.
+pub struct JwtKeys {
+    encoding: EncodingKey,
+    // The current secret first, then secrets being rotated out.
+    decoding: Vec<DecodingKey>,
+    token_lifetime: Duration,
+}
+
+impl JwtKeys {
+    pub fn from_config(auth: &AuthConfig) -> Self {
+        let decoding = std::iter::once(&auth.jwt_secret)
+            .chain(&auth.previous_jwt_secrets)
+            .map(|secret| DecodingKey::from_secret(secret.as_bytes()))
+            .collect();
+        JwtKeys {
+            encoding: EncodingKey::from_secret(auth.jwt_secret.as_bytes()),
+            decoding,
+            token_lifetime: Duration::hours(auth.token_lifetime_hours),
+        }
+    }
+
+    fn encode<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+        encode(&Header::default(), claims, &self.encoding)
+    }
+
+    // Tries each key in turn. A token signed with a known key but otherwise
+    // bad (expired, say) reports that rather than a signature mismatch.
+    fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, JwtError> {
+        let mut last = None;
+        for key in &self.decoding {
+            match decode::<T>(token, key, &Validation::default()) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) if *e.kind() == ErrorKind::InvalidSignature => last = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| ErrorKind::InvalidSignature.into()))
+    }
+}
 
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Claims {
     pub sub: String,
     pub role: String,
+    pub tenant_id: String,
     pub exp: usize,
 }
 
@@ -20,81 +68,190 @@ pub struct Claims {
 pub struct AuthUser {
     pub u_id: String,
     pub role: String,
+    pub tenant_id: Uuid,
+    // Set when the request was made with an API key rather than a login token.
+    pub api_key_id: Option<Uuid>,
+}
+
+pub const SUPER_ADMIN: &str = "super_admin";
+
+// Super admins may act inside another organization by naming it in this header.
+pub const TENANT_OVERRIDE_HEADER: &str = "X-Tenant-Id";
+
+impl AuthUser {
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin" || self.role == SUPER_ADMIN
+    }
+
+    // Fills in the caller fields left empty on the request span.
+    fn record_in_span(&self) {
+        let span = tracing::Span::current();
+        span.record("user_id", self.u_id.as_str());
+        span.record("tenant_id", tracing::field::display(self.tenant_id));
+        if let Some(key) = self.api_key_id {
+            span.record("api_key_id", tracing::field::display(key));
+        }
+    }
 }
 
 // #[async_trait]
-impl<S> FromRequestParts<S> for AuthUser
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<AppState> for AuthUser {
     type Rejection = (StatusCode, AppError);
 
     async fn from_request_parts(
         parts: &mut Parts,
-        _state: &S,
+        state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let auth_header = parts.headers.get("Authorization")
-            .ok_or((StatusCode::UNAUTHORIZED, AppError::Unauthorized))?
-            .to_str().map_err(|_| {
-                (StatusCode::BAD_REQUEST, AppError::BadRequest("Invalid Authorization header".to_string()))
-        })?;
+        let user = authenticate(parts, state).await?;
+        user.record_in_span();
+        Ok(user)
+    }
+}
 
+async fn authenticate(parts: &Parts, state: &AppState) -> Result<AuthUser, (StatusCode, AppError)> {
+    if let Some(key) = api_keys::from_headers(&parts.headers) {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip());
+        return api_keys::authenticate(&state.db, key, peer, &parts.method, parts.uri.path())
+            .await
+            .map_err(|e| match e {
+                AppError::Forbidden => (StatusCode::FORBIDDEN, e),
+                AppError::Db(_) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+                _ => (StatusCode::UNAUTHORIZED, e),
+            });
+    }
 
-        if !auth_header.starts_with("Bearer ") {
-           return Err((StatusCode::UNAUTHORIZED, AppError::Unauthorized));
-        }
+    let auth_header = parts.headers.get("Authorization")
+        .ok_or((StatusCode::UNAUTHORIZED, AppError::Unauthorized))?
+        .to_str().map_err(|_| {
+            (StatusCode::BAD_REQUEST, AppError::BadRequest("Invalid Authorization header".to_string()))
+    })?;
 
-        let token = auth_header.trim_start_matches("Bearer ").trim();
 
-        let secret = env::var("JWT_SECRET")
-                    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, AppError::Internal("JWT_SECRET not set".to_string())))?;
-        let decoding_key = DecodingKey::from_secret(secret.as_bytes());
+    if !auth_header.starts_with("Bearer ") {
+       return Err((StatusCode::UNAUTHORIZED, AppError::Unauthorized));
+    }
 
-        let decoded = decode::<Claims>(
-        token,
-        &decoding_key,
-        &Validation::default()
-         ).map_err(|_err| {
-            (StatusCode::UNAUTHORIZED, AppError::Unauthorized)
-        })?;
+    let token = auth_header.trim_start_matches("Bearer ").trim();
 
-        Ok(AuthUser {
-            u_id: decoded.claims.sub,
-            role: decoded.claims.role,
-        })
+    let claims = state.jwt.decode::<Claims>(token).map_err(|_err| {
+        (StatusCode::UNAUTHORIZED, AppError::Unauthorized)
+    })?;
+
+    let mut tenant_id = Uuid::parse_str(&claims.tenant_id)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, AppError::Unauthorized))?;
+
+    if let Some(value) = parts.headers.get(TENANT_OVERRIDE_HEADER) {
+        if claims.role != SUPER_ADMIN {
+            return Err((StatusCode::FORBIDDEN, AppError::Forbidden));
+        }
+        tenant_id = value
+            .to_str()
+            .ok()
+            .and_then(|v| Uuid::parse_str(v).ok())
+            .ok_or((StatusCode::BAD_REQUEST, AppError::BadRequest("Invalid X-Tenant-Id header".to_string())))?;
     }
+
+    Ok(AuthUser {
+        u_id: claims.sub,
+        role: claims.role,
+        tenant_id,
+        api_key_id: None,
+    })
 }
 
-pub fn generate_jwt(user_id: &str, role: &str) -> String {
+pub fn generate_jwt(keys: &JwtKeys, user_id: &str, role: &str, tenant_id: &str) -> String {
     let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
+        .checked_add_signed(keys.token_lifetime)
         .expect("valid timestamp")
         .timestamp() as usize;
 
     let claims = Claims {
         sub: user_id.to_owned(),
         role: role.to_owned(),
+        tenant_id: tenant_id.to_owned(),
         exp: expiration,
     };
 
-    let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let encoding_key = EncodingKey::from_secret(jwt_secret.as_bytes());
+    keys.encode(&claims).expect("Token creation failed")
+}
+
+// User id from a valid bearer token, without the checks the AuthUser extractor
+// does. Used by middleware that only needs to tell callers apart.
+pub fn bearer_subject(keys: &JwtKeys, headers: &HeaderMap) -> Option<String> {
+    let token = headers
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .trim();
+    keys.decode::<Claims>(token).ok().map(|claims| claims.sub)
+}
+
+// CSAT survey links carry their own signed token so customers can answer without logging in.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SurveyClaims {
+    pub sub: String,
+    pub purpose: String,
+    pub exp: usize,
+}
+
+const SURVEY_PURPOSE: &str = "csat";
 
+pub fn generate_survey_token(keys: &JwtKeys, survey_id: &str, expires_at: DateTime<Utc>) -> Result<String, AppError> {
+    let claims = SurveyClaims {
+        sub: survey_id.to_owned(),
+        purpose: SURVEY_PURPOSE.to_owned(),
+        exp: expires_at.timestamp() as usize,
+    };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &encoding_key,
-    )
-    .expect("Token creation failed")
+    keys.encode(&claims)
+        .map_err(|_| AppError::Internal("Survey token creation failed".to_string()))
 }
 
+// Errors are in the caller's language; survey links are opened by customers.
+pub fn decode_survey_token(keys: &JwtKeys, token: &str, requested: &[String]) -> Result<SurveyClaims, AppError> {
+    let claims = keys.decode::<SurveyClaims>(token)
+        .map_err(|err| match err.kind() {
+            ErrorKind::ExpiredSignature => AppError::BadRequest(Text::SurveyExpired.pick(requested)),
+            _ => AppError::BadRequest(Text::SurveyInvalidToken.pick(requested)),
+        })?;
+
+    if claims.purpose != SURVEY_PURPOSE {
+        return Err(AppError::BadRequest(Text::SurveyInvalidToken.pick(requested)));
+    }
+
+    Ok(claims)
+}
 
 //verify
+// A super admin passes admin checks, but is not a customer or an agent.
 pub fn require_role(user: &AuthUser, required_role: &str) -> Result<(), AppError> {
+    if user.role == SUPER_ADMIN && required_role == "admin" {
+        return Ok(());
+    }
     if user.role != required_role {
         Err(AppError::Forbidden)
     } else {
         Ok(())
     }
-}   
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(role: &str) -> AuthUser {
+        AuthUser { u_id: Uuid::nil().to_string(), role: role.into(), tenant_id: Uuid::nil(), api_key_id: None }
+    }
+
+    #[test]
+    fn super_admins_pass_admin_checks_only() {
+        assert!(require_role(&user(SUPER_ADMIN), "admin").is_ok());
+        assert!(require_role(&user(SUPER_ADMIN), SUPER_ADMIN).is_ok());
+        assert!(require_role(&user(SUPER_ADMIN), "customer").is_err());
+        assert!(require_role(&user("admin"), SUPER_ADMIN).is_err());
+        assert!(require_role(&user("customer"), "customer").is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+use axum::body::{Body, Bytes};
+use futures_util::TryStreamExt;
+use sea_orm::DbErr;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::future::Future;
+use std::io::{self, BufRead};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// Rows parsed ahead of the database work; keeps memory flat on large files.
+const ROW_BUFFER: usize = 256;
+pub const EXPORT_PAGE_SIZE: u64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "csv" => Some(Format::Csv),
+            "jsonl" => Some(Format::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Jsonl => "application/x-ndjson",
+        }
+    }
+}
+
+// One input record; `row` is 1-based and excludes the CSV header line.
+pub struct Row<T> {
+    pub row: u64,
+    pub value: Result<T, String>,
+}
+
+// Parses the request body on a blocking thread and hands rows over one at a
+// time, so an import never holds the whole file in memory.
+pub fn read_rows<T>(body: Body, format: Format) -> mpsc::Receiver<Row<T>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(ROW_BUFFER);
+    let stream = body.into_data_stream().map_err(io::Error::other);
+    let reader = SyncIoBridge::new(StreamReader::new(stream));
+
+    tokio::task::spawn_blocking(move || match format {
+        Format::Csv => {
+            let mut csv = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+            for (i, record) in csv.deserialize::<T>().enumerate() {
+                let broken = record.as_ref().is_err_and(|e| e.is_io_error());
+                let row = Row { row: i as u64 + 1, value: record.map_err(|e| e.to_string()) };
+                if tx.blocking_send(row).is_err() || broken {
+                    break;
+                }
+            }
+        }
+        Format::Jsonl => {
+            let mut row = 0;
+            for line in io::BufReader::new(reader).lines() {
+                let (value, broken) = match line {
+                    Ok(line) if line.trim().is_empty() => continue,
+                    Ok(line) => (serde_json::from_str(&line).map_err(|e| e.to_string()), false),
+                    Err(e) => (Err(e.to_string()), true),
+                };
+                row += 1;
+                if tx.blocking_send(Row { row, value }).is_err() || broken {
+                    break;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+// Collects failed rows for the downloadable error report.
+#[derive(Default)]
+pub struct ErrorReport {
+    rows: Vec<(u64, String, String)>,
+}
+
+impl ErrorReport {
+    pub fn push(&mut self, row: u64, external_id: &str, error: impl Into<String>) {
+        self.rows.push((row, external_id.to_string(), error.into()));
+    }
+
+    // `earlier` followed by these rows, for a report written in parts. The
+    // header comes first, once.
+    pub fn append_to(&self, earlier: &str) -> String {
+        let mut out = csv::Writer::from_writer(earlier.as_bytes().to_vec());
+        if earlier.is_empty() {
+            let _ = out.write_record(["row", "external_id", "error"]);
+        }
+        for (row, external_id, error) in &self.rows {
+            let _ = out.write_record([row.to_string().as_str(), external_id, error]);
+        }
+        String::from_utf8(out.into_inner().unwrap_or_default()).unwrap_or_default()
+    }
+}
+
+pub fn encode_rows<T: Serialize>(rows: &[T], format: Format, with_header: bool) -> Result<Bytes, String> {
+    match format {
+        Format::Csv => {
+            let mut out = csv::WriterBuilder::new()
+                .has_headers(with_header)
+                .from_writer(Vec::new());
+            for row in rows {
+                out.serialize(row).map_err(|e| e.to_string())?;
+            }
+            out.into_inner().map(Bytes::from).map_err(|e| e.to_string())
+        }
+        Format::Jsonl => {
+            let mut out = Vec::new();
+            for row in rows {
+                serde_json::to_writer(&mut out, row).map_err(|e| e.to_string())?;
+                out.push(b'\n');
+            }
+            Ok(Bytes::from(out))
+        }
+    }
+}
+
+// Export rows are fetched in id order; the key of the last row of a page is
+// where the next page starts.
+pub trait Keyed {
+    fn key(&self) -> Uuid;
+}
+
+// Streams an export page by page from a background task. `fetch` is given the
+// key of the last row already sent and returns the next page.
+pub fn export_body<T, F, Fut>(format: Format, mut fetch: F) -> Body
+where
+    T: Serialize + Keyed + Send + 'static,
+    F: FnMut(Option<Uuid>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Vec<T>, DbErr>> + Send,
+{
+    let (tx, rx) = mpsc::channel::<Result<Bytes, io::Error>>(4);
+
+    tokio::spawn(async move {
+        let mut after = None;
+        let mut first = true;
+        loop {
+            let page = match fetch(after).await {
+                Ok(page) => page,
+                Err(e) => {
+                    let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+                    return;
+                }
+            };
+            let Some(last) = page.last() else {
+                return;
+            };
+            after = Some(last.key());
+
+            let chunk = encode_rows(&page, format, first).map_err(io::Error::other);
+            first = false;
+            if tx.send(chunk).await.is_err() || (page.len() as u64) < EXPORT_PAGE_SIZE {
+                return;
+            }
+        }
+    });
+
+    Body::from_stream(ReceiverStream::new(rx))
+}
@@ -0,0 +1,140 @@
+use axum::{
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel, Iterable,
+    PrimaryKeyToColumn, QueryFilter, Value,
+};
+use sea_orm::sea_query::ValueTuple;
+use serde::Serialize;
+use crate::entity::{customers, knowledge_base, tickets};
+use crate::error_handle::AppError;
+
+// Tables with a `version` column that every update increments.
+pub trait Versioned: EntityTrait {
+    fn version_column() -> Self::Column;
+}
+
+macro_rules! versioned {
+    ($($module:ident),* $(,)?) => {
+        $(
+            impl Versioned for $module::Entity {
+                fn version_column() -> Self::Column {
+                    $module::Column::Version
+                }
+            }
+        )*
+    };
+}
+
+versioned!(tickets, customers, knowledge_base);
+
+pub fn etag(version: i32) -> String {
+    format!("\"v{}\"", version)
+}
+
+// Adds the ETag of `version` to a response.
+pub fn with_etag<T: IntoResponse>(version: i32, body: T) -> Response {
+    ([(header::ETAG, etag(version))], body).into_response()
+}
+
+// The versions a client is willing to overwrite, from its If-Match header.
+pub enum IfMatch {
+    Any,
+    Versions(Vec<i32>),
+}
+
+impl IfMatch {
+    // Updates must say which version they were based on.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let value = headers
+            .get(header::IF_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .ok_or(AppError::PreconditionRequired)?;
+        if value.trim() == "*" {
+            return Ok(IfMatch::Any);
+        }
+
+        let versions = value
+            .split(',')
+            .filter_map(|tag| {
+                let tag = tag.trim().trim_start_matches("W/").trim_matches('"');
+                tag.strip_prefix('v')?.parse().ok()
+            })
+            .collect();
+        Ok(IfMatch::Versions(versions))
+    }
+
+    // Fails with 412 and the current representation when the client's copy is stale.
+    pub fn check<T: Serialize>(&self, version: i32, current: impl FnOnce() -> T) -> Result<(), AppError> {
+        match self {
+            IfMatch::Versions(versions) if !versions.contains(&version) => Err(stale(version, &current())),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn stale<T: Serialize>(version: i32, current: &T) -> AppError {
+    AppError::PreconditionFailed {
+        etag: etag(version),
+        current: serde_json::to_value(current).unwrap_or_default(),
+    }
+}
+
+pub enum Saved<M> {
+    Updated(M),
+    // Someone else saved first; this is the row as it is now.
+    Stale(M),
+    Gone,
+}
+
+impl<M> Saved<M> {
+    // `respond` gives the version and the representation to send back on 412.
+    pub fn or_stale<T: Serialize>(self, respond: impl FnOnce(M) -> (i32, T)) -> Result<M, AppError> {
+        match self {
+            Saved::Updated(model) => Ok(model),
+            Saved::Stale(model) => {
+                let (version, current) = respond(model);
+                Err(stale(version, &current))
+            }
+            Saved::Gone => Err(AppError::NotFound("Record no longer exists".into())),
+        }
+    }
+}
+
+// Saves `active` only if the row is still at `expected`, bumping the version.
+// The check is part of the UPDATE itself, so two writers cannot both pass it.
+pub async fn save_versioned<A, C>(db: &C, mut active: A, expected: i32) -> Result<Saved<<A::Entity as EntityTrait>::Model>, DbErr>
+where
+    A: ActiveModelTrait + Send,
+    A::Entity: Versioned,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+    C: ConnectionTrait,
+{
+    let column = <A::Entity as Versioned>::version_column();
+    let key = active.get_primary_key_value();
+    active.set(column, Value::Int(Some(expected + 1)));
+
+    match <A::Entity as EntityTrait>::update(active)
+        .filter(column.eq(expected))
+        .exec(db)
+        .await
+    {
+        Ok(model) => Ok(Saved::Updated(model)),
+        Err(DbErr::RecordNotUpdated) => {
+            let Some(ValueTuple::One(id)) = key else {
+                return Ok(Saved::Gone);
+            };
+            let Some(primary_key) = <<A::Entity as EntityTrait>::PrimaryKey as Iterable>::iter().next() else {
+                return Ok(Saved::Gone);
+            };
+            let current = <A::Entity as EntityTrait>::find()
+                .filter(primary_key.into_column().eq(id))
+                .one(db)
+                .await?;
+            Ok(current.map_or(Saved::Gone, Saved::Stale))
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,708 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use toml::{Table, Value};
+use crate::rate_limit::RateLimits;
+
+// Settings are read once at startup, in this order, each layer overriding the
+// one before:
+//
+//   1. defaults in this file
+//   2. config.toml (or the file named by CONFIG_FILE)
+//   3. config.<profile>.toml, where the profile comes from APP_PROFILE (dev by default)
+//   4. environment variables: APP__<SECTION>__<KEY>, e.g. APP__DATABASE__MAX_CONNECTIONS=20,
+//      plus DATABASE_URL, JWT_SECRET and TRASH_RETENTION_DAYS
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const ENV_PREFIX: &str = "APP__";
+
+// Plain variables that predate the config file, with the setting they map to.
+// Later entries win, so DATABASE_URL beats the old DB_url.
+const ENV_ALIASES: [(&str, &str); 4] = [
+    ("DB_url", "database.url"),
+    ("DATABASE_URL", "database.url"),
+    ("JWT_SECRET", "auth.jwt_secret"),
+    ("TRASH_RETENTION_DAYS", "retention.trash_days"),
+];
+
+// Shortest JWT secret accepted in prod: 256 bits of HS256 key.
+const MIN_PROD_SECRET_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Profile {
+    #[default]
+    Dev,
+    Test,
+    Prod,
+}
+
+impl Profile {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "dev" => Ok(Profile::Dev),
+            "test" => Ok(Profile::Test),
+            "prod" => Ok(Profile::Prod),
+            other => Err(format!("APP_PROFILE: unknown profile {:?}; expected dev, test or prod", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Test => "test",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip)]
+    pub profile: Profile,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub logging: LoggingConfig,
+    pub retention: RetentionConfig,
+    pub rate_limits: RateLimits,
+    pub jobs: JobsConfig,
+    pub sla: SlaConfig,
+    pub staffing: StaffingConfig,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    // How long shutdown waits for in-flight requests, then again for
+    // background jobs, before giving up on them.
+    pub shutdown_timeout_secs: u64,
+    // On SIGTERM /readyz fails for this long before the listener closes, so
+    // load balancers stop sending traffic first.
+    pub drain_secs: u64,
+    // Load balancers or reverse proxies in front of the server, as addresses
+    // or CIDR ranges. X-Forwarded-For is only believed when they send it.
+    pub trusted_proxies: Vec<String>,
+    // Separate listener for /metrics, so the metrics stay off the public
+    // port. Keep it on an address only the scraper can reach.
+    pub metrics_bind: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout_secs: u64,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // Signs new tokens. Old secrets stay accepted for verification until the
+    // tokens they signed have expired, so a secret can be rotated without
+    // logging everyone out.
+    pub jwt_secret: String,
+    pub previous_jwt_secrets: Vec<String>,
+    pub token_lifetime_hours: i64,
+}
+
+// The config is logged and printed in errors, so secrets are left out of
+// Debug: the database password and every JWT secret.
+const REDACTED: &str = "[redacted]";
+
+fn redact_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some(REDACTED));
+            parsed.to_string()
+        }
+        Ok(_) => url.to_string(),
+        Err(_) if url.is_empty() => String::new(),
+        Err(_) => REDACTED.to_string(),
+    }
+}
+
+impl fmt::Debug for DatabaseConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatabaseConfig")
+            .field("url", &redact_url(&self.url))
+            .field("max_connections", &self.max_connections)
+            .field("min_connections", &self.min_connections)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .field("acquire_timeout_secs", &self.acquire_timeout_secs)
+            .field("idle_timeout_secs", &self.idle_timeout_secs)
+            .finish()
+    }
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |secret: &String| if secret.is_empty() { "" } else { REDACTED };
+        f.debug_struct("AuthConfig")
+            .field("jwt_secret", &redacted(&self.jwt_secret))
+            .field("previous_jwt_secrets", &self.previous_jwt_secrets.iter().map(redacted).collect::<Vec<_>>())
+            .field("token_lifetime_hours", &self.token_lifetime_hours)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    // Browser origins allowed to call the API, e.g. "https://support.example.com".
+    // Empty turns CORS off; "*" allows any origin (not accepted in prod).
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    // Directives in RUST_LOG syntax, e.g. "info,sqlx=warn". RUST_LOG itself
+    // wins when set.
+    pub filter: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub trash_days: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    // Run the job worker inside the server. Turn off when workers run as a
+    // separate process (`customer_support_system worker`).
+    pub run_in_server: bool,
+    // Jobs one process runs at the same time.
+    pub concurrency: usize,
+    pub poll_interval_ms: u64,
+    // A job locked longer than this by a worker that died is put back in the
+    // queue. Runs are stopped a margin earlier (a tenth, at least 5 seconds).
+    pub lease_secs: u64,
+    // First retry delay; it doubles with each further attempt, up to an hour.
+    pub retry_base_secs: u64,
+    // Succeeded jobs are deleted after this long. Dead ones are kept.
+    pub keep_succeeded_days: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlaConfig {
+    // Targets in minutes, keyed by ticket priority. Tickets whose priority
+    // has no target are left out of SLA compliance in reports.
+    pub first_response_minutes: BTreeMap<String, i64>,
+    pub resolution_minutes: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StaffingConfig {
+    // Average agent time per ticket, replies and wrap-up included.
+    pub handle_secs: u64,
+    // Service level staffing recommendations aim for: this share of tickets
+    // picked up within answer_secs.
+    pub service_level: f64,
+    pub answer_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    // Public, unauthenticated /help routes.
+    pub help_center: bool,
+    // Swagger UI at / and the OpenAPI document at /api-doc/openapi.json.
+    pub swagger_ui: bool,
+    // Delivery of mention notifications to agents' webhooks.
+    pub webhook_notifications: bool,
+    // Hourly purge of records that have been in the trash too long (a
+    // scheduled job, so it runs wherever the job worker runs).
+    pub trash_purge: bool,
+    // Prometheus metrics at /metrics on server.metrics_bind.
+    pub metrics: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "127.0.0.1:3000".into(),
+            shutdown_timeout_secs: 30,
+            drain_secs: 5,
+            trusted_proxies: Vec::new(),
+            metrics_bind: "127.0.0.1:9090".into(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            max_connections: 10,
+            min_connections: 1,
+            connect_timeout_secs: 8,
+            acquire_timeout_secs: 8,
+            idle_timeout_secs: 600,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: String::new(),
+            previous_jwt_secrets: Vec::new(),
+            token_lifetime_hours: 24,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig { format: LogFormat::Text, filter: "info,sqlx=warn".into() }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig { trash_days: crate::retention::DEFAULT_RETENTION_DAYS }
+    }
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig {
+            run_in_server: true,
+            concurrency: 4,
+            poll_interval_ms: 1000,
+            lease_secs: 300,
+            retry_base_secs: 10,
+            keep_succeeded_days: 7,
+        }
+    }
+}
+
+impl Default for SlaConfig {
+    fn default() -> Self {
+        let targets = |pairs: [(&str, i64); 4]| pairs.into_iter().map(|(p, m)| (p.to_string(), m)).collect();
+        SlaConfig {
+            first_response_minutes: targets([("urgent", 60), ("high", 240), ("medium", 480), ("low", 1440)]),
+            resolution_minutes: targets([("urgent", 480), ("high", 1440), ("medium", 4320), ("low", 10080)]),
+        }
+    }
+}
+
+impl Default for StaffingConfig {
+    fn default() -> Self {
+        StaffingConfig {
+            handle_secs: 600,
+            service_level: 0.8,
+            answer_secs: 3600,
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            help_center: true,
+            swagger_ui: true,
+            webhook_notifications: true,
+            trash_purge: true,
+            metrics: true,
+        }
+    }
+}
+
+// Everything wrong with the configuration, reported together so it can be
+// fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_from(&env::vars().collect::<Vec<_>>())
+    }
+
+    // `load` with the environment passed in.
+    fn load_from(vars: &[(String, String)]) -> Result<Self, ConfigError> {
+        let var = |name: &str| vars.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        let profile = var("APP_PROFILE")
+            .map(|name| Profile::parse(&name))
+            .unwrap_or(Ok(Profile::Dev))
+            .map_err(|e| ConfigError(vec![e]))?;
+
+        let explicit_file = var("CONFIG_FILE");
+        let base = PathBuf::from(explicit_file.as_deref().unwrap_or(DEFAULT_CONFIG_FILE));
+        let overlay = base.with_file_name(format!(
+            "{}.{}.toml",
+            base.file_stem().and_then(|s| s.to_str()).unwrap_or("config"),
+            profile.name()
+        ));
+
+        let mut merged = Table::new();
+        // A missing config.toml is fine (defaults and env are enough); a
+        // missing file that was asked for by name is not.
+        for (path, required) in [(&base, explicit_file.is_some()), (&overlay, false)] {
+            match std::fs::read_to_string(path) {
+                Ok(text) => {
+                    let table: Table = toml::from_str(&text)
+                        .map_err(|e| ConfigError(vec![format!("{}: {}", path.display(), e)]))?;
+                    merge(&mut merged, table);
+                }
+                Err(_) if !required => {}
+                Err(e) => return Err(ConfigError(vec![format!("{}: {}", path.display(), e)])),
+            }
+        }
+        apply_env(&mut merged, vars)?;
+
+        let mut config: Config = Value::Table(merged)
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError(vec![e.message().to_string()]))?;
+        config.profile = profile;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn bind_addr(&self) -> SocketAddr {
+        // Checked in validate().
+        self.server.bind.parse().expect("validated bind address")
+    }
+
+    pub fn metrics_addr(&self) -> SocketAddr {
+        self.server.metrics_bind.parse().expect("validated metrics address")
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let prod = self.profile == Profile::Prod;
+        let mut problems = Vec::new();
+
+        if self.server.bind.parse::<SocketAddr>().is_err() {
+            problems.push(format!("server.bind: {:?} is not an address like 127.0.0.1:3000", self.server.bind));
+        }
+        if self.server.metrics_bind.parse::<SocketAddr>().is_err() {
+            problems.push(format!(
+                "server.metrics_bind: {:?} is not an address like 127.0.0.1:9090",
+                self.server.metrics_bind
+            ));
+        }
+        for rule in &self.server.trusted_proxies {
+            if let Err(e) = crate::api_keys::validate_ip_rule(rule) {
+                problems.push(format!("server.trusted_proxies: {}", e));
+            }
+        }
+
+        let db = &self.database;
+        if db.url.is_empty() {
+            problems.push("database.url is not set (or set DATABASE_URL)".into());
+        } else if !db.url.starts_with("postgres://") && !db.url.starts_with("postgresql://") {
+            problems.push("database.url must be a postgres:// URL".into());
+        }
+        if db.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".into());
+        }
+        if db.min_connections > db.max_connections {
+            problems.push(format!(
+                "database.min_connections ({}) is more than max_connections ({})",
+                db.min_connections, db.max_connections
+            ));
+        }
+
+        let auth = &self.auth;
+        if auth.jwt_secret.is_empty() {
+            problems.push("auth.jwt_secret is not set (or set JWT_SECRET)".into());
+        } else if prod && auth.jwt_secret.len() < MIN_PROD_SECRET_LEN {
+            problems.push(format!("auth.jwt_secret must be at least {} characters in prod", MIN_PROD_SECRET_LEN));
+        }
+        if auth.previous_jwt_secrets.iter().any(String::is_empty) {
+            problems.push("auth.previous_jwt_secrets must not contain empty secrets".into());
+        }
+        if auth.token_lifetime_hours <= 0 {
+            problems.push("auth.token_lifetime_hours must be positive".into());
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if origin == "*" {
+                if prod {
+                    problems.push("cors.allowed_origins: \"*\" is not allowed in prod".into());
+                }
+            } else if !(origin.starts_with("http://") || origin.starts_with("https://")) || origin.ends_with('/') {
+                problems.push(format!("cors.allowed_origins: {:?} should look like https://example.com", origin));
+            }
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.filter) {
+            problems.push(format!("logging.filter: {}", e));
+        }
+
+        if self.retention.trash_days < 1 {
+            problems.push("retention.trash_days must be at least 1".into());
+        }
+
+        let limits = &self.rate_limits;
+        for (name, rate) in [
+            ("help_center", limits.help_center),
+            ("login", limits.login),
+            ("bulk", limits.bulk),
+            ("api", limits.api),
+        ] {
+            if rate.burst == 0 || rate.per_sec <= 0.0 {
+                problems.push(format!("rate_limits.{}: burst and per_sec must be positive", name));
+            }
+        }
+
+        let jobs = &self.jobs;
+        if jobs.concurrency == 0 {
+            problems.push("jobs.concurrency must be at least 1".into());
+        }
+        if jobs.poll_interval_ms == 0 || jobs.retry_base_secs == 0 {
+            problems.push("jobs.poll_interval_ms and retry_base_secs must be positive".into());
+        }
+        if jobs.lease_secs < 10 {
+            problems.push("jobs.lease_secs must be at least 10".into());
+        }
+        if jobs.keep_succeeded_days < 1 {
+            problems.push("jobs.keep_succeeded_days must be at least 1".into());
+        }
+
+        for (name, targets) in [
+            ("first_response_minutes", &self.sla.first_response_minutes),
+            ("resolution_minutes", &self.sla.resolution_minutes),
+        ] {
+            for (priority, minutes) in targets {
+                if *minutes <= 0 {
+                    problems.push(format!("sla.{}.{} must be positive", name, priority));
+                }
+            }
+        }
+
+        let staffing = &self.staffing;
+        let secs = 1..=crate::forecast::MAX_STAFFING_SECS;
+        if !secs.contains(&staffing.handle_secs) || !secs.contains(&staffing.answer_secs) {
+            problems.push(format!(
+                "staffing.handle_secs and answer_secs must be 1 to {}",
+                crate::forecast::MAX_STAFFING_SECS
+            ));
+        }
+        if !(staffing.service_level > 0.0 && staffing.service_level < 1.0) {
+            problems.push("staffing.service_level must be between 0 and 1".into());
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(ConfigError(problems)) }
+    }
+}
+
+// Overlays `from` onto `into`, merging nested tables key by key.
+fn merge(into: &mut Table, from: Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(table)) => merge(existing, table),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+// Environment values are read as TOML when they parse as such (numbers,
+// booleans, arrays) and as plain strings otherwise.
+fn env_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn apply_env(table: &mut Table, vars: &[(String, String)]) -> Result<(), ConfigError> {
+    let mut overrides: Vec<(String, String, String)> = Vec::new();
+    for (alias, key) in ENV_ALIASES {
+        if let Some((_, raw)) = vars.iter().find(|(name, _)| name == alias) {
+            if alias == "DB_url" {
+                // Logging is not set up yet: it is configured from this file.
+                eprintln!("warning: DB_url is deprecated; use DATABASE_URL");
+            }
+            overrides.push((alias.to_string(), key.to_string(), raw.clone()));
+        }
+    }
+    for (name, raw) in vars {
+        if let Some(path) = name.strip_prefix(ENV_PREFIX) {
+            let key = path.split("__").map(str::to_lowercase).collect::<Vec<_>>().join(".");
+            overrides.push((name.clone(), key, raw.clone()));
+        }
+    }
+
+    for (name, key, raw) in overrides {
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let leaf = parts.pop().unwrap_or_default();
+        let mut node = &mut *table;
+        for part in parts {
+            node = match node
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                Value::Table(t) => t,
+                _ => return Err(ConfigError(vec![format!("{}: {} is not a section", name, part)])),
+            };
+        }
+        // Secrets, URLs and addresses are always strings, even when they
+        // happen to parse as something else.
+        let value = if matches!(leaf, "url" | "jwt_secret" | "bind" | "metrics_bind" | "filter" | "format") {
+            Value::String(raw)
+        } else {
+            env_value(&raw)
+        };
+        node.insert(leaf.to_string(), value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "a-jwt-secret-that-is-long-enough-for-prod";
+
+    fn vars(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    // The environment plus an empty config file, so the checkout's own
+    // config.toml is not read.
+    fn env_only(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        let mut vars = vars(list);
+        vars.push(("CONFIG_FILE".into(), files("", &[])));
+        vars
+    }
+
+    // A config.toml and its overlays in a fresh directory; returns the path
+    // of config.toml.
+    fn files(base: &str, overlays: &[(&str, &str)]) -> String {
+        let dir = env::temp_dir().join(format!("css-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.toml"), base).unwrap();
+        for (profile, text) in overlays {
+            std::fs::write(dir.join(format!("config.{}.toml", profile)), text).unwrap();
+        }
+        dir.join("config.toml").display().to_string()
+    }
+
+    #[test]
+    fn profile_overlay_and_environment_override_the_file_in_order() {
+        let file = files(
+            "[server]\nbind = \"127.0.0.1:4000\"\n[database]\nmax_connections = 10\nmin_connections = 2\n",
+            &[("prod", "[database]\nmax_connections = 50\n[features]\nswagger_ui = false\n")],
+        );
+        let config = Config::load_from(&vars(&[
+            ("CONFIG_FILE", &file),
+            ("APP_PROFILE", "prod"),
+            ("DB_url", "postgres://old/db"),
+            ("DATABASE_URL", "postgres://app:pw@db/support"),
+            ("JWT_SECRET", SECRET),
+            ("APP__DATABASE__MAX_CONNECTIONS", "20"),
+            ("APP__JOBS__RUN_IN_SERVER", "false"),
+            ("APP__SERVER__TRUSTED_PROXIES", "[\"10.0.0.0/8\"]"),
+        ]))
+        .unwrap();
+
+        assert_eq!(config.profile, Profile::Prod);
+        // Base file, kept where nothing overrides it.
+        assert_eq!(config.server.bind, "127.0.0.1:4000");
+        assert_eq!(config.database.min_connections, 2);
+        // Overlay, merged key by key into the same section.
+        assert!(!config.features.swagger_ui);
+        assert!(config.features.help_center);
+        // Environment last; DATABASE_URL beats the deprecated DB_url.
+        assert_eq!(config.database.max_connections, 20);
+        assert_eq!(config.database.url, "postgres://app:pw@db/support");
+        assert!(!config.jobs.run_in_server);
+        assert_eq!(config.server.trusted_proxies, ["10.0.0.0/8"]);
+    }
+
+    #[test]
+    fn a_missing_overlay_is_fine_but_a_missing_named_file_is_not() {
+        let env = [("DATABASE_URL", "postgres://db/support"), ("JWT_SECRET", SECRET), ("APP_PROFILE", "test")];
+        let config = Config::load_from(&env_only(&env)).unwrap();
+        assert_eq!(config.database.max_connections, DatabaseConfig::default().max_connections);
+
+        let dir = env::temp_dir().join(format!("css-config-{}", uuid::Uuid::new_v4()));
+        let mut named = vars(&env);
+        named.push(("CONFIG_FILE".into(), dir.join("config.toml").display().to_string()));
+        assert!(Config::load_from(&named).is_err());
+    }
+
+    #[test]
+    fn environment_values_are_typed_except_addresses_and_secrets() {
+        assert_eq!(env_value("20"), Value::Integer(20));
+        assert_eq!(env_value("false"), Value::Boolean(false));
+        assert_eq!(env_value("0.5"), Value::Float(0.5));
+        assert_eq!(env_value("text"), Value::String("text".into()));
+
+        let config = Config::load_from(&env_only(&[
+            ("DATABASE_URL", "postgres://db/support"),
+            ("JWT_SECRET", "12345678901234567890123456789012"),
+        ]))
+        .unwrap();
+        assert_eq!(config.auth.jwt_secret, "12345678901234567890123456789012");
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let Err(ConfigError(problems)) = Config::load_from(&env_only(&[
+            ("APP_PROFILE", "prod"),
+            ("JWT_SECRET", "short"),
+            ("APP__SERVER__BIND", "nowhere"),
+            ("APP__DATABASE__MAX_CONNECTIONS", "0"),
+        ])) else {
+            panic!("invalid configuration accepted");
+        };
+        // Bind, missing URL, zero max_connections, min above max, short secret.
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(Config::load_from(&env_only(&[("APP_PROFILE", "staging")])).is_err());
+    }
+
+    #[test]
+    fn debug_output_hides_secrets() {
+        let config = Config::load_from(&env_only(&[
+            ("DATABASE_URL", "postgres://app:hunter2@db/support"),
+            ("JWT_SECRET", SECRET),
+            ("APP__AUTH__PREVIOUS_JWT_SECRETS", "[\"old-secret\"]"),
+        ]))
+        .unwrap();
+        let shown = format!("{:?}", config);
+        for secret in ["hunter2", SECRET, "old-secret"] {
+            assert!(!shown.contains(secret), "{} shown", secret);
+        }
+        assert!(shown.contains("postgres://app:%5Bredacted%5D@db/support"), "{}", shown);
+        assert!(shown.contains("max_connections: 10"));
+    }
+}
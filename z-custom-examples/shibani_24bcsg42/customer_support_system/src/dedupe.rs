@@ -0,0 +1,202 @@
+use crate::entity::customers;
+
+// National numbers written without an international prefix are assumed to be Indian.
+const DEFAULT_COUNTRY_CODE: &str = "91";
+
+// Weights of the three signals in a duplicate score; they add up to 1.0.
+const EMAIL_WEIGHT: f64 = 0.5;
+const PHONE_WEIGHT: f64 = 0.3;
+const NAME_WEIGHT: f64 = 0.2;
+
+pub fn normalise_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// Formats a phone number as E.164 ("+<country><number>", at most 15 digits).
+// Returns None for anything that cannot be a phone number.
+pub fn normalise_phone(phone: &str) -> Option<String> {
+    let trimmed = phone.trim();
+    let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    if trimmed.chars().any(|c| c.is_alphabetic()) {
+        return None;
+    }
+
+    let international = if trimmed.starts_with('+') {
+        digits
+    } else if let Some(rest) = digits.strip_prefix("00") {
+        rest.to_string()
+    } else {
+        format!("{}{}", DEFAULT_COUNTRY_CODE, digits.trim_start_matches('0'))
+    };
+
+    if (8..=15).contains(&international.len()) && !international.starts_with('0') {
+        Some(format!("+{}", international))
+    } else {
+        None
+    }
+}
+
+// Lowercased name tokens in sorted order, so "Doe, Jane" and "jane doe" compare equal.
+fn name_key(name: &str) -> String {
+    let mut tokens: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect();
+    tokens.sort();
+    tokens.join(" ")
+}
+
+fn jaro(a: &[char], b: &[char]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0.0;
+
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1.0;
+                break;
+            }
+        }
+    }
+    if matches == 0.0 {
+        return 0.0;
+    }
+
+    let a_seq = a.iter().zip(&a_matched).filter(|(_, m)| **m).map(|(c, _)| c);
+    let b_seq = b.iter().zip(&b_matched).filter(|(_, m)| **m).map(|(c, _)| c);
+    let transpositions = a_seq.zip(b_seq).filter(|(x, y)| x != y).count() as f64 / 2.0;
+
+    (matches / a.len() as f64 + matches / b.len() as f64 + (matches - transpositions) / matches) / 3.0
+}
+
+// Jaro-Winkler similarity between two names, from 0.0 (unrelated) to 1.0 (same).
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = name_key(a).chars().collect();
+    let b: Vec<char> = name_key(b).chars().collect();
+
+    let score = jaro(&a, &b);
+    let prefix = a.iter().zip(b.iter()).take(4).take_while(|(x, y)| x == y).count() as f64;
+    score + prefix * 0.1 * (1.0 - score)
+}
+
+fn email_local_part(email: &str) -> String {
+    let local = email.split('@').next().unwrap_or("");
+    let local = local.split('+').next().unwrap_or("");
+    local.replace('.', "")
+}
+
+// Scores how likely two customers are the same person, with the reasons that
+// contributed to the score.
+pub fn duplicate_score(a: &customers::Model, b: &customers::Model) -> (f64, Vec<String>) {
+    let mut score = 0.0;
+    let mut reasons = Vec::new();
+
+    let (email_a, email_b) = (normalise_email(&a.email), normalise_email(&b.email));
+    if !email_a.is_empty() && email_a == email_b {
+        score += EMAIL_WEIGHT;
+        reasons.push("same email".to_string());
+    } else if !email_a.is_empty() && email_local_part(&email_a) == email_local_part(&email_b) {
+        score += EMAIL_WEIGHT / 2.0;
+        reasons.push("same email name at a different domain".to_string());
+    }
+
+    match (normalise_phone(&a.phone), normalise_phone(&b.phone)) {
+        (Some(pa), Some(pb)) if pa == pb => {
+            score += PHONE_WEIGHT;
+            reasons.push("same phone".to_string());
+        }
+        (Some(pa), Some(pb)) if pa.len() >= 7 && pb.len() >= 7 && pa[pa.len() - 7..] == pb[pb.len() - 7..] => {
+            score += PHONE_WEIGHT / 2.0;
+            reasons.push("phone ends with the same digits".to_string());
+        }
+        _ => {}
+    }
+
+    let similarity = name_similarity(&a.name, &b.name);
+    if similarity >= 0.85 {
+        score += NAME_WEIGHT * similarity;
+        reasons.push(format!("similar name ({:.0}%)", similarity * 100.0));
+    }
+
+    (score, reasons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn customer(name: &str, email: &str, phone: &str) -> customers::Model {
+        customers::Model {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::nil(),
+            name: name.into(),
+            email: email.into(),
+            phone: phone.into(),
+            company_id: None,
+            merged_into_id: None,
+            external_id: None,
+            preferred_locale: None,
+            erased_at: None,
+            deleted_at: None,
+            version: 1,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn emails_are_trimmed_and_lowercased() {
+        assert_eq!(normalise_email("  Jane.Doe@Example.COM "), "jane.doe@example.com");
+    }
+
+    #[test]
+    fn phones_come_out_in_e164() {
+        assert_eq!(normalise_phone("+44 20 7946 0958").as_deref(), Some("+442079460958"));
+        assert_eq!(normalise_phone("0044 (20) 7946-0958").as_deref(), Some("+442079460958"));
+        // National numbers get the default country code, without the trunk 0.
+        assert_eq!(normalise_phone("098765 43210").as_deref(), Some("+919876543210"));
+        assert_eq!(normalise_phone("call me"), None);
+        assert_eq!(normalise_phone("123"), None);
+        assert_eq!(normalise_phone("+1234567890123456"), None);
+    }
+
+    #[test]
+    fn names_compare_regardless_of_order_and_case() {
+        assert!((name_similarity("Doe, Jane", "jane doe") - 1.0).abs() < 1e-9);
+        assert!(name_similarity("Jane Doe", "Jane Do") > 0.9);
+        assert!(name_similarity("Jane Doe", "Rahul Mehta") < 0.7);
+        assert_eq!(name_similarity("", "Jane"), 0.0);
+    }
+
+    #[test]
+    fn duplicate_score_adds_up_the_signals() {
+        let a = customer("Jane Doe", "jane.doe@example.com", "+919876543210");
+
+        let (score, reasons) = duplicate_score(&a, &customer("Doe Jane", "Jane.Doe@example.com", "09876543210"));
+        assert!((score - 1.0).abs() < 1e-9);
+        assert_eq!(reasons, ["same email", "same phone", "similar name (100%)"]);
+
+        let (score, reasons) = duplicate_score(&a, &customer("Someone Else", "janedoe+support@other.io", "+1 415 876 543210"));
+        assert_eq!(reasons, ["same email name at a different domain", "phone ends with the same digits"]);
+        assert!((score - (EMAIL_WEIGHT / 2.0 + PHONE_WEIGHT / 2.0)).abs() < 1e-9);
+
+        let (score, reasons) = duplicate_score(&a, &customer("Rahul Mehta", "rahul@example.com", ""));
+        assert_eq!(score, 0.0);
+        assert!(reasons.is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema, PartialEq, Eq)]
+pub struct DiffLine {
+    pub op: String,      // "equal", "insert" or "delete"
+    pub text: String,
+}
+
+// Largest LCS table built, in cells (about 32 MB). Past that the changed middle
+// is shown as deleted and re-inserted as a whole.
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+// Line-based diff. Lines shared at the start and end are matched first, and the
+// longest common subsequence table is only built for the changed middle, which
+// for an edited article is small.
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let line = |op: &str, text: &str| DiffLine { op: op.to_string(), text: text.to_string() };
+
+    let mut out: Vec<DiffLine> = a[..prefix].iter().map(|l| line("equal", l)).collect();
+    if (a_mid.len() + 1).saturating_mul(b_mid.len() + 1) > MAX_TABLE_CELLS {
+        out.extend(a_mid.iter().map(|l| line("delete", l)));
+        out.extend(b_mid.iter().map(|l| line("insert", l)));
+    } else {
+        lcs_diff(a_mid, b_mid, &mut out);
+    }
+    out.extend(a[a.len() - suffix..].iter().map(|l| line("equal", l)));
+
+    out
+}
+
+fn lcs_diff(a: &[&str], b: &[&str], out: &mut Vec<DiffLine>) {
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |op: &str, text: &str| DiffLine { op: op.to_string(), text: text.to_string() };
+
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            out.push(line("equal", a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(line("delete", a[i]));
+            i += 1;
+        } else {
+            out.push(line("insert", b[j]));
+            j += 1;
+        }
+    }
+    out.extend(a[i..].iter().map(|l| line("delete", l)));
+    out.extend(b[j..].iter().map(|l| line("insert", l)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(diff: &[DiffLine]) -> Vec<(&str, &str)> {
+        diff.iter().map(|d| (d.op.as_str(), d.text.as_str())).collect()
+    }
+
+    #[test]
+    fn identical_text_is_all_equal() {
+        assert_eq!(ops(&line_diff("a\nb", "a\nb")), [("equal", "a"), ("equal", "b")]);
+        assert!(line_diff("", "").is_empty());
+    }
+
+    #[test]
+    fn changed_line_is_deleted_then_inserted() {
+        assert_eq!(
+            ops(&line_diff("a\nb\nc", "a\nx\nc")),
+            [("equal", "a"), ("delete", "b"), ("insert", "x"), ("equal", "c")]
+        );
+    }
+
+    #[test]
+    fn insertions_and_deletions_keep_common_lines() {
+        assert_eq!(
+            ops(&line_diff("a\nb\nc\nd", "b\nc\ne\nd")),
+            [("delete", "a"), ("equal", "b"), ("equal", "c"), ("insert", "e"), ("equal", "d")]
+        );
+        assert_eq!(ops(&line_diff("", "a")), [("insert", "a")]);
+        assert_eq!(ops(&line_diff("a", "")), [("delete", "a")]);
+    }
+
+    #[test]
+    fn huge_changes_fall_back_to_replacing_the_middle() {
+        let old: String = (0..3000).map(|i| format!("old {}\n", i)).collect();
+        let new: String = (0..3000).map(|i| format!("new {}\n", i)).collect();
+        let diff = line_diff(&format!("top\n{}end", old), &format!("top\n{}end", new));
+
+        assert_eq!(diff.len(), 6002);
+        assert_eq!(ops(&diff[..2]), [("equal", "top"), ("delete", "old 0")]);
+        assert_eq!(ops(&diff[3000..3002]), [("delete", "old 2999"), ("insert", "new 0")]);
+        assert_eq!(ops(&diff[6001..]), [("equal", "end")]);
+    }
+}
@@ -1,41 +1,14 @@
-use utoipa::OpenApi;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 use crate::api;
 
+// Paths are not listed here: routes.rs adds each handler's #[utoipa::path]
+// entry as it registers the route. Endpoints that need no credentials say so
+// with `security(())` on their handler.
+
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(
-        crate::api::create_user,
-        crate::api::login_user,
-        crate::api::get_users,
-        crate::api::create_customer,
-        crate::api::get_customers,
-        crate::api::create_ticket,
-        crate::api::get_ticket_by_id,
-        crate::api::get_all_tickets,
-        crate::api::update_ticket_priority,
-        crate::api::update_ticket_status,
-        crate::api::delete_ticket_by_id,
-        crate::api::assign_ticket,
-        crate::api::get_filtered_tickets,
-        crate::api::create_communication,
-        crate::api::get_communications,
-        crate::api::create_article,
-        crate::api::update_article,
-        crate::api::get_all_articles,
-        crate::api::delete_article,
-        crate::api::search_articles,
-        crate::api::create_tag,
-        crate::api::get_tags_by_id,
-        crate::api::delete_tag,
-        crate::api::create_analytics,
-        crate::api::get_analytics,
-        crate::api::get_analytics_by_id,
-        // crate::api::customer_reply_ticket,
-        // crate::api::get_my_tickets,
-        // crate::api::get_ticket_details,
-
-    ),
     components(
         schemas  (
            api::CreateUserInput, 
@@ -44,6 +17,13 @@ use crate::api;
            api::LoginResponse, 
            api::CreateCustomerInput, 
            api::CustomerResponse, 
+           api::DuplicateQuery,
+           api::DuplicateCandidate,
+           api::MergeCustomersInput,
+           api::ErasureResponse,
+           api::CreateCompanyInput,
+           api::CompanyResponse,
+           api::CustomerCompanyInput,
            api::CreateTicketInput, 
            api::TicketResponse, 
            api::CreateCommunicationInput, 
@@ -51,15 +31,73 @@ use crate::api;
            api::CreateArticleInput, 
            api::ArticleResponse, 
            api::SearchQuery,
+           api::ArticleStatusInput,
+           api::RevisionResponse,
+           api::DiffQuery,
+           api::ArticleDiffResponse,
+           api::CreateTranslationInput,
+           crate::diff::DiffLine,
+           api::SuggestionQuery,
+           api::SuggestedArticle,
+           api::SuggestionFeedbackInput,
+           api::SuggestionQualityResponse,
+           api::HelpArticle,
+           api::HelpArticleSummary,
+           api::CategoryNode,
+           api::HelpArticleQuery,
+           api::LocaleQuery,
+           api::VoteInput,
            api::StatusInput, 
            api::PriorityInput, 
            api::AssignInput,
            api::TicketQuery,
+           api::MergeTicketsInput,
+           api::SplitTicketInput,
+           api::TimelineActor,
+           api::TimelineEntry,
            api::CreateTagInput,
            api::TagResponse,
+           api::TicketTagInput,
+           api::MergeTagInput,
+           api::TagUsageResponse,
            api::CreateAnalyticsInput,    
            api::AnalyticsResponse,
+           api::PerformanceQuery,
+           api::PerformanceReport,
+           crate::reports::PerformanceRow,
+           crate::reports::GroupBy,
+           crate::reports::ReportFormat,
+           api::ForecastQuery,
+           api::ForecastResponse,
+           api::ChannelForecast,
+           api::DailyForecast,
+           api::HourlyForecast,
+           api::StaffingTarget,
            api::Pagination,
+           api::SurveyAnswerInput,
+           api::SurveyResponse,
+           api::SurveyQuestion,
+           api::FormatQuery,
+           crate::bulk::Format,
+           api::CustomerImportRow,
+           api::TicketImportRow,
+           api::CommunicationImportRow,
+           api::ArticleImportRow,
+           api::ImportSummary,
+           api::MentionQuery,
+           api::MentionResponse,
+           api::UnreadMentionsResponse,
+           api::NotificationPreferences,
+           api::TrashQuery,
+           api::TrashItem,
+           api::CreateApiKeyInput,
+           api::ApiKeyResponse,
+           api::CreatedApiKey,
+           api::CreateOrganizationInput,
+           api::OrganizationResponse,
+           api::JobQuery,
+           api::JobResponse,
+           api::JobScheduleResponse,
         //    api::CustomerTicketView,
         //    api::CustomerReplyInput,
         )
@@ -67,12 +105,40 @@ use crate::api;
     tags(
         (name = "User", description = "User endpoints"),
         (name = "Customer", description = "Customer endpoints"),
+        (name = "Company", description = "Company accounts grouping customer contacts"),
         (name = "Ticket", description = "Ticket endpoints"),
         (name = "Communication", description = "Communication endpoints"),
         (name = "Knowledge", description = "Knowledge base endpoints"),
         (name = "Tag", description = "Tag endpoints"),
         (name = "Analytics", description = "Analytics endpoints"),
+        (name = "Survey", description = "Customer satisfaction survey endpoints"),
+        (name = "Help Center", description = "Public, unauthenticated help center endpoints"),
+        (name = "Import/Export", description = "Bulk CSV and JSON Lines import and export"),
+        (name = "Mentions", description = "Agent @mention inbox and notification preferences"),
+        (name = "Trash", description = "Admin view of deleted records, with restore"),
+        (name = "API Keys", description = "Scoped API keys for service-to-service integrations"),
+        (name = "Organization", description = "Super admin organization management"),
+        (name = "Jobs", description = "Background job queue: inspect, retry and view schedules"),
        // (name = "Customer Support", description = "get my tickets and ticket details endpoints"),
-    )
+    ),
+    modifiers(&SecurityAddon),
+    security(("bearer_auth" = []), ("api_key" = []))
 )]
-pub struct ApiDoc;
\ No newline at end of file
+pub struct ApiDoc;
+
+// A login token (`Authorization: Bearer <jwt>`) or an API key (`X-API-Key`).
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(Http::builder().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(crate::api_keys::API_KEY_HEADER))),
+        );
+    }
+}
\ No newline at end of file
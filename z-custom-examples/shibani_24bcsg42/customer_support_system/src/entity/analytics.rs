@@ -7,11 +7,14 @@ use chrono::NaiveDate;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub date: NaiveDate,
     pub total_tickets: i32,
     pub resolved_tickets: i32,
     pub avg_response_time: i64,
     pub agent_id: Uuid,
+    pub csat_responses: i32,
+    pub csat_score_total: i32,
 }
 
 
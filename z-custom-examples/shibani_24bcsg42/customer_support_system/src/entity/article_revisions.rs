@@ -0,0 +1,52 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "article_revisions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub revision_number: i32,
+    pub title: String,
+    pub content: String,
+    pub category: String,
+    pub author_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Article,
+    Author,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Article => Entity::belongs_to(super::knowledge_base::Entity)
+                .from(Column::ArticleId)
+                .to(super::knowledge_base::Column::Id)
+                .into(),
+            Self::Author => Entity::belongs_to(super::users::Entity)
+                .from(Column::AuthorId)
+                .to(super::users::Column::Id)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::knowledge_base::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Article.def()
+    }
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Author.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
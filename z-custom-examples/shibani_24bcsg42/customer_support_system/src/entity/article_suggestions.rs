@@ -0,0 +1,54 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "article_suggestions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub ticket_id: Uuid,
+    pub article_id: Uuid,
+    pub agent_id: Uuid,
+    pub rank: i32,
+    pub score: f64,
+    pub action: Option<String>,   // "linked" or "sent" once the agent uses it
+    pub shown_at: DateTime<Utc>,
+    pub acted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Ticket,
+    Article,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Ticket => Entity::belongs_to(super::tickets::Entity)
+                .from(Column::TicketId)
+                .to(super::tickets::Column::Id)
+                .into(),
+            Self::Article => Entity::belongs_to(super::knowledge_base::Entity)
+                .from(Column::ArticleId)
+                .to(super::knowledge_base::Column::Id)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::tickets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Ticket.def()
+    }
+}
+
+impl Related<super::knowledge_base::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Article.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,14 +1,15 @@
 use sea_orm::entity::prelude::*;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use std::net::Ipv4Addr;
 
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "audit_logs")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub user_id: Uuid,
+    pub api_key_id: Option<Uuid>,   // set when the action was taken with an API key
     pub action: String,
     pub entity: String,
     pub entity_id: Uuid,
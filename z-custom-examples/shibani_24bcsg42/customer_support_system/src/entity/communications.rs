@@ -7,12 +7,16 @@ use chrono::{DateTime, Utc};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub ticket_id: Uuid,
     pub sender_type: String,      // "agent" or "customer"
     pub sender_id: Uuid,
     pub message: String,
     pub channel: String,          // "Email", "Chat", "Social"
     pub is_internal: bool,
+    // Sent by the system, e.g. a survey invitation; not a reply.
+    pub is_system: bool,
+    pub external_id: Option<String>,
     pub timestamp: DateTime<Utc>,
 }
 
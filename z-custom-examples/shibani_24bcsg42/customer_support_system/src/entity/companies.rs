@@ -0,0 +1,43 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "companies")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub shared_visibility: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Domains,
+    Customers,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Domains => Entity::has_many(super::company_domains::Entity).into(),
+            Self::Customers => Entity::has_many(super::customers::Entity).into(),
+        }
+    }
+}
+
+impl Related<super::company_domains::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Domains.def()
+    }
+}
+
+impl Related<super::customers::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Customers.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
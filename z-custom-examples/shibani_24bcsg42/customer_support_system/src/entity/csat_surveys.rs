@@ -0,0 +1,54 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "csat_surveys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub ticket_id: Uuid,
+    pub customer_id: Uuid,
+    pub agent_id: Option<Uuid>,
+    pub rating: Option<i16>,        // 1-5, set once the customer responds
+    pub comment: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Ticket,
+    Agent,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Ticket => Entity::belongs_to(super::tickets::Entity)
+                .from(Column::TicketId)
+                .to(super::tickets::Column::Id)
+                .into(),
+            Self::Agent => Entity::belongs_to(super::users::Entity)
+                .from(Column::AgentId)
+                .to(super::users::Column::Id)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::tickets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Ticket.def()
+    }
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agent.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
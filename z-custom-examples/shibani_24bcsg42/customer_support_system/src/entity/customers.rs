@@ -7,13 +7,53 @@ use chrono::{DateTime, Utc};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub name: String,
     pub email: String,
     pub phone: String,
+    pub company_id: Option<Uuid>,
+    pub merged_into_id: Option<Uuid>,
+    pub external_id: Option<String>,
+    pub preferred_locale: Option<String>,
+    pub erased_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub version: i32,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Company,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Company => Entity::belongs_to(super::companies::Entity)
+                .from(Column::CompanyId)
+                .to(super::companies::Column::Id)
+                .into(),
+        }
+    }
+}
 
-impl ActiveModelBehavior for ActiveModel {}
+impl Related<super::companies::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Company.def()
+    }
+}
+
+// Any update made through an ActiveModel counts as a new version, so ETags
+// handed out earlier stop matching.
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && let sea_orm::ActiveValue::Unchanged(version) = self.version {
+            self.version = sea_orm::ActiveValue::Set(version + 1);
+        }
+        Ok(self)
+    }
+}
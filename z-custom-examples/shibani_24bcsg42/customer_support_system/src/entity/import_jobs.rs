@@ -0,0 +1,27 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "import_jobs")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub kind: String,             // "customers", "tickets", "communications", "articles"
+    pub format: String,           // "csv" or "jsonl"
+    pub status: String,           // "queued", "running" or "finished"
+    pub created_by: Uuid,
+    pub processed: i32,
+    pub created: i32,
+    pub updated: i32,
+    pub failed: i32,
+    pub error_report: String,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+
+// The file of an import that has not finished yet. Reached through its
+// import_jobs row, which carries the tenant.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "import_uploads")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub import_id: Uuid,
+    pub data: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -8,16 +8,29 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub title: String,
     pub content: String,
     pub category: String,
     pub created_by: Uuid,
+    pub status: String,           // "draft", "in_review", "published", "archived"
+    pub published_revision_id: Option<Uuid>,
+    pub view_count: i64,
+    pub helpful_votes: i32,
+    pub unhelpful_votes: i32,
+    pub external_id: Option<String>,
+    pub locale: String,
+    pub translation_of: Option<Uuid>,      // the original this article translates
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub version: i32,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
 pub enum Relation {
     Author,
+    Revisions,
 }
 
 impl RelationTrait for Relation {
@@ -27,14 +40,34 @@ impl RelationTrait for Relation {
                 .from(Column::CreatedBy)
                 .to(super::users::Column::Id)
                 .into(),
+            Self::Revisions => Entity::has_many(super::article_revisions::Entity).into(),
         }
     }
 }
 
+impl Related<super::article_revisions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Revisions.def()
+    }
+}
+
 impl Related<super::users::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Author.def()
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+// Any update made through an ActiveModel counts as a new version, so ETags
+// handed out earlier stop matching.
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && let sea_orm::ActiveValue::Unchanged(version) = self.version {
+            self.version = sea_orm::ActiveValue::Set(version + 1);
+        }
+        Ok(self)
+    }
+}
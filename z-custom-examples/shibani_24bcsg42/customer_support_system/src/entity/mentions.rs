@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "mentions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub communication_id: Uuid,
+    pub ticket_id: Uuid,
+    pub user_id: Uuid,            // the agent who was mentioned
+    pub mentioned_by: Option<Uuid>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Communication,
+    Ticket,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Communication => Entity::belongs_to(super::communications::Entity)
+                .from(Column::CommunicationId)
+                .to(super::communications::Column::Id)
+                .into(),
+            Self::Ticket => Entity::belongs_to(super::tickets::Entity)
+                .from(Column::TicketId)
+                .to(super::tickets::Column::Id)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::communications::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Communication.def()
+    }
+}
+
+impl Related<super::tickets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Ticket.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,12 +2,27 @@
 
 pub mod prelude;
 
+pub mod organizations;
 pub mod users;
 pub mod customers;
+pub mod companies;
+pub mod company_domains;
 pub mod tickets;
 pub mod communications;
 pub mod knowledge_base;
+pub mod article_revisions;
+pub mod article_suggestions;
 pub mod tags;
+pub mod ticket_tags;
+pub mod ticket_events;
+pub mod mentions;
+pub mod login_failures;
+pub mod api_keys;
 pub mod analytics;
 pub mod audit_logs;
+pub mod csat_surveys;
+pub mod import_jobs;
+pub mod import_uploads;
+pub mod jobs;
+pub mod job_schedules;
 
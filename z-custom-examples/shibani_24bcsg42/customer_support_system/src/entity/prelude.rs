@@ -1,13 +1,28 @@
 // ! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.13
 
+pub use super::organizations::Entity as OrganizationEntity;
 pub use super::users::Entity as UserEntity;
 pub use super::customers::Entity as CustomerEntity;
+pub use super::companies::Entity as CompanyEntity;
+pub use super::company_domains::Entity as CompanyDomainEntity;
 pub use super::tickets::Entity as TicketEntity;
 pub use super::communications::Entity as CommunicationEntity;
 pub use super::knowledge_base::Entity as KBEntity;
+pub use super::article_revisions::Entity as ArticleRevisionEntity;
+pub use super::article_suggestions::Entity as ArticleSuggestionEntity;
 pub use super::tags::Entity as TagEntity;
+pub use super::ticket_tags::Entity as TicketTagEntity;
 pub use super::analytics::Entity as AnalyticsEntity;
 pub use super::audit_logs::Entity as AuditLogEntity;
+pub use super::csat_surveys::Entity as CsatSurveyEntity;
+pub use super::import_jobs::Entity as ImportJobEntity;
+pub use super::import_uploads::Entity as ImportUploadEntity;
+pub use super::ticket_events::Entity as TicketEventEntity;
+pub use super::mentions::Entity as MentionEntity;
+pub use super::login_failures::Entity as LoginFailureEntity;
+pub use super::api_keys::Entity as ApiKeyEntity;
+pub use super::jobs::Entity as JobEntity;
+pub use super::job_schedules::Entity as JobScheduleEntity;
 
 
 
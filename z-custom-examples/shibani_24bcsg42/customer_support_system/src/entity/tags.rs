@@ -1,34 +1,45 @@
 use sea_orm::entity::prelude::*;
 use uuid::Uuid;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "tags")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
-    pub ticket_id: Uuid,
-    pub tag_name: String,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub colour: Option<String>,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
 pub enum Relation {
-    Ticket,
+    TicketTags,
 }
 
 impl RelationTrait for Relation {
     fn def(&self) -> RelationDef {
         match self {
-            Self::Ticket => Entity::belongs_to(super::tickets::Entity)
-                .from(Column::TicketId)
-                .to(super::tickets::Column::Id)
-                .into(),
+            Self::TicketTags => Entity::has_many(super::ticket_tags::Entity).into(),
         }
     }
 }
 
+impl Related<super::ticket_tags::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TicketTags.def()
+    }
+}
+
 impl Related<super::tickets::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::Ticket.def()
+        super::ticket_tags::Relation::Ticket.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::ticket_tags::Relation::Tag.def().rev())
     }
 }
 
@@ -0,0 +1,42 @@
+use sea_orm::entity::prelude::*;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "ticket_events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub ticket_id: Uuid,
+    pub actor_type: String,       // "user", "customer", "api_key" or "system"
+    pub actor_id: Option<Uuid>,
+    pub kind: String,             // "status", "priority", "assignment", "tag_added", "tag_removed", "merged_into", ...
+    pub from_value: Option<String>,
+    pub to_value: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {
+    Ticket,
+}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match self {
+            Self::Ticket => Entity::belongs_to(super::tickets::Entity)
+                .from(Column::TicketId)
+                .to(super::tickets::Column::Id)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::tickets::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Ticket.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
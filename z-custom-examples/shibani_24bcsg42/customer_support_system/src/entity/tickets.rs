@@ -9,6 +9,7 @@ use crate::api::TicketResponse;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub title: String,
     pub description: String,
     pub status: String,
@@ -16,6 +17,11 @@ pub struct Model {
     pub channel: String,
     pub customer_id: Uuid,
     pub assigned_agent_id: Option<Uuid>,
+    pub merged_into_id: Option<Uuid>,
+    pub parent_ticket_id: Option<Uuid>,
+    pub external_id: Option<String>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub version: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -53,6 +59,16 @@ impl Related<super::users::Entity> for Entity {
     }
 }
 
+impl Related<super::tags::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::ticket_tags::Relation::Tag.def()
+    }
+
+    fn via() -> Option<RelationDef> {
+        Some(super::ticket_tags::Relation::Ticket.def().rev())
+    }
+}
+
 impl From<Model> for TicketResponse {
     fn from(model: Model) -> Self {
         TicketResponse {
@@ -64,9 +80,25 @@ impl From<Model> for TicketResponse {
             channel: model.channel,
             customer_id: model.customer_id,
             assigned_agent_id: model.assigned_agent_id,
+            merged_into_id: model.merged_into_id,
+            parent_ticket_id: model.parent_ticket_id,
+            version: model.version,
         }
     }
 }
 
 
-impl ActiveModelBehavior for ActiveModel {}
+// Any update made through an ActiveModel counts as a new version, so ETags
+// handed out earlier stop matching.
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && let sea_orm::ActiveValue::Unchanged(version) = self.version {
+            self.version = sea_orm::ActiveValue::Set(version + 1);
+        }
+        Ok(self)
+    }
+}
@@ -7,10 +7,15 @@ use chrono::{DateTime, Utc};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
+    pub tenant_id: Uuid,
     pub name: String,
     pub email: String,
     pub password_hash: String,
     pub role: String,
+    pub team: Option<String>,
+    pub notification_channel: String,   // "inbox" or "webhook"
+    pub notification_target: Option<String>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
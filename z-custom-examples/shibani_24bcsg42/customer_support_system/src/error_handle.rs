@@ -1,9 +1,9 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use sea_orm::DbErr;
+use sea_orm::{DbErr, SqlErr};
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -11,6 +11,12 @@ pub struct ErrorResponse {
     error: String,
 }
 
+impl ErrorResponse {
+    pub fn new(message: &str) -> Self {
+        ErrorResponse { error: message.to_string() }
+    }
+}
+
 #[derive(Debug)]
 pub enum AppError {
     Db(()),
@@ -18,18 +24,40 @@ pub enum AppError {
     Unauthorized,
     Forbidden,
     BadRequest(String),
+    Conflict(String),
     Internal(String),
+    // Update sent without If-Match.
+    PreconditionRequired,
+    // If-Match named an old version; carries what the row looks like now.
+    PreconditionFailed { etag: String, current: serde_json::Value },
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let AppError::PreconditionFailed { etag, current } = self {
+            let body = Json(serde_json::json!({
+                "error": "The resource was changed by someone else",
+                "current": current,
+            }));
+            return (StatusCode::PRECONDITION_FAILED, [(header::ETAG, etag)], body).into_response();
+        }
+
         let (status, message) = match &self {
             AppError::Db(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()),
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
             AppError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden".to_string()),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
             AppError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
+            AppError::PreconditionRequired => (
+                StatusCode::PRECONDITION_REQUIRED,
+                "If-Match header with the ETag of the current version is required".to_string(),
+            ),
+            // Answered above with its own headers.
+            AppError::PreconditionFailed { .. } => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal error".to_string())
+            }
         };
 
         let body = Json(ErrorResponse { error: message });
@@ -43,6 +71,16 @@ impl From<DbErr> for AppError {
     }
 }
 
+// For writes that can lose a race against a uniqueness check done just
+// before them: the unique index has the last word and answers 409 with
+// `message`.
+pub fn conflict_on_unique(message: &str) -> impl FnOnce(DbErr) -> AppError + '_ {
+    move |err| match err.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(_)) => AppError::Conflict(message.to_string()),
+        _ => AppError::Db(()),
+    }
+}
+
 impl From<anyhow::Error> for AppError {
     fn from(err: anyhow::Error) -> Self {
         AppError::Internal(err.to_string())
@@ -0,0 +1,228 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sea_orm::{DbBackend, FromQueryResult, Statement};
+use uuid::Uuid;
+
+// Ticket volume forecasts (additive Holt-Winters) and the agents needed to
+// handle them (Erlang C). Everything here is plain arithmetic on the arrival
+// counts; the only query is the one that fetches them.
+
+// Seasons of a week, in days and in hours.
+pub const DAYS_PER_WEEK: usize = 7;
+pub const HOURS_PER_WEEK: usize = 7 * 24;
+
+// Two-sided 95% interval.
+const Z_95: f64 = 1.96;
+
+// Longest handle and answer times accepted: a day.
+pub const MAX_STAFFING_SECS: u64 = 24 * 60 * 60;
+// Most agents a recommendation goes up to; volumes needing more get this.
+pub const MAX_AGENTS: u32 = 10_000;
+
+// Smoothing parameters tried when fitting. Trend is kept gentle: with weeks
+// of hourly counts a steep trend fits noise and the forecast runs away.
+const ALPHAS: [f64; 6] = [0.05, 0.1, 0.2, 0.3, 0.5, 0.7];
+const BETAS: [f64; 4] = [0.0, 0.01, 0.05, 0.1];
+const GAMMAS: [f64; 5] = [0.05, 0.1, 0.2, 0.3, 0.5];
+
+#[derive(Debug, FromQueryResult)]
+pub struct ArrivalRow {
+    pub channel: String,
+    pub hour: NaiveDateTime,
+    pub tickets: i64,
+}
+
+// Tickets created per channel and UTC hour over [start, end). Tickets split
+// off others are left out; they were not new contacts.
+pub fn arrivals_statement(tenant_id: Uuid, start: DateTime<Utc>, end: DateTime<Utc>) -> Statement {
+    Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"
+SELECT channel, date_trunc('hour', created_at AT TIME ZONE 'UTC') AS hour, COUNT(*) AS tickets
+FROM tickets
+WHERE tenant_id = $1 AND deleted_at IS NULL AND parent_ticket_id IS NULL
+  AND created_at >= $2 AND created_at < $3
+GROUP BY channel, hour
+"#,
+        [tenant_id.into(), start.into(), end.into()],
+    )
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    pub forecast: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+struct Fit {
+    sse: f64,
+    level: f64,
+    trend: f64,
+    seasonal: Vec<f64>,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+}
+
+// One pass of additive Holt-Winters over the series. The first season sets
+// the starting seasonal pattern, the first two the starting trend.
+fn fit(series: &[f64], period: usize, alpha: f64, beta: f64, gamma: f64) -> Fit {
+    let mean = |s: &[f64]| s.iter().sum::<f64>() / s.len() as f64;
+    let first = mean(&series[..period]);
+    let mut trend = (mean(&series[period..2 * period]) - first) / period as f64;
+    let mut level = first + trend * (period - 1) as f64 / 2.0;
+    let mut seasonal: Vec<f64> = series[..period].iter().map(|y| y - first).collect();
+
+    let mut sse = 0.0;
+    for (t, &y) in series.iter().enumerate().skip(period) {
+        let s = seasonal[t % period];
+        let error = y - (level + trend + s);
+        sse += error * error;
+
+        let previous = level;
+        level = alpha * (y - s) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous) + (1.0 - beta) * trend;
+        seasonal[t % period] = gamma * (y - level) + (1.0 - gamma) * s;
+    }
+
+    Fit { sse, level, trend, seasonal, alpha, beta, gamma }
+}
+
+// Forecasts the `horizon` values after `series`, which has a season of
+// `period` values, picking the smoothing parameters with the smallest
+// one-step-ahead error. Needs at least two full seasons of history.
+//
+// Intervals widen with the horizon as in the state space form of the model
+// (Hyndman et al., ETS(A,A,A)): the variance h steps out is
+// sigma^2 * (1 + sum of c_j^2 for j < h), c_j = alpha(1 + j beta) + gamma(1 - alpha)
+// when j is a whole number of seasons, without the gamma term otherwise.
+// Counts cannot go negative, so neither do forecasts or lower bounds.
+pub fn holt_winters(series: &[f64], period: usize, horizon: usize) -> Option<Vec<Interval>> {
+    if period == 0 || series.len() < 2 * period {
+        return None;
+    }
+
+    let mut best: Option<Fit> = None;
+    for alpha in ALPHAS {
+        for beta in BETAS {
+            for gamma in GAMMAS {
+                let candidate = fit(series, period, alpha, beta, gamma);
+                if best.as_ref().is_none_or(|b| candidate.sse < b.sse) {
+                    best = Some(candidate);
+                }
+            }
+        }
+    }
+    let fit = best?;
+
+    let sigma2 = fit.sse / (series.len() - period) as f64;
+    let mut spread = 0.0;
+    let forecasts = (1..=horizon)
+        .map(|h| {
+            if h > 1 {
+                let j = h - 1;
+                let season = if j % period == 0 { fit.gamma * (1.0 - fit.alpha) } else { 0.0 };
+                let c = fit.alpha * (1.0 + j as f64 * fit.beta) + season;
+                spread += c * c;
+            }
+            let point = fit.level + h as f64 * fit.trend + fit.seasonal[(series.len() + h - 1) % period];
+            let half = Z_95 * (sigma2 * (1.0 + spread)).sqrt();
+            Interval {
+                forecast: point.max(0.0),
+                lower: (point - half).max(0.0),
+                upper: (point + half).max(0.0),
+            }
+        })
+        .collect();
+    Some(forecasts)
+}
+
+// Probability that a ticket has to wait, with `agents` agents and `load`
+// Erlangs of work. Goes through Erlang B, whose recurrence stays stable for
+// large agent counts where the factorials in the closed form do not.
+fn erlang_c(agents: u32, load: f64) -> f64 {
+    let mut b = 1.0;
+    for n in 1..=agents {
+        b = load * b / (n as f64 + load * b);
+    }
+    let n = agents as f64;
+    n * b / (n - load * (1.0 - b))
+}
+
+// Fewest agents such that `service_level` of tickets arriving at `per_hour`,
+// each taking `handle_secs`, wait no longer than `answer_secs` (Erlang C),
+// up to MAX_AGENTS.
+pub fn agents_needed(per_hour: f64, handle_secs: f64, answer_secs: f64, service_level: f64) -> u32 {
+    if !(per_hour > 0.0 && handle_secs > 0.0) {
+        return 0;
+    }
+    let load = per_hour * handle_secs / 3600.0;
+    if load >= MAX_AGENTS as f64 {
+        return MAX_AGENTS;
+    }
+    // Fewer agents than the load can never keep up.
+    let Some(fewest) = (load.floor() as u32).checked_add(1) else {
+        return MAX_AGENTS;
+    };
+    (fewest..MAX_AGENTS)
+        .find(|&agents| {
+            let waiting = erlang_c(agents, load);
+            let answered = 1.0 - waiting * (-(agents as f64 - load) * answer_secs / handle_secs).exp();
+            answered >= service_level
+        })
+        .unwrap_or(MAX_AGENTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn erlang_c_matches_the_published_table() {
+        // 10 Erlangs on 11 agents waits 68.21% of the time.
+        assert!(close(erlang_c(11, 10.0), 0.6821), "{}", erlang_c(11, 10.0));
+        assert!(close(erlang_c(1, 0.5), 0.5));
+    }
+
+    #[test]
+    fn agents_cover_the_load_and_stay_bounded() {
+        assert_eq!(agents_needed(0.0, 300.0, 60.0, 0.8), 0);
+        assert_eq!(agents_needed(f64::NAN, 300.0, 60.0, 0.8), 0);
+        // 120 tickets an hour of 5 minutes each is 10 Erlangs.
+        let agents = agents_needed(120.0, 300.0, 60.0, 0.8);
+        assert!(agents > 10 && agents < 20, "{}", agents);
+        assert!(agents_needed(120.0, 300.0, 60.0, 0.95) >= agents);
+        assert_eq!(agents_needed(1e12, MAX_STAFFING_SECS as f64, 1.0, 0.8), MAX_AGENTS);
+        assert_eq!(agents_needed(f64::INFINITY, 300.0, 60.0, 0.8), MAX_AGENTS);
+    }
+
+    #[test]
+    fn fewer_than_two_seasons_cannot_be_fitted() {
+        assert!(holt_winters(&[1.0; 7], 7, 3).is_none());
+        assert!(holt_winters(&[1.0; 13], 7, 3).is_none());
+        assert!(holt_winters(&[1.0; 14], 0, 3).is_none());
+        assert_eq!(holt_winters(&[1.0; 14], 7, 3).map(|f| f.len()), Some(3));
+    }
+
+    #[test]
+    fn a_constant_series_forecasts_the_constant() {
+        let forecast = holt_winters(&[5.0; 28], 7, 10).unwrap();
+        for f in forecast {
+            assert!(close(f.forecast, 5.0) && close(f.lower, 5.0) && close(f.upper, 5.0), "{:?}", f);
+        }
+    }
+
+    #[test]
+    fn a_periodic_series_repeats_its_season() {
+        let season = [0.0, 2.0, 9.0, 4.0, 1.0];
+        let series: Vec<f64> = season.iter().copied().cycle().take(season.len() * 4).collect();
+        let forecast = holt_winters(&series, season.len(), 2 * season.len()).unwrap();
+        for (h, f) in forecast.iter().enumerate() {
+            assert!(close(f.forecast, season[h % season.len()]), "{}: {:?}", h, f);
+        }
+    }
+}
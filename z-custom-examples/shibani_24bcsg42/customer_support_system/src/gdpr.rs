@@ -0,0 +1,41 @@
+use serde::Serialize;
+use std::io::{Cursor, Write};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+// What erased personal data is replaced with. Status, priority, channel,
+// agent and timestamps are kept so reports and analytics stay correct.
+pub const ERASED_NAME: &str = "Erased customer";
+pub const REDACTED: &str = "[erased]";
+
+// Ticket event kinds whose values are kept: statuses, priorities, agents and
+// ticket ids. Any other value, such as a tag name, is redacted.
+pub const KEPT_EVENT_VALUES: [&str; 7] =
+    ["status", "priority", "assignment", "merged_into", "merged_from", "split_to", "split_from"];
+
+// A unique, undeliverable address so the per-tenant email index still holds.
+pub fn erased_email(customer_id: Uuid) -> String {
+    format!("erased-{}@erased.invalid", customer_id)
+}
+
+// Zip archive of JSON documents, built in memory.
+pub struct Archive {
+    zip: ZipWriter<Cursor<Vec<u8>>>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Archive { zip: ZipWriter::new(Cursor::new(Vec::new())) }
+    }
+
+    pub fn add_json<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), String> {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let json = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+        self.zip.start_file(name, options).map_err(|e| e.to_string())?;
+        self.zip.write_all(&json).map_err(|e| e.to_string())
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        self.zip.finish().map(Cursor::into_inner).map_err(|e| e.to_string())
+    }
+}
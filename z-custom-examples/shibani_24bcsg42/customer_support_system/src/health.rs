@@ -0,0 +1,108 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use serde::Serialize;
+use crate::app_state::AppState;
+
+// Liveness: the process is up and serving. Deliberately touches nothing
+// else, so a database outage does not get the pod restarted.
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub shutting_down: bool,
+    pub database: Check,
+    pub migrations: MigrationCheck,
+    pub workers: Vec<WorkerCheck>,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MigrationCheck {
+    pub ok: bool,
+    // False when the database has no _sqlx_migrations table.
+    pub tracked: bool,
+    pub expected: i64,
+    pub applied: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WorkerCheck {
+    pub name: &'static str,
+    pub running: bool,
+}
+
+// Newest migration in migrations/, read at compile time.
+fn expected_migration() -> i64 {
+    sqlx::migrate!().iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+// Migrations are applied with `sqlx migrate run`, which records each one in
+// _sqlx_migrations. Databases migrated by hand before that have no such table;
+// Ok(None) then, and readiness does not hold it against them.
+async fn applied_migration(db: &DatabaseConnection) -> Result<Option<Option<i64>>, sea_orm::DbErr> {
+    let table = db
+        .query_one(Statement::from_string(
+            DbBackend::Postgres,
+            "SELECT to_regclass('_sqlx_migrations') IS NOT NULL",
+        ))
+        .await?;
+    if !table.map(|row| row.try_get_by_index::<bool>(0)).transpose()?.unwrap_or(false) {
+        return Ok(None);
+    }
+    let row = db
+        .query_one(Statement::from_string(
+            DbBackend::Postgres,
+            "SELECT MAX(version) FROM _sqlx_migrations WHERE success",
+        ))
+        .await?;
+    match row {
+        Some(row) => row.try_get_by_index::<Option<i64>>(0).map(Some),
+        None => Ok(Some(None)),
+    }
+}
+
+// Readiness: 200 only when requests can actually be served. 503 with the
+// failing checks otherwise, including while shutting down so traffic moves
+// elsewhere before the listener closes.
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let database = match state.db.ping().await {
+        Ok(()) => Check { ok: true, error: None },
+        Err(e) => Check { ok: false, error: Some(e.to_string()) },
+    };
+
+    let expected = expected_migration();
+    let migrations = match applied_migration(&state.db).await {
+        // A newer schema is fine: during a rolling deploy the old pods keep
+        // serving after the new release has migrated.
+        Ok(Some(applied)) => {
+            MigrationCheck { ok: applied.is_some_and(|v| v >= expected), tracked: true, expected, applied, error: None }
+        }
+        Ok(None) => MigrationCheck { ok: true, tracked: false, expected, applied: None, error: None },
+        Err(e) => MigrationCheck { ok: false, tracked: true, expected, applied: None, error: Some(e.to_string()) },
+    };
+
+    let workers: Vec<WorkerCheck> = state
+        .workers
+        .status()
+        .into_iter()
+        .map(|w| WorkerCheck { name: w.name, running: w.running })
+        .collect();
+
+    let shutting_down = state.workers.is_shutting_down();
+    let ready = !shutting_down && database.ok && migrations.ok && workers.iter().all(|w| w.running);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(Readiness { ready, shutting_down, database, migrations, workers }))
+}
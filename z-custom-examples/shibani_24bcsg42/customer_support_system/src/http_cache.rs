@@ -0,0 +1,64 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Public help-center responses may be cached by browsers and CDNs for a short while.
+const PUBLIC_MAX_AGE: u32 = 60;
+
+pub fn etag_for(bytes: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return if_none_match == "*" || if_none_match.split(',').any(|tag| tag.trim() == etag);
+    }
+
+    match (headers.get(header::IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok()), last_modified) {
+        (Some(since), Some(modified)) => DateTime::parse_from_rfc2822(since)
+            .map(|since| modified.timestamp() <= since.timestamp())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+// Serialises `body` and answers 304 Not Modified when the client already holds
+// the same representation.
+pub fn cached_json<T: Serialize>(
+    headers: &HeaderMap,
+    body: &T,
+    last_modified: Option<DateTime<Utc>>,
+) -> Response {
+    let bytes = match serde_json::to_vec(body) {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let etag = etag_for(&bytes);
+
+    let mut response = if is_not_modified(headers, &etag, last_modified) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        ([(header::CONTENT_TYPE, "application/json")], bytes).into_response()
+    };
+
+    let out = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        out.insert(header::ETAG, value);
+    }
+    if let Some(modified) = last_modified
+        && let Ok(value) = HeaderValue::from_str(&modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+    {
+        out.insert(header::LAST_MODIFIED, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={}", PUBLIC_MAX_AGE)) {
+        out.insert(header::CACHE_CONTROL, value);
+    }
+
+    response
+}
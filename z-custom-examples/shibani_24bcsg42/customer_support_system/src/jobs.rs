@@ -0,0 +1,473 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::{BoxFuture, FutureExt};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    QueryFilter, Statement, TryInsertResult,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+use uuid::Uuid;
+use crate::app_state::AppState;
+use crate::config::Config;
+use crate::entity::{job_schedules, jobs};
+use crate::{api, notify, retention};
+
+// A job moves queued -> running -> succeeded. A failed attempt goes back to
+// queued with a later run_at until max_attempts is used up; then it is dead
+// and stays in the table until an admin retries it.
+pub const QUEUED: &str = "queued";
+pub const RUNNING: &str = "running";
+pub const SUCCEEDED: &str = "succeeded";
+pub const DEAD: &str = "dead";
+pub const STATUSES: [&str; 4] = [QUEUED, RUNNING, SUCCEEDED, DEAD];
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(15);
+// Shortest gap between a run timing out and its lease expiring.
+const LEASE_MARGIN: Duration = Duration::from_secs(5);
+
+// A kind of background work. The job value is the payload: it is stored as
+// JSON when enqueued and handed back to `run` by whichever worker claims it.
+pub trait Job: Serialize + DeserializeOwned + Send + 'static {
+    const KIND: &'static str;
+    const MAX_ATTEMPTS: i32 = 5;
+
+    fn run(self, state: &AppState) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+#[derive(Default)]
+pub struct EnqueueOptions {
+    pub tenant_id: Option<Uuid>,
+    // Not before this time; now when unset.
+    pub run_at: Option<DateTime<Utc>>,
+    // While a job with the same kind and key is queued or running, enqueueing
+    // another is a no-op.
+    pub unique_key: Option<String>,
+}
+
+// Adds a job. Pass a transaction to enqueue atomically with the change that
+// calls for it. Returns None when a job with the same unique key is pending.
+pub async fn enqueue<C: ConnectionTrait, J: Job>(db: &C, job: &J, options: EnqueueOptions) -> Result<Option<Uuid>, DbErr> {
+    let payload = serde_json::to_value(job).map_err(|e| DbErr::Custom(format!("{}: {}", J::KIND, e)))?;
+    insert(db, J::KIND, payload, J::MAX_ATTEMPTS, options).await
+}
+
+async fn insert<C: ConnectionTrait>(
+    db: &C,
+    kind: &str,
+    payload: serde_json::Value,
+    max_attempts: i32,
+    options: EnqueueOptions,
+) -> Result<Option<Uuid>, DbErr> {
+    let now = Utc::now();
+    let id = Uuid::new_v4();
+    let row = jobs::ActiveModel {
+        id: Set(id),
+        tenant_id: Set(options.tenant_id),
+        kind: Set(kind.to_string()),
+        payload: Set(payload),
+        status: Set(QUEUED.into()),
+        attempts: Set(0),
+        max_attempts: Set(max_attempts),
+        run_at: Set(options.run_at.unwrap_or(now)),
+        locked_at: Set(None),
+        locked_by: Set(None),
+        last_error: Set(None),
+        unique_key: Set(options.unique_key),
+        created_at: Set(now),
+        updated_at: Set(now),
+        finished_at: Set(None),
+    };
+    // The unique index is partial, so the conflict target has to name its
+    // predicate.
+    let on_conflict = OnConflict::columns([jobs::Column::Kind, jobs::Column::UniqueKey])
+        .target_and_where(
+            Expr::col(jobs::Column::UniqueKey)
+                .is_not_null()
+                .and(Expr::col(jobs::Column::Status).is_in([QUEUED, RUNNING])),
+        )
+        .do_nothing()
+        .to_owned();
+    let inserted = jobs::Entity::insert(row)
+        .on_conflict(on_conflict)
+        .do_nothing()
+        .exec_without_returning(db)
+        .await?;
+    Ok(matches!(inserted, TryInsertResult::Inserted(n) if n > 0).then_some(id))
+}
+
+type Handler = fn(serde_json::Value, AppState) -> BoxFuture<'static, Result<(), String>>;
+
+fn handle<J: Job>(payload: serde_json::Value, state: AppState) -> BoxFuture<'static, Result<(), String>> {
+    async move {
+        let job: J = serde_json::from_value(payload).map_err(|e| format!("invalid payload: {}", e))?;
+        job.run(&state).await
+    }
+    .boxed()
+}
+
+struct Recurring {
+    name: &'static str,
+    kind: &'static str,
+    max_attempts: i32,
+    cron: cron::Schedule,
+    payload: serde_json::Value,
+}
+
+// The job kinds a worker can run and the recurring ones it keeps enqueueing.
+#[derive(Default)]
+pub struct Registry {
+    handlers: HashMap<&'static str, Handler>,
+    recurring: Vec<Recurring>,
+}
+
+impl Registry {
+    pub fn register<J: Job>(&mut self) -> &mut Self {
+        self.handlers.insert(J::KIND, handle::<J>);
+        self
+    }
+
+    // `cron` has six fields, seconds first: "0 0 * * * *" is hourly. Times
+    // are UTC.
+    pub fn schedule<J: Job>(&mut self, name: &'static str, cron: &str, job: J) -> &mut Self {
+        let cron = cron::Schedule::from_str(cron).unwrap_or_else(|e| panic!("schedule {}: {}", name, e));
+        let payload = serde_json::to_value(&job).expect("job payload serializes");
+        self.register::<J>();
+        self.recurring.push(Recurring { name, kind: J::KIND, max_attempts: J::MAX_ATTEMPTS, cron, payload });
+        self
+    }
+}
+
+// Every job kind in the application.
+pub fn registry(config: &Config) -> Registry {
+    let mut registry = Registry::default();
+    registry.register::<notify::DeliverMention>();
+    registry.register::<api::RunImport>();
+    if config.features.trash_purge {
+        registry.schedule("trash_purge", "0 0 * * * *", retention::PurgeTrash {});
+    }
+    registry
+}
+
+// Starts the worker and the scheduler. Both stop when shutdown starts; jobs
+// already running are waited for by Workers::shutdown.
+pub fn start(state: &AppState, registry: Registry) {
+    let registry = Arc::new(registry);
+    let worker_id = format!("{}-{}", std::process::id(), &Uuid::new_v4().to_string()[..8]);
+    state.workers.spawn_worker("job_runner", run_jobs(state.clone(), registry.clone(), worker_id));
+    state.workers.spawn_worker("job_scheduler", run_schedules(state.clone(), registry));
+}
+
+async fn run_jobs(state: AppState, registry: Arc<Registry>, worker_id: String) {
+    let config = state.config.jobs.clone();
+    let shutdown = state.workers.shutdown_token();
+    let slots = Arc::new(Semaphore::new(config.concurrency));
+    let poll = Duration::from_millis(config.poll_interval_ms);
+    let lease = Duration::from_secs(config.lease_secs);
+
+    loop {
+        if let Err(e) = reclaim_expired(&state.db, lease).await {
+            tracing::error!(error = %e, "could not requeue expired jobs");
+        }
+        let free = slots.available_permits();
+        let claimed = if free > 0 {
+            claim(&state.db, &worker_id, free).await.unwrap_or_else(|e| {
+                tracing::error!(error = %e, "could not claim jobs");
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        let idle = claimed.is_empty();
+        for job in claimed {
+            let permit = slots.clone().acquire_owned().await.expect("semaphore is never closed");
+            let (job_state, registry) = (state.clone(), registry.clone());
+            let span = tracing::info_span!("job", id = %job.id, kind = %job.kind, attempt = job.attempts);
+            state.workers.spawn(
+                async move {
+                    execute(&job_state, &registry, job, lease).await;
+                    drop(permit);
+                }
+                .instrument(span),
+            );
+        }
+        // Straight back for more while there is a backlog.
+        if idle || slots.available_permits() == 0 {
+            tokio::select! {
+                _ = tokio::time::sleep(poll) => {}
+                _ = shutdown.cancelled() => return,
+            }
+        } else if shutdown.is_cancelled() {
+            return;
+        }
+    }
+}
+
+async fn claim(db: &DatabaseConnection, worker_id: &str, limit: usize) -> Result<Vec<jobs::Model>, DbErr> {
+    jobs::Entity::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"UPDATE jobs
+               SET status = $1, attempts = attempts + 1, locked_at = now(), locked_by = $2, updated_at = now()
+               WHERE id IN (
+                   SELECT id FROM jobs
+                   WHERE status = $3 AND run_at <= now()
+                   ORDER BY run_at
+                   LIMIT $4
+                   FOR UPDATE SKIP LOCKED
+               )
+               RETURNING *"#,
+            [RUNNING.into(), worker_id.into(), QUEUED.into(), (limit as i64).into()],
+        ))
+        .all(db)
+        .await
+}
+
+// Jobs whose worker died mid-run. The attempt they were on counts, so a job
+// that keeps taking its worker down ends up dead like any other failure.
+// Compared on the database clock, which also set locked_at in `claim`.
+async fn reclaim_expired(db: &DatabaseConnection, lease: Duration) -> Result<(), DbErr> {
+    let expired = || {
+        Expr::col(jobs::Column::LockedAt)
+            .lt(Expr::cust_with_values("now() - make_interval(secs => $1)", [lease.as_secs_f64()]))
+    };
+    let dead = jobs::Entity::update_many()
+        .col_expr(jobs::Column::Status, Expr::value(DEAD))
+        .col_expr(jobs::Column::LockedAt, Expr::value(Option::<DateTime<Utc>>::None))
+        .col_expr(jobs::Column::LockedBy, Expr::value(Option::<String>::None))
+        .col_expr(jobs::Column::LastError, Expr::value("lease expired"))
+        .col_expr(jobs::Column::FinishedAt, Expr::cust("now()"))
+        .col_expr(jobs::Column::UpdatedAt, Expr::cust("now()"))
+        .filter(jobs::Column::Status.eq(RUNNING))
+        .filter(expired())
+        .filter(Expr::col(jobs::Column::Attempts).gte(Expr::col(jobs::Column::MaxAttempts)))
+        .exec(db)
+        .await?;
+    if dead.rows_affected > 0 {
+        tracing::error!(count = dead.rows_affected, "jobs with expired leases have no attempts left");
+    }
+    let requeued = jobs::Entity::update_many()
+        .col_expr(jobs::Column::Status, Expr::value(QUEUED))
+        .col_expr(jobs::Column::LockedAt, Expr::value(Option::<DateTime<Utc>>::None))
+        .col_expr(jobs::Column::LockedBy, Expr::value(Option::<String>::None))
+        .col_expr(jobs::Column::LastError, Expr::value("lease expired"))
+        .col_expr(jobs::Column::UpdatedAt, Expr::cust("now()"))
+        .filter(jobs::Column::Status.eq(RUNNING))
+        .filter(expired())
+        .exec(db)
+        .await?;
+    if requeued.rows_affected > 0 {
+        tracing::warn!(count = requeued.rows_affected, "requeued jobs with expired leases");
+    }
+    Ok(())
+}
+
+// How long a run may take. Shorter than the lease, so a slow job is stopped
+// before another worker can reclaim it and start a second copy.
+fn run_timeout(lease: Duration) -> Duration {
+    lease.saturating_sub((lease / 10).max(LEASE_MARGIN))
+}
+
+pub fn backoff(base: Duration, attempt: i32) -> Duration {
+    let doublings = (attempt.max(1) - 1).min(16) as u32;
+    base.saturating_mul(1 << doublings).min(MAX_BACKOFF)
+}
+
+async fn execute(state: &AppState, registry: &Registry, job: jobs::Model, lease: Duration) {
+    let result = match registry.handlers.get(job.kind.as_str()) {
+        Some(handler) => {
+            let run = AssertUnwindSafe(handler(job.payload.clone(), state.clone())).catch_unwind();
+            let timeout = run_timeout(lease);
+            match tokio::time::timeout(timeout, run).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err("job panicked".to_string()),
+                Err(_) => Err(format!("timed out after {:?}", timeout)),
+            }
+        }
+        None => Err(format!("no handler for job kind {:?}", job.kind)),
+    };
+
+    let now = Utc::now();
+    let mut update = jobs::Entity::update_many()
+        .col_expr(jobs::Column::LockedAt, Expr::value(Option::<DateTime<Utc>>::None))
+        .col_expr(jobs::Column::LockedBy, Expr::value(Option::<String>::None))
+        .col_expr(jobs::Column::UpdatedAt, Expr::value(now));
+    update = match &result {
+        Ok(()) => {
+            tracing::info!("job succeeded");
+            update
+                .col_expr(jobs::Column::Status, Expr::value(SUCCEEDED))
+                .col_expr(jobs::Column::FinishedAt, Expr::value(now))
+        }
+        Err(e) if job.attempts >= job.max_attempts => {
+            tracing::error!(error = %e, "job failed for the last time");
+            update
+                .col_expr(jobs::Column::Status, Expr::value(DEAD))
+                .col_expr(jobs::Column::LastError, Expr::value(e.as_str()))
+                .col_expr(jobs::Column::FinishedAt, Expr::value(now))
+        }
+        Err(e) => {
+            let delay = backoff(Duration::from_secs(state.config.jobs.retry_base_secs), job.attempts);
+            tracing::warn!(error = %e, retry_in = ?delay, "job failed, will retry");
+            update
+                .col_expr(jobs::Column::Status, Expr::value(QUEUED))
+                .col_expr(jobs::Column::LastError, Expr::value(e.as_str()))
+                .col_expr(jobs::Column::RunAt, Expr::value(now + chrono::Duration::from_std(delay).unwrap_or_default()))
+        }
+    };
+    // Only if the lease is still ours; after a reclaim the row belongs to
+    // whoever claimed it next.
+    let saved = update
+        .filter(jobs::Column::Id.eq(job.id))
+        .filter(jobs::Column::Status.eq(RUNNING))
+        .filter(jobs::Column::LockedBy.eq(job.locked_by.clone()))
+        .exec(state.db.as_ref())
+        .await;
+    if let Err(e) = saved {
+        tracing::error!(error = %e, "could not record job result");
+    }
+}
+
+async fn run_schedules(state: AppState, registry: Arc<Registry>) {
+    let shutdown = state.workers.shutdown_token();
+    if let Err(e) = register_schedules(&state.db, &registry).await {
+        tracing::error!(error = %e, "could not register job schedules");
+    }
+    loop {
+        for recurring in &registry.recurring {
+            if let Err(e) = enqueue_due(&state.db, recurring).await {
+                tracing::error!(schedule = recurring.name, error = %e, "could not enqueue scheduled job");
+            }
+        }
+        if let Err(e) = delete_succeeded(&state).await {
+            tracing::error!(error = %e, "could not delete old succeeded jobs");
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(SCHEDULER_INTERVAL) => {}
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
+
+fn next_after(cron: &cron::Schedule, after: DateTime<Utc>) -> DateTime<Utc> {
+    // Every expression has a next time unless it names a date that never
+    // comes (e.g. 30 February); such a schedule just never runs.
+    cron.after(&after).next().unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+// Creates missing schedules and updates ones whose cron changed. An unchanged
+// schedule keeps its next_run_at, so a restart neither skips nor repeats a run.
+async fn register_schedules(db: &DatabaseConnection, registry: &Registry) -> Result<(), DbErr> {
+    let now = Utc::now();
+    for recurring in &registry.recurring {
+        let row = job_schedules::ActiveModel {
+            name: Set(recurring.name.to_string()),
+            kind: Set(recurring.kind.to_string()),
+            cron: Set(recurring.cron.source().to_string()),
+            next_run_at: Set(next_after(&recurring.cron, now)),
+            last_run_at: Set(None),
+            updated_at: Set(now),
+        };
+        let on_conflict = OnConflict::column(job_schedules::Column::Name)
+            .update_columns([
+                job_schedules::Column::Kind,
+                job_schedules::Column::Cron,
+                job_schedules::Column::NextRunAt,
+                job_schedules::Column::UpdatedAt,
+            ])
+            .action_and_where(Expr::col((job_schedules::Entity, job_schedules::Column::Cron)).ne(recurring.cron.source()))
+            .to_owned();
+        job_schedules::Entity::insert(row)
+            .on_conflict(on_conflict)
+            .do_nothing()
+            .exec_without_returning(db)
+            .await?;
+    }
+    Ok(())
+}
+
+// Moves next_run_at forward only if it is still the value we read, so when
+// several schedulers race exactly one of them enqueues the occurrence.
+async fn enqueue_due(db: &DatabaseConnection, recurring: &Recurring) -> Result<(), DbErr> {
+    let now = Utc::now();
+    let Some(schedule) = job_schedules::Entity::find_by_id(recurring.name.to_string())
+        .filter(job_schedules::Column::NextRunAt.lte(now))
+        .one(db)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let advanced = job_schedules::Entity::update_many()
+        .col_expr(job_schedules::Column::NextRunAt, Expr::value(next_after(&recurring.cron, now)))
+        .col_expr(job_schedules::Column::LastRunAt, Expr::value(now))
+        .col_expr(job_schedules::Column::UpdatedAt, Expr::value(now))
+        .filter(job_schedules::Column::Name.eq(recurring.name))
+        .filter(job_schedules::Column::NextRunAt.eq(schedule.next_run_at))
+        .exec(db)
+        .await?;
+    if advanced.rows_affected == 0 {
+        return Ok(());
+    }
+
+    // A run still going when the next one is due is not doubled up.
+    let options = EnqueueOptions {
+        unique_key: Some(format!("schedule:{}", recurring.name)),
+        ..Default::default()
+    };
+    insert(db, recurring.kind, recurring.payload.clone(), recurring.max_attempts, options).await?;
+    Ok(())
+}
+
+async fn delete_succeeded(state: &AppState) -> Result<(), DbErr> {
+    let cutoff = Utc::now() - chrono::Duration::days(state.config.jobs.keep_succeeded_days);
+    jobs::Entity::delete_many()
+        .filter(jobs::Column::Status.eq(SUCCEEDED))
+        .filter(jobs::Column::FinishedAt.lt(cutoff))
+        .exec(state.db.as_ref())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn backoff_doubles_from_the_base_up_to_an_hour() {
+        let base = Duration::from_secs(10);
+        assert_eq!(backoff(base, 0), base);
+        assert_eq!(backoff(base, 1), base);
+        assert_eq!(backoff(base, 2), Duration::from_secs(20));
+        assert_eq!(backoff(base, 4), Duration::from_secs(80));
+        assert_eq!(backoff(base, 10), MAX_BACKOFF);
+        assert_eq!(backoff(base, i32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn runs_time_out_before_the_lease_expires() {
+        assert_eq!(run_timeout(Duration::from_secs(300)), Duration::from_secs(270));
+        assert_eq!(run_timeout(Duration::from_secs(10)), Duration::from_secs(5));
+        assert_eq!(run_timeout(Duration::from_secs(1)), Duration::ZERO);
+    }
+
+    #[test]
+    fn next_after_is_strictly_later() {
+        let hourly = cron::Schedule::from_str("0 0 * * * *").unwrap();
+        let at = |h, m| Utc.with_ymd_and_hms(2026, 3, 1, h, m, 0).unwrap();
+        assert_eq!(next_after(&hourly, at(9, 30)), at(10, 0));
+        assert_eq!(next_after(&hourly, at(10, 0)), at(11, 0));
+
+        let never = cron::Schedule::from_str("0 0 0 30 2 *").unwrap();
+        assert_eq!(next_after(&never, at(9, 30)), DateTime::<Utc>::MAX_UTC);
+    }
+}
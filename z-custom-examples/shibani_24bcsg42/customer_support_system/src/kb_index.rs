@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+use crate::entity::{article_revisions, knowledge_base};
+use crate::locale;
+
+// Okapi BM25 parameters; the usual defaults work well for short help articles.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+// Stop words per language, written without accents since tokens are folded
+// before they are compared. Languages not listed keep every word.
+const STOP_WORDS_EN: &[&str] = &[
+    "an", "and", "are", "as", "at", "be", "but", "by", "can", "do", "for", "from", "has",
+    "have", "how", "if", "in", "is", "it", "me", "my", "no", "not", "of", "on", "or", "our",
+    "so", "that", "the", "this", "to", "was", "we", "what", "when", "with", "you", "your",
+];
+
+const STOP_WORDS_ES: &[&str] = &[
+    "al", "como", "con", "cual", "de", "del", "donde", "el", "en", "es", "esta", "este", "ha",
+    "hay", "la", "las", "le", "lo", "los", "me", "mi", "mis", "no", "nos", "para", "pero",
+    "por", "que", "se", "si", "sin", "su", "sus", "te", "tu", "tus", "un", "una", "y", "ya",
+];
+
+const STOP_WORDS_FR: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "comment", "dans", "de", "des", "du", "elle", "en",
+    "est", "et", "il", "je", "la", "le", "les", "leur", "ma", "mais", "me", "mes", "mon",
+    "ne", "nos", "notre", "nous", "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se",
+    "ses", "son", "sont", "sur", "ta", "te", "tes", "ton", "tu", "un", "une", "vos", "votre",
+    "vous",
+];
+
+const STOP_WORDS_DE: &[&str] = &[
+    "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "da", "das", "dass",
+    "dem", "den", "der", "des", "die", "du", "ein", "eine", "einem", "einen", "einer", "er",
+    "es", "fur", "hat", "ich", "ihr", "im", "in", "ist", "kann", "mein", "mich", "mir", "mit",
+    "nicht", "oder", "sie", "sind", "uber", "und", "wie", "wir", "wird", "zu", "zum", "zur",
+];
+
+const STOP_WORDS_PT: &[&str] = &[
+    "ao", "aos", "as", "com", "como", "da", "das", "de", "do", "dos", "ela", "ele", "em",
+    "esta", "este", "eu", "foi", "ha", "mais", "mas", "meu", "minha", "na", "nao", "nas",
+    "no", "nos", "os", "ou", "para", "pela", "pelo", "por", "que", "se", "sem", "seu", "sua",
+    "um", "uma", "voce",
+];
+
+fn stop_words(locale: &str) -> &'static [&'static str] {
+    match locale::language(locale) {
+        "en" => STOP_WORDS_EN,
+        "es" => STOP_WORDS_ES,
+        "fr" => STOP_WORDS_FR,
+        "de" => STOP_WORDS_DE,
+        "pt" => STOP_WORDS_PT,
+        _ => &[],
+    }
+}
+
+// Lowercases and strips accents, so "Facturación" and "facturacion" are the
+// same term whichever way the customer typed it.
+fn fold(token: &str) -> String {
+    token.to_lowercase().nfd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+pub fn tokenize(locale: &str, text: &str) -> Vec<String> {
+    let stop_words = stop_words(locale);
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(fold)
+        .filter(|t| t.chars().count() > 1 && !stop_words.contains(&t.as_str()))
+        .collect()
+}
+
+struct IndexedDoc {
+    term_freqs: HashMap<String, u32>,
+    len: u32,
+}
+
+// BM25 over one organization's published articles in one locale. Documents
+// are added and removed one at a time so the index follows publishing changes
+// without a full rebuild.
+struct Bm25Index {
+    locale: String,
+    docs: HashMap<Uuid, IndexedDoc>,
+    doc_freq: HashMap<String, u32>,
+    total_len: u64,
+}
+
+impl Bm25Index {
+    fn new(locale: &str) -> Self {
+        Bm25Index {
+            locale: locale.to_string(),
+            docs: HashMap::new(),
+            doc_freq: HashMap::new(),
+            total_len: 0,
+        }
+    }
+
+    fn upsert(&mut self, article_id: Uuid, title: &str, category: &str, content: &str) {
+        self.remove(article_id);
+
+        // The title is counted twice so that title matches outrank body matches.
+        let text = format!("{} {} {} {}", title, title, category, content);
+        let tokens = tokenize(&self.locale, &text);
+
+        let mut term_freqs: HashMap<String, u32> = HashMap::new();
+        for token in tokens.iter() {
+            *term_freqs.entry(token.clone()).or_insert(0) += 1;
+        }
+        for term in term_freqs.keys() {
+            *self.doc_freq.entry(term.clone()).or_insert(0) += 1;
+        }
+
+        self.total_len += tokens.len() as u64;
+        self.docs.insert(article_id, IndexedDoc { term_freqs, len: tokens.len() as u32 });
+    }
+
+    fn remove(&mut self, article_id: Uuid) {
+        let Some(doc) = self.docs.remove(&article_id) else {
+            return;
+        };
+
+        for term in doc.term_freqs.keys() {
+            if let Some(df) = self.doc_freq.get_mut(term) {
+                *df -= 1;
+                if *df == 0 {
+                    self.doc_freq.remove(term);
+                }
+            }
+        }
+        self.total_len -= doc.len as u64;
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<(Uuid, f64)> {
+        if self.docs.is_empty() {
+            return Vec::new();
+        }
+
+        let mut terms = tokenize(&self.locale, query);
+        terms.sort();
+        terms.dedup();
+
+        let n = self.docs.len() as f64;
+        let avg_len = self.total_len as f64 / n;
+
+        let mut scored: Vec<(Uuid, f64)> = self
+            .docs
+            .iter()
+            .filter_map(|(id, doc)| {
+                let score: f64 = terms
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *doc.term_freqs.get(term)? as f64;
+                        let df = *self.doc_freq.get(term)? as f64;
+                        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                        let norm = K1 * (1.0 - B + B * doc.len as f64 / avg_len);
+                        Some(idf * tf * (K1 + 1.0) / (tf + norm))
+                    })
+                    .sum();
+                (score > 0.0).then_some((*id, score))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+// The index only holds copies of published text and is rebuilt on start, so a
+// handler that panicked while holding the lock is no reason to fail every
+// later search; the lock is taken over rather than unwrapped.
+pub fn read(index: &RwLock<KbIndex>) -> RwLockReadGuard<'_, KbIndex> {
+    index.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn write(index: &RwLock<KbIndex>) -> RwLockWriteGuard<'_, KbIndex> {
+    index.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// One index per organization and locale so rankings never mix tenants, and
+// each language is tokenized with its own stop words.
+#[derive(Default)]
+pub struct KbIndex {
+    indexes: HashMap<(Uuid, String), Bm25Index>,
+}
+
+impl KbIndex {
+    pub fn upsert(
+        &mut self,
+        tenant_id: Uuid,
+        article_id: Uuid,
+        locale: &str,
+        title: &str,
+        category: &str,
+        content: &str,
+    ) {
+        self.indexes
+            .entry((tenant_id, locale.to_string()))
+            .or_insert_with(|| Bm25Index::new(locale))
+            .upsert(article_id, title, category, content);
+    }
+
+    pub fn remove(&mut self, tenant_id: Uuid, article_id: Uuid) {
+        for ((tenant, _), index) in self.indexes.iter_mut() {
+            if *tenant == tenant_id {
+                index.remove(article_id);
+            }
+        }
+    }
+
+    // Searches the articles written in `locale`, or failing that in another
+    // variant of the same language ("pt" for "pt-BR").
+    pub fn search(&self, tenant_id: Uuid, locale: &str, query: &str, limit: usize) -> Vec<(Uuid, f64)> {
+        let index = self.indexes.get(&(tenant_id, locale.to_string())).or_else(|| {
+            self.indexes
+                .iter()
+                .find(|((tenant, other), _)| {
+                    *tenant == tenant_id && locale::language(other) == locale::language(locale)
+                })
+                .map(|(_, index)| index)
+        });
+        index.map(|index| index.search(query, limit)).unwrap_or_default()
+    }
+
+    // Builds the index from the published revision of every visible article.
+    pub async fn load(db: &DatabaseConnection) -> Result<KbIndex, DbErr> {
+        let articles = knowledge_base::Entity::find()
+            .filter(knowledge_base::Column::PublishedRevisionId.is_not_null())
+            .filter(knowledge_base::Column::Status.ne("archived"))
+            .filter(knowledge_base::Column::DeletedAt.is_null())
+            .all(db)
+            .await?;
+
+        let revision_ids: Vec<Uuid> = articles.iter().filter_map(|a| a.published_revision_id).collect();
+        let revisions = article_revisions::Entity::find()
+            .filter(article_revisions::Column::Id.is_in(revision_ids))
+            .all(db)
+            .await?;
+
+        let mut index = KbIndex::default();
+        for rev in revisions {
+            if let Some(article) = articles.iter().find(|a| a.id == rev.article_id) {
+                index.upsert(
+                    article.tenant_id,
+                    rev.article_id,
+                    &article.locale,
+                    &rev.title,
+                    &rev.category,
+                    &rev.content,
+                );
+            }
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_of(docs: &[(Uuid, &str, &str, &str)]) -> (Uuid, KbIndex) {
+        let tenant = Uuid::new_v4();
+        let mut index = KbIndex::default();
+        for (id, title, category, content) in docs {
+            index.upsert(tenant, *id, "en", title, category, content);
+        }
+        (tenant, index)
+    }
+
+    #[test]
+    fn fold_lowercases_and_strips_accents() {
+        assert_eq!(fold("Facturación"), "facturacion");
+        assert_eq!(fold("ÜBER"), "uber");
+        assert_eq!(fold("çà"), "ca");
+        assert_eq!(fold("Straße"), "straße");
+    }
+
+    #[test]
+    fn tokenize_folds_case_and_accents_and_drops_stop_words() {
+        assert_eq!(tokenize("es", "La Facturación de mi cuenta"), ["facturacion", "cuenta"]);
+        assert_eq!(tokenize("en", "How do I reset MY password?"), ["reset", "password"]);
+        // Single characters never match anything useful.
+        assert_eq!(tokenize("en", "a b c refund"), ["refund"]);
+    }
+
+    #[test]
+    fn matching_article_ranks_first_and_title_outweighs_body() {
+        let (refunds, billing, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let (tenant, index) = index_of(&[
+            (refunds, "Refunds", "billing", "How long money takes to come back"),
+            (billing, "Invoices", "billing", "Download invoices. Refunds appear on the next invoice"),
+            (other, "Password reset", "account", "Use the forgot password link"),
+        ]);
+
+        let ranked = index.search(tenant, "en", "refunds", 10);
+        assert_eq!(ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [refunds, billing]);
+        assert!(ranked[0].1 > ranked[1].1);
+        assert_eq!(index.search(tenant, "en", "refunds", 1).len(), 1);
+        assert!(index.search(tenant, "en", "shipping", 10).is_empty());
+    }
+
+    #[test]
+    fn rare_terms_weigh_more_than_common_ones() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let (tenant, index) = index_of(&[
+            (a, "Account export", "account", "export"),
+            (b, "Account settings", "account", "settings"),
+            (c, "Account deletion", "account", "deletion"),
+        ]);
+
+        let ranked = index.search(tenant, "en", "account export", 10);
+        assert_eq!(ranked[0].0, a);
+        assert_eq!(ranked.len(), 3);
+    }
+
+    #[test]
+    fn removed_and_replaced_articles_stop_matching() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let (tenant, mut index) = index_of(&[(a, "Refunds", "billing", ""), (b, "Refunds later", "billing", "")]);
+
+        index.remove(tenant, a);
+        assert_eq!(index.search(tenant, "en", "refunds", 10).len(), 1);
+
+        index.upsert(tenant, b, "en", "Shipping", "orders", "");
+        assert!(index.search(tenant, "en", "refunds", 10).is_empty());
+        assert_eq!(index.search(tenant, "en", "shipping", 10)[0].0, b);
+    }
+
+    #[test]
+    fn tenants_and_languages_are_kept_apart() {
+        let a = Uuid::new_v4();
+        let (tenant, mut index) = index_of(&[(a, "Refunds", "billing", "")]);
+        assert!(index.search(Uuid::new_v4(), "en", "refunds", 10).is_empty());
+
+        let b = Uuid::new_v4();
+        index.upsert(tenant, b, "pt-BR", "Reembolsos", "cobrança", "");
+        assert_eq!(index.search(tenant, "pt-PT", "reembolsos", 10)[0].0, b);
+        assert!(index.search(tenant, "fr", "reembolsos", 10).is_empty());
+    }
+
+    #[test]
+    fn poisoned_lock_is_still_usable() {
+        let lock = std::sync::Arc::new(RwLock::new(KbIndex::default()));
+        let poisoner = lock.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.write().unwrap();
+            panic!("handler panicked");
+        })
+        .join();
+
+        assert!(lock.is_poisoned());
+        write(&lock).upsert(Uuid::new_v4(), Uuid::new_v4(), "en", "Refunds", "billing", "");
+        assert!(read(&lock).indexes.len() == 1);
+    }
+}
@@ -0,0 +1,269 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::Response;
+
+// Articles without a locale, and customers without a preference, are English.
+pub const DEFAULT_LOCALE: &str = "en";
+
+// Normalises a BCP 47 style tag ("pt_br", "PT-BR") to its usual casing
+// ("pt-BR"). Only language, script and region are kept apart; anything that
+// does not look like a tag is rejected.
+pub fn normalise(tag: &str) -> Option<String> {
+    let mut parts = tag.trim().split(['-', '_']);
+    let language = parts.next()?;
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut out = language.to_ascii_lowercase();
+    for (i, part) in parts.enumerate() {
+        let part = match part.len() {
+            4 if i == 0 && part.chars().all(|c| c.is_ascii_alphabetic()) => {
+                let (first, rest) = part.split_at(1);
+                format!("{}{}", first.to_ascii_uppercase(), rest.to_ascii_lowercase())
+            }
+            2 if part.chars().all(|c| c.is_ascii_alphabetic()) => part.to_ascii_uppercase(),
+            3 if part.chars().all(|c| c.is_ascii_digit()) => part.to_string(),
+            _ => return None,
+        };
+        out.push('-');
+        out.push_str(&part);
+    }
+    Some(out)
+}
+
+// "pt-BR" -> "pt".
+pub fn language(locale: &str) -> &str {
+    locale.split('-').next().unwrap_or(locale)
+}
+
+// Locales the caller asked for, most wanted first. An explicit ?locale= wins
+// over Accept-Language; ranges with q=0 and the "*" wildcard are dropped since
+// falling back to the original text is what "*" would mean anyway. Weights
+// are clamped to 0..=1, and one that does not parse counts as 0.
+pub fn requested(headers: &HeaderMap, explicit: Option<&str>) -> Vec<String> {
+    if let Some(locale) = explicit.and_then(normalise) {
+        return vec![locale];
+    }
+    let Some(accept) = headers.get(header::ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok()) else {
+        return Vec::new();
+    };
+
+    let mut ranges: Vec<(String, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let tag = normalise(params.next()?)?;
+            let q = match params.find_map(|p| p.trim().strip_prefix("q=")) {
+                Some(q) => q.trim().parse::<f32>().ok().filter(|q| q.is_finite()).map_or(0.0, |q| q.clamp(0.0, 1.0)),
+                None => 1.0,
+            };
+            (q > 0.0).then_some((tag, q))
+        })
+        .collect();
+    // Stable, so equal weights keep the order the client sent them in.
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().map(|(tag, _)| tag).collect()
+}
+
+// Picks the available locale that best matches the caller's preferences. For
+// each preference an exact match beats one on language alone, so "pt-BR"
+// takes "pt-BR" over "pt", and "pt" or "pt-PT" still gets "pt-BR" when that
+// is all there is.
+pub fn negotiate<'a, S: AsRef<str>>(requested: &[String], available: &'a [S]) -> Option<&'a str> {
+    requested.iter().find_map(|wanted| {
+        let exact = available.iter().find(|a| a.as_ref().eq_ignore_ascii_case(wanted));
+        exact
+            .or_else(|| available.iter().find(|a| language(a.as_ref()).eq_ignore_ascii_case(language(wanted))))
+            .map(AsRef::as_ref)
+    })
+}
+
+// Marks a response as depending on Accept-Language so shared caches keep one
+// copy per language, and names the language of the body when there is one.
+pub fn content_language(mut response: Response, locale: Option<&str>) -> Response {
+    let headers = response.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("Accept-Language"));
+    if let Some(locale) = locale
+        && let Ok(value) = HeaderValue::from_str(locale)
+    {
+        headers.insert(header::CONTENT_LANGUAGE, value);
+    }
+    response
+}
+
+// Languages the messages below are written in.
+pub const TRANSLATED: [&str; 5] = ["en", "de", "es", "fr", "pt"];
+
+// Customer-facing text. Everything shown to customers goes through here so a
+// new language is one more arm per message; unknown languages get English.
+#[derive(Clone, Copy)]
+pub enum Text {
+    OrganizationNotFound,
+    ArticleNotFound,
+    SurveyNotFound,
+    SurveyInvalidToken,
+    SurveyInvalidRating,
+    SurveyExpired,
+    SurveyAnswered,
+    SurveyQuestion,
+    // Placeholder: {link}
+    SurveyInvitation,
+    // Placeholder: {tickets}
+    TicketsMerged,
+}
+
+impl Text {
+    pub fn get(self, locale: &str) -> &'static str {
+        use Text::*;
+        match (self, language(locale)) {
+            (OrganizationNotFound, "es") => "Organización no encontrada",
+            (OrganizationNotFound, "fr") => "Organisation introuvable",
+            (OrganizationNotFound, "de") => "Organisation nicht gefunden",
+            (OrganizationNotFound, "pt") => "Organização não encontrada",
+            (OrganizationNotFound, _) => "Organization not found",
+
+            (ArticleNotFound, "es") => "Artículo no encontrado",
+            (ArticleNotFound, "fr") => "Article introuvable",
+            (ArticleNotFound, "de") => "Artikel nicht gefunden",
+            (ArticleNotFound, "pt") => "Artigo não encontrado",
+            (ArticleNotFound, _) => "Article not found",
+
+            (SurveyNotFound, "es") => "Encuesta no encontrada",
+            (SurveyNotFound, "fr") => "Enquête introuvable",
+            (SurveyNotFound, "de") => "Umfrage nicht gefunden",
+            (SurveyNotFound, "pt") => "Pesquisa não encontrada",
+            (SurveyNotFound, _) => "Survey not found",
+
+            (SurveyInvalidToken, "es") => "Enlace de encuesta no válido",
+            (SurveyInvalidToken, "fr") => "Lien d'enquête invalide",
+            (SurveyInvalidToken, "de") => "Ungültiger Umfragelink",
+            (SurveyInvalidToken, "pt") => "Link de pesquisa inválido",
+            (SurveyInvalidToken, _) => "Invalid survey token",
+
+            (SurveyInvalidRating, "es") => "La valoración debe estar entre 1 y 5",
+            (SurveyInvalidRating, "fr") => "La note doit être comprise entre 1 et 5",
+            (SurveyInvalidRating, "de") => "Die Bewertung muss zwischen 1 und 5 liegen",
+            (SurveyInvalidRating, "pt") => "A avaliação deve estar entre 1 e 5",
+            (SurveyInvalidRating, _) => "Rating must be between 1 and 5",
+
+            (SurveyExpired, "es") => "La encuesta ha caducado",
+            (SurveyExpired, "fr") => "L'enquête a expiré",
+            (SurveyExpired, "de") => "Die Umfrage ist abgelaufen",
+            (SurveyExpired, "pt") => "A pesquisa expirou",
+            (SurveyExpired, _) => "Survey has expired",
+
+            (SurveyAnswered, "es") => "La encuesta ya ha sido respondida",
+            (SurveyAnswered, "fr") => "Vous avez déjà répondu à cette enquête",
+            (SurveyAnswered, "de") => "Die Umfrage wurde bereits beantwortet",
+            (SurveyAnswered, "pt") => "A pesquisa já foi respondida",
+            (SurveyAnswered, _) => "Survey has already been answered",
+
+            (SurveyQuestion, "es") => "¿Cómo valoras la ayuda que recibiste, del 1 (mala) al 5 (excelente)?",
+            (SurveyQuestion, "fr") => "Comment évaluez-vous l'aide reçue, de 1 (mauvaise) à 5 (excellente) ?",
+            (SurveyQuestion, "de") => "Wie bewerten Sie die erhaltene Hilfe, von 1 (schlecht) bis 5 (ausgezeichnet)?",
+            (SurveyQuestion, "pt") => "Como você avalia a ajuda que recebeu, de 1 (ruim) a 5 (excelente)?",
+            (SurveyQuestion, _) => "How would you rate the help you received, from 1 (poor) to 5 (excellent)?",
+
+            (SurveyInvitation, "es") => {
+                "Tu ticket se ha resuelto. ¿Qué tal lo hicimos? Valora tu experiencia del 1 al 5 en {link}"
+            }
+            (SurveyInvitation, "fr") => {
+                "Votre demande a été résolue. Qu'avez-vous pensé de notre service ? Notez votre expérience de 1 à 5 sur {link}"
+            }
+            (SurveyInvitation, "de") => {
+                "Ihr Ticket wurde gelöst. Wie waren wir? Bewerten Sie Ihre Erfahrung von 1 bis 5 unter {link}"
+            }
+            (SurveyInvitation, "pt") => {
+                "Seu ticket foi resolvido. Como nos saímos? Avalie sua experiência de 1 a 5 em {link}"
+            }
+            (SurveyInvitation, _) => {
+                "Your ticket has been resolved. How did we do? Rate your experience from 1 to 5 at {link}"
+            }
+
+            (TicketsMerged, "es") => {
+                "Tus solicitudes {tickets} se han unido a este ticket. Continuaremos la conversación aquí."
+            }
+            (TicketsMerged, "fr") => {
+                "Vos demandes {tickets} ont été fusionnées dans ce ticket. Nous poursuivrons la conversation ici."
+            }
+            (TicketsMerged, "de") => {
+                "Ihre Anfragen {tickets} wurden mit diesem Ticket zusammengeführt. Wir setzen die Unterhaltung hier fort."
+            }
+            (TicketsMerged, "pt") => {
+                "Suas solicitações {tickets} foram mescladas neste ticket. Continuaremos a conversa aqui."
+            }
+            (TicketsMerged, _) => {
+                "Your requests {tickets} have been merged into this ticket. We will continue the conversation here."
+            }
+        }
+    }
+
+    // Fills in {name} placeholders.
+    pub fn render(self, locale: &str, values: &[(&str, &str)]) -> String {
+        values
+            .iter()
+            .fold(self.get(locale).to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+    }
+
+    // The text in the caller's most wanted language that we have, for error
+    // responses.
+    pub fn pick(self, requested: &[String]) -> String {
+        self.get(negotiate(requested, &TRANSLATED).unwrap_or(DEFAULT_LOCALE)).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> Vec<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, HeaderValue::from_str(value).unwrap());
+        requested(&headers, None)
+    }
+
+    #[test]
+    fn normalise_fixes_casing_and_rejects_non_tags() {
+        assert_eq!(normalise("PT_br").as_deref(), Some("pt-BR"));
+        assert_eq!(normalise(" zh-hant-tw ").as_deref(), Some("zh-Hant-TW"));
+        assert_eq!(normalise("es-419").as_deref(), Some("es-419"));
+        assert_eq!(normalise("*"), None);
+        assert_eq!(normalise("english"), None);
+        assert_eq!(normalise("en-"), None);
+    }
+
+    #[test]
+    fn accept_language_is_ordered_by_weight_then_by_position() {
+        assert_eq!(accept("fr;q=0.5, de-de, en;q=0.8, es"), ["de-DE", "es", "en", "fr"]);
+        assert_eq!(accept("en, *;q=0.5, it;q=0"), ["en"]);
+        assert!(requested(&HeaderMap::new(), None).is_empty());
+    }
+
+    #[test]
+    fn weights_are_clamped_and_bad_ones_count_as_zero() {
+        assert_eq!(accept("en;q=0.9, fr;q=9"), ["fr", "en"]);
+        assert_eq!(accept("fr;q=9, en"), ["fr", "en"]);
+        assert_eq!(accept("fr;q=high, de;q=NaN, en;q=0.1"), ["en"]);
+    }
+
+    #[test]
+    fn explicit_locale_wins_over_the_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, HeaderValue::from_static("fr"));
+        assert_eq!(requested(&headers, Some("PT-br")), ["pt-BR"]);
+        assert_eq!(requested(&headers, Some("not a tag")), ["fr"]);
+    }
+
+    #[test]
+    fn negotiate_prefers_exact_matches_then_the_language() {
+        let available = ["en", "pt-BR", "de"];
+        assert_eq!(negotiate(&accept("pt-PT, en;q=0.5"), &available), Some("pt-BR"));
+        assert_eq!(negotiate(&accept("pt"), &available), Some("pt-BR"));
+        assert_eq!(negotiate(&accept("it, de;q=0.2"), &available), Some("de"));
+        assert_eq!(negotiate(&accept("it"), &available), None);
+        assert_eq!(negotiate(&accept("*"), &available), None);
+        assert_eq!(negotiate(&["pt".into()], &["pt-BR", "pt"]), Some("pt"));
+    }
+}
@@ -1,48 +1,222 @@
 use axum::{
+    middleware,
+    routing::get,
     Router,
 };
-use std::env;
-use sea_orm::{Database};
+use sea_orm::{ConnectOptions, Database};
+use std::time::Duration;
 use dotenvy::dotenv;
 use crate::app_state::AppState;
-use std::sync::Arc;
-use utoipa::OpenApi;
+use crate::config::Config;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use tokio_util::sync::CancellationToken;
 use utoipa_swagger_ui::SwaggerUi;
-pub use crate::doc::ApiDoc;
 // use error_handle::AppError;
 
 mod routes; 
+mod config;
 mod app_state;
 mod entity;
 mod api;
 mod auth;
+mod api_keys;
 mod doc;
+mod diff;
+mod dedupe;
+mod bulk;
+mod gdpr;
+mod retention;
+mod mentions;
+mod notify;
+mod concurrency;
+mod kb_index;
+mod locale;
+mod reports;
+mod forecast;
+mod tenant;
+mod rate_limit;
+mod http_cache;
 mod error_handle;
+mod metrics;
+mod telemetry;
+mod health;
+mod workers;
+mod jobs;
 
 
 
 
+// `customer_support_system` or `customer_support_system serve` runs the API
+// (and the job worker, unless jobs.run_in_server is off);
+// `customer_support_system worker` runs only the job worker.
+#[derive(PartialEq)]
+enum Mode {
+    Serve,
+    Worker,
+}
+
 #[tokio::main]
 async fn main() {
+    let mode = match std::env::args().nth(1).as_deref() {
+        None | Some("serve") => Mode::Serve,
+        Some("worker") => Mode::Worker,
+        Some(other) => {
+            eprintln!("unknown command {:?}; expected serve or worker", other);
+            std::process::exit(2);
+        }
+    };
     dotenv().ok();
-    let db_url = env::var("DB_url").expect("DATABASE_URL must be set");
-    let db = Database::connect(&db_url)
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    telemetry::init(&config.logging);
+
+    let pool = &config.database;
+    let mut options = ConnectOptions::new(pool.url.clone());
+    options
+        .max_connections(pool.max_connections)
+        .min_connections(pool.min_connections)
+        .connect_timeout(Duration::from_secs(pool.connect_timeout_secs))
+        .acquire_timeout(Duration::from_secs(pool.acquire_timeout_secs))
+        .idle_timeout(Duration::from_secs(pool.idle_timeout_secs));
+    let mut db = Database::connect(options)
         .await
         .expect("Failed to connect to the database");
+    db.set_metric_callback(metrics::record_query);
 
 
+    let kb_index = kb_index::KbIndex::load(&db)
+        .await
+        .expect("Failed to build knowledge base index");
+
+    let jwt = Arc::new(auth::JwtKeys::from_config(&config.auth));
     let state = AppState {
-        db: Arc::new(db)
+        db: Arc::new(db),
+        kb_index: Arc::new(RwLock::new(kb_index)),
+        config: Arc::new(config.clone()),
+        jwt: jwt.clone(),
+        workers: workers::Workers::new(),
     };
+    let workers = state.workers.clone();
+    let db = state.db.clone();
+
+    let timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+
+    if mode == Mode::Worker {
+        jobs::start(&state, jobs::registry(&config));
+        tracing::info!(profile = config.profile.name(), "job worker running");
+        shutdown_signal().await;
+        tracing::info!("shutdown signal received, draining");
+        if !workers.shutdown(timeout).await {
+            tracing::warn!("jobs still running after {:?}, abandoning them", timeout);
+        }
+        db.get_postgres_connection_pool().close().await;
+        tracing::info!("shutdown complete");
+        return;
+    }
+    if config.jobs.run_in_server {
+        jobs::start(&state, jobs::registry(&config));
+    }
 
-    let app = Router::new()
-    .merge(SwaggerUi::new("/").url("/api-doc/openapi.json", ApiDoc::openapi()))
-    .merge(routes::routes())
-    .with_state(state);
+    let (router, openapi) = routes::router(&config, &jwt).split_for_parts();
+    let mut app = Router::new()
+        .merge(router)
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz));
+    if config.features.swagger_ui {
+        app = app.merge(SwaggerUi::new("/").url("/api-doc/openapi.json", openapi));
+    }
+    if let Some(cors) = routes::cors_layer(&config.cors) {
+        app = app.layer(cors);
+    }
+    // Outermost last: the request id is set before the span opens, so the
+    // span and every log line in it carry the id.
+    let trusted_proxies = Arc::new(config.server.trusted_proxies.clone());
+    let app = app
+        .layer(middleware::from_fn_with_state(trusted_proxies, rate_limit::resolve_client_ip))
+        .layer(middleware::from_fn(metrics::track_requests))
+        .layer(telemetry::propagate_request_id())
+        .layer(telemetry::trace_layer())
+        .layer(telemetry::set_request_id())
+        .with_state(state.clone());
 
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await.unwrap();
-    println!("server running on {} ", listener.local_addr().unwrap());
-    println!("Swagger UI available at http://{}/", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(config.bind_addr()).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tracing::info!(%addr, profile = config.profile.name(), "server running");
+    if config.features.swagger_ui {
+        tracing::info!("Swagger UI available at http://{}/", addr);
+    }
+
+    // SIGTERM (or Ctrl-C) cancels the shutdown token and /readyz starts
+    // failing. After the drain period the listeners close and in-flight
+    // requests get the timeout to finish. Then background jobs get the same
+    // again, and the pool is closed last.
+    let shutdown = workers.shutdown_token();
+    let stop = CancellationToken::new();
+    let drain = Duration::from_secs(config.server.drain_secs);
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        let stop = stop.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!(?drain, "shutdown signal received, draining");
+            shutdown.cancel();
+            tokio::time::sleep(drain).await;
+            stop.cancel();
+        }
+    });
+
+    if config.features.metrics {
+        let metrics_app = Router::new().route("/metrics", get(metrics::render)).with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind(config.metrics_addr()).await.unwrap();
+        tracing::info!(addr = %config.metrics_addr(), "metrics available at /metrics");
+        let stopped = stop.clone().cancelled_owned();
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, metrics_app).with_graceful_shutdown(stopped).await {
+                tracing::error!(error = %e, "metrics listener failed");
+            }
+        });
+    }
+
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(stop.clone().cancelled_owned());
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = async {
+            stop.cancelled().await;
+            tokio::time::sleep(timeout).await;
+        } => tracing::warn!("requests still running after {:?}, closing them", timeout),
+    }
+
+    if !workers.shutdown(timeout).await {
+        tracing::warn!("background jobs still running after {:?}, abandoning them", timeout);
+    }
+    db.get_postgres_connection_pool().close().await;
+    tracing::info!("shutdown complete");
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
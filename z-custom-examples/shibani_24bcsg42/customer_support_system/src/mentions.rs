@@ -0,0 +1,163 @@
+use crate::dedupe::normalise_email;
+use crate::entity::users;
+
+// A mention as written in a note: `@jane`, `@jane.doe`, `@"Jane Doe"` or
+// `@jane@example.com`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mention {
+    Email(String),
+    Name(String),
+}
+
+fn is_handle_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '+' | '@')
+}
+
+// Mentions in the order they appear. An `@` in the middle of a word (a plain
+// email address in the text) does not start a mention.
+pub fn parse(text: &str) -> Vec<Mention> {
+    let mut found = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let starts = c == '@' && prev.is_none_or(|p| !p.is_alphanumeric());
+        prev = Some(c);
+        if !starts {
+            continue;
+        }
+        let rest = &text[i + 1..];
+
+        if let Some(quoted) = rest.strip_prefix('"') {
+            if let Some(end) = quoted.find('"') {
+                let name = quoted[..end].trim();
+                if !name.is_empty() {
+                    found.push(Mention::Name(name.to_string()));
+                }
+                // Skip past the closing quote at i + 2 + end.
+                while chars.peek().is_some_and(|(j, _)| *j <= i + 2 + end) {
+                    prev = chars.next().map(|(_, c)| c);
+                }
+            }
+            continue;
+        }
+
+        let len: usize = rest.chars().take_while(|c| is_handle_char(*c)).map(char::len_utf8).sum();
+        // Sentence punctuation after a handle is not part of it.
+        let handle = rest[..len].trim_end_matches(['.', '-', '_', '@']);
+        if handle.is_empty() {
+            continue;
+        }
+        if handle.contains('@') {
+            found.push(Mention::Email(normalise_email(handle)));
+        } else {
+            found.push(Mention::Name(handle.to_string()));
+        }
+        while chars.peek().is_some_and(|(j, _)| *j < i + 1 + len) {
+            prev = chars.next().map(|(_, c)| c);
+        }
+    }
+    found
+}
+
+// Lowercased letters and digits only, so "Jane Doe", "jane.doe" and
+// "jane_doe" all give "janedoe".
+fn handle_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn first_name_key(name: &str) -> String {
+    handle_key(name.split_whitespace().next().unwrap_or_default())
+}
+
+// Matches mentions against the organization's users. A name matches a user's
+// full name, or their first name when no other user shares it; anything
+// ambiguous or unknown is left unresolved. Each user is returned once.
+pub fn resolve<'a>(mentions: &[Mention], candidates: &'a [users::Model]) -> Vec<&'a users::Model> {
+    let mut resolved: Vec<&users::Model> = Vec::new();
+
+    for mention in mentions {
+        let user = match mention {
+            Mention::Email(email) => candidates.iter().find(|u| normalise_email(&u.email) == *email),
+            Mention::Name(name) => {
+                let key = handle_key(name);
+                let by_full_name: Vec<_> = candidates.iter().filter(|u| handle_key(&u.name) == key).collect();
+                let by_first_name: Vec<_> = candidates.iter().filter(|u| first_name_key(&u.name) == key).collect();
+                match (by_full_name.as_slice(), by_first_name.as_slice()) {
+                    ([user], _) | ([], [user]) => Some(*user),
+                    _ => None,
+                }
+            }
+        };
+        if let Some(user) = user
+            && !resolved.iter().any(|r| r.id == user.id)
+        {
+            resolved.push(user);
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn user(name: &str, email: &str) -> users::Model {
+        users::Model {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::nil(),
+            name: name.into(),
+            email: email.into(),
+            password_hash: String::new(),
+            role: "agent".into(),
+            team: None,
+            notification_channel: "inbox".into(),
+            notification_target: None,
+            deleted_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn handles_quoted_names_and_emails_are_parsed_in_order() {
+        assert_eq!(
+            parse(r#"@jane, ask @"Bob Smith" and @Carl@Example.com."#),
+            [
+                Mention::Name("jane".into()),
+                Mention::Name("Bob Smith".into()),
+                Mention::Email("carl@example.com".into()),
+            ]
+        );
+        assert_eq!(parse("thanks @jane.doe."), [Mention::Name("jane.doe".into())]);
+    }
+
+    #[test]
+    fn at_signs_inside_words_and_empty_handles_are_not_mentions() {
+        assert!(parse("mail jane@example.com about it").is_empty());
+        assert!(parse("@ alone, @\"\" and @.").is_empty());
+        assert!(parse("unterminated @\"Jane").is_empty());
+    }
+
+    #[test]
+    fn names_resolve_by_full_name_then_unique_first_name() {
+        let users = [
+            user("Jane Doe", "jane@example.com"),
+            user("Bob Smith", "bob@example.com"),
+            user("Bob Jones", "bobby@example.com"),
+        ];
+        let ids = |text: &str| -> Vec<Uuid> { resolve(&parse(text), &users).iter().map(|u| u.id).collect() };
+
+        assert_eq!(ids("@jane_doe"), [users[0].id]);
+        assert_eq!(ids("@Jane"), [users[0].id]);
+        assert_eq!(ids(r#"@"bob smith""#), [users[1].id]);
+        // Two Bobs: a first name alone is ambiguous.
+        assert!(ids("@bob").is_empty());
+        assert_eq!(ids("@BOBBY@example.com @jane @jane.doe"), [users[2].id, users[0].id]);
+        assert!(ids("@nobody").is_empty());
+    }
+}
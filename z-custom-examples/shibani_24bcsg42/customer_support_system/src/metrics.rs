@@ -0,0 +1,146 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::time::Instant;
+use crate::app_state::AppState;
+
+// Everything exported at /metrics. A registry of our own rather than the
+// prometheus crate's global one, so only what is registered here shows up.
+static REGISTRY: Lazy<Registry> = Lazy::new(|| {
+    let registry = Registry::new();
+    let collectors: [Box<dyn prometheus::core::Collector>; 8] = [
+        Box::new(HTTP_REQUESTS.clone()),
+        Box::new(HTTP_DURATION.clone()),
+        Box::new(DB_QUERY_DURATION.clone()),
+        Box::new(DB_POOL_SIZE.clone()),
+        Box::new(DB_POOL_IDLE.clone()),
+        Box::new(DB_POOL_MAX.clone()),
+        Box::new(TICKETS_CREATED.clone()),
+        Box::new(REPLIES_SENT.clone()),
+    ];
+    for collector in collectors {
+        registry.register(collector).expect("metric registered twice");
+    }
+    registry
+});
+
+static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new("http_requests_total", "HTTP requests by route and status"),
+        &["method", "route", "status"],
+    )
+    .unwrap()
+});
+
+static HTTP_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route"),
+        &["method", "route"],
+    )
+    .unwrap()
+});
+
+static DB_QUERY_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new("db_query_duration_seconds", "Database query time by statement kind")
+            .buckets(vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+        &["statement", "failed"],
+    )
+    .unwrap()
+});
+
+static DB_POOL_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    IntGauge::new("db_pool_connections", "Open database connections").unwrap()
+});
+
+static DB_POOL_IDLE: Lazy<IntGauge> = Lazy::new(|| {
+    IntGauge::new("db_pool_idle_connections", "Open database connections not in use").unwrap()
+});
+
+static DB_POOL_MAX: Lazy<IntGauge> = Lazy::new(|| {
+    IntGauge::new("db_pool_max_connections", "Configured database pool size").unwrap()
+});
+
+static TICKETS_CREATED: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new("tickets_created_total", "Tickets created, by channel and how they came in"),
+        &["channel", "source"],
+    )
+    .unwrap()
+});
+
+static REPLIES_SENT: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new("replies_sent_total", "Customer-facing agent replies, by channel"),
+        &["channel"],
+    )
+    .unwrap()
+});
+
+pub fn ticket_created(channel: &str, source: &str) {
+    TICKETS_CREATED.with_label_values(&[channel, source]).inc();
+}
+
+// A public reply written by signed-in staff; customer messages and system
+// notices are not replies.
+pub fn reply_sent(channel: &str) {
+    REPLIES_SENT.with_label_values(&[channel]).inc();
+}
+
+// Installed with DatabaseConnection::set_metric_callback. Statements are
+// labelled by their first keyword only; full SQL would be unbounded.
+pub fn record_query(info: &sea_orm::metric::Info<'_>) {
+    let kind = info
+        .statement
+        .sql
+        .split_whitespace()
+        .next()
+        .map(str::to_ascii_uppercase)
+        .filter(|k| matches!(k.as_str(), "SELECT" | "INSERT" | "UPDATE" | "DELETE" | "WITH"))
+        .unwrap_or_else(|| "OTHER".into());
+    DB_QUERY_DURATION
+        .with_label_values(&[kind.as_str(), if info.failed { "true" } else { "false" }])
+        .observe(info.elapsed.as_secs_f64());
+}
+
+// Requests are labelled by route template (/tickets/{id}), not the concrete
+// path, so each route is one series however many ids it is called with.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".into());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    HTTP_DURATION
+        .with_label_values(&[method.as_str(), route.as_str()])
+        .observe(started.elapsed().as_secs_f64());
+    HTTP_REQUESTS
+        .with_label_values(&[method.as_str(), route.as_str(), response.status().as_str()])
+        .inc();
+    response
+}
+
+pub async fn render(State(state): State<AppState>) -> Response {
+    let pool = state.db.get_postgres_connection_pool();
+    DB_POOL_SIZE.set(pool.size() as i64);
+    DB_POOL_IDLE.set(pool.num_idle() as i64);
+    DB_POOL_MAX.set(state.config.database.max_connections as i64);
+
+    let mut body = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut body) {
+        tracing::error!(error = %e, "failed to encode metrics");
+        return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    ([(header::CONTENT_TYPE, encoder.format_type().to_owned())], body).into_response()
+}
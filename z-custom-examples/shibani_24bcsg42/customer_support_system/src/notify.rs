@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::{Expr, ExprTrait};
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use uuid::Uuid;
+use crate::app_state::AppState;
+use crate::entity::{jobs as jobs_entity, mentions, users};
+use crate::jobs::{self, EnqueueOptions, Job};
+use crate::tenant::TenantScoped;
+
+// Notification channels an agent can choose. The inbox is always filled;
+// the other channels are on top of it.
+pub const INBOX: &str = "inbox";
+pub const WEBHOOK: &str = "webhook";
+pub const CHANNELS: [&str; 2] = [INBOX, WEBHOOK];
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Checks a channel choice before it is saved. Webhooks need an http(s) URL
+// on a public address.
+pub async fn validate(channel: &str, target: Option<&str>) -> Result<(), String> {
+    match channel {
+        INBOX => Ok(()),
+        WEBHOOK => {
+            let url = target.ok_or("A webhook URL is required")?;
+            webhook_client(url).await.map(|_| ())
+        }
+        _ => Err(format!("Unknown channel; expected one of {}", CHANNELS.join(", "))),
+    }
+}
+
+// Whether an address is on the public internet. Webhook targets are set by
+// agents, so loopback, private, link-local (cloud metadata at 169.254.169.254)
+// and other special-purpose ranges are refused.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            let carrier_nat = a == 100 && (64..128).contains(&b);
+            let ietf = a == 192 && b == 0 && c == 0;
+            let benchmarking = a == 198 && (18..20).contains(&b);
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                || carrier_nat
+                || ietf
+                || benchmarking)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let [first, second, ..] = ip.segments();
+            let unique_local = first & 0xfe00 == 0xfc00;
+            let link_local = first & 0xffc0 == 0xfe80;
+            let nat64 = first == 0x64 && second == 0xff9b;
+            let documentation = first == 0x2001 && second == 0xdb8;
+            !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local || nat64 || documentation)
+        }
+    }
+}
+
+// A client for one webhook URL, pinned to the addresses its host resolves to
+// now, all of which must be public. Resolving again for every delivery
+// catches a name pointed somewhere internal after it was saved, and pinning
+// stops it changing between the check and the request. Redirects are not
+// followed, since they could lead anywhere.
+async fn webhook_client(url: &str) -> Result<reqwest::Client, String> {
+    let url = reqwest::Url::parse(url)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .ok_or("Webhook URL must be an http(s) URL")?;
+    let host = url.host_str().ok_or("Webhook URL must have a host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|_| format!("Webhook host {} could not be resolved", host))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Webhook host {} could not be resolved", host));
+    }
+    if addrs.iter().any(|a| !is_public(a.ip())) {
+        return Err("Webhook URL must point at a public address".into());
+    }
+
+    reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(host, &addrs)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// What is sent about a mention. `text` makes it readable as-is by Slack and
+// Teams incoming webhooks.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MentionNotice {
+    pub text: String,
+    pub mention_id: Uuid,
+    pub ticket_id: Uuid,
+    pub ticket_title: String,
+    pub mentioned_by: Option<String>,
+    pub note: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// Queues the notice for the user's channel. Pass the transaction that saves
+// the note: the notice goes out only if the note is saved, and is not lost if
+// the process stops before sending it.
+pub async fn deliver<C: ConnectionTrait>(db: &C, user: &users::Model, notice: MentionNotice) -> Result<(), DbErr> {
+    if user.notification_channel != WEBHOOK {
+        return Ok(());
+    }
+    let options = EnqueueOptions {
+        tenant_id: Some(user.tenant_id),
+        unique_key: Some(notice.mention_id.to_string()),
+        ..Default::default()
+    };
+    jobs::enqueue(db, &DeliverMention { user_id: user.id, notice }, options).await?;
+    Ok(())
+}
+
+// Redacts the queued and sent notices about the given tickets, for erasure.
+// The job is kept so its delivery history stays visible.
+pub async fn erase_notices<C: ConnectionTrait>(
+    db: &C,
+    tenant_id: Uuid,
+    ticket_ids: &[Uuid],
+    redacted: &str,
+) -> Result<u64, DbErr> {
+    let ticket_ids: Vec<String> = ticket_ids.iter().map(Uuid::to_string).collect();
+    let erased = jobs_entity::Entity::update_many_in(tenant_id)
+        .col_expr(
+            jobs_entity::Column::Payload,
+            Expr::cust_with_values(
+                "jsonb_set(jsonb_set(jsonb_set(payload, '{notice,text}', to_jsonb($1::text)), \
+                 '{notice,ticket_title}', to_jsonb($1::text)), '{notice,note}', to_jsonb($1::text))",
+                [redacted],
+            ),
+        )
+        .filter(jobs_entity::Column::Kind.eq(DeliverMention::KIND))
+        .filter(Expr::cust("payload #>> '{notice,ticket_id}'").is_in(ticket_ids))
+        .exec(db)
+        .await?;
+    Ok(erased.rows_affected)
+}
+
+// Sends one notice to a webhook. A failure is retried with backoff (see
+// jobs.rs); after the last attempt the mention stays in the inbox,
+// undelivered.
+#[derive(Serialize, Deserialize)]
+pub struct DeliverMention {
+    pub user_id: Uuid,
+    pub notice: MentionNotice,
+}
+
+impl Job for DeliverMention {
+    const KIND: &'static str = "deliver_mention";
+    const MAX_ATTEMPTS: i32 = 8;
+
+    async fn run(self, state: &AppState) -> Result<(), String> {
+        // The target is read when sending, so a URL fixed after a failure is
+        // used by the next attempt, and a user who switched back to the inbox
+        // gets nothing.
+        // cross-tenant: the job was queued for this user by their own tenant.
+        let user = users::Entity::find_by_id(self.user_id)
+            .one(state.db.as_ref())
+            .await
+            .map_err(|e| e.to_string())?;
+        let Some(url) = user
+            .filter(|u| u.notification_channel == WEBHOOK)
+            .and_then(|u| u.notification_target)
+        else {
+            return Ok(());
+        };
+
+        let client = webhook_client(&url).await?;
+        client
+            .post(&url)
+            .json(&self.notice)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| e.to_string())?;
+
+        // cross-tenant: the mention belongs to the same tenant as the user.
+        mentions::Entity::update_many()
+            .col_expr(mentions::Column::DeliveredAt, Expr::value(Utc::now()))
+            .filter(mentions::Column::Id.eq(self.notice.mention_id))
+            .exec(state.db.as_ref())
+            .await
+            .map_err(|e| format!("delivered but not marked: {}", e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn internal_addresses_are_refused() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254",
+            "0.0.0.0", "100.64.0.1", "255.255.255.255", "::1", "::", "fd00::1",
+            "fe80::1", "::ffff:127.0.0.1", "::ffff:169.254.169.254", "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!public(ip), "{} passed", ip);
+        }
+    }
+
+    #[test]
+    fn public_addresses_are_allowed() {
+        for ip in ["1.1.1.1", "93.184.216.34", "172.32.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(public(ip), "{} refused", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_targets_are_checked_after_resolving() {
+        assert!(validate(INBOX, None).await.is_ok());
+        assert!(validate(WEBHOOK, None).await.is_err());
+        assert!(validate(WEBHOOK, Some("ftp://1.1.1.1/")).await.is_err());
+        assert!(validate(WEBHOOK, Some("http://169.254.169.254/latest/meta-data")).await.is_err());
+        assert!(validate(WEBHOOK, Some("http://[::1]:8080/")).await.is_err());
+        assert!(validate(WEBHOOK, Some("http://localhost:3000/hook")).await.is_err());
+        assert!(validate(WEBHOOK, Some("https://1.1.1.1/hook")).await.is_ok());
+        assert!(validate("sms", None).await.is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{header, request::Parts, Extensions, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::api_keys;
+use crate::auth::{bearer_subject, JwtKeys};
+use crate::error_handle::ErrorResponse;
+
+// Requests allowed per client: a burst of `burst`, then `per_sec` a second.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    pub burst: u32,
+    pub per_sec: f64,
+}
+
+// Budgets for each route group in routes.rs, set under [rate_limits] in the
+// config file.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    // Anonymous help center, per IP.
+    pub help_center: Rate,
+    // POST /login, per IP. Account lockout below covers one account from one IP.
+    pub login: Rate,
+    // Bulk import/export and data export, per user.
+    pub bulk: Rate,
+    // Everything else, per user (per IP before login).
+    pub api: Rate,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            help_center: Rate { burst: 30, per_sec: 0.5 },
+            login: Rate { burst: 10, per_sec: 0.2 },
+            bulk: Rate { burst: 5, per_sec: 0.05 },
+            api: Rate { burst: 120, per_sec: 10.0 },
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token bucket per key: `capacity` requests in a burst, refilled at
+// `refill_per_sec` tokens per second.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn from_rate(rate: Rate) -> Arc<Self> {
+        Arc::new(RateLimiter::new(rate.burst, rate.per_sec))
+    }
+
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        RateLimiter {
+            capacity: capacity as f64,
+            refill_per_sec,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Takes one token for `key`. On rejection returns how many seconds until a
+    // token is available again.
+    pub fn check(&self, key: &str) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Keep memory bounded when many distinct clients pass through.
+        if buckets.len() > 10_000 {
+            let capacity = self.capacity;
+            let rate = self.refill_per_sec;
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < capacity);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.refill_per_sec).ceil() as u64)
+        }
+    }
+}
+
+// The address a request came from: the connecting peer, or, when the peer is
+// one of `trusted_proxies` (addresses or CIDR ranges), the hop it forwarded
+// for in X-Forwarded-For. Hops are walked from the right, as each proxy
+// appends the address it saw, and the walk stops at the first one not
+// trusted; anything further left was written by the client and proves nothing.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[String]) -> String {
+    let Some(mut ip) = peer else {
+        return "unknown".to_string();
+    };
+    let forwarded: Vec<&str> = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    for hop in forwarded.iter().rev() {
+        if !trusted_proxies.iter().any(|rule| api_keys::ip_matches(rule, ip)) {
+            break;
+        }
+        match hop.parse() {
+            Ok(next) => ip = next,
+            Err(_) => break,
+        }
+    }
+    ip.to_string()
+}
+
+// The client address of the current request, worked out once by
+// `resolve_client_ip` and read by the limiters and audit entries.
+#[derive(Debug, Clone)]
+pub struct ClientIp(pub String);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp(resolved_ip(&parts.extensions, &parts.headers)))
+    }
+}
+
+// Without `resolve_client_ip` in front (as in tests), no proxy is trusted.
+fn resolved_ip(extensions: &Extensions, headers: &HeaderMap) -> String {
+    match extensions.get::<ClientIp>() {
+        Some(ClientIp(ip)) => ip.clone(),
+        None => {
+            let peer = extensions.get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+            client_ip(headers, peer, &[])
+        }
+    }
+}
+
+pub async fn resolve_client_ip(
+    State(trusted_proxies): State<Arc<Vec<String>>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let ip = client_ip(request.headers(), peer, &trusted_proxies);
+    request.extensions_mut().insert(ClientIp(ip));
+    next.run(request).await
+}
+
+pub fn too_many_requests(retry_after: u64) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse::new("Too many requests")),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+pub async fn limit_by_ip(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = resolved_ip(request.extensions(), request.headers());
+
+    match limiter.check(&key) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+// Keys authenticated requests by user id (or API key), so one account cannot spread its
+// load over many addresses and users behind one NAT do not share a budget.
+// Requests without a valid token fall back to the client IP.
+#[derive(Clone)]
+pub struct UserLimiter {
+    pub limiter: Arc<RateLimiter>,
+    pub jwt: Arc<JwtKeys>,
+}
+
+pub async fn limit_by_user(
+    State(UserLimiter { limiter, jwt }): State<UserLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = match (api_keys::from_headers(request.headers()), bearer_subject(&jwt, request.headers())) {
+        (Some(api_key), _) => format!("key:{}", api_keys::hash(api_key)),
+        (None, Some(user_id)) => format!("user:{}", user_id),
+        (None, None) => format!("ip:{}", resolved_ip(request.extensions(), request.headers())),
+    };
+
+    match limiter.check(&key) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+// Failed logins from one address allowed before the account is locked for
+// that address. The first lockout lasts a minute and every further failure
+// doubles it, up to a day.
+pub const LOCKOUT_THRESHOLD: i32 = 5;
+const FIRST_LOCKOUT_SECS: i64 = 60;
+const MAX_LOCKOUT_SECS: i64 = 24 * 60 * 60;
+
+pub fn lockout_for(failed_logins: i32) -> Option<chrono::Duration> {
+    if failed_logins < LOCKOUT_THRESHOLD {
+        return None;
+    }
+    // 60s << 11 already exceeds a day.
+    let doublings = (failed_logins - LOCKOUT_THRESHOLD).min(11);
+    Some(chrono::Duration::seconds((FIRST_LOCKOUT_SECS << doublings).min(MAX_LOCKOUT_SECS)))
+}
+
+// Failed logins are also counted for the account as a whole, under this
+// address, which no client can have. Guesses spread over many addresses never
+// reach an address lock, so past its own threshold every further failure
+// holds the account back for longer: a second at first, doubling up to fifteen
+// minutes, short enough that its owner is not shut out for long.
+pub const ANY_ADDRESS: &str = "*";
+pub const ACCOUNT_DELAY_THRESHOLD: i32 = 20;
+const FIRST_ACCOUNT_DELAY_SECS: i64 = 1;
+const MAX_ACCOUNT_DELAY_SECS: i64 = 15 * 60;
+
+pub fn account_delay_for(failed_logins: i32) -> Option<chrono::Duration> {
+    if failed_logins < ACCOUNT_DELAY_THRESHOLD {
+        return None;
+    }
+    // 1s << 10 already exceeds fifteen minutes.
+    let doublings = (failed_logins - ACCOUNT_DELAY_THRESHOLD).min(10);
+    Some(chrono::Duration::seconds((FIRST_ACCOUNT_DELAY_SECS << doublings).min(MAX_ACCOUNT_DELAY_SECS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn peer(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let headers = forwarded("1.2.3.4");
+        assert_eq!(client_ip(&headers, peer("203.0.113.9"), &[]), "203.0.113.9");
+        assert_eq!(client_ip(&headers, peer("203.0.113.9"), &["10.0.0.0/8".into()]), "203.0.113.9");
+        assert_eq!(client_ip(&headers, None, &[]), "unknown");
+    }
+
+    #[test]
+    fn trusted_proxies_are_skipped_from_the_right() {
+        let trusted = ["10.0.0.0/8".to_string()];
+        // The client wrote the first entry itself; the proxy appended the
+        // address it actually saw.
+        let headers = forwarded("1.2.3.4, 198.51.100.7, 10.0.0.3");
+        assert_eq!(client_ip(&headers, peer("10.0.0.2"), &trusted), "198.51.100.7");
+        assert_eq!(client_ip(&forwarded("10.0.0.5"), peer("10.0.0.2"), &trusted), "10.0.0.5");
+        assert_eq!(client_ip(&HeaderMap::new(), peer("10.0.0.2"), &trusted), "10.0.0.2");
+    }
+
+    #[test]
+    fn lockouts_start_at_the_threshold_and_double_up_to_a_day() {
+        let secs = |failed: i32| lockout_for(failed).map(|d| d.num_seconds());
+        assert_eq!(secs(0), None);
+        assert_eq!(secs(LOCKOUT_THRESHOLD - 1), None);
+        assert_eq!(secs(LOCKOUT_THRESHOLD), Some(60));
+        assert_eq!(secs(LOCKOUT_THRESHOLD + 1), Some(120));
+        assert_eq!(secs(LOCKOUT_THRESHOLD + 5), Some(60 * 32));
+        assert_eq!(secs(LOCKOUT_THRESHOLD + 11), Some(MAX_LOCKOUT_SECS));
+        assert_eq!(secs(i32::MAX), Some(MAX_LOCKOUT_SECS));
+    }
+
+    #[test]
+    fn account_delays_start_later_and_stay_short() {
+        let secs = |failed: i32| account_delay_for(failed).map(|d| d.num_seconds());
+        assert_eq!(secs(LOCKOUT_THRESHOLD), None);
+        assert_eq!(secs(ACCOUNT_DELAY_THRESHOLD - 1), None);
+        assert_eq!(secs(ACCOUNT_DELAY_THRESHOLD), Some(1));
+        assert_eq!(secs(ACCOUNT_DELAY_THRESHOLD + 3), Some(8));
+        assert_eq!(secs(ACCOUNT_DELAY_THRESHOLD + 10), Some(MAX_ACCOUNT_DELAY_SECS));
+        assert_eq!(secs(i32::MAX), Some(MAX_ACCOUNT_DELAY_SECS));
+    }
+
+    #[test]
+    fn unparseable_hops_stop_the_walk() {
+        let trusted = ["10.0.0.2".to_string()];
+        assert_eq!(client_ip(&forwarded("1.2.3.4, junk"), peer("10.0.0.2"), &trusted), "10.0.0.2");
+    }
+}
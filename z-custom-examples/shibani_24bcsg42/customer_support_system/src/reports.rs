@@ -0,0 +1,277 @@
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use sea_orm::{DatabaseConnection, DbBackend, DbErr, FromQueryResult, Statement};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::bulk::{encode_rows, Format};
+use crate::config::SlaConfig;
+
+// Agent performance reports, aggregated in Postgres over tickets,
+// communications and the status changes in ticket_events.
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Agent,
+    Team,
+    Channel,
+    Priority,
+    Tag,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+// One group of the report. Durations are in seconds; percentiles and SLA
+// compliance are null when no ticket in the group had a value.
+#[derive(Debug, Clone, Default, Serialize, FromQueryResult, ToSchema)]
+pub struct PerformanceRow {
+    // Agent id, team, channel, priority or tag name; null for unassigned,
+    // no team or untagged tickets.
+    pub group: Option<String>,
+    // Display name: the agent's name when grouping by agent, else the group.
+    pub label: Option<String>,
+    // Created during the period, not counting tickets since merged into another.
+    pub received: i64,
+    // Moved to a solved status during the period.
+    pub solved: i64,
+    // Moved from a solved status back to an open one during the period.
+    pub reopened: i64,
+    // Not solved at the end of the period.
+    pub backlog: i64,
+    pub first_response_p50: Option<f64>,
+    pub first_response_p90: Option<f64>,
+    pub resolution_p50: Option<f64>,
+    pub resolution_p90: Option<f64>,
+    // Share of tickets received during the period that met their target,
+    // out of those that have met or missed it so far.
+    pub first_response_sla: Option<f64>,
+    pub resolution_sla: Option<f64>,
+}
+
+// Statuses a ticket counts as solved in.
+const SOLVED: &str = "('resolved', 'closed')";
+
+// Group key, label and the joins they need. A ticket with two tags counts
+// once under each.
+fn group_columns(group_by: Option<GroupBy>) -> (&'static str, &'static str, &'static str) {
+    match group_by {
+        Some(GroupBy::Agent) => ("t.assigned_agent_id::text", "u.name", ""),
+        Some(GroupBy::Team) => ("u.team", "u.team", ""),
+        Some(GroupBy::Channel) => ("t.channel", "t.channel", ""),
+        Some(GroupBy::Priority) => ("t.priority", "t.priority", ""),
+        Some(GroupBy::Tag) => (
+            "tg.name",
+            "tg.name",
+            "LEFT JOIN ticket_tags tt ON tt.ticket_id = t.id LEFT JOIN tags tg ON tg.id = tt.tag_id",
+        ),
+        None => ("NULL::text", "NULL::text", ""),
+    }
+}
+
+// The report over [start, end), looking at every ticket created before the
+// end. `group_by` None gives the totals as a single row.
+//
+// First response is the first public agent message a person wrote. Resolution time runs from
+// creation to the last time the ticket was solved within the period. SLA
+// compliance is over tickets received in the period: a target counts as met
+// or missed once the reply or resolution happened, or once it is overdue.
+pub fn performance_statement(
+    tenant_id: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    group_by: Option<GroupBy>,
+    sla: &SlaConfig,
+) -> Statement {
+    let (key, label, joins) = group_columns(group_by);
+    let sql = format!(
+        r#"
+WITH facts AS (
+    SELECT
+        {key} AS group_key,
+        {label} AS group_label,
+        t.created_at >= $2 AND t.merged_into_id IS NULL AS received,
+        sv.at IS NOT NULL AS solved,
+        COALESCE(ro.times, 0) > 0 AS reopened,
+        COALESCE(before_end.to_value, after_end.from_value, t.status) NOT IN {SOLVED} AS open_at_end,
+        CASE WHEN t.created_at >= $2 THEN EXTRACT(EPOCH FROM fr.at - t.created_at)::float8 END AS first_response_secs,
+        EXTRACT(EPOCH FROM sv.at - t.created_at)::float8 AS resolution_secs,
+        CASE WHEN t.created_at >= $2 AND frt.secs IS NOT NULL THEN
+            CASE
+                WHEN fr.at IS NOT NULL THEN EXTRACT(EPOCH FROM fr.at - t.created_at) <= frt.secs
+                WHEN EXTRACT(EPOCH FROM now() - t.created_at) > frt.secs THEN false
+            END
+        END AS first_response_met,
+        CASE WHEN t.created_at >= $2 AND rt.secs IS NOT NULL THEN
+            CASE
+                WHEN fs.at IS NOT NULL THEN EXTRACT(EPOCH FROM fs.at - t.created_at) <= rt.secs
+                WHEN EXTRACT(EPOCH FROM now() - t.created_at) > rt.secs THEN false
+            END
+        END AS resolution_met
+    FROM tickets t
+    LEFT JOIN users u ON u.id = t.assigned_agent_id
+    {joins}
+    LEFT JOIN LATERAL (
+        SELECT MIN(c.timestamp) AS at FROM communications c
+        WHERE c.ticket_id = t.id AND c.sender_type = 'agent' AND NOT c.is_internal AND NOT c.is_system
+          AND EXISTS (SELECT 1 FROM users s WHERE s.id = c.sender_id AND s.role IN ('agent', 'admin', 'super_admin'))
+    ) fr ON true
+    LEFT JOIN LATERAL (
+        SELECT MAX(e.created_at) AS at FROM ticket_events e
+        WHERE e.ticket_id = t.id AND e.kind = 'status' AND e.to_value IN {SOLVED}
+          AND e.created_at >= $2 AND e.created_at < $3
+    ) sv ON true
+    LEFT JOIN LATERAL (
+        SELECT MIN(e.created_at) AS at FROM ticket_events e
+        WHERE e.ticket_id = t.id AND e.kind = 'status' AND e.to_value IN {SOLVED}
+    ) fs ON true
+    LEFT JOIN LATERAL (
+        SELECT COUNT(*) AS times FROM ticket_events e
+        WHERE e.ticket_id = t.id AND e.kind = 'status'
+          AND e.from_value IN {SOLVED} AND e.to_value NOT IN {SOLVED}
+          AND e.created_at >= $2 AND e.created_at < $3
+    ) ro ON true
+    LEFT JOIN LATERAL (
+        SELECT e.to_value FROM ticket_events e
+        WHERE e.ticket_id = t.id AND e.kind = 'status' AND e.created_at < $3
+        ORDER BY e.created_at DESC LIMIT 1
+    ) before_end ON true
+    LEFT JOIN LATERAL (
+        SELECT e.from_value FROM ticket_events e
+        WHERE e.ticket_id = t.id AND e.kind = 'status' AND e.created_at >= $3
+        ORDER BY e.created_at ASC LIMIT 1
+    ) after_end ON true
+    LEFT JOIN LATERAL (SELECT ($4::jsonb ->> t.priority)::float8 * 60 AS secs) frt ON true
+    LEFT JOIN LATERAL (SELECT ($5::jsonb ->> t.priority)::float8 * 60 AS secs) rt ON true
+    WHERE t.tenant_id = $1 AND t.deleted_at IS NULL AND t.created_at < $3
+)
+SELECT
+    group_key AS "group",
+    group_label AS label,
+    COUNT(*) FILTER (WHERE received) AS received,
+    COUNT(*) FILTER (WHERE solved) AS solved,
+    COUNT(*) FILTER (WHERE reopened) AS reopened,
+    COUNT(*) FILTER (WHERE open_at_end) AS backlog,
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY first_response_secs) AS first_response_p50,
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY first_response_secs) AS first_response_p90,
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY resolution_secs) AS resolution_p50,
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY resolution_secs) AS resolution_p90,
+    (COUNT(*) FILTER (WHERE first_response_met))::float8 / NULLIF(COUNT(first_response_met), 0) AS first_response_sla,
+    (COUNT(*) FILTER (WHERE resolution_met))::float8 / NULLIF(COUNT(resolution_met), 0) AS resolution_sla
+FROM facts
+GROUP BY group_key, group_label
+HAVING COUNT(*) FILTER (WHERE received OR solved OR reopened OR open_at_end) > 0
+ORDER BY received DESC, group_label NULLS LAST
+"#
+    );
+
+    let targets = |minutes: &std::collections::BTreeMap<String, i64>| {
+        serde_json::to_value(minutes).unwrap_or_default()
+    };
+    Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        [
+            tenant_id.into(),
+            start.into(),
+            end.into(),
+            targets(&sla.first_response_minutes).into(),
+            targets(&sla.resolution_minutes).into(),
+        ],
+    )
+}
+
+// The header row on its own, so a report with no rows still names its
+// columns.
+fn csv_header() -> Result<Bytes, String> {
+    let with_row = encode_rows(&[PerformanceRow::default()], Format::Csv, true)?;
+    let end = with_row.iter().position(|b| *b == b'\n').map_or(with_row.len(), |i| i + 1);
+    Ok(with_row.slice(..end))
+}
+
+// Streams the rows as CSV while Postgres produces them.
+pub fn csv_body(db: Arc<DatabaseConnection>, statement: Statement) -> Body {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, io::Error>>(4);
+
+    tokio::spawn(async move {
+        let mut rows = match PerformanceRow::find_by_statement(statement).stream(db.as_ref()).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+                return;
+            }
+        };
+
+        let header = csv_header().map_err(io::Error::other);
+        let failed = header.is_err();
+        if tx.send(header).await.is_err() || failed {
+            return;
+        }
+        while let Some(row) = rows.next().await {
+            let chunk = row
+                .map_err(|e: DbErr| e.to_string())
+                .and_then(|row| encode_rows(&[row], Format::Csv, false))
+                .map_err(io::Error::other);
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_grouping_has_its_key_label_and_joins() {
+        assert_eq!(group_columns(None), ("NULL::text", "NULL::text", ""));
+        assert_eq!(group_columns(Some(GroupBy::Agent)), ("t.assigned_agent_id::text", "u.name", ""));
+        assert_eq!(group_columns(Some(GroupBy::Team)).0, "u.team");
+        assert_eq!(group_columns(Some(GroupBy::Channel)).0, "t.channel");
+        assert_eq!(group_columns(Some(GroupBy::Priority)).0, "t.priority");
+        let (key, _, joins) = group_columns(Some(GroupBy::Tag));
+        assert_eq!(key, "tg.name");
+        assert!(joins.contains("JOIN ticket_tags tt") && joins.contains("JOIN tags tg"));
+        // Only tags need a join: the users join is always there.
+        for group_by in [GroupBy::Agent, GroupBy::Team, GroupBy::Channel, GroupBy::Priority] {
+            assert_eq!(group_columns(Some(group_by)).2, "");
+        }
+    }
+
+    #[test]
+    fn csv_header_names_every_column_on_its_own() {
+        let header = csv_header().unwrap();
+        assert_eq!(
+            std::str::from_utf8(&header).unwrap(),
+            "group,label,received,solved,reopened,backlog,first_response_p50,first_response_p90,\
+             resolution_p50,resolution_p90,first_response_sla,resolution_sla\n"
+        );
+
+        let row = PerformanceRow { group: Some("email".into()), received: 3, ..Default::default() };
+        let body = encode_rows(&[row], Format::Csv, false).unwrap();
+        assert_eq!(std::str::from_utf8(&body).unwrap(), "email,,3,0,0,0,,,,,,\n");
+    }
+
+    #[test]
+    fn merged_tickets_are_not_received_and_only_staff_messages_are_replies() {
+        let sql = performance_statement(Uuid::nil(), Utc::now(), Utc::now(), None, &SlaConfig::default()).sql;
+        assert!(sql.contains("t.merged_into_id IS NULL AS received"));
+        assert!(sql.contains("NOT c.is_internal AND NOT c.is_system"));
+        assert!(sql.contains("s.id = c.sender_id AND s.role IN ('agent', 'admin', 'super_admin')"));
+    }
+}
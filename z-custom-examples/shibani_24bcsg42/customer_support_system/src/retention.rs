@@ -0,0 +1,126 @@
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::app_state::AppState;
+use crate::entity::{communications, customers, knowledge_base, tickets, users};
+use crate::jobs::Job;
+
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+#[derive(Debug, Default)]
+pub struct PurgeCounts {
+    pub tickets: u64,
+    pub articles: u64,
+    pub customers: u64,
+    pub users: u64,
+    pub skipped: u64,
+}
+
+// Deletes what has been in the trash longer than retention.trash_days. Runs
+// hourly (see jobs::registry); a failed run is simply retried by the next one.
+#[derive(Serialize, Deserialize)]
+pub struct PurgeTrash {}
+
+impl Job for PurgeTrash {
+    const KIND: &'static str = "purge_trash";
+    const MAX_ATTEMPTS: i32 = 1;
+
+    async fn run(self, state: &AppState) -> Result<(), String> {
+        let cutoff = Utc::now() - Duration::days(state.config.retention.trash_days);
+        let counts = purge(&state.db, cutoff).await.map_err(|e| e.to_string())?;
+        tracing::info!(?counts, "trash purge");
+        Ok(())
+    }
+}
+
+// Ids of rows trashed before `cutoff`. The job works across all tenants.
+async fn expired<E, C>(db: &DatabaseConnection, id: C, deleted_at: C, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, DbErr>
+where
+    E: EntityTrait,
+    C: ColumnTrait,
+{
+    E::find()
+        .select_only()
+        .column(id)
+        .filter(deleted_at.lt(cutoff))
+        .into_tuple()
+        .all(db)
+        .await
+}
+
+// Permanently deletes everything that has been in the trash since before
+// `cutoff`. A row that is still referenced (a customer with tickets, a user
+// with audit history) stays in the trash and is counted as skipped.
+pub async fn purge(db: &DatabaseConnection, cutoff: DateTime<Utc>) -> Result<PurgeCounts, DbErr> {
+    let mut counts = PurgeCounts::default();
+
+    for id in expired::<tickets::Entity, _>(db, tickets::Column::Id, tickets::Column::DeletedAt, cutoff).await? {
+        match purge_ticket(db, id).await {
+            Ok(()) => counts.tickets += 1,
+            Err(e) => {
+                tracing::warn!(%id, error = %e, "trash purge: keeping ticket");
+                counts.skipped += 1;
+            }
+        }
+    }
+
+    for id in expired::<knowledge_base::Entity, _>(db, knowledge_base::Column::Id, knowledge_base::Column::DeletedAt, cutoff).await? {
+        match knowledge_base::Entity::delete_by_id(id).exec(db).await {
+            Ok(_) => counts.articles += 1,
+            Err(e) => {
+                tracing::warn!(%id, error = %e, "trash purge: keeping article");
+                counts.skipped += 1;
+            }
+        }
+    }
+
+    for id in expired::<customers::Entity, _>(db, customers::Column::Id, customers::Column::DeletedAt, cutoff).await? {
+        match customers::Entity::delete_by_id(id).exec(db).await {
+            Ok(_) => counts.customers += 1,
+            Err(e) => {
+                tracing::warn!(%id, error = %e, "trash purge: keeping customer");
+                counts.skipped += 1;
+            }
+        }
+    }
+
+    for id in expired::<users::Entity, _>(db, users::Column::Id, users::Column::DeletedAt, cutoff).await? {
+        match users::Entity::delete_by_id(id).exec(db).await {
+            Ok(_) => counts.users += 1,
+            Err(e) => {
+                tracing::warn!(%id, error = %e, "trash purge: keeping user");
+                counts.skipped += 1;
+            }
+        }
+    }
+
+    Ok(counts)
+}
+
+// A ticket goes together with its messages. Tickets merged into or split from
+// it keep existing and just lose the link.
+async fn purge_ticket(db: &DatabaseConnection, id: Uuid) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    tickets::Entity::update_many()
+        .col_expr(tickets::Column::MergedIntoId, Expr::value(Option::<Uuid>::None))
+        .filter(tickets::Column::MergedIntoId.eq(id))
+        .exec(&txn)
+        .await?;
+    tickets::Entity::update_many()
+        .col_expr(tickets::Column::ParentTicketId, Expr::value(Option::<Uuid>::None))
+        .filter(tickets::Column::ParentTicketId.eq(id))
+        .exec(&txn)
+        .await?;
+    communications::Entity::delete_many()
+        .filter(communications::Column::TicketId.eq(id))
+        .exec(&txn)
+        .await?;
+    tickets::Entity::delete_by_id(id).exec(&txn).await?;
+
+    txn.commit().await
+}
@@ -1,72 +1,259 @@
-use axum::{
-    Router,
-    routing::{post, put, delete, get, patch },
-};
-use crate::api::{
-    //get_my_tickets, get_ticket_details, customer_reply_ticket,
-    create_user, get_users, update_user, delete_user,
-    create_customer, get_customers, update_customer, delete_customer,
-    create_ticket, delete_ticket_by_id, update_ticket_priority, update_ticket_status, assign_ticket, get_ticket_by_id, get_all_tickets, get_filtered_tickets,
-    create_communication, get_communications,
-    create_article, get_all_articles, update_article, delete_article, search_articles,
-    create_tag, get_tags_by_id, delete_tag,
-    create_analytics, get_analytics, get_analytics_by_id,
-    // create_log, get_logs, delete_log,
-    login_user,
-    // root_handler
-};
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use axum::middleware;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use crate::api;
+use std::sync::Arc;
 use crate::app_state::AppState;
+use crate::auth::JwtKeys;
+use crate::config::{Config, CorsConfig};
+use crate::doc::ApiDoc;
+use crate::rate_limit::{limit_by_ip, limit_by_user, Rate, RateLimiter, RateLimits, UserLimiter};
 
+// Every route is registered from its handler's #[utoipa::path] attribute, so
+// the path, method and OpenAPI entry cannot disagree. Handlers sharing a path
+// go in one routes! call.
 
+// Public help center: anonymous and internet-facing, so every client IP gets a
+// small burst and then a slow trickle.
+fn help_center_routes(limits: &RateLimits) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(api::list_help_articles))
+        .routes(routes!(api::get_help_article))
+        .routes(routes!(api::get_related_articles))
+        .routes(routes!(api::vote_help_article))
+        .routes(routes!(api::get_help_categories))
+        .layer(middleware::from_fn_with_state(RateLimiter::from_rate(limits.help_center), limit_by_ip))
+}
 
-pub fn routes() -> Router<AppState> {
-    Router::new()
+// Login is limited per IP to slow down password guessing across accounts;
+// login_user also locks an account for an address that keeps failing, and
+// holds the account back for a while after failures from many addresses.
+fn login_routes(limits: &RateLimits) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(api::login_user))
+        .layer(middleware::from_fn_with_state(RateLimiter::from_rate(limits.login), limit_by_ip))
+}
 
-        
-        // ---------- Authentication ----------
-        .route("/login", post(login_user))
+fn per_user(rate: Rate, jwt: &Arc<JwtKeys>) -> UserLimiter {
+    UserLimiter { limiter: RateLimiter::from_rate(rate), jwt: jwt.clone() }
+}
+
+// Whole-table imports and exports are expensive, so each user gets only a few.
+fn bulk_routes(limits: &RateLimits, jwt: &Arc<JwtKeys>) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
+        .routes(routes!(api::import_records))
+        .routes(routes!(api::export_customers))
+        .routes(routes!(api::export_tickets))
+        .routes(routes!(api::export_articles))
+        .routes(routes!(api::export_customer_data))
+        .layer(middleware::from_fn_with_state(per_user(limits.bulk, jwt), limit_by_user))
+}
+
+// The whole API with its OpenAPI document. `split_for_parts` gives the axum
+// router and the document served by Swagger UI.
+pub fn router(config: &Config, jwt: &Arc<JwtKeys>) -> OpenApiRouter<AppState> {
+    let limits = &config.rate_limits;
+    let mut router = OpenApiRouter::with_openapi(ApiDoc::openapi());
+    if config.features.help_center {
+        router = router.merge(help_center_routes(limits));
+    }
+    router
+        .merge(login_routes(limits))
+        .merge(bulk_routes(limits, jwt))
+        .merge(api_routes(limits, jwt))
+}
+
+// Browser access for the configured origins; None when CORS is off.
+pub fn cors_layer(cors: &CorsConfig) -> Option<CorsLayer> {
+    if cors.allowed_origins.is_empty() {
+        return None;
+    }
+    let origins = if cors.allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        // Checked in Config::validate().
+        AllowOrigin::list(cors.allowed_origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()))
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+            .allow_headers([
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                header::IF_MATCH,
+                HeaderName::from_static("x-api-key"),
+                HeaderName::from_static("x-tenant-id"),
+            ])
+            .expose_headers([header::ETAG, header::RETRY_AFTER]),
+    )
+}
+
+fn api_routes(limits: &RateLimits, jwt: &Arc<JwtKeys>) -> OpenApiRouter<AppState> {
+    OpenApiRouter::new()
         // ---------- Users ----------
-        .route("/users", post(create_user).get(get_users))
-        .route("/users/id", put(update_user).delete(delete_user))
+        .routes(routes!(api::create_user, api::get_users))
+        .routes(routes!(api::update_user, api::delete_user))
+        .routes(routes!(api::unlock_user))
 
         // ---------- Customers ----------
-        .route("/customers", post(create_customer).get(get_customers))
-        .route("/customers/id", put(update_customer).delete(delete_customer))
+        .routes(routes!(api::create_customer, api::get_customers))
+        .routes(routes!(api::get_customer, api::update_customer, api::delete_customer))
+        .routes(routes!(api::set_customer_company))
+        .routes(routes!(api::get_duplicate_customers))
+        .routes(routes!(api::merge_customers))
+        .routes(routes!(api::erase_customer))
+
+        // ---------- Companies ----------
+        .routes(routes!(api::create_company, api::get_companies))
+        .routes(routes!(api::update_company, api::delete_company))
+        .routes(routes!(api::get_company_contacts))
+        .routes(routes!(api::get_company_tickets))
 
         // ---------- Tickets ----------
-        .route("/tickets", post(create_ticket))
-        .route("/tickets", get(get_all_tickets))
-        .route("/tickets/id", get(get_ticket_by_id))
-        .route("/tickets/id", delete(delete_ticket_by_id))
-        .route("/tickets/id/status", patch(update_ticket_status))
-        .route("/tickets/id/priority", patch(update_ticket_priority))
-        .route("/tickets/id/assign", patch(assign_ticket))
-        .route("/tickets/search", get(get_filtered_tickets))
+        .routes(routes!(api::create_ticket, api::get_all_tickets))
+        .routes(routes!(api::get_filtered_tickets))
+        .routes(routes!(api::get_ticket_by_id, api::delete_ticket_by_id))
+        .routes(routes!(api::get_ticket_timeline))
+        .routes(routes!(api::update_ticket_status))
+        .routes(routes!(api::update_ticket_priority))
+        .routes(routes!(api::assign_ticket))
+        .routes(routes!(api::merge_tickets))
+        .routes(routes!(api::split_ticket))
 
         // ---------- Communications ----------
-        .route("/communications", post(create_communication))
-        .route("/communications/id", get(get_communications))
+        .routes(routes!(api::create_communication))
+        .routes(routes!(api::get_communications))
 
         // ---------- Knowledge Base ----------
-        .route("/kb", post(create_article))
-        .route("/kb/id", put(update_article).delete(delete_article).get(get_all_articles))
-        .route("/kb/search", get(search_articles))
+        .routes(routes!(api::create_article, api::get_all_articles))
+        .routes(routes!(api::search_articles))
+        .routes(routes!(api::get_article, api::update_article, api::delete_article))
+        .routes(routes!(api::update_article_status))
+        .routes(routes!(api::get_article_revisions))
+        .routes(routes!(api::diff_article_revisions))
+        .routes(routes!(api::restore_article_revision))
+        .routes(routes!(api::create_article_translation, api::get_article_translations))
+        .routes(routes!(api::get_suggested_articles, api::record_suggested_articles))
+        .routes(routes!(api::record_suggestion_feedback))
 
         // ---------- Tags ----------
-        .route("/tags", post(create_tag))
-        .route("/tags/id", delete(delete_tag).get(get_tags_by_id))
+        .routes(routes!(api::create_tag, api::get_tags))
+        .routes(routes!(api::get_tag_stats))
+        .routes(routes!(api::update_tag, api::delete_tag))
+        .routes(routes!(api::merge_tags))
+        .routes(routes!(api::get_tags_by_id, api::add_ticket_tag))
+        .routes(routes!(api::remove_ticket_tag))
 
         // ---------- Analytics ----------
-        .route("/analytics", post(create_analytics).get(get_analytics))
-        .route("/analytics/id", get(get_analytics_by_id))
+        .routes(routes!(api::create_analytics, api::get_analytics))
+        .routes(routes!(api::get_suggestion_quality))
+        .routes(routes!(api::get_performance_report))
+        .routes(routes!(api::get_forecast))
+        .routes(routes!(api::get_analytics_by_id))
+
+        // ---------- CSAT Surveys ----------
+        .routes(routes!(api::get_survey, api::submit_survey))
+        .routes(routes!(api::get_ticket_surveys))
+
+        // ---------- Import / Export ----------
+        .routes(routes!(api::get_import))
+        .routes(routes!(api::get_import_errors))
+
+        // ---------- Mentions ----------
+        .routes(routes!(api::get_my_mentions))
+        .routes(routes!(api::get_unread_mention_count))
+        .routes(routes!(api::mark_all_mentions_read))
+        .routes(routes!(api::mark_mention_read, api::mark_mention_unread))
+        .routes(routes!(api::get_notification_preferences, api::update_notification_preferences))
+
+        // ---------- Trash (admin) ----------
+        .routes(routes!(api::get_trash))
+        .routes(routes!(api::restore_from_trash))
+
+        // ---------- API keys (admin) ----------
+        .routes(routes!(api::create_api_key, api::get_api_keys))
+        .routes(routes!(api::revoke_api_key))
+
+        // ---------- Organizations (super admin) ----------
+        .routes(routes!(api::create_organization, api::get_organizations))
+
+        // ---------- Background jobs (admin) ----------
+        .routes(routes!(api::get_jobs))
+        .routes(routes!(api::get_job))
+        .routes(routes!(api::retry_job))
+        .routes(routes!(api::get_job_schedules))
+
+        .layer(middleware::from_fn_with_state(per_user(limits.api, jwt), limit_by_user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use utoipa::openapi::path::ParameterIn;
+
+    // Handler names in api.rs. Commented-out handlers do not start a line
+    // with `pub async fn`, so they are skipped.
+    fn handlers() -> Vec<&'static str> {
+        include_str!("api.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub async fn "))
+            .map(|rest| rest.split(['(', '<']).next().unwrap().trim())
+            .collect()
+    }
+
+    fn full_router() -> OpenApiRouter<AppState> {
+        let config = Config::default();
+        router(&config, &Arc::new(JwtKeys::from_config(&config.auth)))
+    }
+
+    #[test]
+    fn every_handler_is_documented_and_routed() {
+        let (_, doc) = full_router().split_for_parts();
+        let routed: HashSet<String> = doc
+            .paths
+            .paths
+            .values()
+            .flat_map(|item| {
+                [&item.get, &item.put, &item.post, &item.delete, &item.patch]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|op| op.operation_id.clone())
+            })
+            .collect();
 
-        // // ---------- Audit Logs ----------
-        // .route("/audit-logs", post(create_log).get(get_logs))
-        // .route("/audit-logs/id", delete(delete_log))
+        let missing: Vec<_> = handlers().into_iter().filter(|h| !routed.contains(*h)).collect();
+        assert!(missing.is_empty(), "handlers without #[utoipa::path] or missing from routes.rs: {:?}", missing);
+    }
 
-        // // ---------- Customer Support ----------
-        // .route("/api/customer/tickets", get(get_my_tickets))
-        // .route("/api/customer/tickets/:ticket_id", get(get_ticket_details))
-        // .route("/api/customer/tickets/:ticket_id/reply", post(customer_reply_ticket))
+    #[test]
+    fn path_parameters_are_documented() {
+        let (_, doc) = full_router().split_for_parts();
+        let mut undocumented = Vec::new();
 
-}
\ No newline at end of file
+        for (path, item) in &doc.paths.paths {
+            let wanted: Vec<&str> = path
+                .split('/')
+                .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+                .collect();
+            for op in [&item.get, &item.put, &item.post, &item.delete, &item.patch].into_iter().flatten() {
+                let declared: HashSet<&str> = op
+                    .parameters
+                    .iter()
+                    .flatten()
+                    .filter(|p| matches!(p.parameter_in, ParameterIn::Path))
+                    .map(|p| p.name.as_str())
+                    .collect();
+                for name in &wanted {
+                    if !declared.contains(name) {
+                        undocumented.push(format!("{} {}", path, name));
+                    }
+                }
+            }
+        }
+        assert!(undocumented.is_empty(), "path parameters missing from params(...): {:?}", undocumented);
+    }
+}
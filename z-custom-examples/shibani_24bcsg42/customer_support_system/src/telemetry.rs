@@ -0,0 +1,64 @@
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{HeaderName, Request};
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{Level, Span};
+use tracing_subscriber::EnvFilter;
+use crate::config::{LogFormat, LoggingConfig};
+
+// Taken from the caller when present so one id follows a request across
+// services, generated otherwise; echoed back on every response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+pub fn init(logging: &LoggingConfig) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&logging.filter));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match logging.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).with_current_span(true).init(),
+    }
+}
+
+pub fn set_request_id() -> SetRequestIdLayer<MakeRequestUuid> {
+    SetRequestIdLayer::new(HeaderName::from_static(REQUEST_ID_HEADER), MakeRequestUuid)
+}
+
+pub fn propagate_request_id() -> PropagateRequestIdLayer {
+    PropagateRequestIdLayer::new(HeaderName::from_static(REQUEST_ID_HEADER))
+}
+
+// One span per request. The user fields start empty and are filled in by the
+// AuthUser extractor once the caller is known.
+fn request_span(request: &Request<Body>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched");
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        path = %request.uri().path(),
+        request_id,
+        user_id = tracing::field::Empty,
+        tenant_id = tracing::field::Empty,
+        api_key_id = tracing::field::Empty,
+    )
+}
+
+type MakeSpan = fn(&Request<Body>) -> Span;
+
+pub fn trace_layer() -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>, MakeSpan> {
+    TraceLayer::new_for_http()
+        .make_span_with(request_span as MakeSpan)
+        .on_response(DefaultOnResponse::new().level(Level::INFO))
+}
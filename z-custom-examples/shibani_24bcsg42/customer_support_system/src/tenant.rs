@@ -0,0 +1,201 @@
+use sea_orm::{ColumnTrait, DeleteMany, EntityTrait, PrimaryKeyTrait, QueryFilter, Select, UpdateMany};
+use uuid::Uuid;
+use crate::entity::{
+    analytics, api_keys, article_suggestions, audit_logs, communications, companies,
+    company_domains, csat_surveys, customers, import_jobs, jobs, knowledge_base,
+    login_failures, mentions, tags, ticket_events, tickets, users,
+};
+
+// Every tenant-owned table carries a `tenant_id`. Handlers go through these
+// helpers instead of `Entity::find()` and friends so that a query can never
+// reach rows that belong to another organization. The test at the bottom
+// fails on any query that skips them without a `cross-tenant:` comment
+// saying why.
+//
+// Tables with soft delete also have a `deleted_at` column. `find_in` and
+// `find_by_id_in` leave trashed rows out; only the trash, restore and data
+// subject endpoints ask for them with the `_with_trashed` variants. Bulk
+// updates and deletes are not filtered and always name the rows they touch.
+pub trait TenantScoped: EntityTrait {
+    fn tenant_column() -> Self::Column;
+
+    fn deleted_column() -> Option<Self::Column> {
+        None
+    }
+
+    fn find_with_trashed_in(tenant_id: Uuid) -> Select<Self> {
+        Self::find().filter(Self::tenant_column().eq(tenant_id))
+    }
+
+    fn find_in(tenant_id: Uuid) -> Select<Self> {
+        let query = Self::find_with_trashed_in(tenant_id);
+        match Self::deleted_column() {
+            Some(column) => query.filter(column.is_null()),
+            None => query,
+        }
+    }
+
+    fn find_by_id_with_trashed_in<T>(id: T, tenant_id: Uuid) -> Select<Self>
+    where
+        T: Into<<Self::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+    {
+        Self::find_by_id(id).filter(Self::tenant_column().eq(tenant_id))
+    }
+
+    fn find_by_id_in<T>(id: T, tenant_id: Uuid) -> Select<Self>
+    where
+        T: Into<<Self::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+    {
+        let query = Self::find_by_id_with_trashed_in(id, tenant_id);
+        match Self::deleted_column() {
+            Some(column) => query.filter(column.is_null()),
+            None => query,
+        }
+    }
+
+    fn update_many_in(tenant_id: Uuid) -> UpdateMany<Self> {
+        Self::update_many().filter(Self::tenant_column().eq(tenant_id))
+    }
+
+    fn delete_many_in(tenant_id: Uuid) -> DeleteMany<Self> {
+        Self::delete_many().filter(Self::tenant_column().eq(tenant_id))
+    }
+}
+
+macro_rules! tenant_scoped {
+    ($($module:ident),* $(,)?) => {
+        $(
+            impl TenantScoped for $module::Entity {
+                fn tenant_column() -> Self::Column {
+                    $module::Column::TenantId
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! soft_deleted {
+    ($($module:ident),* $(,)?) => {
+        $(
+            impl TenantScoped for $module::Entity {
+                fn tenant_column() -> Self::Column {
+                    $module::Column::TenantId
+                }
+
+                fn deleted_column() -> Option<Self::Column> {
+                    Some($module::Column::DeletedAt)
+                }
+            }
+        )*
+    };
+}
+
+tenant_scoped!(
+    companies,
+    company_domains,
+    communications,
+    tags,
+    analytics,
+    audit_logs,
+    csat_surveys,
+    article_suggestions,
+    import_jobs,
+    ticket_events,
+    mentions,
+    api_keys,
+    login_failures,
+    jobs,
+);
+
+soft_deleted!(
+    users,
+    customers,
+    tickets,
+    knowledge_base,
+);
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    // Background tasks that sweep every organization on purpose.
+    const ALL_TENANT_FILES: [&str; 3] = ["jobs.rs", "kb_index.rs", "retention.rs"];
+
+    const UNSCOPED_CALLS: [&str; 5] = ["::find()", "::find_by_id(", "::update_many()", "::delete_many()", "::delete_by_id("];
+
+    // Module names listed in one of the macro calls at the bottom of this file.
+    fn listed_in(source: &str, macro_call: &str) -> Vec<String> {
+        let start = source.rfind(macro_call).expect("macro call") + macro_call.len();
+        let end = start + source[start..].find(");").expect("end of macro call");
+        source[start..end]
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    // How a scoped table's entity is written in handlers: `tickets::Entity`,
+    // or its alias from the prelude.
+    fn entity_names(modules: &[String]) -> Vec<String> {
+        let prelude = include_str!("entity/prelude.rs");
+        let mut names = Vec::new();
+        for module in modules {
+            names.push(format!("{}::Entity", module));
+            let export = format!("super::{}::Entity as ", module);
+            if let Some(line) = prelude.lines().find(|line| line.contains(&export)) {
+                let alias = line.split(" as ").nth(1).unwrap().trim_end_matches(';');
+                names.push(alias.to_string());
+            }
+        }
+        names
+    }
+
+    // A query that skips the tenant filter must say why, with a comment
+    // starting `cross-tenant:` on the line itself or in the comment above.
+    fn is_excused(lines: &[&str], at: usize) -> bool {
+        lines[at].contains("cross-tenant:")
+            || lines[..at]
+                .iter()
+                .rev()
+                .take_while(|line| line.trim_start().starts_with("//"))
+                .any(|line| line.contains("cross-tenant:"))
+    }
+
+    #[test]
+    fn tenant_tables_are_only_queried_through_tenant_scoped() {
+        // The code above the tests, which mention the macros too.
+        let source = include_str!("tenant.rs").split("#[cfg(test)]").next().unwrap();
+        let mut modules = listed_in(source, "tenant_scoped!(");
+        modules.extend(listed_in(source, "soft_deleted!("));
+        let entities = entity_names(&modules);
+
+        let mut offenders = Vec::new();
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            let file = path.file_name().unwrap().to_str().unwrap().to_string();
+            if !file.ends_with(".rs") || file == "tenant.rs" || ALL_TENANT_FILES.contains(&file.as_str()) {
+                continue;
+            }
+            let text = fs::read_to_string(&path).unwrap();
+            let lines: Vec<&str> = text.lines().collect();
+            for (i, line) in lines.iter().enumerate() {
+                if line.trim_start().starts_with("//") {
+                    continue;
+                }
+                let unscoped = entities.iter().any(|entity| {
+                    UNSCOPED_CALLS.iter().any(|call| {
+                        line.match_indices(&format!("{}{}", entity, call))
+                            .any(|(at, _)| !line[..at].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+                    })
+                });
+                if unscoped && !is_excused(&lines, i) {
+                    offenders.push(format!("{}:{}: {}", file, i + 1, line.trim()));
+                }
+            }
+        }
+        assert!(!entities.is_empty());
+        assert!(offenders.is_empty(), "use the TenantScoped helpers:\n{}", offenders.join("\n"));
+    }
+}
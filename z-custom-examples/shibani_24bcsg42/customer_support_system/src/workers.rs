@@ -0,0 +1,70 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+// Everything the server runs outside the request path. Long-running workers
+// (the trash purge) are registered by name so /readyz can tell whether they
+// are still alive; one-off jobs (webhook deliveries) are only tracked so
+// shutdown can wait for them.
+#[derive(Clone, Default)]
+pub struct Workers {
+    shutdown: CancellationToken,
+    tracker: TaskTracker,
+    running: Arc<Mutex<Vec<(&'static str, AbortHandle)>>>,
+}
+
+pub struct WorkerStatus {
+    pub name: &'static str,
+    pub running: bool,
+}
+
+impl Workers {
+    pub fn new() -> Self {
+        Workers::default()
+    }
+
+    // Cancelled when shutdown starts. Workers check it between runs and
+    // return; work already under way is left to finish.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    pub fn spawn_worker<F>(&self, name: &'static str, worker: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = self.tracker.spawn(worker);
+        self.running.lock().unwrap().push((name, handle.abort_handle()));
+    }
+
+    pub fn spawn<F>(&self, job: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(job);
+    }
+
+    pub fn status(&self) -> Vec<WorkerStatus> {
+        self.running
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, handle)| WorkerStatus { name, running: !handle.is_finished() })
+            .collect()
+    }
+
+    // Tells workers to stop and waits up to `timeout` for them and any
+    // outstanding jobs. Returns false if something was still running.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.shutdown.cancel();
+        self.tracker.close();
+        tokio::time::timeout(timeout, self.tracker.wait()).await.is_ok()
+    }
+}